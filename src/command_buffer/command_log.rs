@@ -0,0 +1,627 @@
+//! A command buffer backend that records a typed log of commands instead of calling into Vulkan.
+//!
+//! Command buffers created with [`IncompleteCommandBuffer::new_logged()`](crate::IncompleteCommandBuffer::new_logged) accept every command a Vulkan command buffer accepts, but
+//! append a [`Command`] to a [`CommandLog`] instead of recording it. This makes it possible to test the barriers, layout transitions, rendering scopes,
+//! pipeline binds and draw calls recorded by a pass graph or by pass executors on machines without a GPU.
+//!
+//! Resources used in a logged command buffer can be created with [`ImageView::detached()`](crate::ImageView::detached),
+//! [`BufferView::detached()`](crate::BufferView::detached), [`Event::detached()`](crate::Event::detached) and
+//! [`LocalPool::detached()`](crate::pool::LocalPool::detached). Detached resources are not backed by Vulkan objects. Instead, each one gets a
+//! unique handle that identifies it in the log. Creating them is `unsafe`, since they must never be used with a Vulkan command buffer.
+//!
+//! Some things still need a GPU:
+//! * Pipelines and descriptor sets are not looked up in a cache. A pipeline bind is logged with the name of the pipeline, and
+//!   descriptor sets are logged with the descriptors bound in them.
+//! * Transient resources of a pass graph cannot be allocated, so graphs with transient resources cannot be recorded to a log.
+//! * Acceleration structure commands are not supported.
+//! * `BuiltPassGraph::record_parallel()` records every secondary command buffer to its own log, which is stored in the
+//!   [`Command::ExecuteCommands`] entry executing it.
+//!
+//! # Example
+//! ```
+//! use phobos::prelude::*;
+//! use phobos::command_buffer::command_log::Command;
+//!
+//! let mut bindings = PhysicalResourceBindings::new();
+//! // Safety: these are only used with the logged command buffer.
+//! bindings.bind_image("swapchain", &unsafe { ImageView::detached(&info, vk::ImageAspectFlags::COLOR) });
+//! let mut local_pool = unsafe { LocalPool::detached() };
+//! let cmd = IncompleteCommandBuffer::<domain::All>::new_logged();
+//! let cmd = graph.record(cmd, &bindings, &mut local_pool, None, &mut ())?;
+//! let log = cmd.into_command_log()?;
+//! assert!(matches!(log.commands()[0], Command::PipelineBarrier(_)));
+//! ```
+
+use std::ffi::CStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use ash::vk;
+use ash::vk::Handle;
+
+use crate::command_buffer::state::{RenderingAttachmentInfo, RenderingInfo};
+use crate::PipelineStage;
+
+/// Get a new unique handle for a detached resource. Detached handles never collide with each other, so they identify
+/// resources in a [`CommandLog`].
+pub(crate) fn detached_handle<H: Handle>() -> H {
+    static COUNTER: AtomicU64 = AtomicU64::new(1);
+    H::from_raw(COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Read an array from a Vulkan structure.
+/// # Safety
+/// `ptr` must point to at least `count` valid elements if `count` is not zero.
+unsafe fn vk_slice<'a, T>(ptr: *const T, count: u32) -> &'a [T] {
+    match count {
+        0 => &[],
+        count => std::slice::from_raw_parts(ptr, count as usize),
+    }
+}
+
+/// A global memory barrier in a [`Dependency`].
+#[derive(Debug, Copy, Clone)]
+pub struct MemoryBarrier {
+    /// Stages that must complete before the barrier.
+    pub src_stage: PipelineStage,
+    /// Writes made available by the barrier.
+    pub src_access: vk::AccessFlags2,
+    /// Stages that wait on the barrier.
+    pub dst_stage: PipelineStage,
+    /// Accesses the writes are made visible to.
+    pub dst_access: vk::AccessFlags2,
+}
+
+/// A buffer memory barrier in a [`Dependency`].
+#[derive(Debug, Copy, Clone)]
+pub struct BufferBarrier {
+    /// Stages that must complete before the barrier.
+    pub src_stage: PipelineStage,
+    /// Writes made available by the barrier.
+    pub src_access: vk::AccessFlags2,
+    /// Stages that wait on the barrier.
+    pub dst_stage: PipelineStage,
+    /// Accesses the writes are made visible to.
+    pub dst_access: vk::AccessFlags2,
+    /// Queue family releasing ownership of the buffer.
+    pub src_queue_family_index: u32,
+    /// Queue family acquiring ownership of the buffer.
+    pub dst_queue_family_index: u32,
+    /// The buffer.
+    pub buffer: vk::Buffer,
+    /// Start of the range of the buffer.
+    pub offset: vk::DeviceSize,
+    /// Size of the range of the buffer.
+    pub size: vk::DeviceSize,
+}
+
+/// An image memory barrier in a [`Dependency`].
+#[derive(Debug, Copy, Clone)]
+pub struct ImageBarrier {
+    /// Stages that must complete before the barrier.
+    pub src_stage: PipelineStage,
+    /// Writes made available by the barrier.
+    pub src_access: vk::AccessFlags2,
+    /// Stages that wait on the barrier.
+    pub dst_stage: PipelineStage,
+    /// Accesses the writes are made visible to.
+    pub dst_access: vk::AccessFlags2,
+    /// Layout the image is transitioned from.
+    pub old_layout: vk::ImageLayout,
+    /// Layout the image is transitioned to.
+    pub new_layout: vk::ImageLayout,
+    /// Queue family releasing ownership of the image.
+    pub src_queue_family_index: u32,
+    /// Queue family acquiring ownership of the image.
+    pub dst_queue_family_index: u32,
+    /// The image, see [`ImageView::image()`](crate::image::ImgView::image).
+    pub image: vk::Image,
+    /// Range of the image the barrier applies to.
+    pub subresource_range: vk::ImageSubresourceRange,
+}
+
+/// The barriers of a `VkDependencyInfo`.
+#[derive(Debug, Default, Clone)]
+pub struct Dependency {
+    /// Dependency flags.
+    pub flags: vk::DependencyFlags,
+    /// Global memory barriers.
+    pub memory_barriers: Vec<MemoryBarrier>,
+    /// Buffer memory barriers.
+    pub buffer_barriers: Vec<BufferBarrier>,
+    /// Image memory barriers.
+    pub image_barriers: Vec<ImageBarrier>,
+}
+
+impl Dependency {
+    /// Copy the barriers of a dependency info.
+    /// # Safety
+    /// All barrier arrays of `info` must be valid.
+    pub(crate) unsafe fn from_vk(info: &vk::DependencyInfo) -> Self {
+        Self {
+            flags: info.dependency_flags,
+            memory_barriers: vk_slice(info.p_memory_barriers, info.memory_barrier_count)
+                .iter()
+                .map(|barrier| MemoryBarrier {
+                    src_stage: barrier.src_stage_mask,
+                    src_access: barrier.src_access_mask,
+                    dst_stage: barrier.dst_stage_mask,
+                    dst_access: barrier.dst_access_mask,
+                })
+                .collect(),
+            buffer_barriers: vk_slice(info.p_buffer_memory_barriers, info.buffer_memory_barrier_count)
+                .iter()
+                .map(|barrier| BufferBarrier {
+                    src_stage: barrier.src_stage_mask,
+                    src_access: barrier.src_access_mask,
+                    dst_stage: barrier.dst_stage_mask,
+                    dst_access: barrier.dst_access_mask,
+                    src_queue_family_index: barrier.src_queue_family_index,
+                    dst_queue_family_index: barrier.dst_queue_family_index,
+                    buffer: barrier.buffer,
+                    offset: barrier.offset,
+                    size: barrier.size,
+                })
+                .collect(),
+            image_barriers: vk_slice(info.p_image_memory_barriers, info.image_memory_barrier_count)
+                .iter()
+                .map(|barrier| ImageBarrier {
+                    src_stage: barrier.src_stage_mask,
+                    src_access: barrier.src_access_mask,
+                    dst_stage: barrier.dst_stage_mask,
+                    dst_access: barrier.dst_access_mask,
+                    old_layout: barrier.old_layout,
+                    new_layout: barrier.new_layout,
+                    src_queue_family_index: barrier.src_queue_family_index,
+                    dst_queue_family_index: barrier.dst_queue_family_index,
+                    image: barrier.image,
+                    subresource_range: barrier.subresource_range,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// An attachment of a [`Rendering`] scope.
+#[derive(Derivative, Copy, Clone)]
+#[derivative(Debug)]
+pub struct RenderingAttachment {
+    /// The image view rendered to, see [`ImageView::handle()`](crate::image::ImgView::handle).
+    pub image_view: vk::ImageView,
+    /// Layout of the image view while rendering.
+    pub image_layout: vk::ImageLayout,
+    /// The image view multisampled contents are resolved to, if any.
+    pub resolve_image_view: Option<vk::ImageView>,
+    /// Load operation.
+    pub load_op: vk::AttachmentLoadOp,
+    /// Store operation.
+    pub store_op: vk::AttachmentStoreOp,
+    /// Clear value, only used if `load_op` is [`vk::AttachmentLoadOp::CLEAR`].
+    #[derivative(Debug = "ignore")]
+    pub clear_value: vk::ClearValue,
+}
+
+impl From<&RenderingAttachmentInfo> for RenderingAttachment {
+    fn from(info: &RenderingAttachmentInfo) -> Self {
+        Self {
+            // SAFETY: Handles are only used to identify the image views.
+            image_view: unsafe { info.image_view.handle() },
+            image_layout: info.image_layout,
+            resolve_image_view: info
+                .resolve_image_view
+                .as_ref()
+                .map(|view| unsafe { view.handle() }),
+            load_op: info.load_op,
+            store_op: info.store_op,
+            clear_value: info.clear_value,
+        }
+    }
+}
+
+/// A dynamic rendering scope started with `vkCmdBeginRendering`.
+#[derive(Debug, Clone)]
+pub struct Rendering {
+    /// Rendering flags.
+    pub flags: vk::RenderingFlags,
+    /// Area rendered to.
+    pub render_area: vk::Rect2D,
+    /// Number of layers rendered to.
+    pub layer_count: u32,
+    /// View mask for multiview rendering.
+    pub view_mask: u32,
+    /// Color attachments.
+    pub color_attachments: Vec<RenderingAttachment>,
+    /// Depth attachment.
+    pub depth_attachment: Option<RenderingAttachment>,
+    /// Stencil attachment.
+    pub stencil_attachment: Option<RenderingAttachment>,
+}
+
+impl From<&RenderingInfo> for Rendering {
+    fn from(info: &RenderingInfo) -> Self {
+        Self {
+            flags: info.flags,
+            render_area: info.render_area,
+            layer_count: info.layer_count,
+            view_mask: info.view_mask,
+            color_attachments: info.color_attachments.iter().map(RenderingAttachment::from).collect(),
+            depth_attachment: info.depth_attachment.as_ref().map(RenderingAttachment::from),
+            stencil_attachment: info.stencil_attachment.as_ref().map(RenderingAttachment::from),
+        }
+    }
+}
+
+/// A binding in a descriptor set bound with [`Command::BindDescriptorSet`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DescriptorWrite {
+    /// Binding index.
+    pub binding: u32,
+    /// Type of the descriptors.
+    pub ty: vk::DescriptorType,
+    /// Number of descriptors in the binding.
+    pub count: usize,
+}
+
+/// A command recorded to a [`CommandLog`]. Each variant corresponds to one Vulkan command, see [`Command::name()`].
+#[derive(Derivative, Clone)]
+#[derivative(Debug)]
+pub enum Command {
+    /// `vkCmdPipelineBarrier2`
+    PipelineBarrier(Dependency),
+    /// `vkCmdSetEvent2`
+    SetEvent {
+        /// The event, see [`Event::handle()`](crate::Event::handle).
+        event: vk::Event,
+        /// Dependency the event is signaled with.
+        dependency: Dependency,
+    },
+    /// `vkCmdWaitEvents2`
+    WaitEvents {
+        /// The events waited on.
+        events: Vec<vk::Event>,
+        /// The dependency of each event.
+        dependencies: Vec<Dependency>,
+    },
+    /// `vkCmdResetEvent2`
+    ResetEvent {
+        /// The event.
+        event: vk::Event,
+        /// Stages that must complete before the event is reset.
+        stage: PipelineStage,
+    },
+    /// `vkCmdBeginRendering`
+    BeginRendering(Rendering),
+    /// `vkCmdEndRendering`
+    EndRendering,
+    /// `vkCmdBindPipeline`
+    BindPipeline {
+        /// Name of the pipeline in the pipeline cache.
+        name: String,
+        /// Bind point of the pipeline.
+        bind_point: vk::PipelineBindPoint,
+    },
+    /// `vkCmdBindDescriptorSets`, for a single descriptor set.
+    BindDescriptorSet {
+        /// Bind point of the pipeline the set is bound for.
+        bind_point: vk::PipelineBindPoint,
+        /// Set index.
+        set: u32,
+        /// Bindings in the set, sorted by binding index.
+        descriptors: Vec<DescriptorWrite>,
+    },
+    /// `vkCmdPushConstants`
+    PushConstants {
+        /// Shader stages that can access the push constants.
+        stage: vk::ShaderStageFlags,
+        /// Byte offset of the data.
+        offset: u32,
+        /// The data.
+        data: Vec<u8>,
+    },
+    /// `vkCmdSetViewport`
+    SetViewport(vk::Viewport),
+    /// `vkCmdSetScissor`
+    SetScissor(vk::Rect2D),
+    /// `vkCmdSetPolygonModeEXT`
+    SetPolygonMode(vk::PolygonMode),
+    /// `vkCmdBindVertexBuffers`, for a single binding.
+    BindVertexBuffer {
+        /// Vertex input binding.
+        binding: u32,
+        /// The buffer.
+        buffer: vk::Buffer,
+        /// Byte offset into the buffer.
+        offset: vk::DeviceSize,
+    },
+    /// `vkCmdBindIndexBuffer`
+    BindIndexBuffer {
+        /// The buffer.
+        buffer: vk::Buffer,
+        /// Byte offset into the buffer.
+        offset: vk::DeviceSize,
+        /// Type of the indices.
+        index_type: vk::IndexType,
+    },
+    /// `vkCmdDraw`
+    Draw {
+        /// Number of vertices.
+        vertex_count: u32,
+        /// Number of instances.
+        instance_count: u32,
+        /// First vertex.
+        first_vertex: u32,
+        /// First instance.
+        first_instance: u32,
+    },
+    /// `vkCmdDrawIndexed`
+    DrawIndexed {
+        /// Number of indices.
+        index_count: u32,
+        /// Number of instances.
+        instance_count: u32,
+        /// First index.
+        first_index: u32,
+        /// Value added to each index.
+        vertex_offset: i32,
+        /// First instance.
+        first_instance: u32,
+    },
+    /// `vkCmdDrawIndirect`
+    DrawIndirect {
+        /// Buffer with the draw commands.
+        buffer: vk::Buffer,
+        /// Byte offset of the first draw command.
+        offset: vk::DeviceSize,
+        /// Number of draws.
+        draw_count: u32,
+        /// Byte stride between draw commands.
+        stride: u32,
+    },
+    /// `vkCmdDrawIndexedIndirect`
+    DrawIndexedIndirect {
+        /// Buffer with the draw commands.
+        buffer: vk::Buffer,
+        /// Byte offset of the first draw command.
+        offset: vk::DeviceSize,
+        /// Number of draws.
+        draw_count: u32,
+        /// Byte stride between draw commands.
+        stride: u32,
+    },
+    /// `vkCmdDrawIndirectCount`
+    DrawIndirectCount {
+        /// Buffer with the draw commands.
+        buffer: vk::Buffer,
+        /// Byte offset of the first draw command.
+        offset: vk::DeviceSize,
+        /// Buffer with the number of draws.
+        count_buffer: vk::Buffer,
+        /// Byte offset of the number of draws.
+        count_offset: vk::DeviceSize,
+        /// Maximum number of draws.
+        max_draw_count: u32,
+        /// Byte stride between draw commands.
+        stride: u32,
+    },
+    /// `vkCmdDrawIndexedIndirectCount`
+    DrawIndexedIndirectCount {
+        /// Buffer with the draw commands.
+        buffer: vk::Buffer,
+        /// Byte offset of the first draw command.
+        offset: vk::DeviceSize,
+        /// Buffer with the number of draws.
+        count_buffer: vk::Buffer,
+        /// Byte offset of the number of draws.
+        count_offset: vk::DeviceSize,
+        /// Maximum number of draws.
+        max_draw_count: u32,
+        /// Byte stride between draw commands.
+        stride: u32,
+    },
+    /// `vkCmdDispatch`
+    Dispatch {
+        /// Work groups in the x dimension.
+        x: u32,
+        /// Work groups in the y dimension.
+        y: u32,
+        /// Work groups in the z dimension.
+        z: u32,
+    },
+    /// `vkCmdTraceRaysKHR`
+    TraceRays {
+        /// Width of the ray grid.
+        width: u32,
+        /// Height of the ray grid.
+        height: u32,
+        /// Depth of the ray grid.
+        depth: u32,
+    },
+    /// `vkCmdCopyBuffer`
+    CopyBuffer {
+        /// Source buffer.
+        src: vk::Buffer,
+        /// Destination buffer.
+        dst: vk::Buffer,
+        /// Copied region.
+        region: vk::BufferCopy,
+    },
+    /// `vkCmdCopyBufferToImage`
+    CopyBufferToImage {
+        /// Source buffer.
+        src: vk::Buffer,
+        /// Destination image.
+        dst: vk::Image,
+        /// Copied region.
+        region: vk::BufferImageCopy,
+    },
+    /// `vkCmdCopyImage`
+    CopyImage {
+        /// Source image.
+        src: vk::Image,
+        /// Destination image.
+        dst: vk::Image,
+        /// Copied region.
+        region: vk::ImageCopy,
+    },
+    /// `vkCmdCopyImageToBuffer`
+    CopyImageToBuffer {
+        /// Source image.
+        src: vk::Image,
+        /// Destination buffer.
+        dst: vk::Buffer,
+        /// Copied region.
+        region: vk::BufferImageCopy,
+    },
+    /// `vkCmdFillBuffer`
+    FillBuffer {
+        /// The buffer.
+        buffer: vk::Buffer,
+        /// Byte offset into the buffer.
+        offset: vk::DeviceSize,
+        /// Number of bytes filled.
+        size: vk::DeviceSize,
+        /// Value written.
+        value: u32,
+    },
+    /// `vkCmdBlitImage`
+    BlitImage {
+        /// Source image.
+        src: vk::Image,
+        /// Destination image.
+        dst: vk::Image,
+        /// Blitted region.
+        region: vk::ImageBlit,
+        /// Filter used for scaling.
+        filter: vk::Filter,
+    },
+    /// `vkCmdClearColorImage`
+    ClearColorImage {
+        /// The image.
+        image: vk::Image,
+        /// Clear color.
+        #[derivative(Debug = "ignore")]
+        color: vk::ClearColorValue,
+        /// Cleared range of the image.
+        range: vk::ImageSubresourceRange,
+    },
+    /// `vkCmdBeginQuery`
+    BeginQuery {
+        /// The query pool.
+        query_pool: vk::QueryPool,
+        /// Query index.
+        index: u32,
+    },
+    /// `vkCmdEndQuery`
+    EndQuery {
+        /// The query pool.
+        query_pool: vk::QueryPool,
+        /// Query index.
+        index: u32,
+    },
+    /// `vkCmdWriteTimestamp2`
+    WriteTimestamp {
+        /// The query pool.
+        query_pool: vk::QueryPool,
+        /// Stage the timestamp is written after.
+        stage: PipelineStage,
+        /// Query index.
+        index: u32,
+    },
+    /// `vkCmdBeginDebugUtilsLabelEXT`
+    BeginLabel {
+        /// Name of the label.
+        name: String,
+        /// Color of the label.
+        color: [f32; 4],
+    },
+    /// `vkCmdEndDebugUtilsLabelEXT`
+    EndLabel,
+    /// `vkCmdExecuteCommands`
+    ExecuteCommands {
+        /// The secondary command buffers.
+        buffers: Vec<vk::CommandBuffer>,
+        /// The commands recorded to each secondary command buffer, if these were recorded to a command log.
+        logs: Vec<CommandLog>,
+    },
+}
+
+impl Command {
+    /// Get the name of the Vulkan command.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::PipelineBarrier(_) => "vkCmdPipelineBarrier2",
+            Command::SetEvent { .. } => "vkCmdSetEvent2",
+            Command::WaitEvents { .. } => "vkCmdWaitEvents2",
+            Command::ResetEvent { .. } => "vkCmdResetEvent2",
+            Command::BeginRendering(_) => "vkCmdBeginRendering",
+            Command::EndRendering => "vkCmdEndRendering",
+            Command::BindPipeline { .. } => "vkCmdBindPipeline",
+            Command::BindDescriptorSet { .. } => "vkCmdBindDescriptorSets",
+            Command::PushConstants { .. } => "vkCmdPushConstants",
+            Command::SetViewport(_) => "vkCmdSetViewport",
+            Command::SetScissor(_) => "vkCmdSetScissor",
+            Command::SetPolygonMode(_) => "vkCmdSetPolygonModeEXT",
+            Command::BindVertexBuffer { .. } => "vkCmdBindVertexBuffers",
+            Command::BindIndexBuffer { .. } => "vkCmdBindIndexBuffer",
+            Command::Draw { .. } => "vkCmdDraw",
+            Command::DrawIndexed { .. } => "vkCmdDrawIndexed",
+            Command::DrawIndirect { .. } => "vkCmdDrawIndirect",
+            Command::DrawIndexedIndirect { .. } => "vkCmdDrawIndexedIndirect",
+            Command::DrawIndirectCount { .. } => "vkCmdDrawIndirectCount",
+            Command::DrawIndexedIndirectCount { .. } => "vkCmdDrawIndexedIndirectCount",
+            Command::Dispatch { .. } => "vkCmdDispatch",
+            Command::TraceRays { .. } => "vkCmdTraceRaysKHR",
+            Command::CopyBuffer { .. } => "vkCmdCopyBuffer",
+            Command::CopyBufferToImage { .. } => "vkCmdCopyBufferToImage",
+            Command::CopyImage { .. } => "vkCmdCopyImage",
+            Command::CopyImageToBuffer { .. } => "vkCmdCopyImageToBuffer",
+            Command::FillBuffer { .. } => "vkCmdFillBuffer",
+            Command::BlitImage { .. } => "vkCmdBlitImage",
+            Command::ClearColorImage { .. } => "vkCmdClearColorImage",
+            Command::BeginQuery { .. } => "vkCmdBeginQuery",
+            Command::EndQuery { .. } => "vkCmdEndQuery",
+            Command::WriteTimestamp { .. } => "vkCmdWriteTimestamp2",
+            Command::BeginLabel { .. } => "vkCmdBeginDebugUtilsLabelEXT",
+            Command::EndLabel => "vkCmdEndDebugUtilsLabelEXT",
+            Command::ExecuteCommands { .. } => "vkCmdExecuteCommands",
+        }
+    }
+
+    /// Create a label command from a debug label.
+    /// # Safety
+    /// The label name must be a valid null-terminated string.
+    pub(crate) unsafe fn label(label: &vk::DebugUtilsLabelEXT) -> Self {
+        Command::BeginLabel {
+            name: CStr::from_ptr(label.p_label_name).to_string_lossy().into_owned(),
+            color: label.color,
+        }
+    }
+}
+
+/// The commands recorded to a command buffer created with [`IncompleteCommandBuffer::new_logged()`](crate::IncompleteCommandBuffer::new_logged),
+/// in recording order.
+#[derive(Debug, Default, Clone)]
+pub struct CommandLog {
+    commands: Vec<Command>,
+}
+
+impl CommandLog {
+    /// Create an empty command log.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a command to the log.
+    pub(crate) fn push(&mut self, command: Command) {
+        self.commands.push(command);
+    }
+
+    /// Get all commands in recording order.
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// Get the names of all commands in recording order, see [`Command::name()`]. Useful to check the order of the commands.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.commands.iter().map(Command::name).collect()
+    }
+}
@@ -4,6 +4,7 @@ use anyhow::Result;
 use ash::vk;
 
 use crate::{Allocator, ComputeCmdBuffer, ComputeSupport};
+use crate::command_buffer::command_log::Command;
 use crate::command_buffer::IncompleteCommandBuffer;
 use crate::core::device::ExtensionID;
 use crate::query_pool::{AccelerationStructurePropertyQuery, QueryPool};
@@ -29,7 +30,10 @@ impl<D: ComputeSupport + ExecutionDomain, A: Allocator> ComputeCmdBuffer
     fn bind_compute_pipeline(mut self, name: &str) -> Result<Self>
     where
         Self: Sized, {
-        let cache = self.pipeline_cache.clone();
+        let Some(cache) = self.pipeline_cache() else {
+            self.log_bind_pipeline(name, vk::PipelineBindPoint::COMPUTE);
+            return Ok(self);
+        };
         cache.with_compute_pipeline(name, |pipeline| {
             self.bind_pipeline_impl(
                 pipeline.handle,
@@ -67,9 +71,14 @@ impl<D: ComputeSupport + ExecutionDomain, A: Allocator> ComputeCmdBuffer
     /// ```
     fn dispatch(mut self, x: u32, y: u32, z: u32) -> Result<Self> {
         self = self.ensure_descriptor_state()?;
-        unsafe {
-            self.device.cmd_dispatch(self.handle, x, y, z);
-        }
+        self.record_command(
+            || Command::Dispatch {
+                x,
+                y,
+                z,
+            },
+            |device, handle| unsafe { device.cmd_dispatch(handle, x, y, z) },
+        );
         Ok(self)
     }
 
@@ -90,8 +99,8 @@ impl<D: ComputeSupport + ExecutionDomain, A: Allocator> ComputeCmdBuffer
     ) -> Result<Self>
     where
         Self: Sized, {
-        self.device
-            .require_extension(ExtensionID::AccelerationStructure)?;
+        let device = self.vulkan_device()?;
+        device.require_extension(ExtensionID::AccelerationStructure)?;
         let as_vk = info.iter().map(|info| info.as_vulkan()).collect::<Vec<_>>();
         let geometries = as_vk
             .iter()
@@ -99,11 +108,11 @@ impl<D: ComputeSupport + ExecutionDomain, A: Allocator> ComputeCmdBuffer
             .collect::<Vec<_>>();
         let infos = as_vk.iter().map(|(_, ranges)| *ranges).collect::<Vec<_>>();
         unsafe {
-            self.device
+            device
                 .acceleration_structure()
                 .unwrap()
                 .cmd_build_acceleration_structures(
-                    self.handle,
+                    self.handle(),
                     geometries.as_slice(),
                     infos.as_slice(),
                 );
@@ -119,9 +128,9 @@ impl<D: ComputeSupport + ExecutionDomain, A: Allocator> ComputeCmdBuffer
         src: &AccelerationStructure,
         dst: &AccelerationStructure,
     ) -> Result<Self> {
-        self.device
-            .require_extension(ExtensionID::AccelerationStructure)?;
-        let fns = self.device.acceleration_structure().unwrap();
+        let device = self.vulkan_device()?;
+        device.require_extension(ExtensionID::AccelerationStructure)?;
+        let fns = device.acceleration_structure().unwrap();
         let info = vk::CopyAccelerationStructureInfoKHR {
             s_type: vk::StructureType::COPY_ACCELERATION_STRUCTURE_INFO_KHR,
             p_next: std::ptr::null(),
@@ -130,7 +139,7 @@ impl<D: ComputeSupport + ExecutionDomain, A: Allocator> ComputeCmdBuffer
             mode: vk::CopyAccelerationStructureModeKHR::COMPACT,
         };
         unsafe {
-            fns.cmd_copy_acceleration_structure(self.handle, &info);
+            fns.cmd_copy_acceleration_structure(self.handle(), &info);
         };
         Ok(self)
     }
@@ -145,9 +154,9 @@ impl<D: ComputeSupport + ExecutionDomain, A: Allocator> ComputeCmdBuffer
         src: &[AccelerationStructure],
         query_pool: &mut QueryPool<Q>,
     ) -> Result<Self> {
-        self.device
-            .require_extension(ExtensionID::AccelerationStructure)?;
-        let fns = self.device.acceleration_structure().unwrap();
+        let device = self.vulkan_device()?;
+        device.require_extension(ExtensionID::AccelerationStructure)?;
+        let fns = device.acceleration_structure().unwrap();
 
         let handles = src
             .iter()
@@ -156,7 +165,7 @@ impl<D: ComputeSupport + ExecutionDomain, A: Allocator> ComputeCmdBuffer
         let first = query_pool.current();
         unsafe {
             fns.cmd_write_acceleration_structures_properties(
-                self.handle,
+                self.handle(),
                 handles.as_slice(),
                 Q::QUERY_TYPE,
                 query_pool.handle(),
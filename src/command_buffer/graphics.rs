@@ -4,6 +4,7 @@ use anyhow::{bail, Result};
 use ash::vk;
 
 use crate::{Allocator, BufferView, Error, GfxSupport, GraphicsCmdBuffer, ImageView};
+use crate::command_buffer::command_log::Command;
 use crate::command_buffer::IncompleteCommandBuffer;
 use crate::core::device::ExtensionID;
 use crate::sync::domain::ExecutionDomain;
@@ -52,11 +53,13 @@ impl<D: GfxSupport + ExecutionDomain, A: Allocator> GraphicsCmdBuffer
     ///     })
     /// }
     /// ```
-    fn viewport(self, viewport: vk::Viewport) -> Self {
-        unsafe {
-            self.device
-                .cmd_set_viewport(self.handle, 0, std::slice::from_ref(&viewport));
-        }
+    fn viewport(mut self, viewport: vk::Viewport) -> Self {
+        self.record_command(
+            || Command::SetViewport(viewport),
+            |device, handle| unsafe {
+                device.cmd_set_viewport(handle, 0, std::slice::from_ref(&viewport));
+            },
+        );
         self
     }
 
@@ -76,11 +79,13 @@ impl<D: GfxSupport + ExecutionDomain, A: Allocator> GraphicsCmdBuffer
     ///     })
     /// }
     /// ```
-    fn scissor(self, scissor: vk::Rect2D) -> Self {
-        unsafe {
-            self.device
-                .cmd_set_scissor(self.handle, 0, std::slice::from_ref(&scissor));
-        }
+    fn scissor(mut self, scissor: vk::Rect2D) -> Self {
+        self.record_command(
+            || Command::SetScissor(scissor),
+            |device, handle| unsafe {
+                device.cmd_set_scissor(handle, 0, std::slice::from_ref(&scissor));
+            },
+        );
         self
     }
 
@@ -108,15 +113,17 @@ impl<D: GfxSupport + ExecutionDomain, A: Allocator> GraphicsCmdBuffer
         first_instance: u32,
     ) -> Result<Self> {
         self = self.ensure_descriptor_state()?;
-        unsafe {
-            self.device.cmd_draw(
-                self.handle,
+        self.record_command(
+            || Command::Draw {
                 vertex_count,
                 instance_count,
                 first_vertex,
                 first_instance,
-            );
-        }
+            },
+            |device, handle| unsafe {
+                device.cmd_draw(handle, vertex_count, instance_count, first_vertex, first_instance);
+            },
+        );
         Ok(self)
     }
 
@@ -146,16 +153,161 @@ impl<D: GfxSupport + ExecutionDomain, A: Allocator> GraphicsCmdBuffer
         first_instance: u32,
     ) -> Result<Self> {
         self = self.ensure_descriptor_state()?;
-        unsafe {
-            self.device.cmd_draw_indexed(
-                self.handle,
+        self.record_command(
+            || Command::DrawIndexed {
                 index_count,
                 instance_count,
                 first_index,
                 vertex_offset,
                 first_instance,
-            )
+            },
+            |device, handle| unsafe {
+                device.cmd_draw_indexed(
+                    handle,
+                    index_count,
+                    instance_count,
+                    first_index,
+                    vertex_offset,
+                    first_instance,
+                );
+            },
+        );
+        Ok(self)
+    }
+
+    /// Issue `draw_count` drawcalls with parameters read from `buffer`, which holds a [`vk::DrawIndirectCommand`] every `stride` bytes
+    /// starting at the offset of the view. This will flush the current descriptor state and actually bind the descriptor sets.
+    /// Drawing more than once requires the `multiDrawIndirect` feature.
+    /// Directly translates to [`vkCmdDrawIndirect`](https://registry.khronos.org/vulkan/specs/1.3-extensions/man/html/vkCmdDrawIndirect.html).
+    /// # Errors
+    /// * Fails if the offset of `buffer` is not a multiple of 4.
+    /// * Fails if `draw_count` is greater than 1 and `stride` is not a multiple of 4 or smaller than a draw command.
+    /// * Fails if `buffer` is too small to hold `draw_count` draw commands.
+    /// * Fails if flushing the descriptor state fails.
+    fn draw_indirect(mut self, buffer: &BufferView, draw_count: u32, stride: u32) -> Result<Self> {
+        let command_size = std::mem::size_of::<vk::DrawIndirectCommand>() as u32;
+        if draw_count > 1 {
+            validate_indirect_stride(stride, command_size)?;
         }
+        validate_indirect_buffer(buffer, draw_count, stride, command_size)?;
+        self = self.ensure_descriptor_state()?;
+        let (handle, offset) = (unsafe { buffer.handle() }, buffer.offset());
+        self.record_command(
+            || Command::DrawIndirect {
+                buffer: handle,
+                offset,
+                draw_count,
+                stride,
+            },
+            |device, cmd| unsafe { device.cmd_draw_indirect(cmd, handle, offset, draw_count, stride) },
+        );
+        Ok(self)
+    }
+
+    /// Issue `draw_count` indexed drawcalls with parameters read from `buffer`, which holds a [`vk::DrawIndexedIndirectCommand`] every `stride` bytes
+    /// starting at the offset of the view. This will flush the current descriptor state and actually bind the descriptor sets.
+    /// Drawing more than once requires the `multiDrawIndirect` feature.
+    /// Directly translates to [`vkCmdDrawIndexedIndirect`](https://registry.khronos.org/vulkan/specs/1.3-extensions/man/html/vkCmdDrawIndexedIndirect.html).
+    /// # Errors
+    /// * Fails if the offset of `buffer` is not a multiple of 4.
+    /// * Fails if `draw_count` is greater than 1 and `stride` is not a multiple of 4 or smaller than a draw command.
+    /// * Fails if `buffer` is too small to hold `draw_count` draw commands.
+    /// * Fails if flushing the descriptor state fails.
+    fn draw_indexed_indirect(mut self, buffer: &BufferView, draw_count: u32, stride: u32) -> Result<Self> {
+        let command_size = std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+        if draw_count > 1 {
+            validate_indirect_stride(stride, command_size)?;
+        }
+        validate_indirect_buffer(buffer, draw_count, stride, command_size)?;
+        self = self.ensure_descriptor_state()?;
+        let (handle, offset) = (unsafe { buffer.handle() }, buffer.offset());
+        self.record_command(
+            || Command::DrawIndexedIndirect {
+                buffer: handle,
+                offset,
+                draw_count,
+                stride,
+            },
+            |device, cmd| unsafe { device.cmd_draw_indexed_indirect(cmd, handle, offset, draw_count, stride) },
+        );
+        Ok(self)
+    }
+
+    /// Issue drawcalls with parameters read from `buffer`, which holds a [`vk::DrawIndirectCommand`] every `stride` bytes starting at
+    /// the offset of the view. The number of draws is read as a `u32` from the start of `count_buffer`, and is clamped to `max_draw_count`.
+    /// This will flush the current descriptor state and actually bind the descriptor sets. Requires the `drawIndirectCount` feature.
+    /// Directly translates to [`vkCmdDrawIndirectCount`](https://registry.khronos.org/vulkan/specs/1.3-extensions/man/html/vkCmdDrawIndirectCount.html).
+    /// # Errors
+    /// * Fails if the offset of `buffer` or `count_buffer` is not a multiple of 4.
+    /// * Fails if `stride` is not a multiple of 4 or smaller than a draw command.
+    /// * Fails if `buffer` is too small to hold `max_draw_count` draw commands, or `count_buffer` is too small to hold the count.
+    /// * Fails if flushing the descriptor state fails.
+    fn draw_indirect_count(
+        mut self,
+        buffer: &BufferView,
+        count_buffer: &BufferView,
+        max_draw_count: u32,
+        stride: u32,
+    ) -> Result<Self> {
+        let command_size = std::mem::size_of::<vk::DrawIndirectCommand>() as u32;
+        validate_indirect_stride(stride, command_size)?;
+        validate_indirect_buffer(buffer, max_draw_count, stride, command_size)?;
+        validate_indirect_count_buffer(count_buffer)?;
+        self = self.ensure_descriptor_state()?;
+        let (handle, offset) = (unsafe { buffer.handle() }, buffer.offset());
+        let (count_handle, count_offset) = (unsafe { count_buffer.handle() }, count_buffer.offset());
+        self.record_command(
+            || Command::DrawIndirectCount {
+                buffer: handle,
+                offset,
+                count_buffer: count_handle,
+                count_offset,
+                max_draw_count,
+                stride,
+            },
+            |device, cmd| unsafe {
+                device.cmd_draw_indirect_count(cmd, handle, offset, count_handle, count_offset, max_draw_count, stride);
+            },
+        );
+        Ok(self)
+    }
+
+    /// Issue indexed drawcalls with parameters read from `buffer`, which holds a [`vk::DrawIndexedIndirectCommand`] every `stride` bytes starting at
+    /// the offset of the view. The number of draws is read as a `u32` from the start of `count_buffer`, and is clamped to `max_draw_count`.
+    /// This will flush the current descriptor state and actually bind the descriptor sets. Requires the `drawIndirectCount` feature.
+    /// Directly translates to [`vkCmdDrawIndexedIndirectCount`](https://registry.khronos.org/vulkan/specs/1.3-extensions/man/html/vkCmdDrawIndexedIndirectCount.html).
+    /// # Errors
+    /// * Fails if the offset of `buffer` or `count_buffer` is not a multiple of 4.
+    /// * Fails if `stride` is not a multiple of 4 or smaller than a draw command.
+    /// * Fails if `buffer` is too small to hold `max_draw_count` draw commands, or `count_buffer` is too small to hold the count.
+    /// * Fails if flushing the descriptor state fails.
+    fn draw_indexed_indirect_count(
+        mut self,
+        buffer: &BufferView,
+        count_buffer: &BufferView,
+        max_draw_count: u32,
+        stride: u32,
+    ) -> Result<Self> {
+        let command_size = std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+        validate_indirect_stride(stride, command_size)?;
+        validate_indirect_buffer(buffer, max_draw_count, stride, command_size)?;
+        validate_indirect_count_buffer(count_buffer)?;
+        self = self.ensure_descriptor_state()?;
+        let (handle, offset) = (unsafe { buffer.handle() }, buffer.offset());
+        let (count_handle, count_offset) = (unsafe { count_buffer.handle() }, count_buffer.offset());
+        self.record_command(
+            || Command::DrawIndexedIndirectCount {
+                buffer: handle,
+                offset,
+                count_buffer: count_handle,
+                count_offset,
+                max_draw_count,
+                stride,
+            },
+            |device, cmd| unsafe {
+                device.cmd_draw_indexed_indirect_count(cmd, handle, offset, count_handle, count_offset, max_draw_count, stride);
+            },
+        );
         Ok(self)
     }
 
@@ -163,23 +315,35 @@ impl<D: GfxSupport + ExecutionDomain, A: Allocator> GraphicsCmdBuffer
     fn trace_rays(mut self, width: u32, height: u32, depth: u32) -> Result<Self>
     where
         Self: Sized, {
-        self.device
-            .require_extension(ExtensionID::RayTracingPipeline)?;
+        if let Some(device) = self.device() {
+            device.require_extension(ExtensionID::RayTracingPipeline)?;
+        }
         self = self.ensure_descriptor_state()?;
-        let fns = self.device.raytracing_pipeline().unwrap();
-        let Some(regions) = self.current_sbt_regions else { bail!("called trace_rays() without a valid raytracing pipeline build"); };
-        unsafe {
-            fns.cmd_trace_rays(
-                self.handle,
-                regions.get(0).unwrap(),
-                regions.get(1).unwrap(),
-                regions.get(2).unwrap(),
-                regions.get(3).unwrap(),
+        // Command logs do not look up pipelines, so there is no shader binding table.
+        let regions = self.current_sbt_regions;
+        if self.device().is_some() && regions.is_none() {
+            bail!("called trace_rays() without a valid raytracing pipeline build");
+        }
+        self.record_command(
+            || Command::TraceRays {
                 width,
                 height,
                 depth,
-            )
-        };
+            },
+            |device, handle| unsafe {
+                let regions = regions.unwrap();
+                device.raytracing_pipeline().unwrap().cmd_trace_rays(
+                    handle,
+                    regions.get(0).unwrap(),
+                    regions.get(1).unwrap(),
+                    regions.get(2).unwrap(),
+                    regions.get(3).unwrap(),
+                    width,
+                    height,
+                    depth,
+                )
+            },
+        );
         Ok(self)
     }
 
@@ -199,7 +363,10 @@ impl<D: GfxSupport + ExecutionDomain, A: Allocator> GraphicsCmdBuffer
     /// ```
     fn bind_graphics_pipeline(mut self, name: &str) -> Result<Self> {
         let Some(rendering_state) = self.current_rendering_state.clone() else { return Err(Error::NoRenderpass.into()) };
-        let cache = self.pipeline_cache.clone();
+        let Some(cache) = self.pipeline_cache() else {
+            self.log_bind_pipeline(name, vk::PipelineBindPoint::GRAPHICS);
+            return Ok(self);
+        };
         cache.with_pipeline(name, rendering_state, |pipeline| {
             self.bind_pipeline_impl(
                 pipeline.handle,
@@ -218,7 +385,10 @@ impl<D: GfxSupport + ExecutionDomain, A: Allocator> GraphicsCmdBuffer
     fn bind_ray_tracing_pipeline(mut self, name: &str) -> Result<Self>
     where
         Self: Sized, {
-        let cache = self.pipeline_cache.clone();
+        let Some(cache) = self.pipeline_cache() else {
+            self.log_bind_pipeline(name, vk::PipelineBindPoint::RAY_TRACING_KHR);
+            return Ok(self);
+        };
         cache.with_raytracing_pipeline(name, |pipeline| {
             self.current_sbt_regions = Some(pipeline.shader_binding_table.regions);
             self.bind_pipeline_impl(
@@ -244,19 +414,26 @@ impl<D: GfxSupport + ExecutionDomain, A: Allocator> GraphicsCmdBuffer
     ///        .draw(6, 1, 0, 0)
     /// }
     /// ```
-    fn bind_vertex_buffer(self, binding: u32, buffer: &BufferView) -> Self
+    fn bind_vertex_buffer(mut self, binding: u32, buffer: &BufferView) -> Self
     where
         Self: Sized, {
         let handle = unsafe { buffer.handle() };
         let offset = buffer.offset();
-        unsafe {
-            self.device.cmd_bind_vertex_buffers(
-                self.handle,
+        self.record_command(
+            || Command::BindVertexBuffer {
                 binding,
-                std::slice::from_ref(&handle),
-                std::slice::from_ref(&offset),
-            )
-        };
+                buffer: handle,
+                offset,
+            },
+            |device, cmd| unsafe {
+                device.cmd_bind_vertex_buffers(
+                    cmd,
+                    binding,
+                    std::slice::from_ref(&handle),
+                    std::slice::from_ref(&offset),
+                )
+            },
+        );
         self
     }
 
@@ -273,20 +450,25 @@ impl<D: GfxSupport + ExecutionDomain, A: Allocator> GraphicsCmdBuffer
     ///        .draw_indexed(6, 1, 0, 0, 0)
     /// }
     /// ```
-    fn bind_index_buffer(self, buffer: &BufferView, ty: vk::IndexType) -> Self
+    fn bind_index_buffer(mut self, buffer: &BufferView, ty: vk::IndexType) -> Self
     where
         Self: Sized, {
-        unsafe {
-            self.device
-                .cmd_bind_index_buffer(self.handle, buffer.handle(), buffer.offset(), ty);
-        }
+        let (handle, offset) = (unsafe { buffer.handle() }, buffer.offset());
+        self.record_command(
+            || Command::BindIndexBuffer {
+                buffer: handle,
+                offset,
+                index_type: ty,
+            },
+            |device, cmd| unsafe { device.cmd_bind_index_buffer(cmd, handle, offset, ty) },
+        );
         self
     }
 
     /// Blit a source image to a destination image, using the specified offsets into the images and a filter. Direct and thin wrapper around
     /// [`vkCmdBlitImage`](https://registry.khronos.org/vulkan/specs/1.3-extensions/man/html/vkCmdBlitImage.html)
     fn blit_image(
-        self,
+        mut self,
         src: &ImageView,
         dst: &ImageView,
         src_offsets: &[vk::Offset3D; 2],
@@ -312,17 +494,53 @@ impl<D: GfxSupport + ExecutionDomain, A: Allocator> GraphicsCmdBuffer
             dst_offsets: *dst_offsets,
         };
 
-        unsafe {
-            self.device.cmd_blit_image(
-                self.handle,
-                src.image(),
-                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
-                dst.image(),
-                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                std::slice::from_ref(&blit),
+        let (src, dst) = (unsafe { src.image() }, unsafe { dst.image() });
+        self.record_command(
+            || Command::BlitImage {
+                src,
+                dst,
+                region: blit,
                 filter,
-            );
-        }
+            },
+            |device, handle| unsafe {
+                device.cmd_blit_image(
+                    handle,
+                    src,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    dst,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    std::slice::from_ref(&blit),
+                    filter,
+                );
+            },
+        );
+        self
+    }
+
+    /// Clear all mip levels and layers of the image view to a color, outside of a renderpass. The image must be in
+    /// `VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL`. Direct and thin wrapper around
+    /// [`vkCmdClearColorImage`](https://registry.khronos.org/vulkan/specs/1.3-extensions/man/html/vkCmdClearColorImage.html)
+    fn clear_color_image(mut self, image: &ImageView, color: vk::ClearColorValue) -> Self
+    where
+        Self: Sized, {
+        let range = image.subresource_range();
+        let image = unsafe { image.image() };
+        self.record_command(
+            || Command::ClearColorImage {
+                image,
+                color,
+                range,
+            },
+            |device, handle| unsafe {
+                device.cmd_clear_color_image(
+                    handle,
+                    image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &color,
+                    std::slice::from_ref(&range),
+                );
+            },
+        );
         self
     }
 
@@ -339,17 +557,51 @@ impl<D: GfxSupport + ExecutionDomain, A: Allocator> GraphicsCmdBuffer
     ///     cmd.set_polygon_mode(vk::PolygonMode::LINE)
     /// }
     /// ```
-    fn set_polygon_mode(self, mode: vk::PolygonMode) -> Result<Self> {
-        let funcs = self
-            .device
-            .dynamic_state3()
-            .ok_or_else::<anyhow::Error, _>(|| {
-                Error::ExtensionNotSupported(ExtensionID::ExtendedDynamicState3).into()
-            })?;
-        // SAFETY: Vulkan API call. This function pointer is not null because we just verified its availability.
-        unsafe {
-            funcs.cmd_set_polygon_mode(self.handle, mode);
+    fn set_polygon_mode(mut self, mode: vk::PolygonMode) -> Result<Self> {
+        if let Some(device) = self.device() {
+            device
+                .dynamic_state3()
+                .ok_or_else::<anyhow::Error, _>(|| {
+                    Error::ExtensionNotSupported(ExtensionID::ExtendedDynamicState3).into()
+                })?;
         }
+        self.record_command(
+            || Command::SetPolygonMode(mode),
+            // SAFETY: Vulkan API call. This function pointer is not null because we just verified its availability.
+            |device, handle| unsafe { device.dynamic_state3().unwrap().cmd_set_polygon_mode(handle, mode) },
+        );
         Ok(self)
     }
 }
+
+/// Check the alignment and size of a buffer with indirect draw commands of `command_size` bytes.
+fn validate_indirect_buffer(buffer: &BufferView, draw_count: u32, stride: u32, command_size: u32) -> Result<()> {
+    if buffer.offset() & 3 != 0 {
+        return Err(Error::Uncategorized("Indirect buffer offset must be a multiple of 4").into());
+    }
+    if draw_count > 0
+        && (draw_count as vk::DeviceSize - 1) * stride as vk::DeviceSize + command_size as vk::DeviceSize > buffer.size()
+    {
+        return Err(Error::Uncategorized("Indirect buffer is too small for the number of draws").into());
+    }
+    Ok(())
+}
+
+/// Check the stride between indirect draw commands of `command_size` bytes.
+fn validate_indirect_stride(stride: u32, command_size: u32) -> Result<()> {
+    if stride & 3 != 0 || stride < command_size {
+        return Err(Error::Uncategorized("Indirect draw stride must be a multiple of 4 and at least the size of a draw command").into());
+    }
+    Ok(())
+}
+
+/// Check the alignment and size of a buffer with the number of indirect draws.
+fn validate_indirect_count_buffer(count_buffer: &BufferView) -> Result<()> {
+    if count_buffer.offset() & 3 != 0 {
+        return Err(Error::Uncategorized("Indirect count buffer offset must be a multiple of 4").into());
+    }
+    if count_buffer.size() < std::mem::size_of::<u32>() as vk::DeviceSize {
+        return Err(Error::Uncategorized("Indirect count buffer is too small to hold the number of draws").into());
+    }
+    Ok(())
+}
@@ -5,19 +5,22 @@ use std::collections::HashMap;
 use std::marker::PhantomData;
 use std::sync::{Arc, MutexGuard};
 
-use anyhow::{anyhow, ensure, Result};
+use anyhow::{anyhow, bail, ensure, Result};
 use ash::vk;
 
 use crate::command_buffer::state::{RenderingAttachmentInfo, RenderingInfo};
-use crate::command_buffer::{CommandBuffer, IncompleteCommandBuffer};
+#[cfg(feature = "rayon")]
+use crate::command_buffer::state::SecondaryInheritance;
+use crate::command_buffer::{CommandBackend, CommandBuffer, IncompleteCommandBuffer, VulkanCommandBuffer};
+use crate::command_buffer::command_log::{Command, CommandLog, DescriptorWrite, Dependency, Rendering};
 use crate::core::queue::Queue;
 use crate::descriptor::builder::DescriptorSetBuilder;
-use crate::pipeline::create_info::PipelineRenderingInfo;
 use crate::query_pool::{QueryPool, ScopedQuery, TimestampQuery};
 use crate::raytracing::acceleration_structure::AccelerationStructure;
 use crate::sync::domain::ExecutionDomain;
+use crate::pipeline::create_info::PipelineRenderingInfo;
 use crate::{
-    Allocator, BufferView, DebugMessenger, DescriptorCache, DescriptorSet, Device, ImageView,
+    Allocator, BufferView, DebugMessenger, DescriptorCache, DescriptorSet, Device, Event, ImageView,
     IncompleteCmdBuffer, PhysicalResourceBindings, PipelineCache, PipelineStage, Sampler,
     VirtualResource,
 };
@@ -50,23 +53,18 @@ impl<'q, D: ExecutionDomain, A: Allocator> IncompleteCmdBuffer<'q, A>
             // * The begin_info structure is valid.
             device.begin_command_buffer(handle, &begin_info)?;
         };
-        Ok(IncompleteCommandBuffer {
-            device,
-            handle,
-            timestamp_valid_bits: queue_lock.family_properties().timestamp_valid_bits,
-            queue_lock,
-            current_pipeline_layout: vk::PipelineLayout::null(),
-            current_set_layouts: vec![],
-            current_bindpoint: vk::PipelineBindPoint::default(),
-            current_rendering_state: None,
-            current_render_area: Default::default(),
-            current_descriptor_sets: None,
-            descriptor_state_needs_update: false,
-            current_sbt_regions: None,
-            descriptor_cache: descriptors,
-            pipeline_cache: pipelines,
-            _domain: PhantomData,
-        })
+        Ok(IncompleteCommandBuffer::with_backend(
+            CommandBackend::Vulkan(VulkanCommandBuffer {
+                device,
+                handle,
+                timestamp_valid_bits: queue_lock.family_properties().timestamp_valid_bits,
+                queue_lock,
+                descriptor_cache: descriptors,
+                pipeline_cache: pipelines,
+            }),
+            vk::Rect2D::default(),
+            None,
+        ))
     }
 
     /// Finish recording this command buffer. After calling this, no more commands can be
@@ -84,42 +82,275 @@ impl<'q, D: ExecutionDomain, A: Allocator> IncompleteCmdBuffer<'q, A>
     ///     Ok(cmd)
     /// }
     /// ```
+    /// # Errors
+    /// * Fails if ending the command buffer fails.
+    /// * Fails if this command buffer records to a [`CommandLog`], use [`IncompleteCommandBuffer::into_command_log()`] instead.
     fn finish(self) -> Result<CommandBuffer<D>> {
+        let CommandBackend::Vulkan(vulkan) = &self.backend else {
+            bail!("Command buffers recording to a command log cannot be finished, use IncompleteCommandBuffer::into_command_log() instead.");
+        };
         // SAFETY:
-        // * `self` is valid, so `device` and `self.handle` are valid.
+        // * `self` is valid, so `device` and `handle` are valid.
         // * `self` is valid, so this command buffer is in the recording state (see `new()`).
-        unsafe { self.device.end_command_buffer(self.handle)? }
+        unsafe { vulkan.device.end_command_buffer(vulkan.handle)? }
         Ok(CommandBuffer {
-            handle: self.handle,
+            handle: vulkan.handle,
             _domain: PhantomData,
         })
     }
 }
 
+#[cfg(feature = "rayon")]
+impl<'q, D: ExecutionDomain, A: Allocator> IncompleteCommandBuffer<'q, D, A> {
+    /// Begin recording a secondary command buffer. If the inherited state has rendering info, the command buffer
+    /// continues the dynamic render pass of the primary command buffer it is executed in.
+    pub(crate) fn new_secondary(
+        device: Device,
+        queue_lock: MutexGuard<'q, Queue>,
+        handle: vk::CommandBuffer,
+        inheritance: &SecondaryInheritance,
+        pipelines: PipelineCache<A>,
+        descriptors: DescriptorCache,
+    ) -> Result<Self> {
+        let color_formats = inheritance
+            .rendering
+            .as_ref()
+            .map(|rendering| rendering.color_formats.clone())
+            .unwrap_or_default();
+        let rendering_info = inheritance
+            .rendering
+            .as_ref()
+            .map(|rendering| vk::CommandBufferInheritanceRenderingInfo {
+                s_type: vk::StructureType::COMMAND_BUFFER_INHERITANCE_RENDERING_INFO,
+                p_next: std::ptr::null(),
+                flags: vk::RenderingFlags::empty(),
+                view_mask: rendering.view_mask,
+                color_attachment_count: color_formats.len() as u32,
+                p_color_attachment_formats: color_formats.as_ptr(),
+                depth_attachment_format: rendering.depth_format.unwrap_or(vk::Format::UNDEFINED),
+                stencil_attachment_format: rendering.stencil_format.unwrap_or(vk::Format::UNDEFINED),
+                rasterization_samples: inheritance.samples,
+            });
+        let inheritance_info = vk::CommandBufferInheritanceInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_INHERITANCE_INFO,
+            p_next: match &rendering_info {
+                Some(info) => info as *const _ as *const std::ffi::c_void,
+                None => std::ptr::null(),
+            },
+            render_pass: vk::RenderPass::null(),
+            subpass: 0,
+            framebuffer: vk::Framebuffer::null(),
+            occlusion_query_enable: vk::FALSE,
+            query_flags: vk::QueryControlFlags::empty(),
+            pipeline_statistics: vk::QueryPipelineStatisticFlags::empty(),
+        };
+        let flags = match rendering_info {
+            Some(_) => {
+                vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE
+            }
+            None => vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+        };
+        let begin_info = vk::CommandBufferBeginInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
+            p_next: std::ptr::null(),
+            flags,
+            p_inheritance_info: &inheritance_info,
+        };
+        // SAFETY:
+        // * A valid VkDevice was passed in
+        // * The command buffer passed in is a valid secondary command buffer.
+        // * The begin_info structure and everything it points to is valid.
+        unsafe { device.begin_command_buffer(handle, &begin_info)? };
+        Ok(IncompleteCommandBuffer::with_backend(
+            CommandBackend::Vulkan(VulkanCommandBuffer {
+                device,
+                handle,
+                timestamp_valid_bits: queue_lock.family_properties().timestamp_valid_bits,
+                queue_lock,
+                descriptor_cache: descriptors,
+                pipeline_cache: pipelines,
+            }),
+            inheritance.render_area,
+            inheritance.rendering.clone(),
+        ))
+    }
+
+    /// Get the Vulkan command buffer, secondary command buffers of command logs are not allocated from a queue.
+    fn vulkan(&self) -> Result<&VulkanCommandBuffer<'q, A>> {
+        match &self.backend {
+            CommandBackend::Vulkan(vulkan) => Ok(vulkan),
+            CommandBackend::Log(_) => bail!("Command buffers recording to a command log have no queue or caches"),
+        }
+    }
+
+    /// Create a new logical queue with its own command pool over the queue this command buffer was allocated from.
+    /// See [`Queue::with_new_pool()`].
+    pub(crate) fn new_queue_with_pool(&self) -> Result<Queue> {
+        self.vulkan()?.queue_lock.with_new_pool()
+    }
+
+    /// Get the pipeline and descriptor caches of this command buffer.
+    pub(crate) fn caches(&self) -> Result<(Device, PipelineCache<A>, DescriptorCache)> {
+        let vulkan = self.vulkan()?;
+        Ok((
+            vulkan.device.clone(),
+            vulkan.pipeline_cache.clone(),
+            vulkan.descriptor_cache.clone(),
+        ))
+    }
+
+    /// Execute secondary command buffers. Direct translation of
+    /// [`vkCmdExecuteCommands`](https://registry.khronos.org/vulkan/specs/1.3-extensions/man/html/vkCmdExecuteCommands.html).
+    /// `logs` holds the commands of secondary command buffers that were recorded to a [`CommandLog`].
+    pub(crate) fn execute_commands(mut self, buffers: &[vk::CommandBuffer], logs: Vec<CommandLog>) -> Self {
+        if !buffers.is_empty() {
+            self.record_command(
+                || Command::ExecuteCommands {
+                    buffers: buffers.to_vec(),
+                    logs,
+                },
+                // SAFETY: self is valid, the caller passes valid secondary command buffers in the executable state.
+                |device, handle| unsafe { device.cmd_execute_commands(handle, buffers) },
+            );
+        }
+        self
+    }
+}
+
+impl<D: ExecutionDomain, A: Allocator> IncompleteCommandBuffer<'static, D, A> {
+    /// Create a command buffer that records a [`CommandLog`] instead of Vulkan commands, for testing recording code without a GPU.
+    /// See the [`command_log`](crate::command_buffer::command_log) module for what is supported.
+    /// # Example
+    /// ```
+    /// # use phobos::*;
+    /// # use anyhow::Result;
+    /// fn draw_log() -> Result<()> {
+    ///     let cmd = IncompleteCommandBuffer::<domain::All>::new_logged().draw(3, 1, 0, 0)?;
+    ///     assert_eq!(cmd.into_command_log()?.names(), ["vkCmdDraw"]);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn new_logged() -> Self {
+        Self::with_backend(CommandBackend::Log(CommandLog::new()), vk::Rect2D::default(), None)
+    }
+
+    /// Create a secondary command buffer that records a [`CommandLog`], with the state inherited from the primary command buffer.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn new_logged_secondary(inheritance: &SecondaryInheritance) -> Self {
+        Self::with_backend(
+            CommandBackend::Log(CommandLog::new()),
+            inheritance.render_area,
+            inheritance.rendering.clone(),
+        )
+    }
+}
+
+impl<'q, D: ExecutionDomain, A: Allocator> IncompleteCommandBuffer<'q, D, A> {
+    fn with_backend(
+        backend: CommandBackend<'q, A>,
+        render_area: vk::Rect2D,
+        rendering_state: Option<PipelineRenderingInfo>,
+    ) -> Self {
+        IncompleteCommandBuffer {
+            backend,
+            current_pipeline_layout: vk::PipelineLayout::null(),
+            current_set_layouts: vec![],
+            current_bindpoint: vk::PipelineBindPoint::default(),
+            current_rendering_state: rendering_state,
+            current_render_area: render_area,
+            current_descriptor_sets: None,
+            descriptor_state_needs_update: false,
+            current_sbt_regions: None,
+            _domain: PhantomData,
+        }
+    }
+
+    /// Record a command. `vulkan` records it to the Vulkan command buffer, `command` creates the entry for the command log.
+    pub(super) fn record_command(
+        &mut self,
+        command: impl FnOnce() -> Command,
+        vulkan: impl FnOnce(&Device, vk::CommandBuffer),
+    ) {
+        match &mut self.backend {
+            CommandBackend::Vulkan(backend) => vulkan(&backend.device, backend.handle),
+            CommandBackend::Log(log) => log.push(command()),
+        }
+    }
+
+    /// Get the device this command buffer records commands for, or `None` if it records to a [`CommandLog`].
+    pub(super) fn device(&self) -> Option<&Device> {
+        match &self.backend {
+            CommandBackend::Vulkan(vulkan) => Some(&vulkan.device),
+            CommandBackend::Log(_) => None,
+        }
+    }
+
+    /// Get the device for commands that cannot be recorded to a [`CommandLog`].
+    /// # Errors
+    /// * Fails if this command buffer records to a [`CommandLog`].
+    pub(super) fn vulkan_device(&self) -> Result<&Device> {
+        match self.device() {
+            Some(device) => Ok(device),
+            None => bail!("This command cannot be recorded to a command log"),
+        }
+    }
+
+    /// Get the pipeline cache of this command buffer, or `None` if it records to a [`CommandLog`].
+    pub(super) fn pipeline_cache(&self) -> Option<PipelineCache<A>> {
+        match &self.backend {
+            CommandBackend::Vulkan(vulkan) => Some(vulkan.pipeline_cache.clone()),
+            CommandBackend::Log(_) => None,
+        }
+    }
+
+    /// Get the commands recorded so far, or `None` if this command buffer records Vulkan commands.
+    /// See [`IncompleteCommandBuffer::new_logged()`].
+    pub fn command_log(&self) -> Option<&CommandLog> {
+        match &self.backend {
+            CommandBackend::Vulkan(_) => None,
+            CommandBackend::Log(log) => Some(log),
+        }
+    }
+
+    /// Finish recording to a [`CommandLog`] and get all recorded commands. See [`IncompleteCommandBuffer::new_logged()`].
+    /// # Errors
+    /// * Fails if this command buffer records Vulkan commands.
+    pub fn into_command_log(self) -> Result<CommandLog> {
+        match self.backend {
+            CommandBackend::Vulkan(_) => bail!("Command buffer does not record to a command log"),
+            CommandBackend::Log(log) => Ok(log),
+        }
+    }
+}
+
 impl<D: ExecutionDomain, A: Allocator> IncompleteCommandBuffer<'_, D, A> {
     /// Bind a descriptor set to the command buffer.
     /// # Errors
     /// - Fails if no pipeline was bound.
-    pub(super) fn bind_descriptor_set(&self, index: u32, set: &DescriptorSet) -> Result<()> {
+    pub(super) fn bind_descriptor_set(&mut self, index: u32, set: &DescriptorSet) -> Result<()> {
         ensure!(
             self.current_pipeline_layout != vk::PipelineLayout::null(),
             "cannot bind descriptor set at index {index} without binding a pipeline first."
         );
-        unsafe {
-            // SAFETY:
-            // * self is valid, so self.handle is valid.
-            // * We just verified using the ensure statement above that a pipeline is bound.
-            // * We assume index is a valid descriptor set index, otherwise we get a validation layer error
-            // * Caller passed in a valid descriptor set object.
-            self.device.cmd_bind_descriptor_sets(
-                self.handle,
-                self.current_bindpoint,
-                self.current_pipeline_layout,
-                index,
-                std::slice::from_ref(&set.handle),
-                &[],
-            );
-        }
+        let (bind_point, layout) = (self.current_bindpoint, self.current_pipeline_layout);
+        self.record_command(
+            || unreachable!("descriptor sets are not allocated for command logs"),
+            |device, handle| unsafe {
+                // SAFETY:
+                // * self is valid, so handle is valid.
+                // * We just verified using the ensure statement above that a pipeline is bound.
+                // * We assume index is a valid descriptor set index, otherwise we get a validation layer error
+                // * Caller passed in a valid descriptor set object.
+                device.cmd_bind_descriptor_sets(
+                    handle,
+                    bind_point,
+                    layout,
+                    index,
+                    std::slice::from_ref(&set.handle),
+                    &[],
+                );
+            },
+        );
         Ok(())
     }
 
@@ -159,7 +390,10 @@ impl<D: ExecutionDomain, A: Allocator> IncompleteCommandBuffer<'_, D, A> {
             return Ok(self);
         }
 
-        let cache = self.descriptor_cache.clone();
+        let cache = match &self.backend {
+            CommandBackend::Vulkan(vulkan) => vulkan.descriptor_cache.clone(),
+            CommandBackend::Log(_) => return self.log_descriptor_state(),
+        };
         for (index, builder) in self.current_descriptor_sets.take().unwrap() {
             let mut info = builder.build();
             info.layout = *self.current_set_layouts.get(index as usize).unwrap();
@@ -174,6 +408,58 @@ impl<D: ExecutionDomain, A: Allocator> IncompleteCommandBuffer<'_, D, A> {
         Ok(self)
     }
 
+    /// Log a descriptor set bind for every set in the descriptor state, instead of allocating descriptor sets.
+    fn log_descriptor_state(mut self) -> Result<Self> {
+        let CommandBackend::Log(log) = &mut self.backend else {
+            unreachable!("only called for command logs");
+        };
+        ensure!(
+            log.commands()
+                .iter()
+                .any(|command| matches!(command, Command::BindPipeline { .. })),
+            "cannot bind descriptor sets without binding a pipeline first."
+        );
+        let mut sets = self
+            .current_descriptor_sets
+            .take()
+            .unwrap()
+            .into_iter()
+            .collect::<Vec<_>>();
+        sets.sort_by_key(|(index, _)| *index);
+        for (set, builder) in sets {
+            let mut descriptors = builder
+                .build()
+                .bindings
+                .iter()
+                .map(|binding| DescriptorWrite {
+                    binding: binding.binding,
+                    ty: binding.ty,
+                    count: binding.descriptors.len(),
+                })
+                .collect::<Vec<_>>();
+            descriptors.sort_by_key(|write| write.binding);
+            log.push(Command::BindDescriptorSet {
+                bind_point: self.current_bindpoint,
+                set,
+                descriptors,
+            });
+        }
+        self.descriptor_state_needs_update = false;
+        Ok(self)
+    }
+
+    /// Log binding a pipeline by name. Command logs have no pipeline cache, so the pipeline layout is unknown.
+    pub(super) fn log_bind_pipeline(&mut self, name: &str, bind_point: vk::PipelineBindPoint) {
+        self.record_command(
+            || Command::BindPipeline {
+                name: name.to_owned(),
+                bind_point,
+            },
+            |_, _| unreachable!("only called for command logs"),
+        );
+        self.current_bindpoint = bind_point;
+    }
+
     /// Binds the given pipeline to the given bindpoint.
     /// # Errors
     /// None
@@ -184,13 +470,15 @@ impl<D: ExecutionDomain, A: Allocator> IncompleteCommandBuffer<'_, D, A> {
         set_layouts: Vec<vk::DescriptorSetLayout>,
         bind_point: vk::PipelineBindPoint,
     ) -> Result<()> {
-        unsafe {
-            // SAFETY:
-            // * `self` is valid, so `self.device` and `self.handle` are valid vulkan objects.
-            // * `pipeline.handle` is a valid entry from the pipeline cache, so it is a valid compute pipeline.
-            self.device
-                .cmd_bind_pipeline(self.handle, bind_point, handle);
-        }
+        self.record_command(
+            || unreachable!("pipelines are not looked up for command logs"),
+            |device, cmd| unsafe {
+                // SAFETY:
+                // * `self` is valid, so `device` and `cmd` are valid vulkan objects.
+                // * `pipeline.handle` is a valid entry from the pipeline cache, so it is a valid compute pipeline.
+                device.cmd_bind_pipeline(cmd, bind_point, handle);
+            },
+        );
         self.current_bindpoint = bind_point;
         self.current_pipeline_layout = layout;
         self.current_set_layouts = set_layouts.clone();
@@ -284,6 +572,26 @@ impl<D: ExecutionDomain, A: Allocator> IncompleteCommandBuffer<'_, D, A> {
         Ok(self)
     }
 
+    /// Binds a new descriptor with type [`vk::DescriptorType::COMBINED_IMAGE_SAMPLER`], for an image that is sampled in `layout`.
+    /// Use this to sample a read-only depth attachment in [`vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL`] in the same pass it is
+    /// used for depth testing. This binding is not actually flushed to the command buffer until the next draw or dispatch call.
+    /// # Errors
+    /// None
+    pub fn bind_sampled_image_in_layout(
+        mut self,
+        set: u32,
+        binding: u32,
+        image: &ImageView,
+        sampler: &Sampler,
+        layout: vk::ImageLayout,
+    ) -> Result<Self> {
+        self.modify_descriptor_set(set, |builder| {
+            builder.bind_sampled_image_in_layout(binding, image, sampler, layout);
+            Ok(())
+        })?;
+        Ok(self)
+    }
+
     /// Bind an entire array of sampled images using the same sampler.
     pub fn bind_sampled_image_array(
         mut self,
@@ -517,10 +825,75 @@ impl<D: ExecutionDomain, A: Allocator> IncompleteCommandBuffer<'_, D, A> {
     /// The direct equivalent of a raw [`vkCmdPipelineBarrier2`](https://registry.khronos.org/vulkan/specs/1.3-extensions/man/html/vkCmdPipelineBarrier2KHR.html) call.
     /// Before calling this, make sure there is not an automatic way to insert this barrier, for example
     /// using the pass graph or using [`IncompleteCommandBuffer::transition_image()`].
-    pub fn pipeline_barrier(self, dependency: &vk::DependencyInfo) -> Self {
-        unsafe {
-            self.device.cmd_pipeline_barrier2(self.handle, dependency);
+    pub fn pipeline_barrier(mut self, dependency: &vk::DependencyInfo) -> Self {
+        self.record_command(
+            // SAFETY: the caller passes a valid dependency info.
+            || Command::PipelineBarrier(unsafe { Dependency::from_vk(dependency) }),
+            |device, handle| unsafe { device.cmd_pipeline_barrier2(handle, dependency) },
+        );
+        self
+    }
+
+    /// Signal an event once all commands before it have completed the first synchronization scope of `dependency`.
+    /// This is the first half of a split barrier, the second half is a call to [`IncompleteCommandBuffer::wait_events()`]
+    /// with the same dependency info. Direct translation of [`vkCmdSetEvent2`](https://registry.khronos.org/vulkan/specs/1.3-extensions/man/html/vkCmdSetEvent2.html).
+    pub fn set_event(mut self, event: &Event, dependency: &vk::DependencyInfo) -> Self {
+        // SAFETY: the handle is only passed to the command buffer.
+        let event = unsafe { event.handle() };
+        self.record_command(
+            || Command::SetEvent {
+                event,
+                // SAFETY: the caller passes a valid dependency info.
+                dependency: unsafe { Dependency::from_vk(dependency) },
+            },
+            |device, handle| unsafe { device.cmd_set_event2(handle, event, dependency) },
+        );
+        self
+    }
+
+    /// Wait for events signaled with [`IncompleteCommandBuffer::set_event()`]. The dependency info of each event must be
+    /// identical to the one it was signaled with.
+    /// Direct translation of [`vkCmdWaitEvents2`](https://registry.khronos.org/vulkan/specs/1.3-extensions/man/html/vkCmdWaitEvents2.html).
+    /// # Errors
+    /// * Fails if `events` and `dependencies` have a different length.
+    pub fn wait_events(mut self, events: &[Event], dependencies: &[vk::DependencyInfo]) -> Result<Self> {
+        ensure!(
+            events.len() == dependencies.len(),
+            "Every event waited on needs exactly one dependency info"
+        );
+        if events.is_empty() {
+            return Ok(self);
         }
+        let handles = events
+            .iter()
+            .map(|event| unsafe { event.handle() })
+            .collect::<Vec<_>>();
+        self.record_command(
+            || Command::WaitEvents {
+                events: handles.clone(),
+                dependencies: dependencies
+                    .iter()
+                    // SAFETY: the caller passes valid dependency infos.
+                    .map(|dependency| unsafe { Dependency::from_vk(dependency) })
+                    .collect(),
+            },
+            |device, handle| unsafe { device.cmd_wait_events2(handle, &handles, dependencies) },
+        );
+        Ok(self)
+    }
+
+    /// Reset an event to the unsignaled state once all commands before it have completed `stage`.
+    /// Direct translation of [`vkCmdResetEvent2`](https://registry.khronos.org/vulkan/specs/1.3-extensions/man/html/vkCmdResetEvent2.html).
+    pub fn reset_event(mut self, event: &Event, stage: PipelineStage) -> Self {
+        // SAFETY: the handle is only passed to the command buffer.
+        let event = unsafe { event.handle() };
+        self.record_command(
+            || Command::ResetEvent {
+                event,
+                stage,
+            },
+            |device, handle| unsafe { device.cmd_reset_event2(handle, event, stage) },
+        );
         self
     }
 
@@ -557,47 +930,53 @@ impl<D: ExecutionDomain, A: Allocator> IncompleteCommandBuffer<'_, D, A> {
     /// }
     /// ```
     pub fn push_constants<T: Copy + Sized>(
-        self,
+        mut self,
         stage: vk::ShaderStageFlags,
         offset: u32,
         data: &[T],
     ) -> Self {
         // TODO: Validate push constant ranges with current pipeline layout to prevent crashes.
-        unsafe {
-            // SAFETY: every data structure can be aligned to a byte slice.
-            let (_, data, _) = data.align_to::<u8>();
-            // SAFETY: self is valid, everything else is up to validation layers.
-            self.device.cmd_push_constants(
-                self.handle,
-                self.current_pipeline_layout,
+        // SAFETY: every data structure can be aligned to a byte slice.
+        let (_, data, _) = unsafe { data.align_to::<u8>() };
+        let layout = self.current_pipeline_layout;
+        self.record_command(
+            || Command::PushConstants {
                 stage,
                 offset,
-                data,
-            );
-        }
+                data: data.to_vec(),
+            },
+            // SAFETY: self is valid, everything else is up to validation layers.
+            |device, handle| unsafe { device.cmd_push_constants(handle, layout, stage, offset, data) },
+        );
         self
     }
 
     /// Begin a scoped query. Not all query types are scoped, so the query type must implement
     /// [`ScopedQuery`].
-    pub fn begin_query<Q: ScopedQuery>(self, query_pool: &QueryPool<Q>, index: u32) -> Self {
-        unsafe {
-            self.device.cmd_begin_query(
-                self.handle,
-                query_pool.handle(),
+    pub fn begin_query<Q: ScopedQuery>(mut self, query_pool: &QueryPool<Q>, index: u32) -> Self {
+        let query_pool = unsafe { query_pool.handle() };
+        self.record_command(
+            || Command::BeginQuery {
+                query_pool,
                 index,
-                vk::QueryControlFlags::default(),
-            );
-        }
+            },
+            |device, handle| unsafe {
+                device.cmd_begin_query(handle, query_pool, index, vk::QueryControlFlags::default());
+            },
+        );
         self
     }
 
     /// End a scoped query. This query must be started with [`Self::begin_query()`] first.
-    pub fn end_query<Q: ScopedQuery>(self, query_pool: &QueryPool<Q>, index: u32) -> Self {
-        unsafe {
-            self.device
-                .cmd_end_query(self.handle, query_pool.handle(), index);
-        }
+    pub fn end_query<Q: ScopedQuery>(mut self, query_pool: &QueryPool<Q>, index: u32) -> Self {
+        let query_pool = unsafe { query_pool.handle() };
+        self.record_command(
+            || Command::EndQuery {
+                query_pool,
+                index,
+            },
+            |device, handle| unsafe { device.cmd_end_query(handle, query_pool, index) },
+        );
         self
     }
 
@@ -605,14 +984,23 @@ impl<D: ExecutionDomain, A: Allocator> IncompleteCommandBuffer<'_, D, A> {
     /// # Errors
     /// * Fails if the query pool is out of entries.
     pub fn write_timestamp(
-        self,
+        mut self,
         query_pool: &mut QueryPool<TimestampQuery>,
         stage: PipelineStage,
     ) -> Result<Self> {
         let index = query_pool
             .next()
             .ok_or_else(|| anyhow!("Query pool capacity exceeded"))?;
-        query_pool.write_timestamp(self.timestamp_valid_bits, self.handle, stage, index);
+        match &mut self.backend {
+            CommandBackend::Vulkan(vulkan) => {
+                query_pool.write_timestamp(vulkan.timestamp_valid_bits, vulkan.handle, stage, index)
+            }
+            CommandBackend::Log(log) => log.push(Command::WriteTimestamp {
+                query_pool: unsafe { query_pool.handle() },
+                stage,
+                index,
+            }),
+        }
         Ok(self)
     }
 
@@ -666,27 +1054,13 @@ impl<D: ExecutionDomain, A: Allocator> IncompleteCommandBuffer<'_, D, A> {
             },
         };
 
-        unsafe {
+        self.record_command(
+            || Command::BeginRendering(Rendering::from(info)),
             // SAFETY: self is valid, vk_info is valid.
-            self.device.cmd_begin_rendering(self.handle, &vk_info);
-        }
+            |device, handle| unsafe { device.cmd_begin_rendering(handle, &vk_info) },
+        );
 
-        self.current_rendering_state = Some(PipelineRenderingInfo {
-            view_mask: info.view_mask,
-            color_formats: info
-                .color_attachments
-                .iter()
-                .map(|attachment| attachment.image_view.format())
-                .collect(),
-            depth_format: info
-                .depth_attachment
-                .as_ref()
-                .map(|attachment| attachment.image_view.format()),
-            stencil_format: info
-                .stencil_attachment
-                .as_ref()
-                .map(|attachment| attachment.image_view.format()),
-        });
+        self.current_rendering_state = Some(info.pipeline_rendering_info());
         self.current_render_area = info.render_area;
 
         self
@@ -694,10 +1068,11 @@ impl<D: ExecutionDomain, A: Allocator> IncompleteCommandBuffer<'_, D, A> {
 
     /// Ends a dynamic renderpass.
     pub(crate) fn end_rendering(mut self) -> Self {
-        unsafe {
+        self.record_command(
+            || Command::EndRendering,
             // Safety: self is valid, the caller must ensure begin_rendering() was called first.
-            self.device.cmd_end_rendering(self.handle);
-        }
+            |device, handle| unsafe { device.cmd_end_rendering(handle) },
+        );
         self.current_rendering_state = None;
         self.current_render_area = vk::Rect2D::default();
 
@@ -707,29 +1082,36 @@ impl<D: ExecutionDomain, A: Allocator> IncompleteCommandBuffer<'_, D, A> {
     /// Start a label region.
     #[cfg(feature = "debug-markers")]
     pub(crate) fn begin_label(
-        self,
+        mut self,
         label: vk::DebugUtilsLabelEXT,
         debug: &Arc<DebugMessenger>,
     ) -> Self {
-        unsafe {
-            debug.cmd_begin_debug_utils_label(self.handle, &label);
-        }
+        self.record_command(
+            // SAFETY: the caller passes a valid label.
+            || unsafe { Command::label(&label) },
+            |_, handle| unsafe { debug.cmd_begin_debug_utils_label(handle, &label) },
+        );
         self
     }
 
     /// End a label region.
     #[cfg(feature = "debug-markers")]
-    pub(crate) fn end_label(self, debug: &Arc<DebugMessenger>) -> Self {
-        unsafe {
-            debug.cmd_end_debug_utils_label(self.handle);
-        }
+    pub(crate) fn end_label(mut self, debug: &Arc<DebugMessenger>) -> Self {
+        self.record_command(
+            || Command::EndLabel,
+            |_, handle| unsafe { debug.cmd_end_debug_utils_label(handle) },
+        );
         self
     }
 
-    /// Get unsafe access to the underlying `VkCommandBuffer` handle.
+    /// Get unsafe access to the underlying `VkCommandBuffer` handle. This is a null handle for command buffers
+    /// recording to a [`CommandLog`].
     /// # Safety
     /// Any vulkan calls that mutate the command buffer's state may put the system in an undefined state.
     pub unsafe fn handle(&self) -> vk::CommandBuffer {
-        self.handle
+        match &self.backend {
+            CommandBackend::Vulkan(vulkan) => vulkan.handle,
+            CommandBackend::Log(_) => vk::CommandBuffer::null(),
+        }
     }
 }
@@ -23,11 +23,13 @@ use crate::{
     Allocator, CmdBuffer, DefaultAllocator, DescriptorCache, Device, Error,
     ExecutionManager, PipelineCache,
 };
+use crate::command_buffer::command_log::CommandLog;
 use crate::core::queue::Queue;
 use crate::descriptor::builder::DescriptorSetBuilder;
 use crate::pipeline::create_info::PipelineRenderingInfo;
 use crate::sync::domain::ExecutionDomain;
 
+pub mod command_log;
 pub mod compute;
 pub mod graphics;
 pub mod incomplete;
@@ -85,11 +87,7 @@ pub struct CommandBuffer<D: ExecutionDomain> {
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct IncompleteCommandBuffer<'q, D: ExecutionDomain, A: Allocator = DefaultAllocator> {
-    #[derivative(Debug = "ignore")]
-    device: Device,
-    handle: vk::CommandBuffer,
-    queue_lock: MutexGuard<'q, Queue>,
-    timestamp_valid_bits: u32,
+    backend: CommandBackend<'q, A>,
     current_pipeline_layout: vk::PipelineLayout,
     current_set_layouts: Vec<vk::DescriptorSetLayout>,
     // TODO: Note: technically not correct
@@ -99,10 +97,30 @@ pub struct IncompleteCommandBuffer<'q, D: ExecutionDomain, A: Allocator = Defaul
     current_descriptor_sets: Option<HashMap<u32, DescriptorSetBuilder<'static>>>,
     descriptor_state_needs_update: bool,
     current_sbt_regions: Option<[vk::StridedDeviceAddressRegionKHR; 4]>,
+    _domain: PhantomData<D>,
+}
+
+/// Where the commands of an [`IncompleteCommandBuffer`] are recorded.
+#[derive(Derivative)]
+#[derivative(Debug)]
+enum CommandBackend<'q, A: Allocator> {
+    /// A Vulkan command buffer allocated from a queue.
+    Vulkan(VulkanCommandBuffer<'q, A>),
+    /// A [`CommandLog`], see [`IncompleteCommandBuffer::new_logged()`].
+    Log(CommandLog),
+}
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+struct VulkanCommandBuffer<'q, A: Allocator> {
+    #[derivative(Debug = "ignore")]
+    device: Device,
+    handle: vk::CommandBuffer,
+    queue_lock: MutexGuard<'q, Queue>,
+    timestamp_valid_bits: u32,
     // TODO: Only update disturbed descriptor sets
     descriptor_cache: DescriptorCache,
     pipeline_cache: PipelineCache<A>,
-    _domain: PhantomData<D>,
 }
 
 impl<D: ExecutionDomain, A: Allocator> CmdBuffer<A> for CommandBuffer<D> {
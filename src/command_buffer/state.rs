@@ -3,6 +3,7 @@
 use ash::vk;
 
 use crate::ImageView;
+use crate::pipeline::create_info::PipelineRenderingInfo;
 
 pub(crate) struct RenderingAttachmentInfo {
     pub image_view: ImageView,
@@ -24,3 +25,35 @@ pub(crate) struct RenderingInfo {
     pub depth_attachment: Option<RenderingAttachmentInfo>,
     pub stencil_attachment: Option<RenderingAttachmentInfo>,
 }
+
+impl RenderingInfo {
+    /// Get the attachment formats used for creating pipelines compatible with this render pass.
+    pub fn pipeline_rendering_info(&self) -> PipelineRenderingInfo {
+        PipelineRenderingInfo {
+            view_mask: self.view_mask,
+            color_formats: self
+                .color_attachments
+                .iter()
+                .map(|attachment| attachment.image_view.format())
+                .collect(),
+            depth_format: self
+                .depth_attachment
+                .as_ref()
+                .map(|attachment| attachment.image_view.format()),
+            stencil_format: self
+                .stencil_attachment
+                .as_ref()
+                .map(|attachment| attachment.image_view.format()),
+        }
+    }
+}
+
+/// State inherited by a secondary command buffer from the primary command buffer executing it.
+#[cfg(feature = "rayon")]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SecondaryInheritance {
+    /// Attachment formats of the dynamic render pass the secondary command buffer executes in, if any.
+    pub rendering: Option<PipelineRenderingInfo>,
+    pub render_area: vk::Rect2D,
+    pub samples: vk::SampleCountFlags,
+}
@@ -26,6 +26,18 @@ pub trait TransferCmdBuffer {
     fn copy_buffer_to_image(self, src: &BufferView, dst: &ImageView) -> Result<Self>
     where
         Self: Sized;
+    /// Copy an image to another image. Both views must have the same size.
+    fn copy_image(self, src: &ImageView, dst: &ImageView) -> Result<Self>
+    where
+        Self: Sized;
+    /// Copy an image to a buffer.
+    fn copy_image_to_buffer(self, src: &ImageView, dst: &BufferView) -> Result<Self>
+    where
+        Self: Sized;
+    /// Fill a buffer view with a repeated 32-bit value. Equivalent of `vkCmdFillBuffer`.
+    fn fill_buffer(self, dst: &BufferView, value: u32) -> Result<Self>
+    where
+        Self: Sized;
 }
 
 /// Trait representing a command buffer that supports graphics commands.
@@ -55,6 +67,37 @@ pub trait GraphicsCmdBuffer: TransferCmdBuffer {
         vertex_offset: i32,
         first_instance: u32,
     ) -> Result<Self>
+    where
+        Self: Sized;
+    /// Record `draw_count` drawcalls with parameters read from a buffer of [`vk::DrawIndirectCommand`]s. Equivalent of `vkCmdDrawIndirect`
+    fn draw_indirect(self, buffer: &BufferView, draw_count: u32, stride: u32) -> Result<Self>
+    where
+        Self: Sized;
+    /// Record `draw_count` indexed drawcalls with parameters read from a buffer of [`vk::DrawIndexedIndirectCommand`]s.
+    /// Equivalent of `vkCmdDrawIndexedIndirect`
+    fn draw_indexed_indirect(self, buffer: &BufferView, draw_count: u32, stride: u32) -> Result<Self>
+    where
+        Self: Sized;
+    /// Record drawcalls with parameters read from a buffer of [`vk::DrawIndirectCommand`]s, and the number of draws read from
+    /// `count_buffer`. Equivalent of `vkCmdDrawIndirectCount`
+    fn draw_indirect_count(
+        self,
+        buffer: &BufferView,
+        count_buffer: &BufferView,
+        max_draw_count: u32,
+        stride: u32,
+    ) -> Result<Self>
+    where
+        Self: Sized;
+    /// Record indexed drawcalls with parameters read from a buffer of [`vk::DrawIndexedIndirectCommand`]s, and the number of
+    /// draws read from `count_buffer`. Equivalent of `vkCmdDrawIndexedIndirectCount`
+    fn draw_indexed_indirect_count(
+        self,
+        buffer: &BufferView,
+        count_buffer: &BufferView,
+        max_draw_count: u32,
+        stride: u32,
+    ) -> Result<Self>
     where
         Self: Sized;
     /// Start raytracing. Equivalent of `vkCmdTraceRays`.
@@ -90,6 +133,10 @@ pub trait GraphicsCmdBuffer: TransferCmdBuffer {
         dst_offsets: &[vk::Offset3D; 2],
         filter: vk::Filter,
     ) -> Self
+    where
+        Self: Sized;
+    /// Clear a color image outside of a renderpass. Equivalent to `vkCmdClearColorImage`
+    fn clear_color_image(self, image: &ImageView, color: vk::ClearColorValue) -> Self
     where
         Self: Sized;
 
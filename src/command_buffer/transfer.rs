@@ -3,6 +3,7 @@
 use anyhow::Result;
 use ash::vk;
 
+use crate::command_buffer::command_log::Command;
 use crate::command_buffer::IncompleteCommandBuffer;
 use crate::sync::domain::ExecutionDomain;
 use crate::{Allocator, BufferView, Error, ImageView, TransferCmdBuffer, TransferSupport};
@@ -22,7 +23,7 @@ impl<D: TransferSupport + ExecutionDomain, A: Allocator> TransferCmdBuffer
     ///     cmd.copy_buffer(src, dst)
     /// }
     /// ```
-    fn copy_buffer(self, src: &BufferView, dst: &BufferView) -> Result<Self> {
+    fn copy_buffer(mut self, src: &BufferView, dst: &BufferView) -> Result<Self> {
         if src.size() != dst.size() {
             return Err(Error::InvalidBufferCopy.into());
         }
@@ -33,14 +34,15 @@ impl<D: TransferSupport + ExecutionDomain, A: Allocator> TransferCmdBuffer
             size: src.size(),
         };
 
-        unsafe {
-            self.device.cmd_copy_buffer(
-                self.handle,
-                src.handle(),
-                dst.handle(),
-                std::slice::from_ref(&copy),
-            );
-        }
+        let (src, dst) = unsafe { (src.handle(), dst.handle()) };
+        self.record_command(
+            || Command::CopyBuffer {
+                src,
+                dst,
+                region: copy,
+            },
+            |device, handle| unsafe { device.cmd_copy_buffer(handle, src, dst, std::slice::from_ref(&copy)) },
+        );
 
         Ok(self)
     }
@@ -55,7 +57,7 @@ impl<D: TransferSupport + ExecutionDomain, A: Allocator> TransferCmdBuffer
     ///     cmd.copy_buffer_to_image(src, dst)
     /// }
     /// ```
-    fn copy_buffer_to_image(self, src: &BufferView, dst: &ImageView) -> Result<Self>
+    fn copy_buffer_to_image(mut self, src: &BufferView, dst: &ImageView) -> Result<Self>
     where
         Self: Sized, {
         let copy = vk::BufferImageCopy {
@@ -72,16 +74,165 @@ impl<D: TransferSupport + ExecutionDomain, A: Allocator> TransferCmdBuffer
             image_extent: dst.size(),
         };
 
-        unsafe {
-            self.device.cmd_copy_buffer_to_image(
-                self.handle,
-                src.handle(),
-                dst.image(),
-                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                std::slice::from_ref(&copy),
-            );
+        let (src, dst) = unsafe { (src.handle(), dst.image()) };
+        self.record_command(
+            || Command::CopyBufferToImage {
+                src,
+                dst,
+                region: copy,
+            },
+            |device, handle| unsafe {
+                device.cmd_copy_buffer_to_image(
+                    handle,
+                    src,
+                    dst,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    std::slice::from_ref(&copy),
+                );
+            },
+        );
+
+        Ok(self)
+    }
+
+    /// Copy the subresource range of one image view to another image view, starting at the base mip level of both views.
+    /// `src` must be in `VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL`, and `dst` in `VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL`.
+    /// # Errors
+    /// * Fails if the image views do not have the same size.
+    /// # Example
+    /// ```
+    /// # use anyhow::Result;
+    /// # use phobos::*;
+    /// # use phobos::sync::domain::*;
+    /// fn copy_image<C: TransferCmdBuffer>(cmd: C, src: &ImageView, dst: &ImageView) -> Result<C> {
+    ///     cmd.copy_image(src, dst)
+    /// }
+    /// ```
+    fn copy_image(mut self, src: &ImageView, dst: &ImageView) -> Result<Self>
+    where
+        Self: Sized, {
+        if src.size() != dst.size() {
+            return Err(Error::InvalidImageCopy.into());
+        }
+
+        let copy = vk::ImageCopy {
+            src_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: src.aspect(),
+                mip_level: src.base_level(),
+                base_array_layer: src.base_layer(),
+                layer_count: src.layer_count(),
+            },
+            src_offset: Default::default(),
+            dst_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: dst.aspect(),
+                mip_level: dst.base_level(),
+                base_array_layer: dst.base_layer(),
+                layer_count: dst.layer_count(),
+            },
+            dst_offset: Default::default(),
+            extent: src.size(),
+        };
+
+        let (src, dst) = unsafe { (src.image(), dst.image()) };
+        self.record_command(
+            || Command::CopyImage {
+                src,
+                dst,
+                region: copy,
+            },
+            |device, handle| unsafe {
+                device.cmd_copy_image(
+                    handle,
+                    src,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    dst,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    std::slice::from_ref(&copy),
+                );
+            },
+        );
+
+        Ok(self)
+    }
+
+    /// Copy the base mip level of the specified image to a buffer. The image must be in `VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL`.
+    /// # Example
+    /// ```
+    /// # use anyhow::Result;
+    /// # use phobos::*;
+    /// # use phobos::sync::domain::*;
+    /// fn copy_image_to_buffer<C: TransferCmdBuffer>(cmd: C, src: &ImageView, dst: &BufferView) -> Result<C> {
+    ///     cmd.copy_image_to_buffer(src, dst)
+    /// }
+    /// ```
+    fn copy_image_to_buffer(mut self, src: &ImageView, dst: &BufferView) -> Result<Self>
+    where
+        Self: Sized, {
+        let copy = vk::BufferImageCopy {
+            buffer_offset: dst.offset(),
+            buffer_row_length: src.width(),
+            buffer_image_height: src.height(),
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: src.aspect(),
+                mip_level: src.base_level(),
+                base_array_layer: src.base_layer(),
+                layer_count: src.layer_count(),
+            },
+            image_offset: Default::default(),
+            image_extent: src.size(),
+        };
+
+        let (src, dst) = unsafe { (src.image(), dst.handle()) };
+        self.record_command(
+            || Command::CopyImageToBuffer {
+                src,
+                dst,
+                region: copy,
+            },
+            |device, handle| unsafe {
+                device.cmd_copy_image_to_buffer(
+                    handle,
+                    src,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    dst,
+                    std::slice::from_ref(&copy),
+                );
+            },
+        );
+
+        Ok(self)
+    }
+
+    /// Fill a buffer view with a repeated 32-bit value.
+    /// # Errors
+    /// * Fails if the offset or size of the buffer view is not a multiple of 4.
+    /// # Example
+    /// ```
+    /// # use anyhow::Result;
+    /// # use phobos::*;
+    /// # use phobos::sync::domain::*;
+    /// fn clear_buffer<C: TransferCmdBuffer>(cmd: C, buffer: &BufferView) -> Result<C> {
+    ///     cmd.fill_buffer(buffer, 0)
+    /// }
+    /// ```
+    fn fill_buffer(mut self, dst: &BufferView, value: u32) -> Result<Self>
+    where
+        Self: Sized, {
+        if (dst.offset() | dst.size()) & 3 != 0 {
+            return Err(Error::Uncategorized("Buffer fill offset and size must be a multiple of 4").into());
         }
 
+        let (buffer, offset, size) = (unsafe { dst.handle() }, dst.offset(), dst.size());
+        self.record_command(
+            || Command::FillBuffer {
+                buffer,
+                offset,
+                size,
+                value,
+            },
+            |device, handle| unsafe { device.cmd_fill_buffer(handle, buffer, offset, size, value) },
+        );
+
         Ok(self)
     }
 }
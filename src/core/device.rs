@@ -217,6 +217,7 @@ impl Device {
         features.pipeline_statistics_query = vk::TRUE;
         features_1_2.buffer_device_address = vk::TRUE;
         features_1_2.host_query_reset = vk::TRUE;
+        features_1_2.timeline_semaphore = vk::TRUE;
         features_1_2.descriptor_indexing = vk::TRUE;
         features_1_2.runtime_descriptor_array = vk::TRUE;
         features_1_2.descriptor_binding_partially_bound = vk::TRUE;
@@ -78,6 +78,9 @@ pub enum Error {
     /// Buffer copy between views of different sizes is not allowed.
     #[error("Buffer copy has invalid buffer views as range.")]
     InvalidBufferCopy,
+    /// Image copy between views of different sizes is not allowed.
+    #[error("Image copy has image views of different sizes.")]
+    InvalidImageCopy,
     /// Mappable buffer expected
     #[error("Requested mappable buffer, but buffer does not have a memory map")]
     UnmappableBuffer,
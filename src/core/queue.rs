@@ -9,10 +9,16 @@ use crate::{
     Allocator, CmdBuffer, DescriptorCache, Device, Error, Fence, IncompleteCmdBuffer, PipelineCache,
 };
 use crate::command_buffer::command_pool::CommandPool;
+#[cfg(feature = "rayon")]
+use crate::command_buffer::IncompleteCommandBuffer;
+#[cfg(feature = "rayon")]
+use crate::command_buffer::state::SecondaryInheritance;
+#[cfg(feature = "rayon")]
+use crate::sync::domain::ExecutionDomain;
 
 /// Abstraction over vulkan queue capabilities. Note that in raw Vulkan, there is no 'Graphics queue'. Phobos will expose one, but behind the scenes the exposed
 /// e.g. graphics and transfer queues could point to the same hardware queue. Synchronization for this is handled for you.
-#[derive(Copy, Clone, Default, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq, Hash)]
 #[repr(u32)]
 pub enum QueueType {
     /// Queue that supports graphics operations. Per the vulkan spec, this queue also always supports
@@ -169,6 +175,55 @@ impl Queue {
         )
     }
 
+    /// Allocate a secondary command buffer and begin recording it with the given inherited state.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn allocate_secondary_command_buffer<'q, D: ExecutionDomain, A: Allocator>(
+        device: Device,
+        queue_lock: MutexGuard<'q, Queue>,
+        inheritance: &SecondaryInheritance,
+        pipelines: PipelineCache<A>,
+        descriptors: DescriptorCache,
+    ) -> Result<IncompleteCommandBuffer<'q, D, A>> {
+        let info = vk::CommandBufferAllocateInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
+            p_next: std::ptr::null(),
+            command_pool: unsafe { queue_lock.pool.handle() },
+            level: vk::CommandBufferLevel::SECONDARY,
+            command_buffer_count: 1,
+        };
+        let handle = unsafe { device.allocate_command_buffers(&info)? }
+            .into_iter()
+            .next()
+            .ok_or(Error::Uncategorized("Command buffer allocation failed."))?;
+
+        IncompleteCommandBuffer::new_secondary(device, queue_lock, handle, inheritance, pipelines, descriptors)
+    }
+
+    /// Create a new logical queue over the same device queue, but with its own command pool. This allows recording
+    /// command buffers allocated from it on another thread while this queue is locked.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn with_new_pool(&self) -> Result<Queue> {
+        Queue::new(
+            self.device.clone(),
+            self.queue.clone(),
+            self.info,
+            self.family_properties,
+        )
+    }
+
+    /// Free the given command buffers allocated from this queue, and reset its command pool so the memory can be reused.
+    /// # Safety
+    /// None of the command buffers allocated from this queue may be pending execution.
+    #[cfg(feature = "rayon")]
+    pub(crate) unsafe fn reset_pool(&self, buffers: &[vk::CommandBuffer]) -> Result<()> {
+        if !buffers.is_empty() {
+            self.device.free_command_buffers(self.pool.handle(), buffers);
+        }
+        self.device
+            .reset_command_pool(self.pool.handle(), vk::CommandPoolResetFlags::empty())?;
+        Ok(())
+    }
+
     /// Instantly delete a command buffer, without taking synchronization into account.
     /// This function **must** be externally synchronized.
     pub(crate) unsafe fn free_command_buffer<CmdBuf: CmdBuffer<A>, A: Allocator>(
@@ -93,6 +93,7 @@ impl<'r> DescriptorSetBuilder<'r> {
     }
 
     /// Resolve the virtual resource through the given bindings, and bind it to a specific slot as a combined image sampler.
+    /// If the resource refers to a subresource range, only that range is bound.
     /// # Errors
     /// Fails if the binding did not exist, or did not contain an image.
     pub fn resolve_and_bind_sampled_image(
@@ -102,8 +103,8 @@ impl<'r> DescriptorSetBuilder<'r> {
         sampler: &Sampler,
         bindings: &PhysicalResourceBindings,
     ) -> Result<()> {
-        if let Some(PhysicalResource::Image(image)) = bindings.resolve(resource) {
-            self.bind_sampled_image(binding, image, sampler);
+        if let Some(PhysicalResource::Image(image)) = bindings.resolve_subresource(resource)? {
+            self.bind_sampled_image(binding, &image, sampler);
             Ok(())
         } else {
             Err(Error::NoResourceBound(resource.uid().to_owned()).into())
@@ -112,13 +113,25 @@ impl<'r> DescriptorSetBuilder<'r> {
 
     /// Bind an image view to the given binding as a [`vk::DescriptorType::COMBINED_IMAGE_SAMPLER`]
     pub fn bind_sampled_image(&mut self, binding: u32, image: &ImageView, sampler: &Sampler) {
+        self.bind_sampled_image_in_layout(binding, image, sampler, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+    }
+
+    /// Bind an image view that is sampled in the given layout to the given binding as a [`vk::DescriptorType::COMBINED_IMAGE_SAMPLER`].
+    /// This is used to sample read-only depth attachments in [`vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL`].
+    pub fn bind_sampled_image_in_layout(
+        &mut self,
+        binding: u32,
+        image: &ImageView,
+        sampler: &Sampler,
+        layout: vk::ImageLayout,
+    ) {
         self.inner.bindings.push(DescriptorBinding {
             binding,
             ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
             descriptors: vec![DescriptorContents::Image(DescriptorImageInfo {
                 sampler: unsafe { sampler.handle() },
                 view: image.clone(),
-                layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                layout,
             })],
         });
     }
@@ -212,15 +225,15 @@ impl<'r> DescriptorSetBuilder<'r> {
         })
     }
 
-    /// Resolve and bind a storage image to a specified slot.
+    /// Resolve and bind a storage image to a specified slot. If the resource refers to a subresource range, only that range is bound.
     pub fn resolve_and_bind_storage_image(
         &mut self,
         binding: u32,
         resource: &VirtualResource,
         bindings: &PhysicalResourceBindings,
     ) -> Result<()> {
-        if let Some(PhysicalResource::Image(image)) = bindings.resolve(resource) {
-            self.bind_storage_image(binding, image);
+        if let Some(PhysicalResource::Image(image)) = bindings.resolve_subresource(resource)? {
+            self.bind_storage_image(binding, &image);
             Ok(())
         } else {
             Err(Error::NoResourceBound(resource.uid().to_owned()).into())
@@ -0,0 +1,196 @@
+//! Cache for built pass graphs, so graphs with the same structure do not have to be built again every frame.
+//!
+//! Building a graph inserts every pass into the task graph and creates all barriers, which can become expensive
+//! for graphs with many passes. Most applications construct the exact same graph every frame, only with different
+//! pass executors. A [`PassGraphCache`] stores built graphs by their [`structure hash`](crate::PassGraph::structure_hash).
+//! When a graph with a known structure is requested, the executors and conditions of the new passes are moved into the cached graph
+//! and the rest of the new graph is discarded. Physical resources are bound when recording, so these can change every frame.
+//!
+//! Transient resources allocated on a cached graph are kept, so [`BuiltPassGraph::update_transients()`] only recreates them
+//! after a resize.
+//!
+//! Graphs are only reused if their structure is exactly equal, so a collision of structure hashes never returns the wrong graph.
+//! The cache holds a limited number of graphs, see [`PassGraphCache::with_capacity()`]. When it is full, the least recently used
+//! graph is evicted.
+//!
+//! Since passes are only inserted into the graph when it is built, a graph with cyclic dependencies fails in
+//! [`PassGraph::build()`] and therefore in [`PassGraphCache::get_or_build()`], not in [`PassGraph::add_pass()`].
+//!
+//! # Example
+//!
+//! ```
+//! use phobos::prelude::*;
+//! use phobos::graph::cache::PassGraphCache;
+//!
+//! let mut cache = PassGraphCache::<domain::All>::new();
+//! loop {
+//!     let swapchain = VirtualResource::image("swapchain");
+//!     let render = PassBuilder::render("render")
+//!         .clear_color_attachment(&swapchain, ClearColor::Float([0.0, 0.0, 0.0, 1.0]))?
+//!         .execute_fn(|cmd, _, _, _| Ok(cmd))
+//!         .build();
+//!     let present = PassBuilder::present("present", render.output(&swapchain).unwrap());
+//!     let graph = PassGraph::new()
+//!         .add_pass(render)?
+//!         .add_pass(present)?;
+//!     // Only builds the graph on the first iteration.
+//!     let graph = cache.get_or_build(graph)?;
+//!     let cmd = graph.record(cmd, &bindings, &mut pool, None, &mut ())?;
+//! }
+//! ```
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use crate::{Allocator, DefaultAllocator, PassGraph};
+use crate::graph::pass_graph::BuiltPassGraph;
+use crate::graph::task_graph::Node;
+use crate::sync::domain::ExecutionDomain;
+
+/// Number of graphs a cache created with [`PassGraphCache::new()`] can hold.
+pub const DEFAULT_CAPACITY: usize = 16;
+
+struct CachedGraph<'cb, D: ExecutionDomain, U, A: Allocator> {
+    // Compared on every lookup, since different structures can have the same hash.
+    key: Vec<u8>,
+    graph: BuiltPassGraph<'cb, D, U, A>,
+    // Value of the use counter of the cache when this graph was last returned.
+    last_used: u64,
+}
+
+/// Stores built pass graphs by the hash of their structure.
+pub struct PassGraphCache<'cb, D: ExecutionDomain, U = (), A: Allocator = DefaultAllocator> {
+    graphs: HashMap<u64, CachedGraph<'cb, D, U, A>>,
+    capacity: usize,
+    uses: u64,
+    hits: usize,
+    misses: usize,
+}
+
+impl<'cb, D: ExecutionDomain, U, A: Allocator> Default for PassGraphCache<'cb, D, U, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'cb, D: ExecutionDomain, U, A: Allocator> PassGraphCache<'cb, D, U, A> {
+    /// Create a new, empty cache that holds up to [`DEFAULT_CAPACITY`] graphs.
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// Create a new, empty cache that holds up to `capacity` graphs. A capacity of zero is treated as one.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            graphs: HashMap::new(),
+            capacity: capacity.max(1),
+            uses: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Get the built version of `graph`. If a graph with the same structure was built before, the pass executors and conditions
+    /// of `graph` are moved into the cached graph. Otherwise `graph` is built and stored in the cache, evicting the least recently
+    /// used graph if the cache is full. A cached graph with the same structure hash but a different structure is replaced.
+    /// # Errors
+    /// * Fails if `graph` is not in the cache and building it fails. See [`PassGraph::build()`].
+    pub fn get_or_build(&mut self, mut graph: PassGraph<'cb, D, U, A>) -> Result<&mut BuiltPassGraph<'cb, D, U, A>> {
+        self.uses += 1;
+        let hash = graph.structure_hash();
+        let key = graph.structure_key();
+        if self
+            .graphs
+            .get(&hash)
+            .is_none_or(|cached| cached.key != key)
+        {
+            self.misses += 1;
+            let built = graph.build()?;
+            if !self.graphs.contains_key(&hash) && self.graphs.len() >= self.capacity {
+                self.evict_least_recently_used();
+            }
+            let cached = CachedGraph {
+                key,
+                graph: built,
+                last_used: self.uses,
+            };
+            self.graphs.insert(hash, cached);
+            return Ok(&mut self.graphs.get_mut(&hash).unwrap().graph);
+        }
+
+        self.hits += 1;
+        let cached = self.graphs.get_mut(&hash).unwrap();
+        cached.last_used = self.uses;
+        let cached = &mut cached.graph;
+        let mut executors = graph
+            .take_passes()
+            .into_iter()
+            .map(|pass| Some((pass.execute, pass.condition)))
+            .collect::<Vec<_>>();
+        // Culled passes are not in the cached graph, their executors are dropped together with the new graph.
+        for node in cached.graph.graph.node_weights_mut() {
+            let Node::Task(task) = node else { continue };
+            let Some(index) = task.pass_index else { continue };
+            if let Some((execute, condition)) = executors.get_mut(index).and_then(Option::take) {
+                task.execute = execute;
+                task.condition = condition;
+            }
+        }
+        Ok(cached)
+    }
+
+    fn evict_least_recently_used(&mut self) {
+        let oldest = self
+            .graphs
+            .iter()
+            .min_by_key(|(_, cached)| cached.last_used)
+            .map(|(hash, _)| *hash);
+        if let Some(hash) = oldest {
+            self.graphs.remove(&hash);
+        }
+    }
+
+    /// Returns true if a graph with this structure hash is in the cache.
+    pub fn contains(&self, hash: u64) -> bool {
+        self.graphs.contains_key(&hash)
+    }
+
+    /// Remove the graph with this structure hash from the cache, and return it. This can be used to evict graphs that
+    /// are no longer needed, for example to free their transient resources.
+    pub fn remove(&mut self, hash: u64) -> Option<BuiltPassGraph<'cb, D, U, A>> {
+        self.graphs
+            .remove(&hash)
+            .map(|cached| cached.graph)
+    }
+
+    /// Remove all graphs from the cache.
+    pub fn clear(&mut self) {
+        self.graphs.clear();
+    }
+
+    /// Get the maximum number of graphs in the cache.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Get the number of graphs in the cache.
+    pub fn len(&self) -> usize {
+        self.graphs.len()
+    }
+
+    /// Returns true if the cache holds no graphs.
+    pub fn is_empty(&self) -> bool {
+        self.graphs.is_empty()
+    }
+
+    /// Get the number of calls to [`PassGraphCache::get_or_build()`] that reused a cached graph.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Get the number of calls to [`PassGraphCache::get_or_build()`] that had to build a new graph.
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+}
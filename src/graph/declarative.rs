@@ -0,0 +1,460 @@
+//! Load pass graphs from a declarative description, so the frame can be reconfigured without recompiling.
+//!
+//! A [`GraphDeclaration`] lists passes with the resources they use, their attachments and clear values, and the name of the pipeline
+//! each pass uses. With the `serde` feature enabled, declarations can be loaded from JSON using [`GraphDeclaration::from_json()`].
+//! [`PassGraph::from_declaration()`] turns a declaration into a pass graph using the regular [`PassBuilder`] API.
+//!
+//! Passes refer to resources by name only. Every pass uses the newest version of a resource, so a pass reading `scene` after another
+//! pass wrote it automatically reads `scene+`. The type of a resource is derived from how it is first used, images are assumed otherwise.
+//! Resources that are only copied can be declared as buffers in [`GraphDeclaration::resources`].
+//!
+//! Executors cannot be described in a file. Instead, each pass names an executor that is looked up in an [`ExecutorRegistry`]. The executor
+//! is registered as a function that receives the [`PassBuilder`] and the declaration of the pass, so it can set the executor of the pass
+//! using the pipeline named in the declaration.
+//!
+//! Pipeline stages are written using their Vulkan names without the `VK_PIPELINE_STAGE_2_` prefix and `_BIT` suffix, and can be
+//! combined with `|`, for example `"VERTEX_SHADER | FRAGMENT_SHADER"`.
+//!
+//! # Example
+//! ```
+//! use phobos::prelude::*;
+//! use phobos::graph::declarative::{ExecutorRegistry, GraphDeclaration};
+//!
+//! let json = r#"{
+//!     "passes": [
+//!         {
+//!             "name": "tonemap",
+//!             "render": true,
+//!             "pipeline": "tonemap",
+//!             "executor": "fullscreen",
+//!             "resources": [
+//!                 { "usage": "sample_image", "resource": "hdr", "stage": "FRAGMENT_SHADER" },
+//!                 { "usage": "color_attachment", "resource": "swapchain", "clear": { "float": [0.0, 0.0, 0.0, 1.0] } }
+//!             ]
+//!         }
+//!     ],
+//!     "present": "swapchain"
+//! }"#;
+//!
+//! let registry = ExecutorRegistry::new().register("fullscreen", |pass, declaration| {
+//!     let pipeline = declaration.pipeline.clone().unwrap_or_default();
+//!     Ok(pass.execute_fn(move |cmd, _, _, _| cmd.bind_graphics_pipeline(&pipeline)?.draw(3, 1, 0, 0)))
+//! });
+//! let graph = PassGraph::<domain::All>::from_declaration(&GraphDeclaration::from_json(json)?, &registry)?.build()?;
+//! ```
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    Allocator, BufferUsage, ClearColor, ClearDepthStencil, DefaultAllocator, Pass, PassBuilder,
+    PassGraph, PipelineStage, VirtualResource,
+};
+use crate::graph::resource::ResourceType;
+use crate::sync::domain::ExecutionDomain;
+use crate::traits::GfxSupport;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Declarative description of a pass graph. See the [`declarative`](crate::graph::declarative) module.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GraphDeclaration {
+    /// Types of resources that cannot be derived from how they are used.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub resources: Vec<ResourceDeclaration>,
+    /// All passes, in the order they are added to the graph.
+    pub passes: Vec<PassDeclaration>,
+    /// Name of the swapchain image. If set, a pass presenting it is added after all other passes.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub present: Option<String>,
+    /// Names of resources that are read after the graph, see [`PassGraph::mark_external()`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub external: Vec<String>,
+}
+
+/// Declares the type of a resource.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ResourceDeclaration {
+    /// Name of the resource.
+    pub name: String,
+    /// Type of the resource.
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub resource_type: ResourceType,
+}
+
+/// Declaration of a single pass.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PassDeclaration {
+    /// Name of the pass.
+    pub name: String,
+    /// Whether this pass is a render pass, see [`PassBuilder::render()`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub render: bool,
+    /// View mask for multiview rendering, see [`PassBuilder::view_mask()`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub view_mask: Option<u32>,
+    /// Number of layers to render to, see [`PassBuilder::layers()`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub layers: Option<u32>,
+    /// Name of the pipeline this pass uses. This is passed on to the executor.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub pipeline: Option<String>,
+    /// Name of the executor in the [`ExecutorRegistry`]. Passes without an executor do not record any commands.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub executor: Option<String>,
+    /// Color of the pass in graphics debuggers.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub color: Option<[f32; 4]>,
+    /// Resources used by this pass.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub resources: Vec<PassResourceDeclaration>,
+}
+
+/// A resource used by a pass. Each variant corresponds to a method of [`PassBuilder`]. Stages are names of pipeline stages, see the
+/// [`declarative`](crate::graph::declarative) module.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(tag = "usage", rename_all = "snake_case"))]
+pub enum PassResourceDeclaration {
+    /// See [`PassBuilder::sample_image()`].
+    SampleImage {
+        /// Name of the resource.
+        resource: String,
+        /// Stages the image is sampled in.
+        stage: String,
+    },
+    /// See [`PassBuilder::read_storage_image()`].
+    ReadStorageImage {
+        /// Name of the resource.
+        resource: String,
+        /// Stages the image is read in.
+        stage: String,
+    },
+    /// See [`PassBuilder::write_storage_image()`].
+    WriteStorageImage {
+        /// Name of the resource.
+        resource: String,
+        /// Stages the image is written in.
+        stage: String,
+    },
+    /// See [`PassBuilder::read_history()`].
+    ReadHistory {
+        /// Name of the resource whose history is read.
+        resource: String,
+        /// Stages the history is sampled in.
+        stage: String,
+    },
+    /// See [`PassBuilder::read_buffer()`].
+    ReadBuffer {
+        /// Name of the resource.
+        resource: String,
+        /// How the buffer is read.
+        buffer_usage: BufferUsage,
+        /// Stages the buffer is read in.
+        stage: String,
+    },
+    /// See [`PassBuilder::write_buffer()`].
+    WriteBuffer {
+        /// Name of the resource.
+        resource: String,
+        /// How the buffer is written.
+        buffer_usage: BufferUsage,
+        /// Stages the buffer is written in.
+        stage: String,
+    },
+    /// See [`PassBuilder::read_write_buffer()`].
+    ReadWriteBuffer {
+        /// Name of the resource.
+        resource: String,
+        /// How the buffer is accessed.
+        buffer_usage: BufferUsage,
+        /// Stages the buffer is accessed in.
+        stage: String,
+    },
+    /// See [`PassBuilder::transfer_read()`].
+    TransferRead {
+        /// Name of the resource.
+        resource: String,
+    },
+    /// See [`PassBuilder::transfer_write()`].
+    TransferWrite {
+        /// Name of the resource.
+        resource: String,
+    },
+    /// A color attachment that is cleared to `clear`, or loaded if no clear value is given.
+    ColorAttachment {
+        /// Name of the resource.
+        resource: String,
+        /// Clear value of the attachment.
+        clear: Option<ClearColor>,
+    },
+    /// A depth attachment that is cleared to `clear`, or loaded if no clear value is given.
+    DepthAttachment {
+        /// Name of the resource.
+        resource: String,
+        /// Clear value of the attachment.
+        clear: Option<ClearDepthStencil>,
+    },
+    /// See [`PassBuilder::read_only_depth_attachment()`].
+    ReadOnlyDepthAttachment {
+        /// Name of the resource.
+        resource: String,
+        /// Stages the attachment is also sampled in, if it is sampled.
+        sample_stage: Option<String>,
+    },
+    /// See [`PassBuilder::resolve()`].
+    Resolve {
+        /// Name of the multisampled attachment.
+        src: String,
+        /// Name of the resolved image.
+        dst: String,
+    },
+    /// See [`PassBuilder::resolve_depth()`].
+    ResolveDepth {
+        /// Name of the multisampled depth attachment.
+        src: String,
+        /// Name of the resolved image.
+        dst: String,
+    },
+}
+
+/// Function setting the executor of a declared pass.
+pub type ExecutorFactory<'cb, D, U, A> =
+    dyn Fn(PassBuilder<'cb, D, U, A>, &PassDeclaration) -> Result<PassBuilder<'cb, D, U, A>> + 'cb;
+
+/// Executors that declared passes can refer to by name.
+pub struct ExecutorRegistry<'cb, D: ExecutionDomain, U = (), A: Allocator = DefaultAllocator> {
+    executors: HashMap<String, Box<ExecutorFactory<'cb, D, U, A>>>,
+}
+
+impl GraphDeclaration {
+    /// Parse a declaration from JSON.
+    /// # Errors
+    /// * Fails if `json` is not a valid graph declaration.
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Export this declaration as pretty-printed JSON.
+    /// # Errors
+    /// * Fails if serialization fails.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl<'cb, D: ExecutionDomain, U, A: Allocator> ExecutorRegistry<'cb, D, U, A> {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self {
+            executors: HashMap::new(),
+        }
+    }
+
+    /// Register an executor under `name`. When a pass using this executor is loaded, `factory` is called with the builder of the pass
+    /// and its declaration, and should set the executor using for example [`PassBuilder::execute_fn()`]. Registering an executor
+    /// with the same name again replaces it.
+    pub fn register(
+        mut self,
+        name: impl Into<String>,
+        factory: impl Fn(PassBuilder<'cb, D, U, A>, &PassDeclaration) -> Result<PassBuilder<'cb, D, U, A>> + 'cb,
+    ) -> Self {
+        self.executors.insert(name.into(), Box::new(factory));
+        self
+    }
+
+    /// Returns true if an executor with this name was registered.
+    pub fn contains(&self, name: &str) -> bool {
+        self.executors.contains_key(name)
+    }
+}
+
+impl<'cb, D: ExecutionDomain, U, A: Allocator> Default for ExecutorRegistry<'cb, D, U, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parse a pipeline stage mask like `"VERTEX_SHADER | FRAGMENT_SHADER"`.
+fn parse_stage(stage: &str) -> Result<PipelineStage> {
+    stage
+        .split('|')
+        .map(str::trim)
+        .try_fold(PipelineStage::NONE, |mask, name| {
+            if name == "NONE" {
+                return Ok(mask);
+            }
+            (0..64)
+                .map(|bit| PipelineStage::from_raw(1 << bit))
+                .find(|flag| format!("{flag:?}") == name)
+                .map(|flag| mask | flag)
+                .ok_or_else(|| anyhow!("Unknown pipeline stage `{name}`"))
+        })
+}
+
+/// Tracks the newest version of every resource while loading passes.
+struct Resources<'a> {
+    declared: &'a [ResourceDeclaration],
+    current: HashMap<String, VirtualResource>,
+}
+
+impl Resources<'_> {
+    /// Get the newest version of a resource. Resources that were not used before are created with their declared type,
+    /// or with `usage_type` if it was not declared.
+    fn get(&mut self, name: &str, usage_type: Option<ResourceType>) -> Result<VirtualResource> {
+        let resource_type = match self.current.get(name) {
+            Some(resource) => resource.resource_type(),
+            None => self
+                .declared
+                .iter()
+                .find(|resource| resource.name == name)
+                .map(|resource| resource.resource_type)
+                .or(usage_type)
+                .unwrap_or_default(),
+        };
+        if usage_type.is_some_and(|usage_type| usage_type != resource_type) {
+            bail!("Resource `{name}` is used as a {usage_type:?}, but is a {resource_type:?}");
+        }
+        Ok(self
+            .current
+            .entry(name.to_owned())
+            .or_insert_with(|| match resource_type {
+                ResourceType::Image => VirtualResource::image(name),
+                ResourceType::Buffer => VirtualResource::buffer(name),
+            })
+            .clone())
+    }
+
+    /// Get the newest version of a resource that must have been used before.
+    fn existing(&self, name: &str) -> Result<VirtualResource> {
+        self.current
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow!("Resource `{name}` is not used by any pass"))
+    }
+
+    /// Advance all resources written by a pass to their new version.
+    fn update<D: ExecutionDomain, U, A: Allocator>(&mut self, pass: &Pass<'_, D, U, A>) {
+        for resource in self.current.values_mut() {
+            if let Some(output) = pass.output(resource) {
+                *resource = output.clone();
+            }
+        }
+    }
+}
+
+fn declare_resource<'cb, D: GfxSupport + ExecutionDomain, U, A: Allocator>(
+    pass: PassBuilder<'cb, D, U, A>,
+    usage: &PassResourceDeclaration,
+    resources: &mut Resources,
+) -> Result<PassBuilder<'cb, D, U, A>> {
+    use ResourceType::{Buffer, Image};
+    Ok(match usage {
+        PassResourceDeclaration::SampleImage { resource, stage } => {
+            pass.sample_image(&resources.get(resource, Some(Image))?, parse_stage(stage)?)
+        }
+        PassResourceDeclaration::ReadStorageImage { resource, stage } => {
+            pass.read_storage_image(&resources.get(resource, Some(Image))?, parse_stage(stage)?)
+        }
+        PassResourceDeclaration::WriteStorageImage { resource, stage } => {
+            pass.write_storage_image(&resources.get(resource, Some(Image))?, parse_stage(stage)?)
+        }
+        PassResourceDeclaration::ReadHistory { resource, stage } => {
+            pass.read_history(&resources.get(resource, Some(Image))?, parse_stage(stage)?)
+        }
+        PassResourceDeclaration::ReadBuffer { resource, buffer_usage, stage } => {
+            pass.read_buffer(&resources.get(resource, Some(Buffer))?, *buffer_usage, parse_stage(stage)?)?
+        }
+        PassResourceDeclaration::WriteBuffer { resource, buffer_usage, stage } => {
+            pass.write_buffer(&resources.get(resource, Some(Buffer))?, *buffer_usage, parse_stage(stage)?)?
+        }
+        PassResourceDeclaration::ReadWriteBuffer { resource, buffer_usage, stage } => {
+            pass.read_write_buffer(&resources.get(resource, Some(Buffer))?, *buffer_usage, parse_stage(stage)?)?
+        }
+        PassResourceDeclaration::TransferRead { resource } => pass.transfer_read(&resources.get(resource, None)?),
+        PassResourceDeclaration::TransferWrite { resource } => pass.transfer_write(&resources.get(resource, None)?),
+        PassResourceDeclaration::ColorAttachment { resource, clear } => {
+            let resource = resources.get(resource, Some(Image))?;
+            match clear {
+                Some(clear) => pass.clear_color_attachment(&resource, *clear)?,
+                None => pass.load_color_attachment(&resource)?,
+            }
+        }
+        PassResourceDeclaration::DepthAttachment { resource, clear } => {
+            let resource = resources.get(resource, Some(Image))?;
+            match clear {
+                Some(clear) => pass.clear_depth_attachment(&resource, *clear)?,
+                None => pass.load_depth_attachment(&resource)?,
+            }
+        }
+        PassResourceDeclaration::ReadOnlyDepthAttachment { resource, sample_stage } => {
+            let sample_stage = sample_stage.as_deref().map(parse_stage).transpose()?;
+            pass.read_only_depth_attachment(&resources.get(resource, Some(Image))?, sample_stage)?
+        }
+        PassResourceDeclaration::Resolve { src, dst } => {
+            pass.resolve(&resources.existing(src)?, &resources.get(dst, Some(Image))?)
+        }
+        PassResourceDeclaration::ResolveDepth { src, dst } => {
+            pass.resolve_depth(&resources.existing(src)?, &resources.get(dst, Some(Image))?)
+        }
+    })
+}
+
+impl<'cb, D: GfxSupport + ExecutionDomain, U, A: Allocator> PassGraph<'cb, D, U, A> {
+    /// Create a pass graph from a declaration, looking up the executors of its passes in `registry`.
+    /// See the [`declarative`](crate::graph::declarative) module for more information.
+    /// # Errors
+    /// * Fails if a pass uses an executor that is not in the registry, or the executor fails.
+    /// * Fails if a pipeline stage name is unknown.
+    /// * Fails if a resource is used as both an image and a buffer.
+    /// * Fails if a resolve source, presented resource or external resource is not used by any pass.
+    /// * Fails if a pass declares attachments but is not a render pass, or any other error [`PassGraph::add_pass()`] reports.
+    pub fn from_declaration(
+        declaration: &GraphDeclaration,
+        registry: &ExecutorRegistry<'cb, D, U, A>,
+    ) -> Result<Self> {
+        let mut resources = Resources {
+            declared: &declaration.resources,
+            current: HashMap::new(),
+        };
+        let mut graph = PassGraph::new();
+        for declared in &declaration.passes {
+            let mut pass = match declared.render {
+                true => PassBuilder::render(declared.name.clone()),
+                false => PassBuilder::new(declared.name.clone()),
+            };
+            if let Some(view_mask) = declared.view_mask {
+                pass = pass.view_mask(view_mask)?;
+            }
+            if let Some(layers) = declared.layers {
+                pass = pass.layers(layers)?;
+            }
+            // Every usage within a pass refers to the version the pass starts with, resources are only advanced after the pass.
+            for usage in &declared.resources {
+                pass = declare_resource(pass, usage, &mut resources)?;
+            }
+            if let Some(name) = &declared.executor {
+                let Some(factory) = registry.executors.get(name) else {
+                    bail!("Pass `{}` uses unknown executor `{name}`", declared.name);
+                };
+                pass = factory(pass, declared)?;
+            }
+            let mut pass = pass.build();
+            pass.color = declared.color;
+            resources.update(&pass);
+            graph = graph.add_pass(pass)?;
+        }
+        if let Some(swapchain) = &declaration.present {
+            graph = graph.add_pass(PassBuilder::present("present", &resources.existing(swapchain)?))?;
+        }
+        for name in &declaration.external {
+            graph = graph.mark_external(&resources.existing(name)?);
+        }
+        Ok(graph)
+    }
+}
@@ -0,0 +1,283 @@
+//! Structured export of built pass graphs.
+//!
+//! [`BuiltPassGraph::describe()`] returns a [`GraphDescription`] listing every pass with its resources, and every barrier with its
+//! stages, access flags and layouts, in the order they are recorded. Flags and layouts are stored as their Vulkan names, so
+//! descriptions are easy to read and to diff. Descriptions can be exported as [Mermaid](https://mermaid.js.org/) flowcharts for documentation,
+//! and as JSON when the `serde` feature is enabled. Building and describing a graph does not require a GPU, so this can be used for
+//! snapshot tests of graph construction.
+//!
+//! # Example
+//! ```
+//! use phobos::prelude::*;
+//!
+//! let graph = graph.build()?;
+//! let description = graph.describe()?;
+//! println!("{}", description.to_mermaid());
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use anyhow::Result;
+use petgraph::{Incoming, Outgoing};
+
+use crate::{Allocator, PassGraph};
+use crate::graph::pass_graph::{BuiltPassGraph, PassResource, PassResourceBarrier};
+use crate::graph::record::{barrier_kind, written_before, BarrierKind};
+use crate::graph::task_graph::Node;
+use crate::sync::domain::ExecutionDomain;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Description of a built pass graph.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GraphDescription {
+    /// All passes, in recording order.
+    pub passes: Vec<PassDescription>,
+    /// All barriers, in recording order.
+    pub barriers: Vec<BarrierDescription>,
+}
+
+/// Description of a single pass.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PassDescription {
+    /// Name of the pass.
+    pub name: String,
+    /// Queue the pass executes on.
+    pub queue: String,
+    /// Whether this pass is a render pass.
+    pub renderpass: bool,
+    /// View mask used for multiview rendering, zero if multiview is not used.
+    pub view_mask: u32,
+    /// Number of layers rendered to with layered rendering.
+    pub layers: u32,
+    /// Position of the pass in the recording order of all nodes.
+    pub order: usize,
+    /// Resources used by this pass.
+    pub inputs: Vec<ResourceDescription>,
+    /// Resources produced by this pass.
+    pub outputs: Vec<ResourceDescription>,
+}
+
+/// Description of a versioned resource used by a pass.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ResourceDescription {
+    /// Unique name of this version of the resource.
+    pub uid: String,
+    /// Name of the resource.
+    pub name: String,
+    /// Version of the resource.
+    pub version: usize,
+    /// How the resource is used.
+    pub usage: String,
+    /// Pipeline stages the resource is used in.
+    pub stage: String,
+    /// Access flags of this usage.
+    pub access: String,
+    /// Image layout the resource is in while it is used.
+    pub layout: String,
+    /// Store op of attachments, if it was set on the pass or inferred by the graph. Written attachments without a store op are stored.
+    pub store_op: Option<String>,
+}
+
+/// Description of a single barrier. Barriers transitioning imported resources to their final state have no destination passes.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BarrierDescription {
+    /// Unique name of the version of the resource this barrier synchronizes.
+    pub resource: String,
+    /// Pass producing the resource, or `None` if the resource comes from a previous frame.
+    pub src_pass: Option<String>,
+    /// Passes consuming the resource.
+    pub dst_passes: Vec<String>,
+    /// How the barrier is recorded: `image`, `memory`, `execution`, or `skipped` if no barrier is needed.
+    pub kind: String,
+    /// Whether the barrier is split into an event signal after the source pass and a wait before the destination passes.
+    pub split: bool,
+    /// Source stage mask.
+    pub src_stage: String,
+    /// Destination stage mask.
+    pub dst_stage: String,
+    /// Source access mask.
+    pub src_access: String,
+    /// Destination access mask.
+    pub dst_access: String,
+    /// Layout before the barrier.
+    pub old_layout: String,
+    /// Layout after the barrier.
+    pub new_layout: String,
+    /// Position of the barrier in the recording order of all nodes.
+    pub order: usize,
+    /// Index of the `vkCmdPipelineBarrier2` call this barrier is batched into. Split barriers are waited on right before this call.
+    pub batch: usize,
+}
+
+fn describe_resource(resource: &PassResource) -> ResourceDescription {
+    ResourceDescription {
+        uid: resource.resource.uid(),
+        name: resource.resource.name().to_owned(),
+        version: resource.resource.version(),
+        usage: format!("{:?}", resource.usage),
+        stage: format!("{:?}", resource.stage),
+        access: format!("{:?}", resource.usage.access()),
+        layout: format!("{:?}", resource.layout),
+        store_op: resource.store_op.map(|op| format!("{op:?}")),
+    }
+}
+
+fn describe_barrier(
+    barrier: &PassResourceBarrier,
+    dst_resource: &PassResource,
+    kind: BarrierKind,
+    src_pass: Option<String>,
+    dst_passes: Vec<String>,
+) -> BarrierDescription {
+    BarrierDescription {
+        resource: barrier.resource.resource.uid(),
+        src_pass,
+        dst_passes,
+        kind: kind_name(kind).to_owned(),
+        split: false,
+        src_stage: format!("{:?}", barrier.src_stage),
+        dst_stage: format!("{:?}", barrier.dst_stage),
+        src_access: format!("{:?}", barrier.src_access),
+        dst_access: format!("{:?}", barrier.dst_access),
+        old_layout: format!("{:?}", barrier.resource.layout),
+        new_layout: format!("{:?}", dst_resource.layout),
+        order: 0,
+        batch: 0,
+    }
+}
+
+fn kind_name(kind: BarrierKind) -> &'static str {
+    match kind {
+        BarrierKind::Skip => "skipped",
+        BarrierKind::Execution => "execution",
+        BarrierKind::Memory => "memory",
+        BarrierKind::Image => "image",
+    }
+}
+
+impl GraphDescription {
+    /// Export this description as a Mermaid flowchart. Passes are drawn as boxes and barriers as hexagons
+    /// listing the resource, its layout transition and the stages they synchronize.
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("flowchart TD\n");
+        let mut ids = HashMap::new();
+        for (index, pass) in self.passes.iter().enumerate() {
+            ids.entry(pass.name.as_str()).or_insert_with(|| format!("p{index}"));
+            writeln!(out, "    p{index}[\"{}\"]", pass.name).unwrap();
+        }
+        for (index, barrier) in self.barriers.iter().enumerate() {
+            writeln!(
+                out,
+                "    b{index}{{{{\"{}<br/>{} -> {}<br/>{} -> {}\"}}}}",
+                barrier.resource, barrier.old_layout, barrier.new_layout, barrier.src_stage, barrier.dst_stage
+            )
+            .unwrap();
+        }
+        for (index, barrier) in self.barriers.iter().enumerate() {
+            if let Some(id) = barrier.src_pass.as_deref().and_then(|pass| ids.get(pass)) {
+                writeln!(out, "    {id} --> b{index}").unwrap();
+            }
+            for id in barrier.dst_passes.iter().filter_map(|pass| ids.get(pass.as_str())) {
+                writeln!(out, "    b{index} --> {id}").unwrap();
+            }
+        }
+        out
+    }
+
+    /// Export this description as pretty-printed JSON.
+    /// # Errors
+    /// * Fails if serialization fails.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Parse a description from JSON produced by [`GraphDescription::to_json()`].
+    /// # Errors
+    /// * Fails if `json` is not a valid graph description.
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+impl<'cb, D: ExecutionDomain, U, A: Allocator> BuiltPassGraph<'cb, D, U, A> {
+    /// Describe the passes and barriers of this graph, in the order they are recorded.
+    /// # Errors
+    /// * Fails if the graph was constructed incorrectly. This generally does not happen.
+    pub fn describe(&self) -> Result<GraphDescription> {
+        let graph = &self.task_graph().graph;
+        let task_name = |node| match graph.node_weight(node) {
+            Some(Node::Task(task)) if node != self.source() => Some(task.identifier.clone()),
+            _ => None,
+        };
+
+        let mut description = GraphDescription::default();
+        let mut order = 0;
+        let mut batch = 0;
+        for step in self.steps() {
+            let mut barriers_in_step = false;
+            for &node in step {
+                match graph.node_weight(node).unwrap() {
+                    Node::Task(task) => {
+                        if node != self.source() {
+                            description.passes.push(PassDescription {
+                                name: task.identifier.clone(),
+                                queue: format!("{:?}", task.queue),
+                                renderpass: task.is_renderpass,
+                                view_mask: task.view_mask,
+                                layers: task.layers,
+                                order,
+                                inputs: task.inputs.iter().map(describe_resource).collect(),
+                                outputs: task.outputs.iter().map(describe_resource).collect(),
+                            });
+                        }
+                    }
+                    Node::Barrier(barrier) => {
+                        let dst_resource = PassGraph::<D, U, A>::barrier_dst_resource(graph, node)?;
+                        let kind = barrier_kind(barrier, dst_resource, written_before(self, node));
+                        let split = self.is_split_barrier(node);
+                        barriers_in_step |= kind != BarrierKind::Skip && !split;
+                        let src_pass = graph
+                            .neighbors_directed(node, Incoming)
+                            .find_map(task_name);
+                        let mut dst_passes = graph
+                            .neighbors_directed(node, Outgoing)
+                            .filter_map(task_name)
+                            .collect::<Vec<_>>();
+                        dst_passes.sort();
+                        description.barriers.push(BarrierDescription {
+                            split,
+                            order,
+                            batch,
+                            ..describe_barrier(barrier, dst_resource, kind, src_pass, dst_passes)
+                        });
+                    }
+                    Node::_Unreachable(_) => unreachable!(),
+                }
+                order += 1;
+            }
+            if barriers_in_step {
+                batch += 1;
+            }
+        }
+
+        // Imported resources are transitioned to their final state after all passes.
+        for transition in self.final_transitions() {
+            let kind = barrier_kind(&transition.barrier, &transition.dst, true);
+            description.barriers.push(BarrierDescription {
+                order,
+                batch,
+                ..describe_barrier(&transition.barrier, &transition.dst, kind, transition.passes.last().cloned(), vec![])
+            });
+            order += 1;
+        }
+        Ok(description)
+    }
+}
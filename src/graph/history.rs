@@ -0,0 +1,217 @@
+//! History resources hold the contents an image had at the end of the previous frame, for temporal effects such as TAA
+//! or motion vector reprojection.
+//!
+//! A pass reads the history of a resource with [`PassBuilder::read_history()`](crate::PassBuilder::read_history). This declares a read of
+//! [`VirtualResource::history()`](crate::VirtualResource::history), which is a separate resource that starts every frame in the state the
+//! resource itself was left in at the end of the graph. [`HistoryResources`] owns two images for every history resource, and swaps them each frame:
+//! the image written in one frame is bound as the history in the next one. Barriers between the two frames are inserted by the graph.
+//!
+//! On the first frame after the images were created, for example after a resize, there is no valid history yet. The history image is then
+//! [invalidated](crate::PhysicalResourceBindings::invalidate), so the graph transitions it from an undefined layout instead. Use
+//! [`HistoryResources::is_valid()`] to also ignore its contents in shaders.
+//!
+//! # Example
+//! ```
+//! use phobos::prelude::*;
+//! use phobos::graph::history::{HistoryImageInfo, HistoryResources};
+//!
+//! let taa = image!("taa");
+//! let resolve = PassBuilder::render("taa_resolve")
+//!     .clear_color_attachment(&taa, ClearColor::Float([0.0, 0.0, 0.0, 0.0]))?
+//!     .sample_image(&color, PipelineStage::FRAGMENT_SHADER)
+//!     .read_history(&taa, PipelineStage::FRAGMENT_SHADER)
+//!     .build();
+//!
+//! let mut history = HistoryResources::new(device.clone());
+//! history.add_image(&taa, HistoryImageInfo {
+//!     format: vk::Format::R16G16B16A16_SFLOAT,
+//!     ..Default::default()
+//! })?;
+//! // Every frame, inside the closure passed to FrameManager::new_frame():
+//! history.new_frame(&mut allocator, swapchain_extent)?;
+//! history.bind(&mut bindings);
+//! ```
+
+use anyhow::Result;
+use ash::vk;
+
+use crate::{
+    Allocator, DefaultAllocator, DeletionQueue, Device, Error, Image, ImageView, MemoryType,
+    PhysicalResourceBindings, VirtualResource,
+};
+use crate::graph::resource::ResourceType;
+use crate::graph::transient::{ReferenceExtents, TransientSize};
+use crate::resource::image::ImageCreateInfo;
+use crate::wsi::frame::FRAMES_IN_FLIGHT;
+
+/// Describes the images backing a history resource.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HistoryImageInfo {
+    /// Size of the images
+    pub size: TransientSize,
+    /// Pixel format of the images
+    pub format: vk::Format,
+    /// Usage flags of the images. These must include every usage of the resource and of its history in the graph.
+    pub usage: vk::ImageUsageFlags,
+    /// Aspect of the image views that are bound to the virtual resources.
+    pub aspect: vk::ImageAspectFlags,
+}
+
+impl Default for HistoryImageInfo {
+    fn default() -> Self {
+        Self {
+            size: TransientSize::default(),
+            format: vk::Format::R8G8B8A8_UNORM,
+            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            aspect: vk::ImageAspectFlags::COLOR,
+        }
+    }
+}
+
+// Views are declared first, so they are destroyed before their images.
+#[derive(Debug)]
+struct HistoryImages<A: Allocator> {
+    views: [ImageView; 2],
+    _images: [Image<A>; 2],
+    extent: vk::Extent2D,
+}
+
+#[derive(Debug)]
+struct HistoryResource<A: Allocator> {
+    resource: VirtualResource,
+    info: HistoryImageInfo,
+    images: Option<HistoryImages<A>>,
+    valid: bool,
+}
+
+/// Owns the images of all history resources, and binds them for the current frame.
+/// See the [`history`](crate::graph::history) module for more information.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct HistoryResources<A: Allocator = DefaultAllocator> {
+    #[derivative(Debug = "ignore")]
+    device: Device,
+    resources: Vec<HistoryResource<A>>,
+    // Images replaced after a resize may still be in use by frames in flight.
+    #[derivative(Debug = "ignore")]
+    retired: DeletionQueue<HistoryImages<A>>,
+    frame: usize,
+}
+
+impl<A: Allocator> HistoryResources<A> {
+    /// Create an empty set of history resources.
+    pub fn new(device: Device) -> Self {
+        Self {
+            device,
+            resources: vec![],
+            retired: DeletionQueue::new(FRAMES_IN_FLIGHT as u32 + 1),
+            frame: 0,
+        }
+    }
+
+    /// Keep the history of an image resource. Its images are created on the next call to [`HistoryResources::new_frame()`].
+    /// # Errors
+    /// * Fails if `resource` is not an image resource, or is itself a history resource.
+    /// * Fails if the history of a resource with the same name was already added.
+    pub fn add_image(&mut self, resource: &VirtualResource, info: HistoryImageInfo) -> Result<()> {
+        if resource.resource_type() != ResourceType::Image || resource.is_history() {
+            return Err(Error::Uncategorized("History resources must be image resources").into());
+        }
+        if self
+            .resources
+            .iter()
+            .any(|history| history.resource.name() == resource.name())
+        {
+            return Err(Error::Uncategorized("History of this resource was already added").into());
+        }
+        self.resources.push(HistoryResource {
+            resource: resource.clone(),
+            info,
+            images: None,
+            valid: false,
+        });
+        Ok(())
+    }
+
+    /// Advance to the next frame, swapping the current and history image of every resource. Images are (re)created
+    /// when they do not exist yet or their size relative to `reference_extent` changed, which invalidates their history.
+    /// Pass [`FrameManager::reference_extents()`](crate::FrameManager::reference_extents) to follow swapchain resizes and render scale changes.
+    /// This must be called exactly once per frame, before binding the resources with [`HistoryResources::bind()`].
+    /// # Errors
+    /// * Fails if creating an image or image view fails.
+    pub fn new_frame(&mut self, allocator: &mut A, reference_extent: impl Into<ReferenceExtents>) -> Result<()> {
+        let reference_extent = reference_extent.into();
+        self.retired.next_frame();
+        self.frame = self.frame.wrapping_add(1);
+        for history in &mut self.resources {
+            let extent = history.info.size.resolve(reference_extent);
+            match &history.images {
+                Some(images) if images.extent == extent => {
+                    history.valid = true;
+                    continue;
+                }
+                _ => {}
+            }
+            let info = ImageCreateInfo {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+                usage: history.info.usage,
+                format: history.info.format,
+                samples: vk::SampleCountFlags::TYPE_1,
+                mip_levels: 1,
+                layers: 1,
+                memory_type: MemoryType::GpuOnly,
+            };
+            let images = [
+                Image::new(self.device.clone(), allocator, info)?,
+                Image::new(self.device.clone(), allocator, info)?,
+            ];
+            let views = [
+                images[0].whole_view(history.info.aspect)?,
+                images[1].whole_view(history.info.aspect)?,
+            ];
+            let old = history.images.replace(HistoryImages {
+                views,
+                _images: images,
+                extent,
+            });
+            if let Some(old) = old {
+                self.retired.push(old);
+            }
+            history.valid = false;
+        }
+        Ok(())
+    }
+
+    /// Bind the image written this frame and the history image of every resource. History images without
+    /// valid contents are [invalidated](PhysicalResourceBindings::invalidate).
+    /// Resources whose images were not created yet are skipped.
+    pub fn bind(&self, bindings: &mut PhysicalResourceBindings) {
+        for history in &self.resources {
+            let Some(images) = &history.images else { continue };
+            let current = self.frame % 2;
+            let name = history.resource.history().name().to_owned();
+            bindings.bind_image(history.resource.name(), &images.views[current]);
+            bindings.bind_image(name.clone(), &images.views[1 - current]);
+            if !history.valid {
+                bindings.invalidate(name);
+            }
+        }
+    }
+
+    /// Discard the history of all resources for this frame, for example after a camera cut.
+    pub fn invalidate(&mut self) {
+        for history in &mut self.resources {
+            history.valid = false;
+        }
+    }
+
+    /// Returns true if the history of `resource` holds the contents of the previous frame.
+    pub fn is_valid(&self, resource: &VirtualResource) -> bool {
+        let name = resource.history_of().unwrap_or(resource.name());
+        self.resources
+            .iter()
+            .any(|history| history.resource.name() == name && history.valid)
+    }
+}
@@ -1,4 +1,4 @@
-//! The pass graph system is a powerful abstraction that allows you to automatically manage synchronization **within a single queue**,
+//! The pass graph system is a powerful abstraction that allows you to automatically manage synchronization between passes,
 //! and automatically transition image layouts based on usage.
 //!
 //! Each pass needs to declare its inputs and outputs, and then the graph
@@ -8,6 +8,19 @@
 //! Actual resources need to be bound to each virtual resource before recording the graph into a command buffer.
 //! This is done using the [`PhysicalResourceBindings`](crate::PhysicalResourceBindings) struct.
 //!
+//! Images and buffers that only live for the duration of the graph can also be owned by the graph itself, see the [`transient`] module.
+//! Images that are read again in the next frame, for example for temporal anti-aliasing, are managed by the [`history`] module.
+//!
+//! Copies, blits, clears and fills can be added as passes of their own, see the [`transfer`] module.
+//!
+//! Groups of passes that are used more than once, like a blur chain, can be packaged as a reusable [`subgraph`].
+//! Graphs can also be loaded from a description file, see the [`declarative`] module.
+//!
+//! Independent passes can be reordered to reduce the number of barriers, see the [`ordering`] module. Barriers between distant passes
+//! are split into event signals and waits, see the [`split_barrier`] module.
+//!
+//! Passes can also execute on other queues, such as an async compute queue. See the [`multi_queue`] module.
+//!
 //! Through the [`GraphViz`](task_graph::GraphViz) trait, it's possible to export a graphviz-compatible dot file to display the task graph.
 //!
 //! # Example
@@ -49,12 +62,27 @@
 //! let final_cmd = graph.record(cmd, &bindings, &mut pool, Some(debug_messenger))?
 //!                 .finish();
 //! ```
+//!
+//! Before recording, a graph can be checked against its bindings with [`BuiltPassGraph::validate()`](crate::graph::pass_graph::BuiltPassGraph::validate).
+//! See the [`validate`] module for the list of checks.
 
+pub mod cache;
+pub mod declarative;
+pub mod export;
+pub mod history;
+pub mod multi_queue;
+pub mod ordering;
 pub mod pass;
 pub mod pass_graph;
 pub mod physical_resource;
+pub mod profiler;
 pub mod record;
 pub mod resource;
+pub mod split_barrier;
+pub mod subgraph;
+pub mod transfer;
+pub mod transient;
+pub mod validate;
 pub mod virtual_resource;
 
 pub(crate) mod task_graph;
@@ -0,0 +1,355 @@
+//! Splits pass graphs with passes on multiple queues into batches that can be submitted separately.
+//!
+//! Passes can be moved to another queue using [`PassBuilder::on_domain()`](crate::PassBuilder::on_domain), for example
+//! to run a compute pass on an async compute queue. When the graph is built, passes are grouped into [`QueueBatch`]es.
+//! Every batch is recorded into its own command buffer and waits on the batches it depends on using semaphores.
+//! Exclusive images that cross a queue boundary additionally get a queue family ownership transfer, consisting of a
+//! release barrier in the producing batch and an acquire barrier in the consuming batch.
+//!
+//! Frames recorded with the same graph are ordered as well. The first batch on every queue other than the queue of the domain waits
+//! on the last batch on the queue of the domain of the previous frame, see [`QueueBatch::previous_frame_wait()`]. Images used first
+//! on another queue than the queue that used them last in the previous frame are released at the end of the frame, and acquired
+//! again when the next frame starts.
+//!
+//! # Example
+//!
+//! ```
+//! use phobos::prelude::*;
+//!
+//! let particles = VirtualResource::buffer("particles");
+//! let swapchain = VirtualResource::image("swapchain");
+//! // Simulate particles on the async compute queue
+//! let simulate = PassBuilder::new("simulate")
+//!     .on_domain::<domain::Compute>()
+//!     .write_buffer(&particles, BufferUsage::Storage, PipelineStage::COMPUTE_SHADER)?
+//!     .build();
+//! // And render them on the graphics queue
+//! let render = PassBuilder::render("render")
+//!     .clear_color_attachment(&swapchain, ClearColor::Float([0.0, 0.0, 0.0, 1.0]))?
+//!     .read_buffer(simulate.output(&particles).unwrap(), BufferUsage::Vertex, PipelineStage::VERTEX_SHADER)?
+//!     .build();
+//! let present = PassBuilder::present("present", render.output(&swapchain).unwrap());
+//! let mut graph = PassGraph::<domain::All>::new()
+//!     .add_pass(simulate)?
+//!     .add_pass(render)?
+//!     .add_pass(present)?
+//!     .build()?;
+//!
+//! let mut batch = exec.start_submit_batch()?;
+//! graph.record_to_batch(&exec, &mut batch, &bindings, &mut local_pool, None, &mut ())?;
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use petgraph::graph::NodeIndex;
+use petgraph::{Incoming, Outgoing};
+
+use crate::command_buffer::command_log::CommandLog;
+use crate::graph::pass_graph::PassGraphInner;
+use crate::graph::resource::ResourceType;
+use crate::graph::task_graph::Node;
+use crate::pipeline::PipelineStage;
+use crate::sync::domain::ExecutionDomain;
+use crate::{Allocator, QueueType, Semaphore};
+
+/// A node recorded into the command buffer of a queue batch.
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum BatchNode {
+    /// A pass, or a barrier within a single queue.
+    Node(NodeIndex),
+    /// Release ownership of the resource of a barrier to another queue.
+    Release {
+        barrier: NodeIndex,
+        dst_queue: QueueType,
+    },
+    /// Acquire ownership of the resource of a barrier from another queue.
+    Acquire {
+        barrier: NodeIndex,
+        src_queue: QueueType,
+    },
+    /// Release ownership of the resource of a barrier after the source node to the queue using it first in the next frame.
+    FrameRelease {
+        barrier: NodeIndex,
+        dst_queue: QueueType,
+        // History resources swap images every frame, so the image of the resource is released under the name of its history,
+        // or the other way around.
+        swapped: bool,
+    },
+    /// Acquire ownership of the resource of a barrier after the source node, released by `src_queue` in the previous frame.
+    /// The first frame recorded with a graph has nothing to acquire, so it records the barrier like [`BatchNode::Node`].
+    FrameAcquire {
+        barrier: NodeIndex,
+        src_queue: QueueType,
+    },
+}
+
+/// A group of passes that is recorded into a single command buffer and submitted to one queue.
+#[derive(Debug, Clone)]
+pub struct QueueBatch {
+    pub(crate) queue: QueueType,
+    pub(crate) passes: Vec<String>,
+    pub(crate) waits: Vec<(usize, PipelineStage)>,
+    pub(crate) previous_frame_wait: Option<PipelineStage>,
+    pub(crate) nodes: Vec<BatchNode>,
+}
+
+impl QueueBatch {
+    fn new(queue: QueueType) -> Self {
+        Self {
+            queue,
+            passes: vec![],
+            waits: vec![],
+            previous_frame_wait: None,
+            nodes: vec![],
+        }
+    }
+
+    fn add_wait(&mut self, batch: usize, stage: PipelineStage) {
+        match self.waits.iter_mut().find(|(index, _)| *index == batch) {
+            None => self.waits.push((batch, stage)),
+            Some((_, wait_stage)) => *wait_stage |= stage,
+        }
+    }
+
+    /// Get the type of queue this batch is submitted to.
+    pub fn queue(&self) -> QueueType {
+        self.queue
+    }
+
+    /// Get the names of all passes in this batch, in recording order.
+    pub fn passes(&self) -> &[String] {
+        &self.passes
+    }
+
+    /// Get the batches this batch waits on, as an index into the list of batches together with the wait stage.
+    pub fn waits(&self) -> &[(usize, PipelineStage)] {
+        &self.waits
+    }
+
+    /// Get the stage at which this batch waits on the last batch on the queue of the domain from the previous frame, if it does.
+    /// The first batch on every other queue waits, so it does not overwrite resources still in use by the previous frame.
+    pub fn previous_frame_wait(&self) -> Option<PipelineStage> {
+        self.previous_frame_wait
+    }
+}
+
+/// A queue batch recorded to a command log with [`BuiltPassGraph::record_to_logs()`](crate::graph::pass_graph::BuiltPassGraph::record_to_logs).
+#[derive(Debug)]
+pub struct LoggedQueueBatch {
+    /// Type of queue the batch is submitted to.
+    pub queue: QueueType,
+    /// Commands recorded for the batch.
+    pub log: CommandLog,
+    /// Frame number of the previous frame this batch waits for, and the wait stage. See [`QueueBatch::previous_frame_wait()`].
+    pub previous_frame_wait: Option<(u64, PipelineStage)>,
+    /// Frame number this batch signals when it completes. Only the last batch on the queue of the domain signals.
+    pub frame_signal: Option<u64>,
+}
+
+/// Orders the queue batches of consecutive frames recorded with the same graph.
+#[derive(Debug, Default)]
+pub(crate) struct FrameSync {
+    // Set to the number of its frame by the last batch on the queue of the domain. Created when first recording to a submit batch.
+    pub(crate) timeline: Option<Arc<Semaphore>>,
+    // Number of frames recorded so far.
+    pub(crate) frame: u64,
+}
+
+/// Frame number a queue batch waits for with its wait stage, and the frame number it signals.
+pub(crate) type FrameTimeline = (Option<(u64, PipelineStage)>, Option<u64>);
+
+/// Get the frame number a batch waits for on the timeline of a graph, and the frame number it signals, when recording `frame`.
+pub(crate) fn frame_timeline<D: ExecutionDomain>(batches: &[QueueBatch], index: usize, frame: u64) -> FrameTimeline {
+    // The first frame has no previous frame to wait for.
+    let wait = batches[index]
+        .previous_frame_wait
+        .filter(|_| frame > 1)
+        .map(|stage| (frame - 1, stage));
+    let signal = (batches
+        .iter()
+        .rposition(|batch| batch.queue == D::QUEUE_TYPE)
+        == Some(index))
+    .then_some(frame);
+    (wait, signal)
+}
+
+/// Find the task producing the resource that flows into `node`. For barriers this is the task before the barrier.
+fn producer<D: ExecutionDomain, U, A: Allocator>(
+    graph: &PassGraphInner<'_, D, U, A>,
+    node: NodeIndex,
+) -> Option<NodeIndex> {
+    match graph.node_weight(node)? {
+        Node::Task(_) => Some(node),
+        Node::Barrier(_) => graph.neighbors_directed(node, Incoming).next(),
+        Node::_Unreachable(_) => unreachable!(),
+    }
+}
+
+/// Split a built graph into queue batches. Batches are ordered so that every batch only waits on earlier batches,
+/// and passes on the same queue stay in a single batch whenever their dependencies allow it.
+pub(crate) fn plan_queue_batches<D: ExecutionDomain, U, A: Allocator>(
+    graph: &PassGraphInner<'_, D, U, A>,
+    source: NodeIndex,
+    order: &[NodeIndex],
+) -> Result<Vec<QueueBatch>> {
+    let mut batches: Vec<QueueBatch> = Vec::new();
+    let mut assigned: HashMap<NodeIndex, usize> = HashMap::new();
+
+    // Assign each pass to a batch. A pass must be in a later batch than every pass it depends on from another queue,
+    // so it can wait on it with a semaphore.
+    for &node in order {
+        if node == source {
+            continue;
+        }
+        let Some(Node::Task(task)) = graph.node_weight(node) else { continue };
+        let mut required = 0;
+        for parent in graph.neighbors_directed(node, Incoming) {
+            let Some(producer) = producer(graph, parent) else { continue };
+            if producer == source {
+                continue;
+            }
+            let batch = assigned[&producer];
+            if batches[batch].queue == task.queue {
+                required = required.max(batch);
+            } else {
+                required = required.max(batch + 1);
+            }
+        }
+
+        let index = match batches
+            .iter()
+            .rposition(|batch| batch.queue == task.queue)
+            .filter(|&index| index >= required)
+        {
+            Some(index) => index,
+            None => {
+                batches.push(QueueBatch::new(task.queue));
+                batches.len() - 1
+            }
+        };
+        batches[index].passes.push(task.identifier.clone());
+        assigned.insert(node, index);
+    }
+
+    // Now distribute all nodes over the batches in recording order.
+    for &node in order {
+        match graph.node_weight(node).unwrap() {
+            Node::Task(_) => {
+                if let Some(&batch) = assigned.get(&node) {
+                    batches[batch].nodes.push(BatchNode::Node(node));
+                }
+            }
+            Node::Barrier(barrier) => {
+                // All consumers of a barrier are on the same queue, this is checked when merging barriers.
+                let Some(dst_batch) = graph
+                    .neighbors_directed(node, Outgoing)
+                    .filter_map(|consumer| assigned.get(&consumer).copied())
+                    .min() else { continue };
+                let dst_queue = batches[dst_batch].queue;
+                let producer = producer(graph, node).unwrap();
+                match assigned.get(&producer).copied() {
+                    Some(src_batch) if batches[src_batch].queue != dst_queue => {
+                        let src_queue = batches[src_batch].queue;
+                        batches[src_batch].nodes.push(BatchNode::Release {
+                            barrier: node,
+                            dst_queue,
+                        });
+                        batches[dst_batch].nodes.push(BatchNode::Acquire {
+                            barrier: node,
+                            src_queue,
+                        });
+                        batches[dst_batch].add_wait(src_batch, barrier.dst_stage);
+                    }
+                    _ => batches[dst_batch].nodes.push(BatchNode::Node(node)),
+                }
+            }
+            Node::_Unreachable(_) => unreachable!(),
+        }
+    }
+
+    plan_frame_transfers(graph, source, order, &assigned, &mut batches);
+    // Batches on other queues are only ordered after the previous frame's batches on the queue of the domain through this wait.
+    // The other way around, `SubmitBatch::finish()` joins every submit nothing waits on into the final submit on the queue of the domain,
+    // which orders the next frame after them.
+    // Barriers at the start of a batch chain to the wait with stages of the previous frame, so it waits for all commands.
+    if batches.iter().any(|batch| batch.queue == D::QUEUE_TYPE) {
+        for index in 0..batches.len() {
+            let queue = batches[index].queue;
+            if queue != D::QUEUE_TYPE && batches[..index].iter().all(|batch| batch.queue != queue) {
+                batches[index].previous_frame_wait = Some(PipelineStage::ALL_COMMANDS);
+            }
+        }
+    }
+
+    Ok(batches)
+}
+
+/// Transfer ownership of images the next frame first uses on another queue than the queue that used them last in this frame.
+/// The last batch on the queue using the image last releases it, and the barrier after the source node acquires it in the next frame.
+/// Only transfers between the queue of the domain and another queue are planned, since these are the only ones ordered across frames.
+fn plan_frame_transfers<D: ExecutionDomain, U, A: Allocator>(
+    graph: &PassGraphInner<'_, D, U, A>,
+    source: NodeIndex,
+    order: &[NodeIndex],
+    assigned: &HashMap<NodeIndex, usize>,
+    batches: &mut [QueueBatch],
+) {
+    for barrier in graph.neighbors_directed(source, Outgoing) {
+        let Some(Node::Barrier(resource)) = graph.node_weight(barrier) else { continue };
+        let resource = &resource.resource.resource;
+        if resource.resource_type() != ResourceType::Image {
+            continue;
+        }
+        let Some(dst_batch) = graph
+            .neighbors_directed(barrier, Outgoing)
+            .filter_map(|consumer| assigned.get(&consumer).copied())
+            .min() else { continue };
+        // History resources swap images every frame: the history is the resource of the previous frame, and the other way around.
+        let history = resource.history();
+        let (name, swapped) = match resource.history_of() {
+            Some(name) => (name, true),
+            None if last_user(graph, order, history.name()).is_some() => (history.name(), true),
+            None => (resource.name(), false),
+        };
+        let Some(src_batch) = last_user(graph, order, name).and_then(|node| assigned.get(&node).copied()) else { continue };
+        let (src_queue, dst_queue) = (batches[src_batch].queue, batches[dst_batch].queue);
+        if src_queue == dst_queue || (src_queue != D::QUEUE_TYPE && dst_queue != D::QUEUE_TYPE) {
+            continue;
+        }
+        let last = batches
+            .iter()
+            .rposition(|batch| batch.queue == src_queue)
+            .unwrap();
+        batches[last].nodes.push(BatchNode::FrameRelease {
+            barrier,
+            dst_queue,
+            swapped,
+        });
+        for node in &mut batches[dst_batch].nodes {
+            if matches!(node, BatchNode::Node(node) if *node == barrier) {
+                *node = BatchNode::FrameAcquire {
+                    barrier,
+                    src_queue,
+                };
+            }
+        }
+    }
+}
+
+/// Find the last task in `order` using the resource with the given name.
+fn last_user<D: ExecutionDomain, U, A: Allocator>(
+    graph: &PassGraphInner<'_, D, U, A>,
+    order: &[NodeIndex],
+    name: &str,
+) -> Option<NodeIndex> {
+    order.iter().rev().copied().find(|&node| match graph.node_weight(node) {
+        Some(Node::Task(task)) => task
+            .inputs
+            .iter()
+            .chain(&task.outputs)
+            .any(|used| used.resource.name() == name),
+        _ => false,
+    })
+}
@@ -0,0 +1,262 @@
+//! Strategies for ordering the passes of a pass graph.
+//!
+//! Passes are always recorded in an order that respects their dependencies, but independent passes can be recorded in any order.
+//! The order determines where barriers are recorded: every barrier is recorded after the pass producing its resource and before the
+//! first pass consuming it, and barriers that are recorded at the same point are batched into a single `vkCmdPipelineBarrier2` call.
+//! By default, a graph is recorded level by level: all passes whose dependencies are recorded are recorded together, after a single
+//! barrier for all of them. This is [`PassOrdering::Traversal`].
+//!
+//! [`PassOrdering::MinimizeBarriers`] instead postpones barriers for as long as there is other work that does not need a new barrier.
+//! Passes reading a resource that was already transitioned are recorded right away, so reads of the same resource are grouped together,
+//! and independent work is recorded between producers and consumers so the GPU can overlap it with the producer.
+//!
+//! The chosen order is available through [`BuiltPassGraph::pass_order()`], and its estimated cost through [`BuiltPassGraph::order_cost()`].
+//!
+//! # Example
+//! ```
+//! use phobos::prelude::*;
+//! use phobos::graph::ordering::PassOrdering;
+//!
+//! let graph = graph.pass_ordering(PassOrdering::MinimizeBarriers).build()?;
+//! println!("Recording {:?} with {} barrier(s)", graph.pass_order(), graph.order_cost().pipeline_barriers);
+//! ```
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use petgraph::graph::NodeIndex;
+use petgraph::{Incoming, Outgoing};
+
+use crate::{Allocator, Error, PassGraph};
+use crate::graph::pass_graph::{BuiltPassGraph, PassGraphInner};
+use crate::graph::record::{barrier_kind, written_before, BarrierKind};
+use crate::graph::task_graph::Node;
+use crate::sync::domain::ExecutionDomain;
+
+/// Strategy used to order the passes of a graph while building it. See the [`ordering`](crate::graph::ordering) module.
+#[derive(Debug, Default, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum PassOrdering {
+    /// Record the graph level by level, batching the barriers of every level.
+    #[default]
+    Traversal,
+    /// Record passes that do not need a new barrier first, and only record a barrier when no such pass is left.
+    MinimizeBarriers,
+}
+
+/// Estimated cost of the order a graph is recorded in.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct OrderCost {
+    /// Number of `vkCmdPipelineBarrier2` calls.
+    pub pipeline_barriers: usize,
+    /// Number of barriers recorded directly after the pass producing their resource. These wait for that pass
+    /// without any independent work to overlap with it.
+    pub stalls: usize,
+}
+
+impl OrderCost {
+    /// Total cost, weighing every pipeline barrier and every stall equally.
+    pub fn total(&self) -> usize {
+        self.pipeline_barriers + self.stalls
+    }
+}
+
+/// Get the task before a barrier node.
+fn producer<D: ExecutionDomain, U, A: Allocator>(
+    graph: &PassGraphInner<'_, D, U, A>,
+    node: NodeIndex,
+) -> Option<NodeIndex> {
+    graph.neighbors_directed(node, Incoming).next()
+}
+
+fn is_barrier<D: ExecutionDomain, U, A: Allocator>(graph: &PassGraphInner<'_, D, U, A>, node: NodeIndex) -> bool {
+    matches!(graph.node_weight(node), Some(Node::Barrier(_)))
+}
+
+/// Length of the longest chain of passes starting at each node.
+fn critical_path<D: ExecutionDomain, U, A: Allocator>(
+    graph: &PassGraphInner<'_, D, U, A>,
+) -> Result<HashMap<NodeIndex, usize>> {
+    let order = petgraph::algo::toposort(graph, None).map_err(|_| Error::GraphHasCycle)?;
+    let mut length = HashMap::new();
+    for &node in order.iter().rev() {
+        let longest = graph
+            .neighbors_directed(node, Outgoing)
+            .map(|child| length[&child])
+            .max()
+            .unwrap_or(0);
+        length.insert(node, longest + !is_barrier(graph, node) as usize);
+    }
+    Ok(length)
+}
+
+/// Order the nodes of a graph using [`PassOrdering::MinimizeBarriers`]. Like [`traversal_steps()`](crate::graph::record::traversal_steps),
+/// nodes are grouped in steps that start with the barriers recorded before the passes of the step.
+pub(crate) fn minimize_barriers<D: ExecutionDomain, U, A: Allocator>(
+    graph: &PassGraph<'_, D, U, A>,
+) -> Result<Vec<Vec<NodeIndex>>> {
+    let inner = &graph.task_graph().graph;
+    let source = graph.source();
+    let mut skipped = HashSet::new();
+    for node in inner.node_indices() {
+        if let Some(Node::Barrier(barrier)) = inner.node_weight(node) {
+            let dst_resource = PassGraph::<D, U, A>::barrier_dst_resource(inner, node)?;
+            if barrier_kind(barrier, dst_resource, written_before(graph, node)) == BarrierKind::Skip {
+                skipped.insert(node);
+            }
+        }
+    }
+    let critical_path = critical_path(inner)?;
+    let pass_index = |node: NodeIndex| match inner.node_weight(node) {
+        Some(Node::Task(task)) => task.pass_index,
+        _ => None,
+    };
+
+    let mut recorded = HashSet::from([source]);
+    let mut steps = vec![vec![source]];
+    let mut step = Vec::new();
+    let mut last = None;
+    loop {
+        // A pass can be recorded once the passes before its barriers are recorded.
+        let ready = inner
+            .node_indices()
+            .filter(|node| !recorded.contains(node) && !is_barrier(inner, *node))
+            .filter(|&node| {
+                inner.neighbors_directed(node, Incoming).all(|parent| match is_barrier(inner, parent) {
+                    true => producer(inner, parent).is_some_and(|task| recorded.contains(&task)),
+                    false => recorded.contains(&parent),
+                })
+            })
+            .collect::<Vec<_>>();
+        if ready.is_empty() {
+            break;
+        }
+
+        let pending = |node: NodeIndex| {
+            inner
+                .neighbors_directed(node, Incoming)
+                .filter(|parent| !recorded.contains(parent) && !skipped.contains(parent))
+                .collect::<Vec<_>>()
+        };
+        // Passes sharing a barrier with the previous pass read the same resource, so record these next.
+        let shares_barrier = |node: NodeIndex| {
+            last.is_some_and(|last| {
+                inner
+                    .neighbors_directed(node, Incoming)
+                    .any(|parent| !skipped.contains(&parent) && inner.find_edge(parent, last).is_some())
+            })
+        };
+        let (free, blocked): (Vec<_>, Vec<_>) = ready.iter().partition(|&&node| pending(node).is_empty());
+        let best = |candidates: &mut dyn Iterator<Item = NodeIndex>| {
+            candidates.min_by_key(|&node| (!shares_barrier(node), Reverse(critical_path[&node]), pass_index(node)))
+        };
+        // Passes without consumers never make other passes ready, so these are kept to fill the gap between a producer
+        // and the barrier after it.
+        let stall = blocked
+            .iter()
+            .flat_map(|&node| pending(node))
+            .any(|barrier| last.is_some() && producer(inner, barrier) == last);
+        let next = match best(&mut free.iter().copied().filter(|node| critical_path[node] > 1)) {
+            Some(node) => Some(node),
+            None if blocked.is_empty() || stall => best(&mut free.iter().copied()),
+            None => None,
+        };
+        match next {
+            Some(node) => {
+                // Barriers without synchronization still need to be recorded before the pass. Their producer may be recorded
+                // earlier in this step, so they are placed right before the pass consuming them.
+                let mut barriers = inner
+                    .neighbors_directed(node, Incoming)
+                    .filter(|parent| !recorded.contains(parent))
+                    .collect::<Vec<_>>();
+                barriers.sort();
+                recorded.extend(barriers.iter().copied());
+                step.extend(barriers);
+                step.push(node);
+                recorded.insert(node);
+                last = Some(node);
+            }
+            None => {
+                // Record the barriers of all remaining ready passes at once.
+                let mut barriers = blocked.iter().flat_map(|&node| pending(node)).collect::<Vec<_>>();
+                barriers.sort();
+                barriers.dedup();
+                recorded.extend(barriers.iter().copied());
+                if !step.is_empty() {
+                    steps.push(std::mem::take(&mut step));
+                }
+                step = barriers;
+            }
+        }
+    }
+    if inner.node_indices().any(|node| !recorded.contains(&node)) {
+        return Err(Error::GraphHasCycle.into());
+    }
+    if !step.is_empty() {
+        steps.push(step);
+    }
+    Ok(steps)
+}
+
+/// Estimate the cost of recording the nodes of a graph in the given steps.
+pub(crate) fn order_cost<D: ExecutionDomain, U, A: Allocator>(
+    graph: &PassGraph<'_, D, U, A>,
+    steps: &[Vec<NodeIndex>],
+) -> Result<OrderCost> {
+    let inner = &graph.task_graph().graph;
+    let mut cost = OrderCost::default();
+    let mut pending = false;
+    let mut last = None;
+    for &node in steps.iter().flatten() {
+        match inner.node_weight(node) {
+            Some(Node::Barrier(barrier)) => {
+                let dst_resource = PassGraph::<D, U, A>::barrier_dst_resource(inner, node)?;
+                if barrier_kind(barrier, dst_resource, written_before(graph, node)) == BarrierKind::Skip {
+                    continue;
+                }
+                pending = true;
+                if last.is_some() && producer(inner, node) == last {
+                    cost.stalls += 1;
+                }
+            }
+            Some(Node::Task(_)) => {
+                cost.pipeline_barriers += pending as usize;
+                pending = false;
+                if node != graph.source() {
+                    last = Some(node);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(cost)
+}
+
+impl<'cb, D: ExecutionDomain, U, A: Allocator> PassGraph<'cb, D, U, A> {
+    /// Set the strategy used to order passes when building this graph. The default is [`PassOrdering::Traversal`].
+    pub fn pass_ordering(mut self, ordering: PassOrdering) -> Self {
+        self.ordering = ordering;
+        self
+    }
+}
+
+impl<'cb, D: ExecutionDomain, U, A: Allocator> BuiltPassGraph<'cb, D, U, A> {
+    /// Get the names of all passes, in the order they are recorded.
+    pub fn pass_order(&self) -> Vec<&str> {
+        let graph = &self.task_graph().graph;
+        self.steps()
+            .iter()
+            .flatten()
+            .filter(|&&node| node != self.source())
+            .filter_map(|&node| match graph.node_weight(node) {
+                Some(Node::Task(task)) => Some(task.identifier.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Get the estimated cost of the order this graph is recorded in.
+    pub fn order_cost(&self) -> OrderCost {
+        self.order_cost
+    }
+}
@@ -76,7 +76,9 @@ use anyhow::{anyhow, bail};
 use anyhow::Result;
 use ash::vk;
 
-use crate::{Allocator, DefaultAllocator, Error, PhysicalResourceBindings, VirtualResource};
+use crate::{
+    Allocator, DefaultAllocator, Error, PhysicalResourceBindings, QueueType, VirtualResource,
+};
 #[cfg(feature = "fsr2")]
 use crate::{ComputeSupport, Device, ImageView};
 use crate::command_buffer::IncompleteCommandBuffer;
@@ -85,12 +87,14 @@ use crate::fsr2::{Fsr2DispatchDescription, Fsr2DispatchResources};
 use crate::graph::pass_graph::PassResource;
 #[cfg(feature = "fsr2")]
 use crate::graph::physical_resource::PhysicalResource;
-use crate::graph::resource::{AttachmentType, ResourceUsage};
+use crate::graph::resource::{AttachmentType, BufferUsage, ResourceType, ResourceUsage};
 use crate::pipeline::PipelineStage;
 use crate::pool::LocalPool;
 use crate::sync::domain::ExecutionDomain;
 use crate::traits::GfxSupport;
 use crate::util::to_vk::IntoVulkanType;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// The returned value from a pass callback function.
 pub type PassFnResult<'q, D, A> = Result<IncompleteCommandBuffer<'q, D, A>>;
@@ -131,6 +135,29 @@ where
 }
 
 pub(crate) type BoxedPassFn<'cb, D, U, A> = Box<dyn PassExecutor<D, U, A> + 'cb>;
+pub(crate) type SendPassFn<'cb, D, U, A> = Box<dyn PassExecutor<D, U, A> + Send + 'cb>;
+pub(crate) type PassCondition<'cb, U> = Box<dyn Fn(&U) -> bool + 'cb>;
+
+/// The executor of a pass. Executors that are `Send` can be recorded on another thread.
+pub(crate) enum PassFn<'cb, D: ExecutionDomain, U, A: Allocator> {
+    Local(BoxedPassFn<'cb, D, U, A>),
+    Send(SendPassFn<'cb, D, U, A>),
+}
+
+impl<'cb, D: ExecutionDomain, U, A: Allocator> PassFn<'cb, D, U, A> {
+    /// An executor that does not record anything.
+    pub(crate) fn empty() -> Self {
+        PassFn::Local(EmptyPassExecutor::new_boxed())
+    }
+
+    /// Get the executor.
+    pub(crate) fn executor(&mut self) -> &mut (dyn PassExecutor<D, U, A> + 'cb) {
+        match self {
+            PassFn::Local(exec) => exec.as_mut(),
+            PassFn::Send(exec) => exec.as_mut(),
+        }
+    }
+}
 
 /// An empty pass executor that does nothing
 pub struct EmptyPassExecutor;
@@ -169,13 +196,22 @@ pub struct Pass<'cb, D: ExecutionDomain, U = (), A: Allocator = DefaultAllocator
     pub(crate) inputs: Vec<PassResource>,
     pub(crate) outputs: Vec<PassResource>,
     #[derivative(Debug = "ignore")]
-    pub(crate) execute: BoxedPassFn<'cb, D, U, A>,
+    pub(crate) execute: PassFn<'cb, D, U, A>,
     pub(crate) is_renderpass: bool,
+    pub(crate) queue: QueueType,
+    #[derivative(Debug = "ignore")]
+    pub(crate) condition: Option<PassCondition<'cb, U>>,
+    // Names of resources as seen by the executor, and the names they were renamed to by a subgraph instance.
+    pub(crate) aliases: Vec<(String, String)>,
+    // Views rendered with multiview, and the number of layers rendered with layered rendering.
+    pub(crate) view_mask: u32,
+    pub(crate) layers: u32,
 }
 
 /// Represents a clear color for an attachment. The variant used should match
 /// the type of the attachment.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "snake_case"))]
 pub enum ClearColor {
     /// Clear color for a floating point attachment
     Float([f32; 4]),
@@ -186,7 +222,8 @@ pub enum ClearColor {
 }
 
 /// Represents a clear value for Depth/Stencil attachmemts.
-#[derive(Copy, Clone, Default, Debug)]
+#[derive(Copy, Clone, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ClearDepthStencil {
     /// Depth buffer clear value
     pub depth: f32,
@@ -194,6 +231,30 @@ pub struct ClearDepthStencil {
     pub stencil: u32,
 }
 
+/// Load and store operations of an attachment, or of one aspect of a depth-stencil attachment.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AttachmentOps {
+    /// How the contents of the attachment are initialized at the start of the pass.
+    pub load: vk::AttachmentLoadOp,
+    /// How the contents of the attachment are written back at the end of the pass.
+    pub store: vk::AttachmentStoreOp,
+}
+
+impl AttachmentOps {
+    /// Load the previous contents and store the result.
+    pub const LOAD: Self = Self::new(vk::AttachmentLoadOp::LOAD, vk::AttachmentStoreOp::STORE);
+    /// Clear the attachment and store the result.
+    pub const CLEAR: Self = Self::new(vk::AttachmentLoadOp::CLEAR, vk::AttachmentStoreOp::STORE);
+
+    /// Create a new set of attachment operations.
+    pub const fn new(load: vk::AttachmentLoadOp, store: vk::AttachmentStoreOp) -> Self {
+        Self {
+            load,
+            store,
+        }
+    }
+}
+
 impl IntoVulkanType for ClearColor {
     type Output = vk::ClearColorValue;
 
@@ -223,6 +284,21 @@ impl IntoVulkanType for ClearDepthStencil {
     }
 }
 
+fn buffer_stage(usage: BufferUsage, stage: PipelineStage) -> PipelineStage {
+    match usage.implied_stage() {
+        None => stage,
+        Some(implied) => implied | stage,
+    }
+}
+
+// Buffers have no layout.
+fn transfer_layout(resource: &VirtualResource, layout: vk::ImageLayout) -> vk::ImageLayout {
+    match resource.resource_type() {
+        ResourceType::Image => layout,
+        ResourceType::Buffer => vk::ImageLayout::UNDEFINED,
+    }
+}
+
 /// Used to create [`Pass`] objects correctly.
 /// # Example
 /// See the [`pass`](crate::graph::pass) module level documentation.
@@ -249,6 +325,11 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> Pass<'cb, D, U, A> {
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    /// Get the type of queue this pass executes on.
+    pub fn queue(&self) -> QueueType {
+        self.queue
+    }
 }
 
 impl<'cb, D: ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D, U, A> {
@@ -258,10 +339,15 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D, U, A> {
             inner: Pass {
                 name: name.into(),
                 color: None,
-                execute: EmptyPassExecutor::new_boxed(),
+                execute: PassFn::empty(),
                 inputs: vec![],
                 outputs: vec![],
                 is_renderpass: false,
+                queue: D::QUEUE_TYPE,
+                condition: None,
+                aliases: vec![],
+                view_mask: 0,
+                layers: 1,
             },
         }
     }
@@ -272,10 +358,15 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D, U, A> {
             inner: Pass {
                 name: name.into(),
                 color: None,
-                execute: EmptyPassExecutor::new_boxed(),
+                execute: PassFn::empty(),
                 inputs: vec![],
                 outputs: vec![],
                 is_renderpass: true,
+                queue: D::QUEUE_TYPE,
+                condition: None,
+                aliases: vec![],
+                view_mask: 0,
+                layers: 1,
             },
         }
     }
@@ -294,10 +385,17 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D, U, A> {
                 layout: vk::ImageLayout::PRESENT_SRC_KHR,
                 clear_value: None,
                 load_op: None,
+                store_op: None,
+                stencil_ops: None,
             }],
             outputs: vec![],
-            execute: EmptyPassExecutor::new_boxed(),
+            execute: PassFn::empty(),
             is_renderpass: false,
+            queue: D::QUEUE_TYPE,
+            condition: None,
+            aliases: vec![],
+            view_mask: 0,
+            layers: 1,
         }
     }
 
@@ -310,6 +408,8 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D, U, A> {
             layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
             clear_value: None,
             load_op: None,
+            store_op: None,
+            stencil_ops: None,
         });
         self
     }
@@ -323,6 +423,8 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D, U, A> {
             layout: vk::ImageLayout::GENERAL,
             clear_value: None,
             load_op: None,
+            store_op: None,
+            stencil_ops: None,
         });
         self.inner.outputs.push(PassResource {
             usage: ResourceUsage::ShaderWrite,
@@ -331,6 +433,8 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D, U, A> {
             layout: vk::ImageLayout::GENERAL,
             clear_value: None,
             load_op: None,
+            store_op: None,
+            stencil_ops: None,
         });
         self
     }
@@ -344,10 +448,156 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D, U, A> {
             layout: vk::ImageLayout::GENERAL,
             clear_value: None,
             load_op: None,
+            store_op: None,
+            stencil_ops: None,
         });
         self
     }
 
+    /// Declare that the contents `resource` had at the end of the previous frame will be used as a sampled image in the
+    /// given pipeline stages. This reads the [`history`](VirtualResource::history) of the resource, which must be bound
+    /// to an image holding these contents, usually by [`HistoryResources`](crate::graph::history::HistoryResources).
+    /// The pass writing `resource` is never culled, since the next frame depends on it.
+    pub fn read_history(self, resource: &VirtualResource, stage: PipelineStage) -> Self {
+        self.sample_image(&resource.history(), stage)
+    }
+
+    /// Declare that a buffer will be read from with the given usage in the given pipeline stages.
+    /// For usages that happen in a fixed-function stage (vertex, index, indirect and transfer), this stage is
+    /// always added to `stage`.
+    /// # Errors
+    /// * Fails if `resource` is not a buffer resource.
+    pub fn read_buffer(
+        mut self,
+        resource: &VirtualResource,
+        usage: BufferUsage,
+        stage: PipelineStage,
+    ) -> Result<Self> {
+        if resource.resource_type() != ResourceType::Buffer {
+            return Err(Error::Uncategorized("Only buffers can be read with a buffer usage").into());
+        }
+        self.inner.inputs.push(PassResource {
+            usage: ResourceUsage::BufferRead(usage),
+            resource: resource.clone(),
+            stage: buffer_stage(usage, stage),
+            layout: vk::ImageLayout::UNDEFINED,
+            clear_value: None,
+            load_op: None,
+            store_op: None,
+            stencil_ops: None,
+        });
+        Ok(self)
+    }
+
+    /// Declare that a buffer will be written to with the given usage in the given pipeline stages.
+    /// # Errors
+    /// * Fails if `resource` is not a buffer resource.
+    /// * Fails if buffers with this usage cannot be written to. Only [`BufferUsage::Storage`] and [`BufferUsage::Transfer`]
+    ///   allow writes.
+    pub fn write_buffer(
+        self,
+        resource: &VirtualResource,
+        usage: BufferUsage,
+        stage: PipelineStage,
+    ) -> Result<Self> {
+        self.buffer_output(resource, ResourceUsage::BufferWrite(usage), usage, stage)
+    }
+
+    /// Declare that a buffer will be both read from and written to with the given usage in the given pipeline stages.
+    /// # Errors
+    /// * Fails if `resource` is not a buffer resource.
+    /// * Fails if buffers with this usage cannot be written to. Only [`BufferUsage::Storage`] and [`BufferUsage::Transfer`]
+    ///   allow writes.
+    pub fn read_write_buffer(
+        self,
+        resource: &VirtualResource,
+        usage: BufferUsage,
+        stage: PipelineStage,
+    ) -> Result<Self> {
+        self.buffer_output(resource, ResourceUsage::BufferReadWrite(usage), usage, stage)
+    }
+
+    /// Declare that an image or buffer will be read by a transfer command, such as a copy or blit.
+    /// Images are transitioned to `VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL`.
+    pub fn transfer_read(mut self, resource: &VirtualResource) -> Self {
+        self.inner.inputs.push(PassResource {
+            usage: ResourceUsage::TransferRead,
+            resource: resource.clone(),
+            stage: PipelineStage::TRANSFER,
+            layout: transfer_layout(resource, vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+            clear_value: None,
+            load_op: None,
+            store_op: None,
+            stencil_ops: None,
+        });
+        self
+    }
+
+    /// Declare that an image or buffer will be written by a transfer command, such as a copy, blit, clear or fill.
+    /// Images are transitioned to `VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL`. The previous contents of the resource are
+    /// assumed to be overwritten entirely.
+    pub fn transfer_write(mut self, resource: &VirtualResource) -> Self {
+        let layout = transfer_layout(resource, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+        self.inner.inputs.push(PassResource {
+            usage: ResourceUsage::TransferWrite,
+            resource: resource.clone(),
+            stage: PipelineStage::TRANSFER,
+            layout,
+            clear_value: None,
+            load_op: None,
+            store_op: None,
+            stencil_ops: None,
+        });
+        self.inner.outputs.push(PassResource {
+            usage: ResourceUsage::TransferWrite,
+            resource: resource.upgrade(),
+            stage: PipelineStage::TRANSFER,
+            layout,
+            clear_value: None,
+            load_op: None,
+            store_op: None,
+            stencil_ops: None,
+        });
+        self
+    }
+
+    fn buffer_output(
+        mut self,
+        resource: &VirtualResource,
+        resource_usage: ResourceUsage,
+        usage: BufferUsage,
+        stage: PipelineStage,
+    ) -> Result<Self> {
+        if resource.resource_type() != ResourceType::Buffer {
+            return Err(Error::Uncategorized("Only buffers can be written with a buffer usage").into());
+        }
+        if usage.write_access().is_none() {
+            return Err(Error::Uncategorized("Cannot write to a buffer with a read-only usage").into());
+        }
+        let stage = buffer_stage(usage, stage);
+        self.inner.inputs.push(PassResource {
+            usage: resource_usage.clone(),
+            resource: resource.clone(),
+            stage,
+            layout: vk::ImageLayout::UNDEFINED,
+            clear_value: None,
+            load_op: None,
+            store_op: None,
+            stencil_ops: None,
+        });
+        self.inner.outputs.push(PassResource {
+            usage: resource_usage,
+            resource: resource.upgrade(),
+            stage,
+            layout: vk::ImageLayout::UNDEFINED,
+            clear_value: None,
+            load_op: None,
+            store_op: None,
+            stencil_ops: None,
+        });
+        Ok(self)
+    }
+
     #[allow(dead_code)]
     fn sample_optional_image(
         self,
@@ -360,9 +610,35 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D, U, A> {
         }
     }
 
+    /// Execute this pass on the queue of another domain, for example to run a compute pass on an async compute queue.
+    /// The executor still receives a command buffer over the graph's domain, so it must only record commands supported by `E`.
+    /// Graphs with passes on multiple queues must be recorded using
+    /// [`BuiltPassGraph::record_to_batch()`](crate::graph::pass_graph::BuiltPassGraph::record_to_batch).
+    pub fn on_domain<E: ExecutionDomain>(mut self) -> Self {
+        self.inner.queue = E::QUEUE_TYPE;
+        self
+    }
+
+    /// Only record this pass if `condition` returns true. The condition is evaluated with the user data every time
+    /// the graph is recorded, so passes can be toggled without rebuilding the graph. The barriers around a skipped pass are still
+    /// recorded, so resources are in the same layout as if the pass executed. The contents of resources written by a skipped pass
+    /// are not modified.
+    pub fn enabled_if(mut self, condition: impl Fn(&U) -> bool + 'cb) -> Self {
+        self.inner.condition = Some(Box::new(condition));
+        self
+    }
+
     /// Set the executor to be called when recording this pass.
     pub fn executor(mut self, exec: impl PassExecutor<D, U, A> + 'cb) -> Self {
-        self.inner.execute = Box::new(exec);
+        self.inner.execute = PassFn::Local(Box::new(exec));
+        self
+    }
+
+    /// Set an executor that can be called from another thread when recording this pass. When the graph is recorded using
+    /// [`BuiltPassGraph::record_parallel()`](crate::graph::pass_graph::BuiltPassGraph::record_parallel), passes with such an
+    /// executor are recorded in parallel into secondary command buffers. Otherwise, this behaves like [`PassBuilder::executor()`].
+    pub fn parallel_executor(mut self, exec: impl PassExecutor<D, U, A> + Send + 'cb) -> Self {
+        self.inner.execute = PassFn::Send(Box::new(exec));
         self
     }
 
@@ -377,7 +653,23 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D, U, A> {
                 &mut U,
             ) -> PassFnResult<'q, D, A>
             + 'cb, {
-        self.inner.execute = Box::new(exec);
+        self.inner.execute = PassFn::Local(Box::new(exec));
+        self
+    }
+
+    /// Set an executor that can be called from another thread when recording this pass. This method can be used to deduce types
+    /// when a function is used as a pass executor. See also [`PassBuilder::parallel_executor()`].
+    pub fn parallel_execute_fn<F>(mut self, exec: F) -> Self
+    where
+        F: for<'q> FnMut(
+                IncompleteCommandBuffer<'q, D, A>,
+                &mut LocalPool<A>,
+                &PhysicalResourceBindings,
+                &mut U,
+            ) -> PassFnResult<'q, D, A>
+            + Send
+            + 'cb, {
+        self.inner.execute = PassFn::Send(Box::new(exec));
         self
     }
 
@@ -395,6 +687,39 @@ impl<'cb, D: GfxSupport + ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D,
         self
     }
 
+    /// Render to several views at once using `VK_KHR_multiview`. Every bit set in `view_mask` renders the pass into the array layer
+    /// with that index of each attachment, and is available to shaders as `gl_ViewIndex`. Pipelines bound in this pass are created with
+    /// the same view mask. Attachments must be bound to image views containing all these layers, which the graph transitions together.
+    /// # Errors
+    /// * Fails if this pass was not created using [`PassBuilder::render()`]
+    /// * Fails if `view_mask` is zero, or if layered rendering is already enabled with [`PassBuilder::layers()`].
+    pub fn view_mask(mut self, view_mask: u32) -> Result<Self> {
+        if !self.inner.is_renderpass {
+            return Err(Error::Uncategorized("Cannot use multiview in a pass that is not a renderpass").into());
+        }
+        if view_mask == 0 || self.inner.layers != 1 {
+            return Err(Error::Uncategorized("Multiview requires a non-zero view mask, and cannot be combined with layered rendering").into());
+        }
+        self.inner.view_mask = view_mask;
+        Ok(self)
+    }
+
+    /// Render into the first `layers` array layers of each attachment at once, with shaders selecting the layer to render to
+    /// using `gl_Layer`. Attachments must be bound to image views containing all these layers, which the graph transitions together.
+    /// # Errors
+    /// * Fails if this pass was not created using [`PassBuilder::render()`]
+    /// * Fails if `layers` is zero, or if multiview is already enabled with [`PassBuilder::view_mask()`].
+    pub fn layers(mut self, layers: u32) -> Result<Self> {
+        if !self.inner.is_renderpass {
+            return Err(Error::Uncategorized("Cannot use layered rendering in a pass that is not a renderpass").into());
+        }
+        if layers == 0 || self.inner.view_mask != 0 {
+            return Err(Error::Uncategorized("Layered rendering requires at least one layer, and cannot be combined with multiview").into());
+        }
+        self.inner.layers = layers;
+        Ok(self)
+    }
+
     /// Adds a color attachment to this pass. If [`vk::AttachmentLoadOp::CLEAR`] was specified, `clear` must not be None.
     /// # Errors
     /// * Fails if this pass was not created using [`PassBuilder::render()`]
@@ -428,6 +753,8 @@ impl<'cb, D: GfxSupport + ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D,
             layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
             clear_value: None,
             load_op: None,
+            store_op: None,
+            stencil_ops: None,
         });
 
         self.inner.outputs.push(PassResource {
@@ -439,6 +766,8 @@ impl<'cb, D: GfxSupport + ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D,
                 color: c,
             }),
             load_op: Some(op),
+            store_op: None,
+            stencil_ops: None,
         });
 
         Ok(self)
@@ -509,6 +838,8 @@ impl<'cb, D: GfxSupport + ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D,
             layout: vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
             clear_value: None,
             load_op: None,
+            store_op: None,
+            stencil_ops: None,
         });
 
         self.inner.outputs.push(PassResource {
@@ -522,11 +853,161 @@ impl<'cb, D: GfxSupport + ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D,
                 depth_stencil: c,
             }),
             load_op: Some(op),
+            store_op: None,
+            stencil_ops: None,
         });
 
         Ok(self)
     }
 
+    /// Adds a read-only depth attachment to this pass. The attachment is used for depth testing without being written to,
+    /// and stays in [`vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL`] so it can also be sampled in `sample_stage` during the same pass.
+    /// Bind it for sampling with [`IncompleteCommandBuffer::bind_sampled_image_in_layout()`].
+    /// # Errors
+    /// * Fails if this pass was not created using [`PassBuilder::render()`]
+    pub fn read_only_depth_attachment(
+        mut self,
+        resource: &VirtualResource,
+        sample_stage: Option<PipelineStage>,
+    ) -> Result<Self> {
+        if !self.inner.is_renderpass {
+            return Err(Error::Uncategorized(
+                "Cannot attach depth attachment to a pass that is not a renderpass",
+            )
+            .into());
+        }
+        // Depth tests read the attachment in both fragment test stages.
+        let stage = PipelineStage::EARLY_FRAGMENT_TESTS | PipelineStage::LATE_FRAGMENT_TESTS;
+        self.inner.inputs.push(PassResource {
+            usage: ResourceUsage::Attachment(AttachmentType::DepthReadOnly {
+                sampled: sample_stage.is_some(),
+            }),
+            resource: resource.clone(),
+            stage: stage | sample_stage.unwrap_or(PipelineStage::NONE),
+            layout: vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL,
+            clear_value: None,
+            load_op: Some(vk::AttachmentLoadOp::LOAD),
+            store_op: Some(vk::AttachmentStoreOp::NONE),
+            stencil_ops: None,
+        });
+        Ok(self)
+    }
+
+    /// Adds a stencil-only attachment to this pass. If [`vk::AttachmentLoadOp::CLEAR`] was specified, `clear` must not be None.
+    /// # Errors
+    /// * Fails if this pass was not created using [`PassBuilder::render()`]
+    /// * Fails if `ops.load` was [`vk::AttachmentLoadOp::CLEAR`], but `clear` was [`None`].
+    pub fn stencil_attachment(
+        mut self,
+        resource: &VirtualResource,
+        ops: AttachmentOps,
+        clear: Option<u32>,
+    ) -> Result<Self> {
+        let clear = clear.map(|stencil| vk::ClearDepthStencilValue {
+            depth: 0.0,
+            stencil,
+        });
+        self.push_depth_stencil(resource, AttachmentType::Stencil, ops, None, clear)?;
+        Ok(self)
+    }
+
+    /// Adds a combined depth-stencil attachment to this pass, with separate operations for the depth and stencil aspects.
+    /// If either aspect is cleared, `clear` must not be None.
+    /// # Errors
+    /// * Fails if this pass was not created using [`PassBuilder::render()`]
+    /// * Fails if either load op was [`vk::AttachmentLoadOp::CLEAR`], but `clear` was [`None`].
+    pub fn depth_stencil_attachment(
+        mut self,
+        resource: &VirtualResource,
+        depth: AttachmentOps,
+        stencil: AttachmentOps,
+        clear: Option<vk::ClearDepthStencilValue>,
+    ) -> Result<Self> {
+        self.push_depth_stencil(resource, AttachmentType::DepthStencil, depth, Some(stencil), clear)?;
+        Ok(self)
+    }
+
+    fn push_depth_stencil(
+        &mut self,
+        resource: &VirtualResource,
+        usage: AttachmentType,
+        ops: AttachmentOps,
+        stencil_ops: Option<AttachmentOps>,
+        clear: Option<vk::ClearDepthStencilValue>,
+    ) -> Result<()> {
+        if !self.inner.is_renderpass {
+            return Err(Error::Uncategorized(
+                "Cannot attach depth attachment to a pass that is not a renderpass",
+            )
+            .into());
+        }
+        let cleared = std::iter::once(ops).chain(stencil_ops).any(|ops| ops.load == vk::AttachmentLoadOp::CLEAR);
+        if cleared && clear.is_none() {
+            return Err(anyhow::Error::from(Error::NoClearValue));
+        }
+        let layout = match usage {
+            AttachmentType::Stencil => vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL,
+            _ => vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        };
+        self.inner.inputs.push(PassResource {
+            usage: ResourceUsage::Attachment(usage.clone()),
+            resource: resource.clone(),
+            // Load operations on depth/stencil attachments happen in EARLY_FRAGMENT_TESTS.
+            stage: PipelineStage::EARLY_FRAGMENT_TESTS,
+            layout,
+            clear_value: None,
+            load_op: None,
+            store_op: None,
+            stencil_ops: None,
+        });
+
+        self.inner.outputs.push(PassResource {
+            usage: ResourceUsage::Attachment(usage),
+            resource: resource.upgrade(),
+            stage: PipelineStage::LATE_FRAGMENT_TESTS,
+            layout,
+            clear_value: clear.map(|c| vk::ClearValue {
+                depth_stencil: c,
+            }),
+            load_op: Some(ops.load),
+            store_op: Some(ops.store),
+            stencil_ops,
+        });
+        Ok(())
+    }
+
+    /// Set the store op of an attachment written by this pass. Use [`vk::AttachmentStoreOp::DONT_CARE`] to discard its contents after this pass.
+    /// [`vk::AttachmentStoreOp::NONE`] does not discard: it skips the store, so an attachment this pass does not write keeps its contents.
+    /// For depth-stencil attachments, this sets the store op of the depth aspect.
+    /// Attachments without a store op are stored, unless the graph infers they can be discarded. See [`PassGraph::infer_store_ops()`](crate::PassGraph::infer_store_ops).
+    /// # Errors
+    /// * Fails if `resource` is not an attachment written by this pass.
+    pub fn store_op(mut self, resource: &VirtualResource, op: vk::AttachmentStoreOp) -> Result<Self> {
+        let output = self.inner.outputs.iter_mut().find(|output| {
+            matches!(
+                output.usage,
+                ResourceUsage::Attachment(
+                    AttachmentType::Color
+                        | AttachmentType::Depth
+                        | AttachmentType::Stencil
+                        | AttachmentType::DepthStencil
+                )
+            ) && output.resource.is_associated_with(resource)
+        });
+        let Some(output) = output else {
+            anyhow::bail!("Resource {} is not an attachment written by pass {}", resource.uid(), self.inner.name);
+        };
+        output.store_op = Some(op);
+        Ok(self)
+    }
+
+    /// Discard the contents of an attachment after this pass, for example a depth buffer that no later pass uses.
+    /// # Errors
+    /// * Fails if `resource` is not an attachment written by this pass.
+    pub fn discard_attachment(self, resource: &VirtualResource) -> Result<Self> {
+        self.store_op(resource, vk::AttachmentStoreOp::DONT_CARE)
+    }
+
     /// Does a hardware MSAA resolve from `src` into `dst`.
     pub fn resolve(mut self, src: &VirtualResource, dst: &VirtualResource) -> Self {
         self.inner.inputs.push(PassResource {
@@ -536,6 +1017,8 @@ impl<'cb, D: GfxSupport + ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D,
             layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
             clear_value: None,
             load_op: None,
+            store_op: None,
+            stencil_ops: None,
         });
 
         self.inner.outputs.push(PassResource {
@@ -545,6 +1028,8 @@ impl<'cb, D: GfxSupport + ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D,
             layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
             clear_value: None,
             load_op: Some(vk::AttachmentLoadOp::DONT_CARE),
+            store_op: None,
+            stencil_ops: None,
         });
 
         self
@@ -559,6 +1044,8 @@ impl<'cb, D: GfxSupport + ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D,
             layout: vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
             clear_value: None,
             load_op: None,
+            store_op: None,
+            stencil_ops: None,
         });
 
         self.inner.outputs.push(PassResource {
@@ -568,6 +1055,8 @@ impl<'cb, D: GfxSupport + ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D,
             layout: vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
             clear_value: None,
             load_op: Some(vk::AttachmentLoadOp::DONT_CARE),
+            store_op: None,
+            stencil_ops: None,
         });
 
         self
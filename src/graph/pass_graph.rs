@@ -1,23 +1,31 @@
 //! The pass graph module holds the render graph implementation.
 
 use std::collections::hash_map::{DefaultHasher, Entry};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Display, Formatter};
 use std::hash::{Hash, Hasher};
 use std::ops::{Deref, DerefMut};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use ash::vk;
 use petgraph::{Direction, Graph};
 use petgraph::dot::Dot;
 use petgraph::graph::NodeIndex;
 use petgraph::prelude::EdgeRef;
 
-use crate::{Allocator, DefaultAllocator, Error};
-use crate::graph::pass::{BoxedPassFn, EmptyPassExecutor, Pass};
-use crate::graph::resource::ResourceUsage;
+use crate::{Allocator, DefaultAllocator, Device, Error, QueueType};
+use crate::graph::multi_queue::{plan_queue_batches, FrameSync, QueueBatch};
+use crate::graph::ordering::{minimize_barriers, order_cost, OrderCost, PassOrdering};
+use crate::graph::record::{barrier_stats, traversal_steps, BarrierStats};
+use crate::graph::pass::{AttachmentOps, Pass, PassCondition, PassFn};
+use crate::graph::resource::{AttachmentType, ResourceState, ResourceUsage};
+use crate::graph::split_barrier::{plan_split_barriers, SplitBarrierSettings};
 use crate::graph::task_graph::{Barrier, Node, Resource, Task, TaskGraph};
-use crate::graph::virtual_resource::{HashedResource, VirtualResource};
+use crate::graph::transient::{
+    ReferenceExtents, TransientAllocation, TransientBufferInfo, TransientImageInfo, TransientInfo,
+    TransientResources,
+};
+use crate::graph::virtual_resource::{HashedResource, SubresourceRange, VirtualResource};
 use crate::pipeline::PipelineStage;
 use crate::sync::domain::ExecutionDomain;
 
@@ -32,6 +40,10 @@ pub struct PassResource {
     #[derivative(Debug = "ignore")]
     pub(crate) clear_value: Option<vk::ClearValue>,
     pub(crate) load_op: Option<vk::AttachmentLoadOp>,
+    // Store op of the attachment, or of its depth aspect for depth-stencil attachments. None stores the attachment.
+    pub(crate) store_op: Option<vk::AttachmentStoreOp>,
+    // Load and store ops of the stencil aspect of depth-stencil attachments.
+    pub(crate) stencil_ops: Option<AttachmentOps>,
 }
 
 /// GPU barrier in a task graph. Directly translates to `vkCmdPipelineBarrier()`.
@@ -50,8 +62,43 @@ pub struct PassNode<'cb, R: Resource, D: ExecutionDomain, U = (), A: Allocator =
     pub(crate) color: Option<[f32; 4]>,
     pub(crate) inputs: Vec<R>,
     pub(crate) outputs: Vec<R>,
-    pub(crate) execute: BoxedPassFn<'cb, D, U, A>,
+    pub(crate) execute: PassFn<'cb, D, U, A>,
     pub(crate) is_renderpass: bool,
+    pub(crate) queue: QueueType,
+    // Index of the pass in the order passes were added to the graph. None for the source node.
+    pub(crate) pass_index: Option<usize>,
+    pub(crate) condition: Option<PassCondition<'cb, U>>,
+    // Index into the alias sets of the graph, for passes in a subgraph instance.
+    pub(crate) alias_set: Option<usize>,
+    pub(crate) view_mask: u32,
+    pub(crate) layers: u32,
+}
+
+impl<R: Resource, D: ExecutionDomain, U, A: Allocator> PassNode<'_, R, D, U, A> {
+    /// Whether this pass should be recorded, see [`PassBuilder::enabled_if()`](crate::PassBuilder::enabled_if).
+    pub(crate) fn is_enabled(&self, user_data: &U) -> bool {
+        match &self.condition {
+            None => true,
+            Some(condition) => condition(user_data),
+        }
+    }
+}
+
+/// A resource imported into the graph with a known state, see [`PassGraph::import_resource()`].
+#[derive(Debug, Clone, Hash)]
+pub(crate) struct ImportedResource {
+    pub(crate) resource: VirtualResource,
+    pub(crate) initial: ResourceState,
+    pub(crate) final_state: Option<ResourceState>,
+}
+
+/// Transition of an imported resource to its final state, recorded after the last pass using it.
+#[derive(Debug, Clone)]
+pub(crate) struct FinalTransition {
+    pub(crate) barrier: PassResourceBarrier,
+    pub(crate) dst: PassResource,
+    // Identifiers of the passes using the last version of the resource, empty if no pass uses it.
+    pub(crate) passes: Vec<String>,
 }
 
 pub(crate) type PassGraphInner<'cb, D, U, A> = Graph<
@@ -59,6 +106,25 @@ pub(crate) type PassGraphInner<'cb, D, U, A> = Graph<
     <PassResource as Resource>::Uid,
 >;
 
+/// Lists the passes that were removed by [`PassGraph::build()`] because they contribute neither to the
+/// present pass nor to any external output.
+#[derive(Debug, Default, Clone)]
+pub struct CullReport {
+    culled: Vec<String>,
+}
+
+impl CullReport {
+    /// Get the names of all culled passes.
+    pub fn culled(&self) -> &[String] {
+        &self.culled
+    }
+
+    /// Returns true if no passes were culled.
+    pub fn is_empty(&self) -> bool {
+        self.culled.is_empty()
+    }
+}
+
 /// Pass graph, used for synchronizing resources over a single queue.
 pub struct PassGraph<'cb, D: ExecutionDomain, U = (), A: Allocator = DefaultAllocator> {
     pub(crate) graph:
@@ -68,7 +134,43 @@ pub struct PassGraph<'cb, D: ExecutionDomain, U = (), A: Allocator = DefaultAllo
     // index is invalidated. Since the source is always the first node, this is never invalidated.
     source: NodeIndex,
     swapchain_final: VirtualResource,
-    last_usages: HashMap<String, (usize, PipelineStage)>,
+    // Passes are only inserted into the task graph when building, so the structure of the graph can be hashed cheaply.
+    passes: Vec<Pass<'cb, D, U, A>>,
+    // Last usage of each resource, keyed by name and subresource range.
+    last_usages: HashMap<(String, Option<SubresourceRange>), (usize, PipelineStage)>,
+    transients: TransientResources<A>,
+    batches: Vec<QueueBatch>,
+    external_outputs: Vec<VirtualResource>,
+    imports: Vec<ImportedResource>,
+    final_transitions: Vec<FinalTransition>,
+    cull_report: CullReport,
+    barrier_stats: BarrierStats,
+    pub(crate) ordering: PassOrdering,
+    // All nodes in recording order, grouped in steps that each start with the barriers recorded before their passes.
+    steps: Vec<Vec<NodeIndex>>,
+    pub(crate) order_cost: OrderCost,
+    pub(crate) split_barriers: SplitBarrierSettings,
+    // Barrier nodes recorded as an event signal and wait.
+    pub(crate) split: HashSet<NodeIndex>,
+    infer_store_ops: bool,
+    // Resource names seen by the executors of each subgraph instance, and the names they were renamed to.
+    pub(crate) alias_sets: Vec<Vec<(String, String)>>,
+    pub(crate) frame_sync: FrameSync,
+}
+
+/// Records everything written to it instead of hashing it, see [`PassGraph::structure_key()`].
+#[derive(Default)]
+struct StructureKey(Vec<u8>);
+
+impl Hasher for StructureKey {
+    /// Only the recorded bytes are used as the key.
+    fn finish(&self) -> u64 {
+        0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
 }
 
 /// A completely built pass graph, ready for recording.
@@ -93,6 +195,79 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> DerefMut for BuiltPassGraph<'cb,
     }
 }
 
+impl<'cb, D: ExecutionDomain, U, A: Allocator> BuiltPassGraph<'cb, D, U, A> {
+    /// Create all transient resources declared on this graph, aliasing memory between resources with non-overlapping lifetimes.
+    /// Relative sizes are resolved against `reference_extent`, which is usually the swapchain size.
+    /// A previous allocation may still be in use by frames in flight, so it is kept alive until [`BuiltPassGraph::update_transients()`]
+    /// was called for enough frames, or until the graph is dropped.
+    /// # Errors
+    /// * Fails if creating any resource or allocating memory fails.
+    #[deprecated(
+        since = "0.10.0",
+        note = "Use BuiltPassGraph::update_transients() every frame instead, which also recreates the resources after a resize."
+    )]
+    pub fn allocate_transients(
+        &mut self,
+        device: Device,
+        allocator: &mut A,
+        reference_extent: impl Into<ReferenceExtents>,
+    ) -> Result<()> {
+        self.graph
+            .transients
+            .allocate(device, allocator, reference_extent.into())
+    }
+
+    /// Create the transient resources of this graph if they do not exist yet, or recreate them if the size of any image changed,
+    /// for example after the swapchain was resized or the render scale changed. Call this once per frame with
+    /// [`FrameManager::reference_extents()`](crate::FrameManager::reference_extents). Replaced resources are kept alive until
+    /// the frames in flight using them are done, and the new resources are bound automatically when recording.
+    /// Returns true if the resources were (re)created.
+    /// # Errors
+    /// * Fails if creating any resource or allocating memory fails.
+    pub fn update_transients(
+        &mut self,
+        device: Device,
+        allocator: &mut A,
+        extents: impl Into<ReferenceExtents>,
+    ) -> Result<bool> {
+        self.graph.transients.update(device, allocator, extents.into())
+    }
+
+    /// Get the report of passes that were culled while building the graph.
+    pub fn cull_report(&self) -> &CullReport {
+        &self.graph.cull_report
+    }
+
+    /// Get statistics about the barriers that are emitted when recording this graph. Barriers that become ready at the same
+    /// point are batched into a single pipeline barrier, and read-after-read dependencies without a layout transition are skipped.
+    pub fn barrier_stats(&self) -> BarrierStats {
+        self.graph.barrier_stats
+    }
+
+    /// Get the queue batches of this graph, in submission order. Graphs with all passes on a single queue have
+    /// at most one batch.
+    pub fn queue_batches(&self) -> &[QueueBatch] {
+        &self.graph.batches
+    }
+
+    /// Whether this graph has passes that execute on a different queue than the graph's domain.
+    /// Such graphs must be recorded using [`BuiltPassGraph::record_to_batch()`].
+    pub fn is_multi_queue(&self) -> bool {
+        self.graph
+            .batches
+            .iter()
+            .any(|batch| batch.queue != D::QUEUE_TYPE)
+    }
+
+    /// Take ownership of the transient resource allocation. The graph can no longer be recorded until
+    /// [`BuiltPassGraph::update_transients()`] is called again.
+    /// This is useful to keep the transient resources alive until the GPU is done using them, for example by
+    /// pushing them onto a [`DeletionQueue`](crate::DeletionQueue).
+    pub fn take_transient_allocation(&mut self) -> Option<TransientAllocation<A>> {
+        self.graph.transients.take_allocation()
+    }
+}
+
 impl PassResource {
     /// Get the virtual resource associated with this pass resource.
     pub fn virtual_resource(&self) -> &VirtualResource {
@@ -118,6 +293,31 @@ impl Barrier<PassResource> for PassResourceBarrier {
     }
 }
 
+impl PassResource {
+    fn hash_structure<H: Hasher>(&self, state: &mut H) {
+        self.usage.hash(state);
+        self.resource.hash(state);
+        self.stage.hash(state);
+        self.layout.hash(state);
+        self.load_op.hash(state);
+        self.store_op.hash(state);
+        self.stencil_ops.hash(state);
+        // Only the union field matching the attachment type is initialized.
+        let clear_value = self.clear_value.map(|value| unsafe {
+            match self.usage {
+                ResourceUsage::Attachment(AttachmentType::Depth | AttachmentType::Stencil | AttachmentType::DepthStencil) => [
+                    value.depth_stencil.depth.to_bits(),
+                    value.depth_stencil.stencil,
+                    0,
+                    0,
+                ],
+                _ => value.color.uint32,
+            }
+        });
+        clear_value.hash(state);
+    }
+}
+
 impl Resource for PassResource {
     type Uid = HashedResource;
 
@@ -173,7 +373,23 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> PassGraph<'cb, D, U, A> {
             graph: TaskGraph::new(),
             source: NodeIndex::default(),
             swapchain_final: VirtualResource::final_image("swapchain"),
+            passes: vec![],
             last_usages: Default::default(),
+            transients: Default::default(),
+            batches: vec![],
+            external_outputs: vec![],
+            imports: vec![],
+            final_transitions: vec![],
+            cull_report: CullReport::default(),
+            barrier_stats: BarrierStats::default(),
+            ordering: PassOrdering::default(),
+            steps: vec![],
+            order_cost: OrderCost::default(),
+            split_barriers: SplitBarrierSettings::default(),
+            split: HashSet::new(),
+            infer_store_ops: false,
+            alias_sets: vec![],
+            frame_sync: FrameSync::default(),
         };
 
         // insert dummy 'source' node. This node produces all initial inputs and is used for start of frame sync.
@@ -184,8 +400,14 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> PassGraph<'cb, D, U, A> {
                 color: None,
                 inputs: vec![],
                 outputs: vec![],
-                execute: EmptyPassExecutor::new_boxed(),
+                execute: PassFn::empty(),
                 is_renderpass: false,
+                queue: D::QUEUE_TYPE,
+                pass_index: None,
+                condition: None,
+                alias_set: None,
+                view_mask: 0,
+                layers: 1,
             })
             .unwrap();
         graph.source = graph.graph.graph.node_indices().next().unwrap();
@@ -193,9 +415,19 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> PassGraph<'cb, D, U, A> {
     }
 
     /// Add a pass to a task graph. To obtain a pass, use the [`PassBuilder`](crate::graph::pass::PassBuilder)
+    /// The pass is inserted into the graph when calling [`PassGraph::build()`], so errors in the dependencies between passes,
+    /// such as cycles, are reported by [`PassGraph::build()`] instead of here.
     /// # Errors
-    /// - Fails if adding the pass results in a cyclic dependency in the graph.
+    /// - Fails if the pass is a renderpass that does not execute on a graphics queue.
     pub fn add_pass(mut self, pass: Pass<'cb, D, U, A>) -> Result<Self> {
+        if pass.is_renderpass && pass.queue != QueueType::Graphics {
+            return Err(Error::Uncategorized("Renderpasses must execute on a graphics queue").into());
+        }
+        self.passes.push(pass);
+        Ok(self)
+    }
+
+    fn insert_pass(&mut self, pass: Pass<'cb, D, U, A>, pass_index: usize) -> Result<()> {
         {
             // Before adding this pass, we need to add every initial input (one with no '+' signs in its uid) to the output of the source node.
             // Note that we dont actually fill the pipeline stages yet, we do that later
@@ -209,6 +441,8 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> PassGraph<'cb, D, U, A> {
                         layout: vk::ImageLayout::UNDEFINED,
                         clear_value: None,
                         load_op: None,
+                        store_op: None,
+                        stencil_ops: None,
                     })
                 }
             }
@@ -222,6 +456,7 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> PassGraph<'cb, D, U, A> {
         //    self.update_last_usage(&output.resource, output.stage)?;
         //}
 
+        let alias_set = self.alias_set(pass.aliases);
         self.graph.add_task(PassNode {
             identifier: pass.name,
             color: pass.color,
@@ -229,25 +464,272 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> PassGraph<'cb, D, U, A> {
             outputs: pass.outputs,
             execute: pass.execute,
             is_renderpass: pass.is_renderpass,
-        })?;
+            queue: pass.queue,
+            pass_index: Some(pass_index),
+            condition: pass.condition,
+            alias_set,
+            view_mask: pass.view_mask,
+            layers: pass.layers,
+        })
+    }
+
+    /// All passes of a subgraph instance share the same aliases, so these are only stored once per instance.
+    fn alias_set(&mut self, aliases: Vec<(String, String)>) -> Option<usize> {
+        if aliases.is_empty() {
+            return None;
+        }
+        match self.alias_sets.iter().position(|set| *set == aliases) {
+            Some(index) => Some(index),
+            None => {
+                self.alias_sets.push(aliases);
+                Some(self.alias_sets.len() - 1)
+            }
+        }
+    }
+
+    /// Compute a hash of the structure of this graph. This includes all passes with their declared resources and usages,
+    /// the resource names seen by executors of subgraph passes, transient resources and external outputs, but not the pass executors.
+    /// Two graphs with the same structure build to the same graph, so this can be used to reuse a previously built graph.
+    /// See [`PassGraphCache`](crate::graph::cache::PassGraphCache).
+    pub fn structure_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash_structure(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Get a key that is equal for two graphs only if they have the same structure. Unlike the [structure hash](PassGraph::structure_hash),
+    /// this cannot collide.
+    pub(crate) fn structure_key(&self) -> Vec<u8> {
+        let mut key = StructureKey::default();
+        self.hash_structure(&mut key);
+        key.0
+    }
 
+    fn hash_structure<H: Hasher>(&self, hasher: &mut H) {
+        self.passes.len().hash(hasher);
+        for pass in &self.passes {
+            pass.name.hash(hasher);
+            pass.color
+                .map(|color| color.map(f32::to_bits))
+                .hash(hasher);
+            pass.is_renderpass.hash(hasher);
+            pass.queue.hash(hasher);
+            pass.view_mask.hash(hasher);
+            pass.layers.hash(hasher);
+            pass.aliases.hash(hasher);
+            pass.inputs.len().hash(hasher);
+            pass.inputs
+                .iter()
+                .for_each(|resource| resource.hash_structure(hasher));
+            pass.outputs.len().hash(hasher);
+            pass.outputs
+                .iter()
+                .for_each(|resource| resource.hash_structure(hasher));
+        }
+        self.external_outputs.hash(hasher);
+        self.imports.hash(hasher);
+        self.ordering.hash(hasher);
+        self.split_barriers.hash(hasher);
+        self.infer_store_ops.hash(hasher);
+        self.transients.hash_structure(hasher);
+    }
+
+    /// Take all passes that were added to this graph, but not inserted into the task graph yet.
+    pub(crate) fn take_passes(&mut self) -> Vec<Pass<'cb, D, U, A>> {
+        std::mem::take(&mut self.passes)
+    }
+
+    /// Mark a resource as an external output of the graph, for example an image that is read back after the graph
+    /// finished executing. The pass writing the newest version of this resource and all passes it depends on are never culled.
+    pub fn mark_external(mut self, resource: &VirtualResource) -> Self {
+        self.external_outputs.push(resource.clone());
+        self
+    }
+
+    /// Set whether attachments that are not read by any later pass and are not an output of the graph are discarded after the pass
+    /// writing them, instead of being stored to memory. This is disabled by default, and only applies to graphs that present or have
+    /// external outputs. Store ops set with [`PassBuilder::store_op()`](crate::PassBuilder::store_op) are never changed.
+    ///
+    /// Only enable this if the application does not read any attachment after the graph executed, other than the outputs marked with
+    /// [`PassGraph::mark_external()`] and imported resources with a final state. Images bound to the graph that are read
+    /// outside of it, for example in the next frame, would otherwise lose their contents.
+    pub fn infer_store_ops(mut self, infer: bool) -> Self {
+        self.infer_store_ops = infer;
+        self
+    }
+
+    /// Import a resource that is in a known state before the graph executes, for example a texture that is streamed in or an image
+    /// written by code outside the graph. The first barrier on the resource then waits on `initial.stage` and `initial.access`, and keeps
+    /// its contents by transitioning the image from `initial.layout` instead of from an undefined layout.
+    ///
+    /// If `final_state` is set, the resource is transitioned to this state after the last pass using it, so code running after the graph
+    /// can use it. The pass writing the newest version of the resource is then never culled, like with [`PassGraph::mark_external()`].
+    /// # Errors
+    /// * Fails if this resource was already imported.
+    pub fn import_resource(
+        mut self,
+        resource: &VirtualResource,
+        initial: ResourceState,
+        final_state: Option<ResourceState>,
+    ) -> Result<Self> {
+        if self
+            .imports
+            .iter()
+            .any(|import| import.resource.is_associated_with(resource))
+        {
+            return Err(Error::Uncategorized("This resource was already imported").into());
+        }
+        self.imports.push(ImportedResource {
+            resource: resource.clone(),
+            initial,
+            final_state,
+        });
+        Ok(self)
+    }
+
+    /// Declare a transient image owned by the graph. The image is created when calling
+    /// [`BuiltPassGraph::update_transients()`], and may share memory with other transient images whose lifetime does not overlap.
+    /// See the [`transient`](crate::graph::transient) module for more information.
+    /// # Errors
+    /// * Fails if `resource` is not an image resource.
+    /// * Fails if a transient resource with the same name was already declared.
+    pub fn add_transient_image(
+        mut self,
+        resource: &VirtualResource,
+        info: TransientImageInfo,
+    ) -> Result<Self> {
+        self.transients
+            .add(resource, TransientInfo::Image(info))?;
+        Ok(self)
+    }
+
+    /// Declare a transient buffer owned by the graph. The buffer is created when calling
+    /// [`BuiltPassGraph::update_transients()`], and may share memory with other transient buffers whose lifetime does not overlap.
+    /// # Errors
+    /// * Fails if `resource` is not a buffer resource.
+    /// * Fails if a transient resource with the same name was already declared.
+    pub fn add_transient_buffer(
+        mut self,
+        resource: &VirtualResource,
+        info: TransientBufferInfo,
+    ) -> Result<Self> {
+        self.transients
+            .add(resource, TransientInfo::Buffer(info))?;
         Ok(self)
     }
 
     /// Builds the task graph so it can be recorded into a command buffer.
+    /// If the graph has a present pass or external outputs (see [`PassGraph::mark_external()`]), passes that contribute to neither
+    /// are culled first. Passes without any outputs are always kept. The culled passes are listed in [`BuiltPassGraph::cull_report()`].
+    /// This also computes the lifetimes and alias groups of all transient resources, and splits the graph into
+    /// [`QueueBatch`]es.
     /// # Errors
     /// * Fails if there are multiple usages of the same resource, which makes it impossible to
     ///   construct an unambiguous graph.
+    /// * Fails if the passes in the graph have a cyclic dependency.
+    /// * Fails if the same version of a resource is used on multiple queues.
+    /// * Fails if overlapping [subresource ranges](VirtualResource::subresource) of an image are used, or a range is used together with the whole image.
     pub fn build(mut self) -> Result<BuiltPassGraph<'cb, D, U, A>> {
+        self.expand_whole_image_reads();
+        self.check_subresource_ranges()?;
+        for (index, pass) in self.take_passes().into_iter().enumerate() {
+            self.insert_pass(pass, index)?;
+        }
+        self.cull_passes()?;
+        self.discard_unused_attachments();
         self.set_source_stages()?;
+        self.final_transitions = self.compute_final_transitions();
         self.graph.create_barrier_nodes();
         self.merge_identical_barriers()?;
+        self.steps = match self.ordering {
+            PassOrdering::Traversal => traversal_steps(&self.graph.graph),
+            PassOrdering::MinimizeBarriers => minimize_barriers(&self)?,
+        };
+        self.transients
+            .compute_lifetimes(&self.graph.graph, self.source)?;
+        self.transients
+            .add_aliasing_barriers(&mut self.graph.graph, self.source, &mut self.steps);
+        self.batches = plan_queue_batches(&self.graph.graph, self.source, &self.traversal_order())?;
+        self.split = plan_split_barriers(&self, &self.batches)?;
+        self.barrier_stats = barrier_stats(&self, &self.batches)?;
+        self.order_cost = order_cost(&self, &self.steps)?;
 
         Ok(BuiltPassGraph {
             graph: self,
         })
     }
 
+    /// A pass reading a whole image after ranges of it were used reads the latest version of every range instead,
+    /// so it depends on all passes writing these ranges, and each range gets its own barrier.
+    fn expand_whole_image_reads(&mut self) {
+        // Latest version of every range used so far, by image name.
+        let mut ranges = HashMap::<String, Vec<VirtualResource>>::new();
+        for pass in &mut self.passes {
+            let inputs = std::mem::take(&mut pass.inputs);
+            for input in inputs {
+                let writes_whole = pass
+                    .outputs
+                    .iter()
+                    .any(|output| output.resource.is_associated_with(&input.resource));
+                match ranges.get(input.resource.name()) {
+                    Some(latest) if input.resource.subresource_range().is_none() && !writes_whole => {
+                        pass.inputs
+                            .extend(latest.iter().map(|range| PassResource {
+                                resource: range.clone(),
+                                ..input.clone()
+                            }))
+                    }
+                    _ => pass.inputs.push(input),
+                }
+            }
+            let used = pass
+                .inputs
+                .iter()
+                .chain(&pass.outputs)
+                .map(|resource| &resource.resource)
+                .filter(|resource| resource.subresource_range().is_some());
+            for resource in used {
+                let latest = ranges.entry(resource.name().to_owned()).or_default();
+                match latest.iter_mut().find(|range| range.is_associated_with(resource)) {
+                    Some(range) if range.version() < resource.version() => *range = resource.clone(),
+                    Some(_) => {}
+                    None => latest.push(resource.clone()),
+                }
+            }
+        }
+    }
+
+    /// Ranges of the same image are tracked as unrelated resources, so overlapping ranges would not be synchronized with each other.
+    fn check_subresource_ranges(&self) -> Result<()> {
+        let mut ranges = HashMap::<&str, Vec<Option<SubresourceRange>>>::new();
+        let resources = self
+            .passes
+            .iter()
+            .flat_map(|pass| pass.inputs.iter().chain(&pass.outputs))
+            .map(|resource| &resource.resource)
+            .chain(&self.external_outputs);
+        for resource in resources {
+            let used = ranges.entry(resource.name()).or_default();
+            let range = resource.subresource_range();
+            if used.contains(&range) {
+                continue;
+            }
+            match (range, used.first()) {
+                (_, None) => {}
+                (None, Some(_)) | (Some(_), Some(None)) => {
+                    bail!("Image `{}` is used both as a whole and as a subresource range", resource.name())
+                }
+                (Some(range), Some(Some(_))) => {
+                    if let Some(other) = used.iter().flatten().find(|other| other.overlaps(&range)) {
+                        bail!("Image `{}` is used with overlapping ranges ({other} and {range})", resource.name());
+                    }
+                }
+            }
+            used.push(range);
+        }
+        Ok(())
+    }
+
     /// Returns the internal task graph structure, useful for creating debug visualizations.
     pub fn task_graph(
         &self,
@@ -255,12 +737,38 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> PassGraph<'cb, D, U, A> {
         &self.graph
     }
 
+    /// Get the transient resources declared on this graph.
+    pub fn transients(&self) -> &TransientResources<A> {
+        &self.transients
+    }
+
     /// Returns the total amount of nodes in the graph. This can be used as a metric of how
     /// complex the graph is.
     pub fn num_nodes(&self) -> usize {
         self.graph.graph.node_count()
     }
 
+    /// Get all nodes grouped by the step they are recorded in.
+    pub(crate) fn steps(&self) -> &[Vec<NodeIndex>] {
+        &self.steps
+    }
+
+    /// Get the order in which all nodes of the graph are recorded.
+    pub(crate) fn traversal_order(&self) -> Vec<NodeIndex> {
+        self.steps.iter().flatten().copied().collect()
+    }
+
+    /// Get the transitions of imported resources to their final state.
+    pub(crate) fn final_transitions(&self) -> &[FinalTransition] {
+        &self.final_transitions
+    }
+
+    pub(crate) fn import_of(&self, resource: &VirtualResource) -> Option<&ImportedResource> {
+        self.imports
+            .iter()
+            .find(|import| import.resource.is_associated_with(resource))
+    }
+
     /// Get the source node of the graph.
     #[allow(dead_code)]
     pub(crate) fn source(&self) -> NodeIndex {
@@ -272,7 +780,9 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> PassGraph<'cb, D, U, A> {
         resource: &VirtualResource,
         stage: PipelineStage,
     ) -> Result<()> {
-        let entry = self.last_usages.entry(resource.name().to_owned());
+        let entry = self
+            .last_usages
+            .entry((resource.name().to_owned(), resource.subresource_range()));
         match entry {
             Entry::Occupied(mut entry) => {
                 let version = resource.version();
@@ -334,19 +844,340 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> PassGraph<'cb, D, U, A> {
             .unwrap())
     }
 
+    fn barrier_dst_queue(graph: &PassGraphInner<D, U, A>, node: NodeIndex) -> Option<QueueType> {
+        let dst_node = graph.edges(node).next()?.target();
+        match graph.node_weight(dst_node)? {
+            Node::Task(task) => Some(task.queue),
+            _ => None,
+        }
+    }
+
+    /// Get all resources that are used after the graph finished executing: external outputs, resources with a history,
+    /// and imported resources with a final state.
+    fn graph_outputs(&self) -> Vec<VirtualResource> {
+        let mut outputs = self.external_outputs.clone();
+        // Resources with a history are used by the next frame, so they are external outputs as well.
+        outputs.extend(self.history_sources());
+        outputs.extend(
+            self.imports
+                .iter()
+                .filter(|import| import.final_state.is_some())
+                .map(|import| import.resource.clone()),
+        );
+        outputs
+    }
+
+    /// Returns true if the graph has a pass presenting to the swapchain.
+    fn has_present(&self) -> bool {
+        let graph = &self.graph.graph;
+        graph.node_indices().any(|node| match graph.node_weight(node) {
+            Some(Node::Task(task)) => task
+                .inputs
+                .iter()
+                .any(|input| matches!(input.usage, ResourceUsage::Present)),
+            _ => false,
+        })
+    }
+
+    /// Remove all passes that contribute neither to the present pass nor to an external output.
+    fn cull_passes(&mut self) -> Result<()> {
+        let graph_outputs = self.graph_outputs();
+        let graph = &self.graph.graph;
+        let mut roots = Vec::new();
+        let mut has_present = false;
+        // For each external output, the newest version written and the pass writing it.
+        let mut external_writers: HashMap<&str, (usize, NodeIndex)> = HashMap::new();
+        for node in graph.node_indices() {
+            let Node::Task(task) = graph.node_weight(node).unwrap() else { continue };
+            if node == self.source {
+                continue;
+            }
+            let presents = task
+                .inputs
+                .iter()
+                .any(|input| matches!(input.usage, ResourceUsage::Present));
+            has_present |= presents;
+            // Passes without outputs can only have side effects we do not know about, so we always keep them.
+            if presents || task.outputs.is_empty() {
+                roots.push(node);
+            }
+            for output in &task.outputs {
+                let resource = &output.resource;
+                if !graph_outputs
+                    .iter()
+                    .any(|external| external.is_associated_with(resource))
+                {
+                    continue;
+                }
+                match external_writers.entry(resource.name()) {
+                    Entry::Occupied(mut entry) => {
+                        if resource.version() > entry.get().0 {
+                            entry.insert((resource.version(), node));
+                        }
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert((resource.version(), node));
+                    }
+                }
+            }
+        }
+
+        // Without a present pass or external outputs, every pass could be an output.
+        if !has_present && self.external_outputs.is_empty() {
+            return Ok(());
+        }
+        roots.extend(external_writers.values().map(|(_, node)| *node));
+
+        // Walk backwards from all roots to find every pass that contributes to them.
+        let mut keep = HashSet::from([self.source]);
+        while let Some(node) = roots.pop() {
+            if keep.insert(node) {
+                roots.extend(graph.neighbors_directed(node, Direction::Incoming));
+            }
+        }
+        let culled = graph
+            .node_indices()
+            .filter(|node| !keep.contains(node))
+            .filter_map(|node| match graph.node_weight(node) {
+                Some(Node::Task(task)) => Some(task.identifier.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        self.cull_report = CullReport {
+            culled,
+        };
+        if self.cull_report.is_empty() {
+            return Ok(());
+        }
+        self.graph
+            .graph
+            .retain_nodes(|_, node| keep.contains(&node));
+
+        // Culled passes may have been the only users of some resources, so recompute the last usages and remove
+        // initial inputs that are no longer used.
+        self.last_usages.clear();
+        let mut inputs = Vec::new();
+        for node in self.graph.graph.node_indices() {
+            let Node::Task(task) = self.graph.graph.node_weight(node).unwrap() else { continue };
+            inputs.extend(
+                task.inputs
+                    .iter()
+                    .map(|input| (input.resource.clone(), input.stage)),
+            );
+        }
+        for (resource, stage) in &inputs {
+            self.update_last_usage(resource, *stage)?;
+        }
+        let Node::Task(source) = self.graph.graph.node_weight_mut(self.source).unwrap() else { panic!("Graph does not have a source node"); };
+        source
+            .outputs
+            .retain(|output| inputs.iter().any(|(input, _)| input.uid() == output.resource.uid()));
+        Ok(())
+    }
+
+    /// Discard the contents of attachments that no later pass reads and that are not used after the graph, by storing
+    /// them with [`vk::AttachmentStoreOp::DONT_CARE`]. Store ops set on the pass are kept.
+    fn discard_unused_attachments(&mut self) {
+        // Without a present pass or external outputs, every resource could be an output of the graph.
+        if !self.infer_store_ops || (!self.has_present() && self.external_outputs.is_empty()) {
+            return;
+        }
+        let graph_outputs = self.graph_outputs();
+        let graph = &mut self.graph.graph;
+        let read = graph
+            .node_weights()
+            .filter_map(|node| match node {
+                Node::Task(task) => Some(task),
+                _ => None,
+            })
+            .flat_map(|task| task.inputs.iter().map(|input| input.resource.clone()))
+            .collect::<Vec<_>>();
+        for node in graph.node_weights_mut() {
+            let Node::Task(task) = node else { continue };
+            for output in &mut task.outputs {
+                let ResourceUsage::Attachment(attachment) = &output.usage else { continue };
+                if matches!(attachment, AttachmentType::Resolve(_)) || output.store_op.is_some() {
+                    continue;
+                }
+                let resource = &output.resource;
+                if read
+                    .iter()
+                    .any(|input| input.is_associated_with(resource) && input.version() >= resource.version())
+                    || graph_outputs.iter().any(|external| external.is_associated_with(resource))
+                {
+                    continue;
+                }
+                output.store_op = Some(vk::AttachmentStoreOp::DONT_CARE);
+            }
+        }
+    }
+
+    /// Get all resources whose history is read by a pass in the graph.
+    fn history_sources(&self) -> Vec<VirtualResource> {
+        let graph = &self.graph.graph;
+        graph
+            .node_indices()
+            .filter_map(|node| match graph.node_weight(node) {
+                Some(Node::Task(task)) => Some(task),
+                _ => None,
+            })
+            .flat_map(|task| task.inputs.iter())
+            .filter_map(|input| input.resource.history_source())
+            .collect()
+    }
+
+    /// Get the state a resource is left in at the end of the frame, together with the identifiers of the passes using its newest version.
+    /// If the newest version is read by several passes, their stages and accesses are merged, so waiting on the returned state waits
+    /// on every reader.
+    fn final_usage(&self, resource: &VirtualResource) -> Option<(PassResource, Vec<String>)> {
+        let graph = &self.graph.graph;
+        let usages = graph
+            .node_indices()
+            .filter(|node| *node != self.source)
+            .filter_map(|node| match graph.node_weight(node) {
+                Some(Node::Task(task)) => Some(task),
+                _ => None,
+            })
+            .flat_map(|task| {
+                // Readers of a version execute after the pass writing it.
+                let inputs = task.inputs.iter().map(move |input| (true, task, input));
+                let outputs = task.outputs.iter().map(move |output| (false, task, output));
+                inputs.chain(outputs)
+            })
+            .filter(|(_, _, usage)| usage.resource.is_associated_with(resource))
+            .collect::<Vec<_>>();
+        let newest = usages
+            .iter()
+            .map(|(is_input, _, usage)| (usage.resource.version(), *is_input))
+            .max()?;
+        let mut last = usages
+            .into_iter()
+            .filter(|(is_input, _, usage)| (usage.resource.version(), *is_input) == newest)
+            .collect::<Vec<_>>();
+        last.sort_by_key(|(_, task, _)| task.pass_index);
+        let passes = last
+            .iter()
+            .map(|(_, task, _)| task.identifier.clone())
+            .collect::<Vec<_>>();
+        let (_, _, usage) = last.pop()?;
+        let mut usage = usage.clone();
+        if !last.is_empty() {
+            let (stage, access) = last.iter().fold((usage.stage, usage.usage.access()), |(stage, access), (_, _, reader)| {
+                (stage | reader.stage, access | reader.usage.access())
+            });
+            usage.stage = stage;
+            usage.usage = ResourceUsage::External(access);
+        }
+        Some((usage, passes))
+    }
+
+    /// Compute the barriers that leave each imported resource in its final state.
+    fn compute_final_transitions(&self) -> Vec<FinalTransition> {
+        self.imports
+            .iter()
+            .filter_map(|import| {
+                let final_state = import.final_state?;
+                // Resources that are not used by any pass are transitioned directly from their initial state.
+                let (src, passes) = match self.final_usage(&import.resource) {
+                    Some((usage, passes)) => (usage, passes),
+                    None => (
+                        PassResource {
+                            usage: ResourceUsage::External(import.initial.access),
+                            resource: import.resource.clone(),
+                            stage: import.initial.stage,
+                            layout: import.initial.layout,
+                            clear_value: None,
+                            load_op: None,
+                            store_op: None,
+                            stencil_ops: None,
+                        },
+                        vec![],
+                    ),
+                };
+                let dst = PassResource {
+                    usage: ResourceUsage::External(final_state.access),
+                    resource: src.resource.clone(),
+                    stage: final_state.stage,
+                    layout: final_state.layout,
+                    clear_value: None,
+                    load_op: None,
+                    store_op: None,
+                    stencil_ops: None,
+                };
+                let mut barrier = PassResourceBarrier::new(src);
+                barrier.dst_access = final_state.access;
+                barrier.dst_stage = final_state.stage;
+                Some(FinalTransition {
+                    barrier,
+                    dst,
+                    passes,
+                })
+            })
+            .collect()
+    }
+
     /// Set source barrier stages to the *last* usage in the frame, for cross-frame sync
     fn set_source_stages(&mut self) -> Result<()> {
+        // History resources start in the state their resource was left in by the previous frame.
+        let mut history_states = HashMap::new();
+        let Node::Task(source) = self.graph.graph.node_weight(self.source).unwrap() else { panic!("Graph does not have a source node"); };
+        for output in &source.outputs {
+            let Some(resource) = output.resource.history_source() else { continue };
+            let state = match self.import_of(&resource).and_then(|import| import.final_state) {
+                // Imported resources are left in their final state.
+                Some(state) => PassResource {
+                    usage: ResourceUsage::External(state.access),
+                    resource: resource.clone(),
+                    stage: state.stage,
+                    layout: state.layout,
+                    clear_value: None,
+                    load_op: None,
+                    store_op: None,
+                    stencil_ops: None,
+                },
+                None => {
+                    self.final_usage(&resource)
+                        .ok_or(Error::Uncategorized(
+                            "The history of a resource is read, but the resource itself is not used in the graph",
+                        ))?
+                        .0
+                }
+            };
+            history_states.insert(output.resource.uid(), state);
+        }
+
         let Node::Task(source) = self.graph.graph.node_weight_mut(self.source).unwrap() else { panic!("Graph does not have a source node"); };
         // For each output, look for the last usage of this resource in the frame.
         for output in &mut source.outputs {
-            if output
+            let import = self
+                .imports
+                .iter()
+                .find(|import| import.resource.is_associated_with(&output.resource));
+            if let Some(import) = import {
+                output.usage = ResourceUsage::External(import.initial.access);
+                output.stage = import.initial.stage;
+                output.layout = import.initial.layout;
+            } else if output
                 .resource
                 .is_associated_with(&self.swapchain_final)
             {
                 output.stage = PipelineStage::COLOR_ATTACHMENT_OUTPUT;
+            } else if let Some(state) = history_states.remove(&output.resource.uid()) {
+                output.usage = state.usage;
+                output.stage = state.stage;
+                output.layout = state.layout;
             } else {
-                let (_, stage) = self.last_usages.get(output.resource.name()).unwrap();
+                let key = (output.resource.name().to_owned(), output.resource.subresource_range());
+                let (_, stage) = self.last_usages.get(&key).unwrap();
                 output.stage = *stage;
+                // The image written this frame was read as history by the previous frame.
+                let history = output.resource.history();
+                if let Some((_, stage)) = self
+                    .last_usages
+                    .get(&(history.name().to_owned(), history.subresource_range()))
+                {
+                    output.stage |= *stage;
+                }
             }
         }
         Ok(())
@@ -379,6 +1210,13 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> PassGraph<'cb, D, U, A> {
                     if !other_usage.is_read() && !dst_usage.is_read() && other_usage != &dst_usage {
                         return Err(anyhow::Error::from(Error::IllegalTaskGraph));
                     }
+                    // A merged barrier is recorded on a single queue, so all consumers must be on the same queue.
+                    if Self::barrier_dst_queue(graph, node) != Self::barrier_dst_queue(graph, other_node) {
+                        return Err(Error::Uncategorized(
+                            "The same version of a resource cannot be used on multiple queues",
+                        )
+                        .into());
+                    }
                     to_remove.push(other_node);
                     edges_to_add.push((
                         node,
@@ -1,10 +1,12 @@
 //! Provides utilities for binding physical resources to virtual resources
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use anyhow::Result;
+use ash::vk;
 
 use crate::{BufferView, Error, ImageView, VirtualResource};
+use crate::resource::image::ImageViewCreateInfo;
 
 /// Describes any physical resource handle on the GPU.
 #[derive(Debug, Clone)]
@@ -29,9 +31,10 @@ pub enum PhysicalResource {
 /// // ... Later, lookup the physical image handle from a virtual resource handle
 /// let view = bindings.resolve(&resource).ok_or_else(|| Error::NoResourceBound)?;
 /// ```
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct PhysicalResourceBindings {
     bindings: HashMap<String, PhysicalResource>,
+    invalidated: HashSet<String>,
 }
 
 impl PhysicalResourceBindings {
@@ -40,10 +43,12 @@ impl PhysicalResourceBindings {
         Self::default()
     }
 
-    /// Bind an image to all virtual resources with `name(+*)` as their uid.
+    /// Bind an image to all virtual resources with `name(+*)` as their uid. This clears a previous [`invalidation`](Self::invalidate).
     pub fn bind_image(&mut self, name: impl Into<String>, image: &ImageView) {
+        let name = name.into();
+        self.invalidated.remove(&name);
         self.bindings
-            .insert(name.into(), PhysicalResource::Image(image.clone()));
+            .insert(name, PhysicalResource::Image(image.clone()));
     }
 
     /// Bind a buffer to all virtual resources with this name as their uid.
@@ -64,8 +69,68 @@ impl PhysicalResourceBindings {
         Ok(())
     }
 
+    /// Mark the contents of the image bound to `name` as undefined. The first barrier on this image in a graph
+    /// then transitions it from `VK_IMAGE_LAYOUT_UNDEFINED`, instead of from the layout the graph expects it to be in.
+    /// This is used for images that were just created, such as [history resources](crate::graph::history) after a resize.
+    pub fn invalidate(&mut self, name: impl Into<String>) {
+        self.invalidated.insert(name.into());
+    }
+
+    /// Returns true if the contents of the image bound to `name` were [`invalidated`](Self::invalidate).
+    pub fn is_invalidated(&self, name: &str) -> bool {
+        self.invalidated.contains(name)
+    }
+
+    /// Add all bindings from `other` to this set of bindings. Existing bindings with the same name are overwritten.
+    pub fn extend(&mut self, other: &PhysicalResourceBindings) {
+        self.bindings.extend(
+            other
+                .bindings
+                .iter()
+                .map(|(name, resource)| (name.clone(), resource.clone())),
+        );
+        for name in other.bindings.keys() {
+            self.invalidated.remove(name);
+        }
+        self.invalidated
+            .extend(other.invalidated.iter().cloned());
+    }
+
     /// Resolve a virtual resource to a physical resource. Returns `None` if the resource was not found.
     pub fn resolve(&self, resource: &VirtualResource) -> Option<&PhysicalResource> {
         self.bindings.get(resource.name())
     }
+
+    /// Resolve a virtual resource to a physical resource, taking its [`subresource range`](VirtualResource::subresource) into account.
+    /// For image resources referring to a range of the image, this returns a view of only that range of the bound image view.
+    /// Returns `None` if the resource was not found.
+    /// # Errors
+    /// * Fails if the range is not inside the bound image view.
+    /// * Fails if creating the image view fails.
+    pub fn resolve_subresource(&self, resource: &VirtualResource) -> Result<Option<PhysicalResource>> {
+        let Some(physical) = self.resolve(resource) else { return Ok(None) };
+        let (PhysicalResource::Image(image), Some(range)) = (physical, resource.subresource_range()) else {
+            return Ok(Some(physical.clone()));
+        };
+        let layers = range
+            .layer_count
+            .unwrap_or(image.layer_count().saturating_sub(range.base_layer));
+        // Shaders access every range of an array image as an array, even if the range has a single layer.
+        let view_type = if image.depth() > 1 {
+            vk::ImageViewType::TYPE_3D
+        } else if layers > 1 || image.layer_count() > 1 {
+            vk::ImageViewType::TYPE_2D_ARRAY
+        } else {
+            vk::ImageViewType::TYPE_2D
+        };
+        let view = image.view(ImageViewCreateInfo {
+            aspect: image.aspect(),
+            view_type,
+            base_mip_level: range.base_level,
+            level_count: range.level_count,
+            base_layer: range.base_layer,
+            layers: range.layer_count,
+        })?;
+        Ok(Some(PhysicalResource::Image(view)))
+    }
 }
@@ -0,0 +1,278 @@
+//! Opt-in GPU profiler for pass graphs.
+//!
+//! A [`PassProfiler`] wraps every pass recorded with [`BuiltPassGraph::record_profiled()`](crate::graph::pass_graph::BuiltPassGraph::record_profiled)
+//! in a pair of timestamp queries, and optionally in a pipeline statistics query. Query results are only available after the GPU has
+//! executed the frame, so the profiler keeps one set of query pools for every frame in flight. Results of a frame are read back when its
+//! query pools are reused by [`PassProfiler::new_frame()`], which must happen after the fence of that frame was signaled.
+//! Calling it inside the closure passed to [`FrameManager::new_frame()`](crate::FrameManager::new_frame) guarantees this.
+//!
+//! Profiling is only supported for graphs with all passes on a single queue, recorded with
+//! [`BuiltPassGraph::record_profiled()`](crate::graph::pass_graph::BuiltPassGraph::record_profiled). Passes recorded in any other way
+//! are not measured.
+//!
+//! # Example
+//! ```
+//! use phobos::prelude::*;
+//! use phobos::graph::profiler::{PassProfiler, PassProfilerCreateInfo};
+//!
+//! let mut profiler = PassProfiler::new(device.clone(), PassProfilerCreateInfo::default())?;
+//! // Every frame, after waiting for the frame's fence:
+//! profiler.new_frame()?;
+//! let cmd = graph.record_profiled(cmd, &bindings, &mut pool, None, &mut (), &mut profiler)?;
+//! // Results of a previous frame.
+//! if let Some(frame) = profiler.results() {
+//!     let shadows = frame.find("shadows").unwrap();
+//!     println!("Shadow pass took {:?}", shadows.duration);
+//! }
+//! ```
+
+use std::time::Duration;
+
+use anyhow::{ensure, Result};
+use ash::vk;
+
+use crate::{Allocator, Device, Error, IncompleteCommandBuffer, PipelineStage};
+use crate::query_pool::{
+    PipelineStatistics, PipelineStatisticsQuery, QueryPool, QueryPoolCreateInfo, Timestamp,
+    TimestampQuery,
+};
+use crate::sync::domain::ExecutionDomain;
+use crate::wsi::frame::FRAMES_IN_FLIGHT;
+
+/// Information required to create a [`PassProfiler`].
+#[derive(Debug, Copy, Clone)]
+pub struct PassProfilerCreateInfo {
+    /// Maximum number of passes that can be profiled in a single frame.
+    pub max_passes: u32,
+    /// Number of frames that can be in flight at the same time. Results are available after this many frames.
+    pub frames_in_flight: usize,
+    /// Pipeline statistics to query for each pass. Set to `None` to only measure timings. Pipeline statistics
+    /// can only be queried on graphics and compute queues.
+    pub statistics: Option<vk::QueryPipelineStatisticFlags>,
+}
+
+impl Default for PassProfilerCreateInfo {
+    fn default() -> Self {
+        Self {
+            max_passes: 64,
+            frames_in_flight: FRAMES_IN_FLIGHT,
+            statistics: None,
+        }
+    }
+}
+
+/// Timing of a single node in the profiling tree.
+#[derive(Debug, Clone, Default)]
+pub struct PassTiming {
+    /// Identifier of the pass. The root of the tree is called `frame`.
+    pub identifier: String,
+    /// Time the GPU spent between the start and the end of this node.
+    pub duration: Duration,
+    /// Pipeline statistics of this node, if these were requested.
+    pub statistics: Option<PipelineStatistics>,
+    /// Child nodes, in recording order.
+    pub children: Vec<PassTiming>,
+}
+
+impl PassTiming {
+    /// Find the node with this identifier in the tree.
+    pub fn find(&self, identifier: &str) -> Option<&PassTiming> {
+        if self.identifier == identifier {
+            return Some(self);
+        }
+        self.children
+            .iter()
+            .find_map(|child| child.find(identifier))
+    }
+}
+
+struct ProfiledPass {
+    identifier: String,
+    start: u32,
+    end: u32,
+    statistics: Option<u32>,
+}
+
+struct FrameQueries {
+    timestamps: QueryPool<TimestampQuery>,
+    statistics: Option<QueryPool<PipelineStatisticsQuery>>,
+    passes: Vec<ProfiledPass>,
+}
+
+/// Measures the GPU time and optionally the pipeline statistics of every pass in a pass graph.
+pub struct PassProfiler {
+    frames: Vec<FrameQueries>,
+    current: usize,
+    results: Option<PassTiming>,
+}
+
+fn elapsed(start: Timestamp, end: Timestamp) -> Duration {
+    Duration::from_nanos(end.nanoseconds().saturating_sub(start.nanoseconds()))
+}
+
+impl PassProfiler {
+    /// Create a new profiler, with query pools for every frame in flight.
+    /// # Errors
+    /// * Fails if `frames_in_flight` is zero.
+    /// * Fails if creating a query pool fails.
+    pub fn new(device: Device, info: PassProfilerCreateInfo) -> Result<Self> {
+        ensure!(info.frames_in_flight > 0, "Profiler needs at least one frame in flight");
+        let frames = (0..info.frames_in_flight)
+            .map(|_| -> Result<FrameQueries> {
+                let timestamps = QueryPool::new(
+                    device.clone(),
+                    QueryPoolCreateInfo {
+                        count: info.max_passes * 2,
+                        statistic_flags: None,
+                    },
+                )?;
+                let statistics = match info.statistics {
+                    None => None,
+                    Some(flags) => Some(QueryPool::new(
+                        device.clone(),
+                        QueryPoolCreateInfo {
+                            count: info.max_passes,
+                            statistic_flags: Some(flags),
+                        },
+                    )?),
+                };
+                Ok(FrameQueries {
+                    timestamps,
+                    statistics,
+                    passes: vec![],
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            frames,
+            current: 0,
+            results: None,
+        })
+    }
+
+    /// Create a profiler with detached query pools, for recording to a [`CommandLog`](crate::command_buffer::command_log::CommandLog).
+    /// See [`QueryPool::detached()`]. Results of a detached profiler can not be read, so [`PassProfiler::new_frame()`] fails
+    /// once a profiled frame was recorded.
+    /// # Errors
+    /// * Fails if `frames_in_flight` is zero.
+    /// # Safety
+    /// The profiler must only be used with command buffers created with [`IncompleteCommandBuffer::new_logged()`].
+    pub unsafe fn detached(info: PassProfilerCreateInfo) -> Result<Self> {
+        ensure!(info.frames_in_flight > 0, "Profiler needs at least one frame in flight");
+        let frames = (0..info.frames_in_flight)
+            .map(|_| FrameQueries {
+                timestamps: QueryPool::detached(QueryPoolCreateInfo {
+                    count: info.max_passes * 2,
+                    statistic_flags: None,
+                }),
+                statistics: info.statistics.map(|flags| {
+                    QueryPool::detached(QueryPoolCreateInfo {
+                        count: info.max_passes,
+                        statistic_flags: Some(flags),
+                    })
+                }),
+                passes: vec![],
+            })
+            .collect();
+        Ok(Self {
+            frames,
+            current: 0,
+            results: None,
+        })
+    }
+
+    /// Advance to the query pools of the next frame. If these were used by an earlier frame, its results are read back first
+    /// and become available through [`PassProfiler::results()`].
+    /// This must be called once per frame before recording, and only after the frame that last used these query pools has
+    /// finished executing. Results are read with `VK_QUERY_RESULT_WAIT_BIT`, so this blocks forever if that frame was never submitted.
+    /// # Errors
+    /// * Fails if reading the query results fails.
+    pub fn new_frame(&mut self) -> Result<()> {
+        self.current = (self.current + 1) % self.frames.len();
+        let frame = &mut self.frames[self.current];
+        if !frame.passes.is_empty() {
+            let count = frame.passes.len() as u32;
+            let timestamps = frame.timestamps.wait_for_results(0, count * 2)?;
+            let statistics = match &mut frame.statistics {
+                None => None,
+                Some(pool) => Some(pool.wait_for_results(0, count)?),
+            };
+            let children = frame
+                .passes
+                .iter()
+                .map(|pass| PassTiming {
+                    identifier: pass.identifier.clone(),
+                    duration: elapsed(timestamps[pass.start as usize], timestamps[pass.end as usize]),
+                    statistics: statistics
+                        .as_ref()
+                        .zip(pass.statistics)
+                        .map(|(statistics, index)| statistics[index as usize]),
+                    children: vec![],
+                })
+                .collect();
+            let first = frame.passes.first().unwrap();
+            let last = frame.passes.last().unwrap();
+            self.results = Some(PassTiming {
+                identifier: "frame".to_owned(),
+                duration: elapsed(timestamps[first.start as usize], timestamps[last.end as usize]),
+                statistics: None,
+                children,
+            });
+        }
+        frame.passes.clear();
+        frame.timestamps.reset();
+        if let Some(pool) = &mut frame.statistics {
+            pool.reset();
+        }
+        Ok(())
+    }
+
+    /// Get the results of the most recent frame that finished executing, or `None` if no results are available yet.
+    pub fn results(&self) -> Option<&PassTiming> {
+        self.results.as_ref()
+    }
+
+    /// Record the start of a pass.
+    pub(crate) fn begin_pass<'q, D: ExecutionDomain, A: Allocator>(
+        &mut self,
+        identifier: &str,
+        cmd: IncompleteCommandBuffer<'q, D, A>,
+    ) -> Result<IncompleteCommandBuffer<'q, D, A>> {
+        let frame = &mut self.frames[self.current];
+        let mut cmd = cmd.write_timestamp(&mut frame.timestamps, PipelineStage::TOP_OF_PIPE)?;
+        let start = frame.timestamps.current();
+        let statistics = match &mut frame.statistics {
+            None => None,
+            Some(pool) => {
+                let index = pool
+                    .next()
+                    .ok_or(Error::Uncategorized("Query pool capacity exceeded"))?;
+                cmd = cmd.begin_query(pool, index);
+                Some(index)
+            }
+        };
+        frame.passes.push(ProfiledPass {
+            identifier: identifier.to_owned(),
+            start,
+            end: start,
+            statistics,
+        });
+        Ok(cmd)
+    }
+
+    /// Record the end of the pass that was last started with [`PassProfiler::begin_pass()`].
+    pub(crate) fn end_pass<'q, D: ExecutionDomain, A: Allocator>(
+        &mut self,
+        cmd: IncompleteCommandBuffer<'q, D, A>,
+    ) -> Result<IncompleteCommandBuffer<'q, D, A>> {
+        let frame = &mut self.frames[self.current];
+        let Some(pass) = frame.passes.last_mut() else { return Ok(cmd) };
+        let mut cmd = cmd;
+        if let (Some(pool), Some(index)) = (&frame.statistics, pass.statistics) {
+            cmd = cmd.end_query(pool, index);
+        }
+        let cmd = cmd.write_timestamp(&mut frame.timestamps, PipelineStage::BOTTOM_OF_PIPE)?;
+        pass.end = frame.timestamps.current();
+        Ok(cmd)
+    }
+}
@@ -1,6 +1,8 @@
 //! Provides methods to record a pass graph to a command buffer
 
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
 use std::ffi::CString;
 use std::sync::Arc;
 
@@ -11,16 +13,36 @@ use petgraph::graph::NodeIndex;
 use petgraph::visit::EdgeRef;
 
 use crate::{
-    Allocator, BufferView, DebugMessenger, Error, ImageView, PassGraph, PhysicalResourceBindings,
+    Allocator, DebugMessenger, Error, Event, ExecutionManager, ImageView, IncompleteCmdBuffer,
+    PassGraph, PhysicalResourceBindings, PipelineStage, QueueType, Semaphore,
 };
 use crate::command_buffer::IncompleteCommandBuffer;
 use crate::command_buffer::state::{RenderingAttachmentInfo, RenderingInfo};
-use crate::graph::pass_graph::{BuiltPassGraph, PassNode, PassResource, PassResourceBarrier};
+use crate::graph::multi_queue::{frame_timeline, BatchNode, FrameTimeline, LoggedQueueBatch, QueueBatch};
+use crate::graph::pass_graph::{
+    BuiltPassGraph, FinalTransition, PassGraphInner, PassNode, PassResource, PassResourceBarrier,
+};
+use crate::graph::pass::AttachmentOps;
 use crate::graph::physical_resource::PhysicalResource;
-use crate::graph::resource::{AttachmentType, ResourceUsage};
+use crate::graph::profiler::PassProfiler;
+use crate::graph::resource::{AttachmentType, ResourceType, ResourceUsage};
 use crate::graph::task_graph::Node;
 use crate::pool::LocalPool;
 use crate::sync::domain::ExecutionDomain;
+use crate::sync::submit_batch::{SubmitBatch, SubmitHandle};
+#[cfg(feature = "rayon")]
+use {
+    crate::command_buffer::state::SecondaryInheritance,
+    crate::core::queue::Queue,
+    crate::graph::pass::{PassFn, SendPassFn},
+    crate::command_buffer::command_log::{detached_handle, CommandLog},
+    crate::pool::ResourcePool,
+    crate::{DefaultAllocator, DescriptorCache, Device, PipelineCache},
+    crate::wsi::frame::FRAMES_IN_FLIGHT,
+    rayon::prelude::*,
+    std::collections::HashSet,
+    std::sync::Mutex,
+};
 
 /// Implement this on a type to be able to record this type to a command buffer.
 pub trait RecordGraphToCommandBuffer<D: ExecutionDomain, U, A: Allocator> {
@@ -42,52 +64,39 @@ pub trait RecordGraphToCommandBuffer<D: ExecutionDomain, U, A: Allocator> {
 
 // Traversal
 // =============
-// Algorithm as follows:
-// - Start with only the source node as an active node.
-// - Each iteration:
-//      - For each node that is a child of an 'active' node
-//          - If all parents of this node are in the active set, then they have all been recorded already.
-//              - Record this node, and add it to the active set.
-// - Continue this until the active set contains all nodes.
-
-// To implement this we will keep track of a set of children.
-// Any time a node is added to the active set, it must come from the children set.
-// Then we can:
-//  1) Remove this node from the children set.
-//  2) Add it to the active set.
-//  3) Add its children to the children set.
-
-macro_rules! children {
-    ($node:ident, $graph:ident) => {
-        $graph
-            .task_graph()
-            .graph
-            .edges_directed($node.clone(), Outgoing)
-            .map(|edge| edge.target())
-    };
-}
-
-macro_rules! parents {
-    ($node:ident, $graph:ident) => {
-        $graph
-            .task_graph()
-            .graph
-            .edges_directed($node.clone(), Incoming)
-            .map(|edge| edge.source())
-    };
-}
+// Nodes are recorded in steps. A node is recorded in the first step where all of its parents were recorded in
+// earlier steps. Within a step, barriers are ordered before passes so all barriers that become ready
+// in the same step can be emitted with a single vkCmdPipelineBarrier2 call.
 
-fn insert_in_active_set<D: ExecutionDomain, U, A: Allocator>(
-    node: NodeIndex,
-    graph: &PassGraph<'_, D, U, A>,
-    active: &mut HashSet<NodeIndex>,
-    children: &mut HashSet<NodeIndex>,
-) {
-    children.remove(&node);
-    active.insert(node);
-    for child in children!(node, graph) {
-        children.insert(child);
+/// Get all nodes of the graph grouped by the step they are recorded in.
+pub(crate) fn traversal_steps<D: ExecutionDomain, U, A: Allocator>(
+    graph: &PassGraphInner<'_, D, U, A>,
+) -> Vec<Vec<NodeIndex>> {
+    let mut remaining_parents = graph
+        .node_indices()
+        .map(|node| (node, graph.edges_directed(node, Incoming).count()))
+        .collect::<HashMap<_, _>>();
+    let mut step = graph
+        .node_indices()
+        .filter(|node| remaining_parents[node] == 0)
+        .collect::<Vec<_>>();
+    let mut steps = Vec::new();
+    while !step.is_empty() {
+        step.sort_by_key(|node| (matches!(graph.node_weight(*node), Some(Node::Task(_))), node.index()));
+        let mut next = Vec::new();
+        for &node in &step {
+            for edge in graph.edges_directed(node, Outgoing) {
+                let parents = remaining_parents.get_mut(&edge.target()).unwrap();
+                *parents -= 1;
+                if *parents == 0 {
+                    next.push(edge.target());
+                }
+            }
+        }
+        steps.push(step);
+        step = next;
     }
+    steps
 }
 
 fn find_resolve_attachment<D: ExecutionDomain, U, A: Allocator>(
@@ -105,10 +114,10 @@ fn find_resolve_attachment<D: ExecutionDomain, U, A: Allocator>(
     Ok(match output {
         None => None,
         Some(resolve) => {
-            let Some(PhysicalResource::Image(image)) = bindings.resolve(&resolve.resource) else {
+            let Some(PhysicalResource::Image(image)) = bindings.resolve_subresource(&resolve.resource)? else {
                 bail!("No image resource bound to resolve attachment {}", &resolve.resource);
             };
-            Some(image.clone())
+            Some(image)
         }
     })
 }
@@ -124,8 +133,10 @@ fn color_attachments<D: ExecutionDomain, U, A: Allocator>(
             if !matches!(resource.usage, ResourceUsage::Attachment(AttachmentType::Color)) {
                 return None;
             }
-            let Some(PhysicalResource::Image(image)) = bindings.resolve(&resource.resource) else {
-                return Some(Err(anyhow!("No image resource bound to color attachment {}", &resource.resource)));
+            let image = match bindings.resolve_subresource(&resource.resource) {
+                Ok(Some(PhysicalResource::Image(image))) => image,
+                Ok(_) => return Some(Err(anyhow!("No image resource bound to color attachment {}", &resource.resource))),
+                Err(e) => return Some(Err(e)),
             };
             let resolve;
             match find_resolve_attachment(pass, bindings, resource) {
@@ -142,7 +153,7 @@ fn color_attachments<D: ExecutionDomain, U, A: Allocator>(
                     .then_some(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
                 resolve_image_view: resolve,
                 load_op: resource.load_op.unwrap(),
-                store_op: vk::AttachmentStoreOp::STORE,
+                store_op: resource.store_op.unwrap_or(vk::AttachmentStoreOp::STORE),
                 clear_value: resource.clear_value.unwrap_or(vk::ClearValue::default()),
             };
             Some(Ok(info))
@@ -150,56 +161,62 @@ fn color_attachments<D: ExecutionDomain, U, A: Allocator>(
         .collect()
 }
 
-fn depth_attachment<D: ExecutionDomain, U, A: Allocator>(
+/// Find the depth and stencil attachments of a pass. Read-only depth attachments are only an input of the pass.
+fn depth_stencil_attachments<D: ExecutionDomain, U, A: Allocator>(
     pass: &PassNode<PassResource, D, U, A>,
     bindings: &PhysicalResourceBindings,
-) -> Option<Result<RenderingAttachmentInfo>> {
-    pass.outputs
-        .iter()
-        .filter_map(|resource| -> Option<Result<RenderingAttachmentInfo>> {
-            if resource.layout != vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL {
-                return None;
-            }
-
-            let Some(PhysicalResource::Image(image)) = bindings.resolve(&resource.resource) else {
-                return Some(Err(anyhow!("No image resource bound to depth attachment {}", &resource.resource)));
-            };
-
-            let resolve;
-            match find_resolve_attachment(pass, bindings, resource) {
-                Ok(image) => resolve = image,
-                Err(e) => {
-                    return Some(Err(e));
-                }
-            };
-
-            let info = RenderingAttachmentInfo {
-                image_view: image.clone(),
-                image_layout: resource.layout,
-                resolve_mode: resolve.is_some().then_some(vk::ResolveModeFlags::AVERAGE),
-                resolve_image_layout: resolve
-                    .is_some()
-                    .then_some(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL),
-                resolve_image_view: resolve,
-                load_op: resource.load_op.unwrap(),
-                store_op: vk::AttachmentStoreOp::STORE,
-                clear_value: resource.clear_value.unwrap_or(vk::ClearValue::default()),
-            };
-            Some(Ok(info))
-        })
-        .next()
+) -> Result<(Option<RenderingAttachmentInfo>, Option<RenderingAttachmentInfo>)> {
+    let resource = pass.outputs.iter().chain(&pass.inputs).find(|resource| {
+        matches!(
+            resource.usage,
+            ResourceUsage::Attachment(
+                AttachmentType::Depth
+                    | AttachmentType::DepthReadOnly { .. }
+                    | AttachmentType::Stencil
+                    | AttachmentType::DepthStencil
+            )
+        )
+    });
+    let Some(resource) = resource else { return Ok((None, None)) };
+    let Some(PhysicalResource::Image(image)) = bindings.resolve_subresource(&resource.resource)? else {
+        bail!("No image resource bound to depth attachment {}", &resource.resource);
+    };
+    let resolve = find_resolve_attachment(pass, bindings, resource)?;
+    let info = |ops: AttachmentOps| RenderingAttachmentInfo {
+        image_view: image.clone(),
+        image_layout: resource.layout,
+        resolve_mode: resolve.is_some().then_some(vk::ResolveModeFlags::AVERAGE),
+        resolve_image_layout: resolve
+            .is_some()
+            .then_some(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL),
+        resolve_image_view: resolve.clone(),
+        load_op: ops.load,
+        store_op: ops.store,
+        clear_value: resource.clear_value.unwrap_or(vk::ClearValue::default()),
+    };
+    // Attachment should always have a load op set, or our library is bugged
+    let ops = AttachmentOps::new(resource.load_op.unwrap(), resource.store_op.unwrap_or(vk::AttachmentStoreOp::STORE));
+    Ok(match resource.usage {
+        ResourceUsage::Attachment(AttachmentType::Stencil) => (None, Some(info(ops))),
+        ResourceUsage::Attachment(AttachmentType::DepthStencil) => {
+            (Some(info(ops)), Some(info(resource.stencil_ops.unwrap_or(ops))))
+        }
+        _ => (Some(info(ops)), None),
+    })
 }
 
 fn render_area<D: ExecutionDomain, U, A: Allocator>(
     pass: &PassNode<PassResource, D, U, A>,
     bindings: &PhysicalResourceBindings,
 ) -> Result<vk::Rect2D> {
+    // Read-only attachments are only an input of the pass.
     let resource = pass
         .outputs
         .iter()
+        .chain(&pass.inputs)
         .find(|resource| matches!(resource.usage, ResourceUsage::Attachment(_)))
         .unwrap();
-    let Some(PhysicalResource::Image(image)) = bindings.resolve(&resource.resource) else {
+    let Some(PhysicalResource::Image(image)) = bindings.resolve_subresource(&resource.resource)? else {
         bail!("No image resource bound to attachment {}", &resource.resource);
     };
     Ok(vk::Rect2D {
@@ -207,7 +224,7 @@ fn render_area<D: ExecutionDomain, U, A: Allocator>(
             x: 0,
             y: 0,
         },
-        // TODO: properly set size of current level?
+        // Views of a subresource range have the size of their first mip level.
         extent: vk::Extent2D {
             width: image.width(),
             height: image.height(),
@@ -240,70 +257,623 @@ fn annotate_pass<D: ExecutionDomain, A: Allocator>(
     Ok(cmd)
 }
 
+fn rendering_info<D: ExecutionDomain, U, A: Allocator>(
+    pass: &PassNode<PassResource, D, U, A>,
+    bindings: &PhysicalResourceBindings,
+    flags: vk::RenderingFlags,
+) -> Result<RenderingInfo> {
+    let (depth_attachment, stencil_attachment) = depth_stencil_attachments(pass, bindings)?;
+    let color_attachments = color_attachments(pass, bindings)?;
+    // With multiview, the highest view in the mask determines the number of layers each attachment needs.
+    let required_layers = match pass.view_mask {
+        0 => pass.layers,
+        mask => u32::BITS - mask.leading_zeros(),
+    };
+    let too_small = color_attachments
+        .iter()
+        .chain(&depth_attachment)
+        .chain(&stencil_attachment)
+        .any(|attachment| attachment.image_view.layer_count() < required_layers);
+    if too_small {
+        bail!(
+            "Pass `{}` renders to {required_layers} layer(s), but not all of its attachments are bound to views with that many layers",
+            pass.identifier
+        );
+    }
+    Ok(RenderingInfo {
+        flags,
+        render_area: render_area(pass, bindings)?,
+        layer_count: pass.layers,
+        view_mask: pass.view_mask,
+        color_attachments,
+        depth_attachment,
+        stencil_attachment,
+    })
+}
+
+/// Record everything that happens before the pass executor is called.
+fn begin_pass<'q, D: ExecutionDomain, U, A: Allocator>(
+    pass: &PassNode<'_, PassResource, D, U, A>,
+    bindings: &PhysicalResourceBindings,
+    mut cmd: IncompleteCommandBuffer<'q, D, A>,
+    debug: Option<&Arc<DebugMessenger>>,
+    flags: vk::RenderingFlags,
+) -> Result<IncompleteCommandBuffer<'q, D, A>> {
+    if let Some(debug) = debug {
+        cmd = annotate_pass(pass, debug, cmd)?;
+    }
+
+    if pass.is_renderpass {
+        cmd = cmd.begin_rendering(&rendering_info(pass, bindings, flags)?);
+    }
+    Ok(cmd)
+}
+
+/// Record everything that happens after the pass executor is called.
+fn end_pass<'q, D: ExecutionDomain, U, A: Allocator>(
+    pass: &PassNode<'_, PassResource, D, U, A>,
+    mut cmd: IncompleteCommandBuffer<'q, D, A>,
+    debug: Option<&Arc<DebugMessenger>>,
+) -> IncompleteCommandBuffer<'q, D, A> {
+    if pass.is_renderpass {
+        cmd = cmd.end_rendering()
+    }
+
+    if let Some(debug) = debug {
+        if cfg!(feature = "debug-markers") {
+            cmd = cmd.end_label(debug);
+        }
+    }
+    cmd
+}
+
+/// Executors of passes in a [subgraph](crate::graph::subgraph) instance resolve resources by the names used inside the subgraph.
+/// This creates the bindings for every alias set of the graph that is used by a pass, once per recording.
+/// # Errors
+/// * Fails if a resource used in a subgraph instance is lacking a physical binding.
+fn subgraph_bindings<D: ExecutionDomain, U, A: Allocator>(
+    graph: &BuiltPassGraph<'_, D, U, A>,
+    bindings: &PhysicalResourceBindings,
+) -> Result<Vec<Option<PhysicalResourceBindings>>> {
+    let mut aliased = vec![None; graph.alias_sets.len()];
+    for node in graph.task_graph().graph.node_weights() {
+        let Node::Task(PassNode { alias_set: Some(index), .. }) = node else { continue };
+        if aliased[*index].is_some() {
+            continue;
+        }
+        let mut set = bindings.clone();
+        for (name, renamed) in &graph.alias_sets[*index] {
+            set.alias(name.clone(), renamed)?;
+        }
+        aliased[*index] = Some(set);
+    }
+    Ok(aliased)
+}
+
+/// Get the bindings the executor of `pass` resolves resources with.
+fn executor_bindings<'a, D: ExecutionDomain, U, A: Allocator>(
+    pass: &PassNode<'_, PassResource, D, U, A>,
+    aliased: &'a [Option<PhysicalResourceBindings>],
+    bindings: &'a PhysicalResourceBindings,
+) -> &'a PhysicalResourceBindings {
+    pass.alias_set
+        .and_then(|index| aliased[index].as_ref())
+        .unwrap_or(bindings)
+}
+
 fn record_pass<'q, D: ExecutionDomain, U, A: Allocator>(
     pass: &mut PassNode<'_, PassResource, D, U, A>,
     bindings: &PhysicalResourceBindings,
+    executor_bindings: &PhysicalResourceBindings,
     local_pool: &mut LocalPool<A>,
-    mut cmd: IncompleteCommandBuffer<'q, D, A>,
+    cmd: IncompleteCommandBuffer<'q, D, A>,
     debug: Option<Arc<DebugMessenger>>,
     user_data: &mut U,
 ) -> Result<IncompleteCommandBuffer<'q, D, A>> {
-    if let Some(debug) = debug.clone() {
-        cmd = annotate_pass(pass, &debug, cmd)?;
+    let cmd = begin_pass(pass, bindings, cmd, debug.as_ref(), vk::RenderingFlags::empty())?;
+    let cmd = pass
+        .execute
+        .executor()
+        .execute(cmd, local_pool, executor_bindings, user_data)?;
+    Ok(end_pass(pass, cmd, debug.as_ref()))
+}
+
+/// How a barrier node is translated into Vulkan barriers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum BarrierKind {
+    /// Read to read dependency without a layout transition, no barrier is needed.
+    Skip,
+    /// No layout transition and no writes to make visible, only an execution dependency is needed.
+    Execution,
+    /// No layout transition, but writes must be made visible using a global memory barrier.
+    Memory,
+    /// Image memory barrier with a layout transition.
+    Image,
+}
+
+pub(crate) const WRITE_ACCESS: vk::AccessFlags2 = vk::AccessFlags2::from_raw(
+    vk::AccessFlags2::SHADER_WRITE.as_raw()
+        | vk::AccessFlags2::SHADER_STORAGE_WRITE.as_raw()
+        | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags2::TRANSFER_WRITE.as_raw()
+        | vk::AccessFlags2::HOST_WRITE.as_raw()
+        | vk::AccessFlags2::MEMORY_WRITE.as_raw()
+        | vk::AccessFlags2::ACCELERATION_STRUCTURE_WRITE_KHR.as_raw(),
+);
+
+/// Determine how a barrier must be recorded. `written_before` indicates that the resource may have been written
+/// before this barrier, either earlier in the graph or by a pass of the previous frame.
+pub(crate) fn barrier_kind(
+    barrier: &PassResourceBarrier,
+    dst_resource: &PassResource,
+    written_before: bool,
+) -> BarrierKind {
+    let transition = barrier.resource.resource.resource_type() == ResourceType::Image
+        && barrier.resource.layout != dst_resource.layout;
+    let src_writes = barrier.src_access.intersects(WRITE_ACCESS);
+    let dst_writes = barrier.dst_access.intersects(WRITE_ACCESS);
+    if transition {
+        BarrierKind::Image
+    } else if !src_writes && !dst_writes && !written_before {
+        BarrierKind::Skip
+    } else if src_writes {
+        BarrierKind::Memory
+    } else {
+        BarrierKind::Execution
     }
+}
 
-    if pass.is_renderpass {
-        let info = RenderingInfo {
-            flags: Default::default(),
-            render_area: render_area(pass, bindings)?,
-            layer_count: 1, // TODO: Multilayer rendering fix
-            view_mask: 0,
-            color_attachments: color_attachments(pass, bindings)?,
-            depth_attachment: match depth_attachment(pass, bindings) {
-                None => None,
-                Some(result) => Some(result?),
-            },
-            stencil_attachment: None, // TODO: Stencil
+/// Returns true if the resource of a barrier node may have been written to before the barrier. Barriers after the source
+/// node synchronize with the previous frame, so these only need to be kept if any pass in the graph writes the resource.
+pub(crate) fn written_before<D: ExecutionDomain, U, A: Allocator>(
+    graph: &PassGraph<'_, D, U, A>,
+    node: NodeIndex,
+) -> bool {
+    let inner = &graph.task_graph().graph;
+    let Some(Node::Barrier(barrier)) = inner.node_weight(node) else { return false };
+    if !inner
+        .neighbors_directed(node, Incoming)
+        .any(|parent| parent == graph.source())
+    {
+        return true;
+    }
+    // History resources are written by the previous frame.
+    if barrier.resource.resource.is_history() {
+        return true;
+    }
+    let name = barrier.resource.resource.name();
+    inner.node_indices().any(|task| match inner.node_weight(task) {
+        Some(Node::Task(pass)) if task != graph.source() => pass
+            .outputs
+            .iter()
+            .any(|output| output.resource.name() == name),
+        _ => false,
+    })
+}
+
+/// Get the final transitions of imported resources that are recorded at the end of a queue batch. These are recorded in the
+/// last batch containing a pass that uses the last version of the resource. Transitions of resources without any pass using them go
+/// into the first batch.
+pub(crate) fn final_transitions_in_batch<'a, D: ExecutionDomain, U, A: Allocator>(
+    graph: &'a PassGraph<'_, D, U, A>,
+    batches: &'a [QueueBatch],
+    index: usize,
+) -> impl Iterator<Item = &'a FinalTransition> {
+    graph
+        .final_transitions()
+        .iter()
+        .filter(move |transition| {
+            let last = batches
+                .iter()
+                .rposition(|batch| transition.passes.iter().any(|pass| batch.passes.contains(pass)));
+            last.unwrap_or(0) == index
+        })
+}
+
+/// Statistics about the barriers recorded for a built graph. Queue family ownership transfers between queues are not included.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct BarrierStats {
+    /// Number of `vkCmdPipelineBarrier2` calls.
+    pub pipeline_barriers: usize,
+    /// Number of image memory barriers.
+    pub image_barriers: usize,
+    /// Number of global memory barriers, including pure execution dependencies.
+    pub memory_barriers: usize,
+    /// Number of barrier nodes that did not need any synchronization.
+    pub skipped: usize,
+    /// Number of barriers split into a `vkCmdSetEvent2` and a `vkCmdWaitEvents2`. These are included in the image and memory
+    /// barrier counts. See the [`split_barrier`](crate::graph::split_barrier) module.
+    pub split_barriers: usize,
+    /// Number of `vkCmdWaitEvents2` calls.
+    pub event_waits: usize,
+}
+
+/// Compute the barrier statistics for the queue batches of a graph. This mirrors what recording the graph emits.
+pub(crate) fn barrier_stats<D: ExecutionDomain, U, A: Allocator>(
+    graph: &PassGraph<'_, D, U, A>,
+    batches: &[QueueBatch],
+) -> Result<BarrierStats> {
+    let inner = &graph.task_graph().graph;
+    let mut stats = BarrierStats::default();
+    for (index, batch) in batches.iter().enumerate() {
+        let mut pending = false;
+        let mut pending_waits = false;
+        for &node in &batch.nodes {
+            if let BatchNode::Node(node) = node {
+                if let Some(Node::Barrier(barrier)) = inner.node_weight(node) {
+                    let dst_resource = PassGraph::barrier_dst_resource(inner, node)?;
+                    let kind = barrier_kind(barrier, dst_resource, written_before(graph, node));
+                    match kind {
+                        BarrierKind::Skip => stats.skipped += 1,
+                        BarrierKind::Execution | BarrierKind::Memory => stats.memory_barriers += 1,
+                        BarrierKind::Image => stats.image_barriers += 1,
+                    }
+                    if kind != BarrierKind::Skip {
+                        match graph.is_split_barrier(node) {
+                            true => {
+                                stats.split_barriers += 1;
+                                pending_waits = true;
+                            }
+                            false => pending = true,
+                        }
+                    }
+                    continue;
+                }
+            }
+            // Any other node flushes the pending barriers
+            stats.pipeline_barriers += pending as usize;
+            stats.event_waits += pending_waits as usize;
+            pending = false;
+            pending_waits = false;
+        }
+        for transition in final_transitions_in_batch(graph, batches, index) {
+            match barrier_kind(&transition.barrier, &transition.dst, true) {
+                BarrierKind::Skip => stats.skipped += 1,
+                BarrierKind::Execution | BarrierKind::Memory => {
+                    stats.memory_barriers += 1;
+                    pending = true;
+                }
+                BarrierKind::Image => {
+                    stats.image_barriers += 1;
+                    pending = true;
+                }
+            }
+        }
+        stats.pipeline_barriers += pending as usize;
+    }
+    Ok(stats)
+}
+
+/// A barrier node translated into a Vulkan barrier.
+#[derive(Copy, Clone)]
+enum VkBarrier {
+    Memory(vk::MemoryBarrier2),
+    Image(vk::ImageMemoryBarrier2),
+}
+
+impl VkBarrier {
+    /// Dependency info containing only this barrier, used to set and wait on the event of a split barrier.
+    fn dependency_info(&self) -> vk::DependencyInfo {
+        let mut dependency = vk::DependencyInfo {
+            s_type: vk::StructureType::DEPENDENCY_INFO,
+            p_next: std::ptr::null(),
+            dependency_flags: vk::DependencyFlags::empty(),
+            memory_barrier_count: 0,
+            p_memory_barriers: std::ptr::null(),
+            buffer_memory_barrier_count: 0,
+            p_buffer_memory_barriers: std::ptr::null(),
+            image_memory_barrier_count: 0,
+            p_image_memory_barriers: std::ptr::null(),
         };
-        cmd = cmd.begin_rendering(&info);
+        match self {
+            VkBarrier::Memory(barrier) => {
+                dependency.memory_barrier_count = 1;
+                dependency.p_memory_barriers = barrier;
+            }
+            VkBarrier::Image(barrier) => {
+                dependency.image_memory_barrier_count = 1;
+                dependency.p_image_memory_barriers = barrier;
+            }
+        }
+        dependency
     }
+}
 
-    cmd = pass.execute.execute(cmd, local_pool, bindings, user_data)?;
+/// State used while recording the nodes of a graph into a single command buffer.
+struct RecordState<'a, U, A: Allocator> {
+    bindings: &'a PhysicalResourceBindings,
+    // Bindings for each alias set of the graph, see `subgraph_bindings()`.
+    aliased: Vec<Option<PhysicalResourceBindings>>,
+    local_pool: &'a mut LocalPool<A>,
+    debug: Option<Arc<DebugMessenger>>,
+    user_data: &'a mut U,
+    profiler: Option<&'a mut PassProfiler>,
+    // Barriers that are ready but were not emitted yet.
+    memory_barriers: Vec<vk::MemoryBarrier2>,
+    image_barriers: Vec<vk::ImageMemoryBarrier2>,
+    // Events set for split barriers whose barrier node was not recorded yet.
+    events: HashMap<NodeIndex, (Event, VkBarrier)>,
+    // Split barriers that are ready, these are waited on before the next pass.
+    waits: Vec<(Event, VkBarrier)>,
+}
 
-    if pass.is_renderpass {
-        cmd = cmd.end_rendering()
+impl<'a, U, A: Allocator> RecordState<'a, U, A> {
+    fn new(
+        bindings: &'a PhysicalResourceBindings,
+        aliased: Vec<Option<PhysicalResourceBindings>>,
+        local_pool: &'a mut LocalPool<A>,
+        debug: Option<Arc<DebugMessenger>>,
+        user_data: &'a mut U,
+        profiler: Option<&'a mut PassProfiler>,
+    ) -> Self {
+        Self {
+            bindings,
+            aliased,
+            local_pool,
+            debug,
+            user_data,
+            profiler,
+            memory_barriers: vec![],
+            image_barriers: vec![],
+            events: HashMap::new(),
+            waits: vec![],
+        }
     }
 
-    if let Some(debug) = debug {
-        if cfg!(feature = "debug-markers") {
-            cmd = cmd.end_label(&debug);
+    /// Record a node. Barriers are collected until the next pass is recorded, so they can be emitted together.
+    fn record_node<'q, D: ExecutionDomain>(
+        &mut self,
+        graph: &mut BuiltPassGraph<'_, D, U, A>,
+        node: NodeIndex,
+        cmd: IncompleteCommandBuffer<'q, D, A>,
+    ) -> Result<IncompleteCommandBuffer<'q, D, A>> {
+        let written_before = written_before(graph, node);
+        let initial = graph
+            .task_graph()
+            .graph
+            .neighbors_directed(node, Incoming)
+            .any(|parent| parent == graph.source());
+        let nodes = &mut graph.graph.graph;
+        let dst_resource_res = PassGraph::barrier_dst_resource(nodes, node).cloned();
+        match nodes.node_weight_mut(node).unwrap() {
+            Node::Task(pass) => {
+                let mut cmd = self.flush_barriers(cmd)?;
+                // Barriers around a skipped pass are still recorded, so all layouts stay as expected by later passes.
+                if pass.is_enabled(self.user_data) {
+                    cmd = match self.profiler.as_deref_mut() {
+                        None => cmd,
+                        Some(profiler) => profiler.begin_pass(&pass.identifier, cmd)?,
+                    };
+                    cmd = record_pass(
+                        pass,
+                        self.bindings,
+                        executor_bindings(pass, &self.aliased, self.bindings),
+                        self.local_pool,
+                        cmd,
+                        self.debug.clone(),
+                        self.user_data,
+                    )?;
+                    cmd = match self.profiler.as_deref_mut() {
+                        None => cmd,
+                        Some(profiler) => profiler.end_pass(cmd)?,
+                    };
+                }
+                self.signal_events(graph, node, cmd)
+            }
+            Node::Barrier(barrier) => {
+                match self.events.remove(&node) {
+                    Some(split) => self.waits.push(split),
+                    None => self.push_barrier(barrier, &dst_resource_res?, written_before, initial)?,
+                }
+                Ok(cmd)
+            }
+            Node::_Unreachable(_) => {
+                unreachable!()
+            }
         }
     }
 
-    Ok(cmd)
+    /// Set the events of all split barriers after a pass, so passes recorded before their first consumer do not wait on them.
+    fn signal_events<'q, D: ExecutionDomain>(
+        &mut self,
+        graph: &PassGraph<'_, D, U, A>,
+        node: NodeIndex,
+        mut cmd: IncompleteCommandBuffer<'q, D, A>,
+    ) -> Result<IncompleteCommandBuffer<'q, D, A>> {
+        for split in graph.split_barriers_after(node) {
+            let inner = &graph.task_graph().graph;
+            let Some(Node::Barrier(barrier)) = inner.node_weight(split) else { unreachable!() };
+            let dst_resource = PassGraph::barrier_dst_resource(inner, split)?;
+            let Some(vk_barrier) = self.vk_barrier(barrier, dst_resource, true, false)? else { continue };
+            let event = self.local_pool.allocate_event()?;
+            cmd = cmd.set_event(&event, &vk_barrier.dependency_info());
+            self.events.insert(split, (event, vk_barrier));
+        }
+        Ok(cmd)
+    }
+
+    fn push_barrier(
+        &mut self,
+        barrier: &PassResourceBarrier,
+        dst_resource: &PassResource,
+        written_before: bool,
+        initial: bool,
+    ) -> Result<()> {
+        match self.vk_barrier(barrier, dst_resource, written_before, initial)? {
+            None => {}
+            Some(VkBarrier::Memory(barrier)) => self.memory_barriers.push(barrier),
+            Some(VkBarrier::Image(barrier)) => self.image_barriers.push(barrier),
+        }
+        Ok(())
+    }
+
+    /// Translate a barrier node into a Vulkan barrier. Returns `None` if no synchronization is needed.
+    fn vk_barrier(
+        &self,
+        barrier: &PassResourceBarrier,
+        dst_resource: &PassResource,
+        written_before: bool,
+        initial: bool,
+    ) -> Result<Option<VkBarrier>> {
+        let resource = &barrier.resource.resource;
+        // The first barrier on an invalidated image must transition it from an undefined layout.
+        let discard = initial
+            && resource.resource_type() == ResourceType::Image
+            && self.bindings.is_invalidated(resource.name());
+        let kind = match discard {
+            true => BarrierKind::Image,
+            false => barrier_kind(barrier, dst_resource, written_before),
+        };
+        Ok(match kind {
+            BarrierKind::Skip => None,
+            BarrierKind::Execution | BarrierKind::Memory => {
+                let memory = kind == BarrierKind::Memory;
+                Some(VkBarrier::Memory(vk::MemoryBarrier2 {
+                    s_type: vk::StructureType::MEMORY_BARRIER_2,
+                    p_next: std::ptr::null(),
+                    src_stage_mask: barrier.src_stage,
+                    src_access_mask: if memory { barrier.src_access } else { vk::AccessFlags2::NONE },
+                    dst_stage_mask: barrier.dst_stage,
+                    dst_access_mask: if memory { barrier.dst_access } else { vk::AccessFlags2::NONE },
+                }))
+            }
+            BarrierKind::Image => {
+                let Some(PhysicalResource::Image(image)) = self.bindings.resolve_subresource(&barrier.resource.resource)? else {
+                    return Err(anyhow::Error::from(Error::NoResourceBound(barrier.resource.resource.uid().to_owned())));
+                };
+                // Image layouts:
+                // barrier.resource has information on srcLayout
+                // dst_resource(barrier) has information on dstLayout
+                Some(VkBarrier::Image(vk::ImageMemoryBarrier2 {
+                    s_type: vk::StructureType::IMAGE_MEMORY_BARRIER_2,
+                    p_next: std::ptr::null(),
+                    src_stage_mask: barrier.src_stage,
+                    src_access_mask: barrier.src_access,
+                    dst_stage_mask: barrier.dst_stage,
+                    dst_access_mask: barrier.dst_access,
+                    old_layout: match discard {
+                        true => vk::ImageLayout::UNDEFINED,
+                        false => barrier.resource.layout,
+                    },
+                    new_layout: dst_resource.layout,
+                    src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    image: unsafe { image.image() },
+                    subresource_range: image.subresource_range(),
+                }))
+            }
+        })
+    }
+
+    /// Queue the transitions of imported resources to their final state. These must be pushed after the last pass.
+    fn push_final_transitions<'t>(
+        &mut self,
+        transitions: impl IntoIterator<Item = &'t FinalTransition>,
+    ) -> Result<()> {
+        for transition in transitions {
+            self.push_barrier(&transition.barrier, &transition.dst, true, false)?;
+        }
+        Ok(())
+    }
+
+    /// Wait on the events of all ready split barriers, and emit all pending barriers in a single pipeline barrier.
+    fn flush_barriers<'q, D: ExecutionDomain>(
+        &mut self,
+        mut cmd: IncompleteCommandBuffer<'q, D, A>,
+    ) -> Result<IncompleteCommandBuffer<'q, D, A>> {
+        if !self.waits.is_empty() {
+            let (events, barriers): (Vec<_>, Vec<_>) = self.waits.drain(..).unzip();
+            let dependencies = barriers
+                .iter()
+                .map(VkBarrier::dependency_info)
+                .collect::<Vec<_>>();
+            cmd = cmd.wait_events(&events, &dependencies)?;
+        }
+        if self.memory_barriers.is_empty() && self.image_barriers.is_empty() {
+            return Ok(cmd);
+        }
+        // Region-local dependencies only make sense for attachments. Global memory barriers are mostly used for
+        // buffers, whose producers and consumers generally do not live in framebuffer-space stages.
+        let dependency_flags = if self.memory_barriers.is_empty() {
+            vk::DependencyFlags::BY_REGION
+        } else {
+            vk::DependencyFlags::empty()
+        };
+        let dependency = vk::DependencyInfo {
+            s_type: vk::StructureType::DEPENDENCY_INFO,
+            p_next: std::ptr::null(),
+            dependency_flags,
+            memory_barrier_count: self.memory_barriers.len() as u32,
+            p_memory_barriers: self.memory_barriers.as_ptr(),
+            buffer_memory_barrier_count: 0,
+            p_buffer_memory_barriers: std::ptr::null(),
+            image_memory_barrier_count: self.image_barriers.len() as u32,
+            p_image_memory_barriers: self.image_barriers.as_ptr(),
+        };
+        let cmd = cmd.pipeline_barrier(&dependency);
+        self.memory_barriers.clear();
+        self.image_barriers.clear();
+        Ok(cmd)
+    }
+}
+
+/// Transient resources are owned by the graph, so we add their bindings to the user bindings.
+/// Returns `None` if the graph has no transient resources.
+fn with_transient_bindings<D: ExecutionDomain, U, A: Allocator>(
+    graph: &BuiltPassGraph<'_, D, U, A>,
+    bindings: &PhysicalResourceBindings,
+) -> Result<Option<PhysicalResourceBindings>> {
+    if graph.transients().is_empty() {
+        return Ok(None);
+    }
+    let Some(allocation) = graph.transients().allocation() else {
+        bail!("Transient resources must be allocated with BuiltPassGraph::update_transients() before recording.");
+    };
+    let mut merged = bindings.clone();
+    merged.extend(allocation.bindings());
+    Ok(Some(merged))
 }
 
-fn record_image_barrier<'q, D: ExecutionDomain, A: Allocator>(
+/// Record one half of a queue family ownership transfer. The release half is recorded on the source queue, the acquire half
+/// on the destination queue after waiting on a semaphore signaled by the source queue.
+/// Only exclusive images on different queue families need an ownership transfer. Buffers are always created with concurrent
+/// sharing on devices with multiple queues, so for those the semaphore is sufficient.
+fn record_ownership_barrier<'q, D: ExecutionDomain, A: Allocator>(
     barrier: &PassResourceBarrier,
-    image: &ImageView,
     dst_resource: &PassResource,
+    bindings: &PhysicalResourceBindings,
+    release: bool,
+    src_family: u32,
+    dst_family: u32,
     cmd: IncompleteCommandBuffer<'q, D, A>,
 ) -> Result<IncompleteCommandBuffer<'q, D, A>> {
-    // Image layouts:
-    // barrier.resource has information on srcLayout
-    // dst_resource(barrier) has information on dstLayout
+    let Some(resource) = bindings.resolve_subresource(&barrier.resource.resource)? else {
+        return Err(anyhow::Error::from(Error::NoResourceBound(barrier.resource.resource.uid().to_owned())));
+    };
+    let PhysicalResource::Image(image) = resource else { return Ok(cmd) };
+    let transfer = image.sharing_mode() == vk::SharingMode::EXCLUSIVE && src_family != dst_family;
+    if release && !transfer {
+        return Ok(cmd);
+    }
 
+    let (src_queue_family_index, dst_queue_family_index) = if transfer {
+        (src_family, dst_family)
+    } else {
+        (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED)
+    };
+    // The acquire barrier is chained to the semaphore wait, which already makes all writes available.
     let vk_barrier = vk::ImageMemoryBarrier2 {
         s_type: vk::StructureType::IMAGE_MEMORY_BARRIER_2,
         p_next: std::ptr::null(),
-        src_stage_mask: barrier.src_stage,
-        src_access_mask: barrier.src_access,
-        dst_stage_mask: barrier.dst_stage,
-        dst_access_mask: barrier.dst_access,
+        src_stage_mask: if release { barrier.src_stage } else { barrier.dst_stage },
+        src_access_mask: if release { barrier.src_access } else { vk::AccessFlags2::NONE },
+        dst_stage_mask: if release { PipelineStage::NONE } else { barrier.dst_stage },
+        dst_access_mask: if release { vk::AccessFlags2::NONE } else { barrier.dst_access },
         old_layout: barrier.resource.layout,
         new_layout: dst_resource.layout,
-        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
-        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        src_queue_family_index,
+        dst_queue_family_index,
         image: unsafe { image.image() },
         subresource_range: image.subresource_range(),
     };
@@ -311,7 +881,7 @@ fn record_image_barrier<'q, D: ExecutionDomain, A: Allocator>(
     let dependency = vk::DependencyInfo {
         s_type: vk::StructureType::DEPENDENCY_INFO,
         p_next: std::ptr::null(),
-        dependency_flags: vk::DependencyFlags::BY_REGION,
+        dependency_flags: vk::DependencyFlags::empty(),
         memory_barrier_count: 0,
         p_memory_barriers: std::ptr::null(),
         buffer_memory_barrier_count: 0,
@@ -323,74 +893,196 @@ fn record_image_barrier<'q, D: ExecutionDomain, A: Allocator>(
     Ok(cmd.pipeline_barrier(&dependency))
 }
 
-fn record_buffer_barrier<'q, D: ExecutionDomain, A: Allocator>(
-    barrier: &PassResourceBarrier,
-    _buffer: &BufferView,
-    _dst_resource: &PassResource,
-    cmd: IncompleteCommandBuffer<'q, D, A>,
-) -> Result<IncompleteCommandBuffer<'q, D, A>> {
-    // Since every driver implements buffer barriers as global memory barriers, we will do the same.
-    let vk_barrier = vk::MemoryBarrier2 {
-        s_type: vk::StructureType::MEMORY_BARRIER_2,
-        p_next: std::ptr::null(),
-        src_stage_mask: barrier.src_stage,
-        src_access_mask: barrier.src_access,
-        dst_stage_mask: barrier.dst_stage,
-        dst_access_mask: barrier.dst_access,
-    };
+impl<'cb, D: ExecutionDomain + 'static, U, A: Allocator> BuiltPassGraph<'cb, D, U, A> {
+    /// Record the graph into one command buffer for each [`QueueBatch`](crate::graph::multi_queue::QueueBatch), and add these to `batch`.
+    /// Each submit waits on the submits it depends on using semaphores. This also works for graphs on a single queue.
+    /// The first submit on every queue other than the queue of the domain also waits on the last submit on the queue of the domain of the
+    /// previous frame recorded with this graph, using a timeline semaphore. Images used on another queue in the next frame are released
+    /// at the end of the frame, and acquired again when the next frame starts.
+    /// Returns the handles of all submits in the order of [`BuiltPassGraph::queue_batches()`]. These can be used to make
+    /// further submits in the batch wait on the graph.
+    /// # Errors
+    /// * Fails if a virtual resource used in the graph is lacking a physical binding.
+    /// * Fails if allocating a command buffer fails.
+    pub fn record_to_batch(
+        &mut self,
+        exec: &ExecutionManager<A>,
+        batch: &mut SubmitBatch<D, A>,
+        bindings: &PhysicalResourceBindings,
+        local_pool: &mut LocalPool<A>,
+        debug: Option<Arc<DebugMessenger>>,
+        user_data: &mut U,
+    ) -> Result<Vec<SubmitHandle>> {
+        let merged_bindings = with_transient_bindings(self, bindings)?;
+        let bindings = merged_bindings.as_ref().unwrap_or(bindings);
 
-    let dependency = vk::DependencyInfo {
-        s_type: vk::StructureType::DEPENDENCY_INFO,
-        p_next: std::ptr::null(),
-        dependency_flags: vk::DependencyFlags::BY_REGION,
-        memory_barrier_count: 1,
-        p_memory_barriers: &vk_barrier,
-        buffer_memory_barrier_count: 0,
-        p_buffer_memory_barriers: std::ptr::null(),
-        image_memory_barrier_count: 0,
-        p_image_memory_barriers: std::ptr::null(),
-    };
+        // Passes on the domain's own queue are submitted to the queue of the domain.
+        let queue_of = |queue: QueueType| (queue != D::QUEUE_TYPE).then_some(queue);
+        let mut families = HashMap::new();
+        for queue_batch in self.queue_batches() {
+            if let Entry::Vacant(entry) = families.entry(queue_batch.queue) {
+                let queue = exec
+                    .get_queue_for::<D>(queue_of(queue_batch.queue))
+                    .ok_or(Error::NoCapableQueue)?;
+                entry.insert(queue.info().family_index);
+            }
+        }
+        let timeline = match &self.frame_sync.timeline {
+            Some(timeline) => timeline.clone(),
+            None => {
+                let timeline = Arc::new(Semaphore::new_timeline(batch.device().clone(), 0)?);
+                self.frame_sync.timeline = Some(timeline.clone());
+                timeline
+            }
+        };
 
-    Ok(cmd.pipeline_barrier(&dependency))
-}
+        let subgraphs = subgraph_bindings(self, bindings)?;
+        let mut state = RecordState::new(bindings, subgraphs, local_pool, debug, user_data, None);
+        let mut handles = Vec::with_capacity(self.queue_batches().len());
+        self.record_queue_batches(
+            &mut state,
+            bindings,
+            &families,
+            |queue| exec.on_queue::<D>(queue_of(queue)),
+            |queue_batch, cmd, (wait, signal)| {
+                let after = queue_batch
+                    .waits
+                    .iter()
+                    .map(|(batch, _)| handles[*batch])
+                    .collect::<Vec<SubmitHandle>>();
+                let stages = queue_batch
+                    .waits
+                    .iter()
+                    .map(|(_, stage)| *stage)
+                    .collect::<Vec<_>>();
+                let handle = batch.submit_on(queue_of(queue_batch.queue), cmd.finish()?, &after, &stages)?;
+                if let Some((frame, stage)) = wait {
+                    batch.wait_timeline(handle, timeline.clone(), frame, stage)?;
+                }
+                if let Some(frame) = signal {
+                    batch.signal_timeline(handle, timeline.clone(), frame)?;
+                }
+                handles.push(handle);
+                Ok(())
+            },
+        )?;
+        Ok(handles)
+    }
 
-fn record_barrier<'q, D: ExecutionDomain, A: Allocator>(
-    barrier: &PassResourceBarrier,
-    dst_resource: &PassResource,
-    bindings: &PhysicalResourceBindings,
-    cmd: IncompleteCommandBuffer<'q, D, A>,
-) -> Result<IncompleteCommandBuffer<'q, D, A>> {
-    let physical_resource = bindings.resolve(&barrier.resource.resource);
-    let Some(resource) = physical_resource else { return Err(anyhow::Error::from(Error::NoResourceBound(barrier.resource.resource.uid().to_owned()))) };
-    match resource {
-        PhysicalResource::Image(image) => record_image_barrier(barrier, image, dst_resource, cmd),
-        PhysicalResource::Buffer(buffer) => {
-            record_buffer_barrier(barrier, buffer, dst_resource, cmd)
+    /// Record the graph like [`BuiltPassGraph::record_to_batch()`], but into a command log for every queue batch instead of submitting it.
+    /// Queue families are numbered in the order their queues are first used by the batches.
+    /// # Errors
+    /// * Fails if a virtual resource used in the graph is lacking a physical binding.
+    /// * Fails if recording any pass fails.
+    pub fn record_to_logs(
+        &mut self,
+        bindings: &PhysicalResourceBindings,
+        local_pool: &mut LocalPool<A>,
+        user_data: &mut U,
+    ) -> Result<Vec<LoggedQueueBatch>> {
+        let merged_bindings = with_transient_bindings(self, bindings)?;
+        let bindings = merged_bindings.as_ref().unwrap_or(bindings);
+        let mut families = HashMap::new();
+        for queue_batch in self.queue_batches() {
+            let family = families.len() as u32;
+            families.entry(queue_batch.queue).or_insert(family);
         }
+
+        let subgraphs = subgraph_bindings(self, bindings)?;
+        let mut state = RecordState::new(bindings, subgraphs, local_pool, None, user_data, None);
+        let mut logs = Vec::with_capacity(self.queue_batches().len());
+        self.record_queue_batches(
+            &mut state,
+            bindings,
+            &families,
+            |_| Ok(IncompleteCommandBuffer::new_logged()),
+            |queue_batch, cmd, (previous_frame_wait, frame_signal)| {
+                logs.push(LoggedQueueBatch {
+                    queue: queue_batch.queue,
+                    log: cmd.into_command_log()?,
+                    previous_frame_wait,
+                    frame_signal,
+                });
+                Ok(())
+            },
+        )?;
+        Ok(logs)
     }
-}
 
-fn record_node<'q, D: ExecutionDomain, U, A: Allocator>(
-    graph: &mut BuiltPassGraph<'_, D, U, A>,
-    node: NodeIndex,
-    bindings: &PhysicalResourceBindings,
-    local_pool: &mut LocalPool<A>,
-    cmd: IncompleteCommandBuffer<'q, D, A>,
-    debug: Option<Arc<DebugMessenger>>,
-    user_data: &mut U,
-) -> Result<IncompleteCommandBuffer<'q, D, A>> {
-    let graph = &mut graph.graph.graph;
-    let dst_resource_res = PassGraph::barrier_dst_resource(graph, node).cloned();
-    let weight = graph.node_weight_mut(node).unwrap();
-    match weight {
-        Node::Task(pass) => record_pass(pass, bindings, local_pool, cmd, debug, user_data),
-        Node::Barrier(barrier) => {
-            // Find destination resource in graph
-            record_barrier(barrier, &dst_resource_res?, bindings, cmd)
-        }
-        Node::_Unreachable(_) => {
-            unreachable!()
+    /// Record every queue batch into a command buffer created by `new_cmd` for its queue, and pass it to `submit` together with the
+    /// frame numbers it waits for and signals. See [`frame_timeline()`].
+    fn record_queue_batches<'q>(
+        &mut self,
+        state: &mut RecordState<'_, U, A>,
+        bindings: &PhysicalResourceBindings,
+        families: &HashMap<QueueType, u32>,
+        mut new_cmd: impl FnMut(QueueType) -> Result<IncompleteCommandBuffer<'q, D, A>>,
+        mut submit: impl FnMut(&QueueBatch, IncompleteCommandBuffer<'q, D, A>, FrameTimeline) -> Result<()>,
+    ) -> Result<()> {
+        self.frame_sync.frame += 1;
+        let frame = self.frame_sync.frame;
+        for index in 0..self.queue_batches().len() {
+            let queue_batch = self.queue_batches()[index].clone();
+            let queue = queue_batch.queue;
+            let mut cmd = new_cmd(queue)?;
+            for &node in &queue_batch.nodes {
+                let (barrier, release, src_queue, dst_queue) = match node {
+                    BatchNode::Node(node) => {
+                        cmd = state.record_node(self, node, cmd)?;
+                        continue;
+                    }
+                    // Nothing was released by a previous frame yet.
+                    BatchNode::FrameAcquire {
+                        barrier,
+                        ..
+                    } if frame == 1 => {
+                        cmd = state.record_node(self, barrier, cmd)?;
+                        continue;
+                    }
+                    BatchNode::Release {
+                        barrier,
+                        dst_queue,
+                    }
+                    | BatchNode::FrameRelease {
+                        barrier,
+                        dst_queue,
+                        ..
+                    } => (barrier, true, queue, dst_queue),
+                    BatchNode::Acquire {
+                        barrier,
+                        src_queue,
+                    }
+                    | BatchNode::FrameAcquire {
+                        barrier,
+                        src_queue,
+                    } => (barrier, false, src_queue, queue),
+                };
+                let graph = &self.task_graph().graph;
+                let dst_resource = PassGraph::barrier_dst_resource(graph, barrier)?;
+                let Node::Barrier(barrier) = graph.node_weight(barrier).unwrap() else { unreachable!() };
+                let mut barrier = Cow::Borrowed(barrier);
+                // Release the image the next frame uses under the name of the barrier resource.
+                if let BatchNode::FrameRelease { swapped: true, .. } = node {
+                    let resource = &barrier.resource.resource;
+                    let swapped = resource.history_source().unwrap_or_else(|| resource.history());
+                    barrier.to_mut().resource.resource = swapped;
+                }
+                cmd = state.flush_barriers(cmd)?;
+                cmd = record_ownership_barrier(
+                    &barrier,
+                    dst_resource,
+                    bindings,
+                    release,
+                    families[&src_queue],
+                    families[&dst_queue],
+                    cmd,
+                )?;
+            }
+            state.push_final_transitions(final_transitions_in_batch(self, self.queue_batches(), index))?;
+            let cmd = state.flush_barriers(cmd)?;
+            submit(&queue_batch, cmd, frame_timeline::<D>(self.queue_batches(), index, frame))?;
         }
+        Ok(())
     }
 }
 
@@ -400,7 +1092,7 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> RecordGraphToCommandBuffer<D, U,
     /// Record the render graph to the command buffer. This will pass `user_data` along to every pass executor in the graph.
     fn record<'q>(
         &mut self,
-        mut cmd: IncompleteCommandBuffer<'q, D, A>,
+        cmd: IncompleteCommandBuffer<'q, D, A>,
         bindings: &PhysicalResourceBindings,
         local_pool: &mut LocalPool<A>,
         debug: Option<Arc<DebugMessenger>>,
@@ -408,39 +1100,396 @@ impl<'cb, D: ExecutionDomain, U, A: Allocator> RecordGraphToCommandBuffer<D, U,
     ) -> Result<IncompleteCommandBuffer<'q, D, A>>
     where
         Self: Sized, {
-        let mut active = HashSet::new();
-        let mut children = HashSet::new();
-        for start in self.graph.sources() {
-            insert_in_active_set(start, self, &mut active, &mut children);
+        self.record_single(cmd, bindings, local_pool, debug, user_data, None)
+    }
+}
+
+impl<'cb, D: ExecutionDomain, U, A: Allocator> BuiltPassGraph<'cb, D, U, A> {
+    /// Record the graph to the command buffer like [`RecordGraphToCommandBuffer::record()`], but measure the GPU time of every pass
+    /// using `profiler`. See the [`profiler`](crate::graph::profiler) module for how to obtain the results.
+    /// This is the only way to profile a graph. Graphs with passes on multiple queues can not be profiled.
+    /// # Errors
+    /// * Fails if a virtual resource used in the graph is lacking a physical binding.
+    /// * Fails if the graph has more passes than the profiler can measure.
+    /// * Fails if the graph has passes on multiple queues.
+    pub fn record_profiled<'q>(
+        &mut self,
+        cmd: IncompleteCommandBuffer<'q, D, A>,
+        bindings: &PhysicalResourceBindings,
+        local_pool: &mut LocalPool<A>,
+        debug: Option<Arc<DebugMessenger>>,
+        user_data: &mut U,
+        profiler: &mut PassProfiler,
+    ) -> Result<IncompleteCommandBuffer<'q, D, A>> {
+        self.record_single(cmd, bindings, local_pool, debug, user_data, Some(profiler))
+    }
+
+    fn record_single<'q>(
+        &mut self,
+        mut cmd: IncompleteCommandBuffer<'q, D, A>,
+        bindings: &PhysicalResourceBindings,
+        local_pool: &mut LocalPool<A>,
+        debug: Option<Arc<DebugMessenger>>,
+        user_data: &mut U,
+        profiler: Option<&mut PassProfiler>,
+    ) -> Result<IncompleteCommandBuffer<'q, D, A>> {
+        if self.is_multi_queue() {
+            bail!("Graphs with passes on multiple queues must be recorded with BuiltPassGraph::record_to_batch().");
         }
-        // Record each initial active node.
-        for node in &active {
-            cmd = record_node(self, *node, bindings, local_pool, cmd, debug.clone(), user_data)?;
+        let merged_bindings = with_transient_bindings(self, bindings)?;
+        let bindings = merged_bindings.as_ref().unwrap_or(bindings);
+
+        let mut state = RecordState::new(bindings, subgraph_bindings(self, bindings)?, local_pool, debug, user_data, profiler);
+
+        let nodes = self
+            .queue_batches()
+            .first()
+            .map(|batch| batch.nodes.clone())
+            .unwrap_or_default();
+        for node in nodes {
+            let BatchNode::Node(node) = node else { unreachable!() };
+            cmd = state.record_node(self, node, cmd)?;
         }
+        state.push_final_transitions(self.final_transitions())?;
+        cmd = state.flush_barriers(cmd)?;
 
-        while active.len() != self.num_nodes() {
-            // For each node that is a child of an active node
-            let mut recorded_nodes = Vec::new();
-            for child in &children {
-                // If all parents of this child node are in the active set, record it.
-                if parents!(child, self).all(|parent| active.contains(&parent)) {
-                    cmd = record_node(
-                        self,
-                        *child,
-                        bindings,
-                        local_pool,
-                        cmd,
-                        debug.clone(),
-                        user_data,
-                    )?;
-                    recorded_nodes.push(*child);
+        Ok(cmd)
+    }
+}
+
+/// Command pools and local pools used to record the secondary command buffers of [`BuiltPassGraph::record_parallel()`].
+/// Every worker thread gets one command pool and one local pool per frame in flight, which are reset and reused when that frame
+/// in flight is recorded again, [`FRAMES_IN_FLIGHT`] calls later. The GPU must have finished executing a frame by then, which
+/// [`FrameManager`](crate::FrameManager) already guarantees when recording once per frame. Its command buffers are freed when this is
+/// dropped, so it must be kept alive until the GPU has finished executing every frame recorded with it.
+#[cfg(feature = "rayon")]
+pub struct ParallelRecording<A: Allocator = DefaultAllocator> {
+    // Pools of every worker, for each frame in flight.
+    frames: [Vec<WorkerPools<A>>; FRAMES_IN_FLIGHT],
+    frame: usize,
+    secondary_count: usize,
+}
+
+#[cfg(feature = "rayon")]
+impl<A: Allocator> Default for ParallelRecording<A> {
+    fn default() -> Self {
+        Self {
+            frames: std::array::from_fn(|_| Vec::new()),
+            frame: 0,
+            secondary_count: 0,
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<A: Allocator> ParallelRecording<A> {
+    /// Create a parallel recording without any pools. Pools are created the first time they are needed.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the number of passes that were recorded into secondary command buffers in the last recorded frame.
+    pub fn secondary_count(&self) -> usize {
+        self.secondary_count
+    }
+
+    /// Get the number of worker pools that were created for all frames in flight.
+    pub fn pool_count(&self) -> usize {
+        self.frames.iter().map(Vec::len).sum()
+    }
+
+    /// Advance to the next frame in flight, and reset the pools that recorded it last time.
+    fn next_frame(&mut self) -> Result<()> {
+        self.frame = (self.frame + 1) % FRAMES_IN_FLIGHT;
+        self.secondary_count = 0;
+        for pools in &mut self.frames[self.frame] {
+            // SAFETY: This frame in flight was last recorded `FRAMES_IN_FLIGHT` frames ago, so the GPU has finished executing it.
+            unsafe { pools.reset()? };
+        }
+        Ok(())
+    }
+
+    /// Get the pools of `count` workers in the current frame, creating them if there are not enough yet.
+    fn workers<D: ExecutionDomain>(
+        &mut self,
+        count: usize,
+        cmd: &IncompleteCommandBuffer<'_, D, A>,
+        target: &SecondaryTarget<A>,
+    ) -> Result<&mut [WorkerPools<A>]> {
+        let workers = &mut self.frames[self.frame];
+        for pools in workers.iter_mut().take(count) {
+            // Pools created for a command log cannot record Vulkan command buffers, and the other way around.
+            if pools.queue.is_some() != matches!(target, SecondaryTarget::Vulkan { .. }) {
+                *pools = WorkerPools::new(cmd, target)?;
+            }
+        }
+        while workers.len() < count {
+            workers.push(WorkerPools::new(cmd, target)?);
+        }
+        Ok(&mut workers[..count])
+    }
+}
+
+/// The command pool and local pool of a single worker thread.
+#[cfg(feature = "rayon")]
+struct WorkerPools<A: Allocator> {
+    // `None` when recording to a command log.
+    queue: Option<Mutex<Queue>>,
+    local_pool: LocalPool<A>,
+    // Secondary command buffers allocated from `queue` since the last reset.
+    command_buffers: Vec<vk::CommandBuffer>,
+}
+
+#[cfg(feature = "rayon")]
+impl<A: Allocator> WorkerPools<A> {
+    fn new<D: ExecutionDomain>(cmd: &IncompleteCommandBuffer<'_, D, A>, target: &SecondaryTarget<A>) -> Result<Self> {
+        let (queue, local_pool) = match target {
+            SecondaryTarget::Vulkan { resource_pool, .. } => {
+                (Some(Mutex::new(cmd.new_queue_with_pool()?)), LocalPool::new(resource_pool.clone())?)
+            }
+            // SAFETY: Passes recorded to a command log only get logged secondary command buffers.
+            SecondaryTarget::Log => (None, unsafe { LocalPool::detached() }),
+        };
+        Ok(Self {
+            queue,
+            local_pool,
+            command_buffers: vec![],
+        })
+    }
+
+    /// Free the command buffers recorded with these pools, and reset both pools.
+    /// # Safety
+    /// The GPU must have finished executing the command buffers recorded with these pools.
+    unsafe fn reset(&mut self) -> Result<()> {
+        if let Some(queue) = &mut self.queue {
+            queue
+                .get_mut()
+                .map_err(|_| Error::PoisonError)?
+                .reset_pool(&self.command_buffers)?;
+        }
+        self.command_buffers.clear();
+        self.local_pool.reset()
+    }
+}
+
+/// Where the secondary command buffers of a parallel recording are recorded to.
+#[cfg(feature = "rayon")]
+enum SecondaryTarget<A: Allocator> {
+    /// Vulkan command buffers, each allocated from its own command pool.
+    Vulkan {
+        resource_pool: ResourcePool<A>,
+        device: Device,
+        pipelines: PipelineCache<A>,
+        descriptors: DescriptorCache,
+    },
+    /// Command logs, if the primary command buffer records to a command log.
+    Log,
+}
+
+/// A pass that is recorded into a secondary command buffer on a worker thread.
+#[cfg(feature = "rayon")]
+struct SecondaryJob<'a, 'cb, D: ExecutionDomain, U, A: Allocator> {
+    node: NodeIndex,
+    executor: &'a mut SendPassFn<'cb, D, U, A>,
+    bindings: &'a PhysicalResourceBindings,
+    inheritance: SecondaryInheritance,
+    user_data: U,
+}
+
+#[cfg(feature = "rayon")]
+struct SecondaryOutput {
+    node: NodeIndex,
+    handle: vk::CommandBuffer,
+    log: Option<CommandLog>,
+}
+
+#[cfg(feature = "rayon")]
+impl<D: ExecutionDomain, U, A: Allocator> SecondaryJob<'_, '_, D, U, A> {
+    fn record(mut self, target: &SecondaryTarget<A>, pools: &mut WorkerPools<A>) -> Result<SecondaryOutput> {
+        let WorkerPools {
+            queue,
+            local_pool,
+            command_buffers,
+        } = pools;
+        let (SecondaryTarget::Vulkan { device, pipelines, descriptors, .. }, Some(queue)) = (target, queue.as_ref()) else {
+            let cmd = IncompleteCommandBuffer::<D, A>::new_logged_secondary(&self.inheritance);
+            let log = self
+                .executor
+                .execute(cmd, local_pool, self.bindings, &mut self.user_data)?
+                .into_command_log()?;
+            return Ok(SecondaryOutput {
+                node: self.node,
+                handle: detached_handle(),
+                log: Some(log),
+            });
+        };
+        let lock = queue.lock().map_err(|_| Error::PoisonError)?;
+        let cmd = Queue::allocate_secondary_command_buffer::<D, A>(
+            device.clone(),
+            lock,
+            &self.inheritance,
+            pipelines.clone(),
+            descriptors.clone(),
+        )?;
+        // SAFETY: The command buffer is only executed by the primary command buffer, and freed when its pool is reset.
+        command_buffers.push(unsafe { cmd.handle() });
+        let cmd = self
+            .executor
+            .execute(cmd, local_pool, self.bindings, &mut self.user_data)?
+            .finish()?;
+        Ok(SecondaryOutput {
+            node: self.node,
+            handle: unsafe { cmd.handle() },
+            log: None,
+        })
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'cb, D: ExecutionDomain, U: Clone + Send, A: Allocator> BuiltPassGraph<'cb, D, U, A> {
+    /// Record the graph to a command buffer, recording independent passes in parallel. Passes in the same traversal step that
+    /// have an executor set with [`PassBuilder::parallel_executor()`](crate::PassBuilder::parallel_executor) are recorded on the
+    /// rayon thread pool into secondary command buffers, which are then executed in the primary command buffer using `vkCmdExecuteCommands`.
+    /// Each worker thread records its passes with the command pool and [`LocalPool`] it has in `recording` for the current frame in flight,
+    /// and every pass gets a clone of `user_data`, so changes made to the user data by these passes are not visible to other passes.
+    /// All other passes and all barriers are recorded into `cmd` exactly like [`RecordGraphToCommandBuffer::record()`].
+    /// # Errors
+    /// * Fails if the graph has passes on multiple queues.
+    /// * Fails if recording any pass fails.
+    pub fn record_parallel<'q>(
+        &mut self,
+        mut cmd: IncompleteCommandBuffer<'q, D, A>,
+        bindings: &PhysicalResourceBindings,
+        local_pool: &mut LocalPool<A>,
+        debug: Option<Arc<DebugMessenger>>,
+        user_data: &mut U,
+        recording: &mut ParallelRecording<A>,
+    ) -> Result<IncompleteCommandBuffer<'q, D, A>> {
+        if self.is_multi_queue() {
+            bail!("Graphs with passes on multiple queues must be recorded with BuiltPassGraph::record_to_batch().");
+        }
+        let merged_bindings = with_transient_bindings(self, bindings)?;
+        let bindings = merged_bindings.as_ref().unwrap_or(bindings);
+        let target = match cmd.command_log() {
+            Some(_) => SecondaryTarget::Log,
+            None => {
+                let Some(resource_pool) = local_pool.try_resource_pool().cloned() else {
+                    bail!("Parallel recording requires a local pool created from a resource pool");
+                };
+                let (device, pipelines, descriptors) = cmd.caches()?;
+                SecondaryTarget::Vulkan {
+                    resource_pool,
+                    device,
+                    pipelines,
+                    descriptors,
+                }
+            }
+        };
+
+        let recorded = self
+            .queue_batches()
+            .first()
+            .map(|batch| {
+                batch
+                    .nodes
+                    .iter()
+                    .filter_map(|node| match node {
+                        BatchNode::Node(node) => Some(*node),
+                        _ => None,
+                    })
+                    .collect::<HashSet<_>>()
+            })
+            .unwrap_or_default();
+        let steps = self.steps().to_vec();
+
+        recording.next_frame()?;
+        let mut state = RecordState::new(bindings, subgraph_bindings(self, bindings)?, local_pool, debug.clone(), user_data, None);
+        for step in steps {
+            let step = step
+                .into_iter()
+                .filter(|node| recorded.contains(node))
+                .collect::<Vec<_>>();
+            let mut jobs = Vec::new();
+            for (index, weight) in self.graph.graph.node_weights_mut().enumerate() {
+                let node = NodeIndex::new(index);
+                let Node::Task(pass) = weight else { continue };
+                if !step.contains(&node)
+                    || !matches!(pass.execute, PassFn::Send(_))
+                    || !pass.is_enabled(state.user_data)
+                {
+                    continue;
                 }
+                let inheritance = match pass.is_renderpass {
+                    true => {
+                        let info = rendering_info(pass, bindings, vk::RenderingFlags::empty())?;
+                        let samples = info
+                            .color_attachments
+                            .iter()
+                            .chain(info.depth_attachment.iter())
+                            .map(|attachment| attachment.image_view.samples())
+                            .next()
+                            .unwrap_or(vk::SampleCountFlags::TYPE_1);
+                        SecondaryInheritance {
+                            rendering: Some(info.pipeline_rendering_info()),
+                            render_area: info.render_area,
+                            samples,
+                        }
+                    }
+                    false => SecondaryInheritance::default(),
+                };
+                let executor_bindings = executor_bindings(pass, &state.aliased, bindings);
+                let PassFn::Send(executor) = &mut pass.execute else { unreachable!() };
+                jobs.push(SecondaryJob {
+                    node,
+                    executor,
+                    bindings: executor_bindings,
+                    inheritance,
+                    user_data: state.user_data.clone(),
+                });
             }
-            // Now we swap all recorded nodes to the active set
-            for node in recorded_nodes {
-                insert_in_active_set(node, self, &mut active, &mut children);
+
+            // Jobs are spread over one set of pools per worker thread, and each worker records its jobs in order.
+            recording.secondary_count += jobs.len();
+            let workers = recording.workers(jobs.len().min(rayon::current_num_threads()), &cmd, &target)?;
+            let mut assigned = workers.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+            for (index, job) in jobs.into_iter().enumerate() {
+                assigned[index % workers.len()].push(job);
+            }
+            let mut outputs = assigned
+                .into_par_iter()
+                .zip(workers.par_iter_mut())
+                .map(|(jobs, pools)| {
+                    jobs.into_iter()
+                        .map(|job| job.record(&target, pools))
+                        .collect::<Result<Vec<_>>>()
+                })
+                .collect::<Result<Vec<_>>>()?
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>();
+
+            for node in step {
+                let Some(output) = outputs.iter_mut().find(|output| output.node == node) else {
+                    cmd = state.record_node(self, node, cmd)?;
+                    continue;
+                };
+                // Barriers are recorded before the first pass of each step, so the placement matches serial recording.
+                cmd = state.flush_barriers(cmd)?;
+                let Some(Node::Task(pass)) = self.task_graph().graph.node_weight(node) else { unreachable!() };
+                cmd = begin_pass(
+                    pass,
+                    bindings,
+                    cmd,
+                    debug.as_ref(),
+                    vk::RenderingFlags::CONTENTS_SECONDARY_COMMAND_BUFFERS,
+                )?;
+                cmd = cmd.execute_commands(std::slice::from_ref(&output.handle), output.log.take().into_iter().collect());
+                cmd = end_pass(pass, cmd, debug.as_ref());
+                cmd = state.signal_events(self, node, cmd)?;
             }
         }
+        state.push_final_transitions(self.final_transitions())?;
+        cmd = state.flush_barriers(cmd)?;
 
         Ok(cmd)
     }
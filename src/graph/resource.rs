@@ -3,9 +3,13 @@
 use ash::vk;
 
 use crate::graph::virtual_resource::VirtualResource;
+use crate::pipeline::PipelineStage;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// Type of a resource in the pass graph.
 #[derive(Debug, Default, Copy, Clone, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "snake_case"))]
 pub enum ResourceType {
     /// Image resource
     #[default]
@@ -14,16 +18,87 @@ pub enum ResourceType {
     Buffer,
 }
 
-#[derive(Debug, Default, PartialEq, Eq, Clone)]
+/// Describes how a buffer resource is used inside a pass.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "snake_case"))]
+pub enum BufferUsage {
+    /// Buffer is bound as a uniform buffer.
+    Uniform,
+    /// Buffer is bound as a storage buffer.
+    Storage,
+    /// Buffer is bound as a vertex buffer.
+    Vertex,
+    /// Buffer is bound as an index buffer.
+    Index,
+    /// Buffer is used as the source of indirect draw or dispatch parameters.
+    Indirect,
+    /// Buffer is the source or destination of a transfer command.
+    Transfer,
+}
+
+impl BufferUsage {
+    /// Get the access flags for reading from a buffer with this usage.
+    pub fn read_access(&self) -> vk::AccessFlags2 {
+        match self {
+            BufferUsage::Uniform => vk::AccessFlags2::UNIFORM_READ,
+            BufferUsage::Storage => vk::AccessFlags2::SHADER_STORAGE_READ,
+            BufferUsage::Vertex => vk::AccessFlags2::VERTEX_ATTRIBUTE_READ,
+            BufferUsage::Index => vk::AccessFlags2::INDEX_READ,
+            BufferUsage::Indirect => vk::AccessFlags2::INDIRECT_COMMAND_READ,
+            BufferUsage::Transfer => vk::AccessFlags2::TRANSFER_READ,
+        }
+    }
+
+    /// Get the access flags for writing to a buffer with this usage. Returns `None` if
+    /// buffers with this usage cannot be written to.
+    pub fn write_access(&self) -> Option<vk::AccessFlags2> {
+        match self {
+            BufferUsage::Storage => Some(vk::AccessFlags2::SHADER_STORAGE_WRITE),
+            BufferUsage::Transfer => Some(vk::AccessFlags2::TRANSFER_WRITE),
+            _ => None,
+        }
+    }
+
+    /// Get the pipeline stage this usage implicitly happens in, if there is one.
+    /// Uniform and storage buffers can be accessed from any shader stage, so these return `None`.
+    pub fn implied_stage(&self) -> Option<PipelineStage> {
+        match self {
+            BufferUsage::Uniform => None,
+            BufferUsage::Storage => None,
+            BufferUsage::Vertex => Some(PipelineStage::VERTEX_ATTRIBUTE_INPUT),
+            BufferUsage::Index => Some(PipelineStage::INDEX_INPUT),
+            BufferUsage::Indirect => Some(PipelineStage::DRAW_INDIRECT),
+            BufferUsage::Transfer => Some(PipelineStage::TRANSFER),
+        }
+    }
+}
+
+/// State of a resource outside of a pass graph: the layout it is in, and how it is accessed there.
+/// See [`PassGraph::import_resource()`](crate::PassGraph::import_resource).
+#[derive(Debug, Default, Copy, Clone, Hash, PartialEq, Eq)]
+pub struct ResourceState {
+    /// Image layout. Ignored for buffers.
+    pub layout: vk::ImageLayout,
+    /// Access flags of the accesses outside the graph.
+    pub access: vk::AccessFlags2,
+    /// Pipeline stages the resource is accessed in outside the graph.
+    pub stage: PipelineStage,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Hash)]
 pub(crate) enum AttachmentType {
     #[default]
     Color,
     Depth,
+    // Depth attachment used for depth testing only, optionally sampled in the same pass.
+    DepthReadOnly { sampled: bool },
+    Stencil,
+    DepthStencil,
     Resolve(VirtualResource),
 }
 
 /// Resource usage in a task graph.
-#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Hash)]
 #[allow(dead_code)]
 pub(crate) enum ResourceUsage {
     #[default]
@@ -32,6 +107,14 @@ pub(crate) enum ResourceUsage {
     Attachment(AttachmentType),
     ShaderRead,
     ShaderWrite,
+    BufferRead(BufferUsage),
+    BufferWrite(BufferUsage),
+    BufferReadWrite(BufferUsage),
+    // Source or destination of a transfer command, for both images and buffers.
+    TransferRead,
+    TransferWrite,
+    // Access by code outside the graph, with these access flags.
+    External(vk::AccessFlags2),
 }
 
 impl ResourceUsage {
@@ -43,14 +126,26 @@ impl ResourceUsage {
             ResourceUsage::Attachment(AttachmentType::Color) => {
                 vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
             }
-            ResourceUsage::Attachment(AttachmentType::Depth) => {
+            ResourceUsage::Attachment(AttachmentType::Depth | AttachmentType::Stencil | AttachmentType::DepthStencil) => {
                 vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE
             }
+            ResourceUsage::Attachment(AttachmentType::DepthReadOnly { sampled }) => match sampled {
+                true => vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags2::SHADER_READ,
+                false => vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ,
+            },
             ResourceUsage::Attachment(AttachmentType::Resolve(_)) => {
                 vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
             }
             ResourceUsage::ShaderRead => vk::AccessFlags2::SHADER_READ,
             ResourceUsage::ShaderWrite => vk::AccessFlags2::SHADER_WRITE,
+            ResourceUsage::BufferRead(usage) => usage.read_access(),
+            ResourceUsage::BufferWrite(usage) => usage.write_access().unwrap_or_default(),
+            ResourceUsage::BufferReadWrite(usage) => {
+                usage.read_access() | usage.write_access().unwrap_or_default()
+            }
+            ResourceUsage::TransferRead => vk::AccessFlags2::TRANSFER_READ,
+            ResourceUsage::TransferWrite => vk::AccessFlags2::TRANSFER_WRITE,
+            ResourceUsage::External(access) => *access,
         }
     }
 
@@ -59,9 +154,17 @@ impl ResourceUsage {
         match self {
             ResourceUsage::Nothing => true,
             ResourceUsage::Present => false,
+            ResourceUsage::Attachment(AttachmentType::DepthReadOnly { .. }) => true,
             ResourceUsage::Attachment(_) => false,
             ResourceUsage::ShaderRead => true,
             ResourceUsage::ShaderWrite => false,
+            ResourceUsage::BufferRead(_) => true,
+            ResourceUsage::BufferWrite(_) => false,
+            ResourceUsage::BufferReadWrite(_) => false,
+            ResourceUsage::TransferRead => true,
+            ResourceUsage::TransferWrite => false,
+            // External accesses only happen before or after the graph, so they never share a barrier with a pass.
+            ResourceUsage::External(_) => true,
         }
     }
 }
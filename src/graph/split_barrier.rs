@@ -0,0 +1,129 @@
+//! Split barriers let independent work overlap with a barrier.
+//!
+//! A pipeline barrier makes every command after it wait for the commands it synchronizes with. When the pass producing a resource
+//! is recorded long before the first pass consuming it, the passes in between do not need to wait for the barrier at all.
+//! Such barriers are split into a `vkCmdSetEvent2` recorded right after the producing pass, and a `vkCmdWaitEvents2` recorded
+//! right before the consuming pass, so the GPU can execute the passes in between while the barrier is resolved.
+//!
+//! A barrier is split when at least [`DEFAULT_SPLIT_BARRIER_DISTANCE`] passes are recorded between the producer and the first consumer.
+//! This can be changed with [`PassGraph::split_barrier_distance()`]. Barriers with the source of the graph as producer and queue family
+//! ownership transfers are never split. [`PassGraph::force_pipeline_barriers()`] disables split barriers entirely, which is useful to rule
+//! them out while debugging synchronization issues.
+//!
+//! Events are allocated from the [`LocalPool`](crate::pool::LocalPool) passed to the recording functions, and are released when it is dropped.
+//!
+//! # Example
+//! ```
+//! use phobos::prelude::*;
+//!
+//! // Only split barriers with at least four passes between the producer and the first consumer.
+//! let graph = graph.split_barrier_distance(4).build()?;
+//! println!("{} barrier(s) split using events", graph.barrier_stats().split_barriers);
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use petgraph::graph::NodeIndex;
+use petgraph::{Incoming, Outgoing};
+
+use crate::{Allocator, PassGraph};
+use crate::graph::multi_queue::{BatchNode, QueueBatch};
+use crate::graph::record::{barrier_kind, written_before, BarrierKind};
+use crate::graph::task_graph::Node;
+use crate::sync::domain::ExecutionDomain;
+
+/// Default minimum number of passes between the producer and the first consumer of a resource for its barrier to be split.
+pub const DEFAULT_SPLIT_BARRIER_DISTANCE: usize = 2;
+
+/// Settings controlling which barriers are split into event signals and waits.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub(crate) struct SplitBarrierSettings {
+    pub distance: usize,
+    pub force_pipeline_barriers: bool,
+}
+
+impl Default for SplitBarrierSettings {
+    fn default() -> Self {
+        Self {
+            distance: DEFAULT_SPLIT_BARRIER_DISTANCE,
+            force_pipeline_barriers: false,
+        }
+    }
+}
+
+/// Find all barrier nodes that are recorded as a split barrier. The producer of a split barrier is always
+/// recorded in the same queue batch as the barrier, so the event is set and waited on in the same command buffer.
+pub(crate) fn plan_split_barriers<D: ExecutionDomain, U, A: Allocator>(
+    graph: &PassGraph<'_, D, U, A>,
+    batches: &[QueueBatch],
+) -> Result<HashSet<NodeIndex>> {
+    let mut split = HashSet::new();
+    if graph.split_barriers.force_pipeline_barriers {
+        return Ok(split);
+    }
+    let inner = &graph.task_graph().graph;
+    for batch in batches {
+        // Number of passes recorded in this batch before each pass.
+        let mut positions = HashMap::new();
+        let mut passes = 0;
+        for &node in &batch.nodes {
+            let BatchNode::Node(node) = node else { continue };
+            match inner.node_weight(node) {
+                Some(Node::Task(_)) => {
+                    positions.insert(node, passes);
+                    passes += 1;
+                }
+                Some(Node::Barrier(barrier)) => {
+                    let Some(producer) = inner.neighbors_directed(node, Incoming).next() else { continue };
+                    if producer == graph.source() {
+                        continue;
+                    }
+                    let Some(position) = positions.get(&producer) else { continue };
+                    // Barriers are recorded right before their first consumer.
+                    if passes - position - 1 < graph.split_barriers.distance {
+                        continue;
+                    }
+                    let dst_resource = PassGraph::<D, U, A>::barrier_dst_resource(inner, node)?;
+                    if barrier_kind(barrier, dst_resource, written_before(graph, node)) != BarrierKind::Skip {
+                        split.insert(node);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(split)
+}
+
+impl<'cb, D: ExecutionDomain, U, A: Allocator> PassGraph<'cb, D, U, A> {
+    /// Set the minimum number of passes between the producer and the first consumer of a resource for its barrier to be split
+    /// into an event signal and wait. The default is [`DEFAULT_SPLIT_BARRIER_DISTANCE`].
+    pub fn split_barrier_distance(mut self, distance: usize) -> Self {
+        self.split_barriers.distance = distance;
+        self
+    }
+
+    /// Record every barrier as a pipeline barrier, even if it could be split. Useful for debugging synchronization issues.
+    pub fn force_pipeline_barriers(mut self, force: bool) -> Self {
+        self.split_barriers.force_pipeline_barriers = force;
+        self
+    }
+
+    /// Returns true if the barrier node is recorded as a split barrier.
+    pub(crate) fn is_split_barrier(&self, node: NodeIndex) -> bool {
+        self.split.contains(&node)
+    }
+
+    /// Get the split barriers that must be signaled after a pass.
+    pub(crate) fn split_barriers_after(&self, node: NodeIndex) -> Vec<NodeIndex> {
+        let mut barriers = self
+            .task_graph()
+            .graph
+            .neighbors_directed(node, Outgoing)
+            .filter(|barrier| self.is_split_barrier(*barrier))
+            .collect::<Vec<_>>();
+        barriers.sort();
+        barriers
+    }
+}
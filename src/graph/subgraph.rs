@@ -0,0 +1,313 @@
+//! Subgraphs package a group of passes, such as a bloom chain or a set of shadow cascades, so they can be added to
+//! a pass graph multiple times.
+//!
+//! A [`Subgraph`] declares the resources it reads from the rest of the graph as inputs, and the resources the rest of the graph
+//! reads from it as outputs. When a subgraph is [instantiated](Subgraph::instantiate), every resource it uses is renamed:
+//! * Inputs, and outputs that are bound to a resource of the graph, are replaced by that resource. Versions carry over, so if an input
+//!   is bound to `hdr+` and a pass of the subgraph writes it, later passes of the subgraph use `hdr++`.
+//!   Ranges carry over as well: if `input.mip(0)` is bound to `hdr.mip(2)`, the subgraph uses mip level 2 of `hdr`. A subgraph using
+//!   a range of an input that was declared with another range can not be instantiated with a range of the bound resource.
+//! * All other resources are internal to the instance, and are prefixed with its namespace, for example `bloom_0/mip1`.
+//!   Internal resources of different instances never collide.
+//!
+//! Pass names are prefixed with the namespace as well. Executors of the passes do not need to know about the namespace, since they
+//! can resolve resources using the names used inside the subgraph. These are aliased to the renamed resources while recording.
+//!
+//! # Example
+//! ```
+//! use phobos::prelude::*;
+//! use phobos::graph::subgraph::Subgraph;
+//!
+//! fn blur<'cb>() -> Result<Subgraph<'cb, domain::All>> {
+//!     let input = image!("input");
+//!     let blurred = image!("blurred");
+//!     let pass = PassBuilder::render("blur")
+//!         .sample_image(&input, PipelineStage::FRAGMENT_SHADER)
+//!         .clear_color_attachment(&blurred, ClearColor::Float([0.0; 4]))?
+//!         .execute_fn(|cmd, _, bindings, _| {
+//!             // Resolves to the image bound to `input` of this instance.
+//!             let input = bindings.resolve(&image!("input"));
+//!             Ok(cmd)
+//!         })
+//!         .build();
+//!     Subgraph::new().input(&input).output(&blurred).add_pass(pass)
+//! }
+//!
+//! let first = blur()?.instantiate("blur_0", &[(&image!("input"), &image!("scene"))])?;
+//! let second = blur()?.instantiate("blur_1", &[(&image!("input"), first.output(&image!("blurred")).unwrap())])?;
+//! // Passes after the subgraphs read `blur_1/blurred+`.
+//! let result = second.output(&image!("blurred")).unwrap().clone();
+//! let graph = PassGraph::new().add_subgraph(first)?.add_subgraph(second)?;
+//! ```
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{Allocator, DefaultAllocator, Error, Pass, PassGraph, QueueType, SubresourceRange, VirtualResource};
+use crate::graph::pass_graph::PassResource;
+use crate::graph::resource::{AttachmentType, ResourceUsage};
+use crate::graph::transient::{TransientBufferInfo, TransientImageInfo, TransientInfo};
+use crate::sync::domain::ExecutionDomain;
+
+/// A group of passes with declared inputs and outputs, that can be added to a pass graph multiple times.
+/// See the [`subgraph`](crate::graph::subgraph) module for more information.
+pub struct Subgraph<'cb, D: ExecutionDomain, U = (), A: Allocator = DefaultAllocator> {
+    inputs: Vec<VirtualResource>,
+    outputs: Vec<VirtualResource>,
+    passes: Vec<Pass<'cb, D, U, A>>,
+    transients: Vec<(VirtualResource, TransientInfo)>,
+}
+
+/// A subgraph with all its resources and passes renamed, ready to be added to a graph with [`PassGraph::add_subgraph()`].
+pub struct SubgraphInstance<'cb, D: ExecutionDomain, U = (), A: Allocator = DefaultAllocator> {
+    namespace: String,
+    // Each declared output, and the newest version of the resource it was renamed to.
+    outputs: Vec<(VirtualResource, VirtualResource)>,
+    passes: Vec<Pass<'cb, D, U, A>>,
+    transients: Vec<(VirtualResource, TransientInfo)>,
+}
+
+struct Renamer<'a> {
+    namespace: &'a str,
+    bindings: &'a [(&'a VirtualResource, &'a VirtualResource)],
+}
+
+impl Renamer<'_> {
+    /// Find the binding of a resource, preferring a declared resource with the same range over one with the same name.
+    fn binding(&self, resource: &VirtualResource) -> Option<(&VirtualResource, &VirtualResource)> {
+        self.bindings
+            .iter()
+            .find(|(formal, _)| formal.is_associated_with(resource))
+            .or_else(|| {
+                self.bindings
+                    .iter()
+                    .find(|(formal, _)| formal.name() == resource.name())
+            })
+            .map(|(formal, actual)| (*formal, *actual))
+    }
+
+    /// Get the range of `actual` that `resource` refers to, given that `formal` is bound to `actual`.
+    fn bound_range(
+        resource: &VirtualResource,
+        formal: &VirtualResource,
+        actual: &VirtualResource,
+    ) -> Result<Option<SubresourceRange>> {
+        let range = resource.subresource_range();
+        match (formal.subresource_range(), actual.subresource_range()) {
+            // The declared range of the subgraph is mapped to the range of the bound resource.
+            (formal_range, actual_range) if formal_range == range => Ok(actual_range.or(range)),
+            (None, None) => Ok(range),
+            (None, Some(actual_range)) if range == Some(actual_range) => Ok(range),
+            _ => Err(anyhow!(
+                "Subgraph uses `{}`, which conflicts with the range of `{}` bound to `{}`",
+                resource.uid(),
+                actual.uid(),
+                formal.uid()
+            )),
+        }
+    }
+
+    fn rename(&self, resource: &VirtualResource) -> Result<VirtualResource> {
+        if let Some(source) = resource.history_source() {
+            let name = match self.binding(&source) {
+                Some((_, actual)) => actual.name().to_owned(),
+                None => format!("{}/{}", self.namespace, source.name()),
+            };
+            return Ok(source.renamed(name, 0).history());
+        }
+        let Some((formal, actual)) = self.binding(resource) else {
+            return Ok(resource.renamed(format!("{}/{}", self.namespace, resource.name()), resource.version()));
+        };
+        let version = resource
+            .version()
+            .checked_sub(formal.version())
+            .ok_or_else(|| {
+                anyhow!("Subgraph uses `{}`, which is older than the version it is bound with", resource.uid())
+            })?;
+        let renamed = resource.renamed(actual.name(), actual.version() + version);
+        Ok(match Self::bound_range(resource, formal, actual)? {
+            Some(range) => renamed.subresource(range),
+            None => renamed,
+        })
+    }
+
+    fn rename_resource(&self, resource: &mut PassResource) -> Result<()> {
+        resource.resource = self.rename(&resource.resource)?;
+        if let ResourceUsage::Attachment(AttachmentType::Resolve(src)) = &mut resource.usage {
+            *src = self.rename(src)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'cb, D: ExecutionDomain, U, A: Allocator> Subgraph<'cb, D, U, A> {
+    /// Create an empty subgraph.
+    pub fn new() -> Self {
+        Self {
+            inputs: vec![],
+            outputs: vec![],
+            passes: vec![],
+            transients: vec![],
+        }
+    }
+
+    /// Declare a resource that is read from the rest of the graph. Every input must be bound when instantiating the subgraph.
+    pub fn input(mut self, resource: &VirtualResource) -> Self {
+        self.inputs.push(resource.clone());
+        self
+    }
+
+    /// Declare a resource that is read by the rest of the graph. Outputs can be bound to a resource of the graph when instantiating
+    /// the subgraph, otherwise they are internal to the instance. Use [`SubgraphInstance::output()`] to obtain the renamed resource.
+    pub fn output(mut self, resource: &VirtualResource) -> Self {
+        self.outputs.push(resource.clone());
+        self
+    }
+
+    /// Add a pass to the subgraph, see [`PassGraph::add_pass()`].
+    /// # Errors
+    /// - Fails if the pass is a renderpass that does not execute on a graphics queue.
+    pub fn add_pass(mut self, pass: Pass<'cb, D, U, A>) -> Result<Self> {
+        if pass.is_renderpass && pass.queue != QueueType::Graphics {
+            return Err(Error::Uncategorized("Renderpasses must execute on a graphics queue").into());
+        }
+        self.passes.push(pass);
+        Ok(self)
+    }
+
+    /// Declare a transient image owned by each instance of the subgraph, see [`PassGraph::add_transient_image()`].
+    pub fn add_transient_image(mut self, resource: &VirtualResource, info: TransientImageInfo) -> Self {
+        self.transients
+            .push((resource.clone(), TransientInfo::Image(info)));
+        self
+    }
+
+    /// Declare a transient buffer owned by each instance of the subgraph, see [`PassGraph::add_transient_buffer()`].
+    pub fn add_transient_buffer(mut self, resource: &VirtualResource, info: TransientBufferInfo) -> Self {
+        self.transients
+            .push((resource.clone(), TransientInfo::Buffer(info)));
+        self
+    }
+
+    /// Rename all resources and passes of this subgraph for use in a graph. `bindings` maps inputs and outputs of the subgraph
+    /// to resources of the graph. All other resources are prefixed with `namespace`.
+    /// # Errors
+    /// * Fails if an input of the subgraph is not bound.
+    /// * Fails if a resource is bound that is not an input or output of the subgraph.
+    /// * Fails if the subgraph uses an older version of a bound resource than the version it was bound with.
+    pub fn instantiate(
+        self,
+        namespace: impl Into<String>,
+        bindings: &[(&VirtualResource, &VirtualResource)],
+    ) -> Result<SubgraphInstance<'cb, D, U, A>> {
+        let namespace = namespace.into();
+        for (formal, _) in bindings {
+            if !self
+                .inputs
+                .iter()
+                .chain(&self.outputs)
+                .any(|resource| resource.name() == formal.name())
+            {
+                bail!("`{}` is not an input or output of the subgraph", formal.name());
+            }
+        }
+        if let Some(input) = self
+            .inputs
+            .iter()
+            .find(|input| !bindings.iter().any(|(formal, _)| formal.name() == input.name()))
+        {
+            bail!("Input `{}` of subgraph `{namespace}` is not bound", input.name());
+        }
+
+        let renamer = Renamer {
+            namespace: &namespace,
+            bindings,
+        };
+        let mut passes = self.passes;
+        let mut aliases = Vec::new();
+        for pass in &mut passes {
+            pass.name = format!("{namespace}/{}", pass.name);
+            for resource in pass.inputs.iter_mut().chain(pass.outputs.iter_mut()) {
+                let name = resource.resource.name().to_owned();
+                renamer.rename_resource(resource)?;
+                let alias = (name, resource.resource.name().to_owned());
+                if alias.0 != alias.1 && !aliases.contains(&alias) {
+                    aliases.push(alias);
+                }
+            }
+        }
+        // Executors may resolve any resource of the subgraph, so every pass gets all aliases.
+        for pass in &mut passes {
+            pass.aliases.extend(aliases.iter().cloned());
+        }
+
+        let transients = self
+            .transients
+            .into_iter()
+            .map(|(resource, info)| Ok((renamer.rename(&resource)?, info)))
+            .collect::<Result<Vec<_>>>()?;
+        let outputs = self
+            .outputs
+            .iter()
+            .map(|output| {
+                let renamed = renamer.rename(output)?;
+                let newest = passes
+                    .iter()
+                    .flat_map(|pass| pass.outputs.iter())
+                    .map(|resource| &resource.resource)
+                    .filter(|resource| resource.is_associated_with(&renamed))
+                    .max_by_key(|resource| resource.version())
+                    .cloned()
+                    .unwrap_or(renamed);
+                Ok((output.clone(), newest))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(SubgraphInstance {
+            namespace,
+            outputs,
+            passes,
+            transients,
+        })
+    }
+}
+
+impl<'cb, D: ExecutionDomain, U, A: Allocator> Default for Subgraph<'cb, D, U, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'cb, D: ExecutionDomain, U, A: Allocator> SubgraphInstance<'cb, D, U, A> {
+    /// Get the namespace of this instance.
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Get the newest version of a declared output, as written by the passes of this instance. Returns `None` if
+    /// `resource` is not an output of the subgraph.
+    pub fn output(&self, resource: &VirtualResource) -> Option<&VirtualResource> {
+        self.outputs
+            .iter()
+            .find(|(output, _)| output.is_associated_with(resource))
+            .map(|(_, renamed)| renamed)
+    }
+}
+
+impl<'cb, D: ExecutionDomain, U, A: Allocator> PassGraph<'cb, D, U, A> {
+    /// Add all passes and transient resources of a subgraph instance to this graph.
+    /// See the [`subgraph`](crate::graph::subgraph) module for more information.
+    /// # Errors
+    /// * Fails if a pass is a renderpass that does not execute on a graphics queue.
+    /// * Fails if a transient resource of the subgraph was already declared on this graph.
+    pub fn add_subgraph(mut self, instance: SubgraphInstance<'cb, D, U, A>) -> Result<Self> {
+        for (resource, info) in instance.transients {
+            self = match info {
+                TransientInfo::Image(info) => self.add_transient_image(&resource, info)?,
+                TransientInfo::Buffer(info) => self.add_transient_buffer(&resource, info)?,
+            };
+        }
+        for pass in instance.passes {
+            self = self.add_pass(pass)?;
+        }
+        Ok(self)
+    }
+}
@@ -0,0 +1,154 @@
+//! Passes that only record a single transfer command: copies, blits, clears and fills.
+//!
+//! These are created with [`PassBuilder::copy()`], [`PassBuilder::blit()`], [`PassBuilder::clear()`] and [`PassBuilder::fill()`].
+//! Each of them declares a transfer read of its source and a transfer write of its destination, so the graph transitions images to
+//! `VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL` and `VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL` before the command, and inserts the barriers
+//! to and from the `TRANSFER` stage. Custom transfer passes can declare the same usages with [`PassBuilder::transfer_read()`]
+//! and [`PassBuilder::transfer_write()`].
+//!
+//! The destination of a transfer pass is overwritten entirely, its previous contents are never read.
+//!
+//! # Example
+//! ```
+//! use phobos::prelude::*;
+//!
+//! let scene = image!("scene");
+//! let downsampled = image!("downsampled");
+//! let readback = buffer!("readback");
+//! let blit = PassBuilder::blit("downsample", &scene, &downsampled, vk::Filter::LINEAR)?;
+//! let copy = PassBuilder::copy("readback", &downsampled.upgrade(), &readback);
+//! graph = graph.add_pass(blit)?.add_pass(copy)?;
+//! ```
+
+use anyhow::Result;
+use ash::vk;
+
+use crate::{
+    Allocator, BufferView, ClearColor, Error, GfxSupport, GraphicsCmdBuffer, ImageView, Pass,
+    PassBuilder, PhysicalResourceBindings, TransferCmdBuffer, TransferSupport, VirtualResource,
+};
+use crate::graph::physical_resource::PhysicalResource;
+use crate::graph::resource::ResourceType;
+use crate::sync::domain::ExecutionDomain;
+use crate::util::to_vk::IntoVulkanType;
+
+fn resolve(resource: &VirtualResource, bindings: &PhysicalResourceBindings) -> Result<PhysicalResource> {
+    bindings
+        .resolve_subresource(resource)?
+        .ok_or_else(|| Error::NoResourceBound(resource.uid()).into())
+}
+
+fn resolve_image(resource: &VirtualResource, bindings: &PhysicalResourceBindings) -> Result<ImageView> {
+    match resolve(resource, bindings)? {
+        PhysicalResource::Image(image) => Ok(image),
+        PhysicalResource::Buffer(_) => Err(Error::Uncategorized("Expected an image to be bound").into()),
+    }
+}
+
+fn resolve_buffer(resource: &VirtualResource, bindings: &PhysicalResourceBindings) -> Result<BufferView> {
+    match resolve(resource, bindings)? {
+        PhysicalResource::Buffer(buffer) => Ok(buffer),
+        PhysicalResource::Image(_) => Err(Error::Uncategorized("Expected a buffer to be bound").into()),
+    }
+}
+
+fn full_extent(image: &ImageView) -> [vk::Offset3D; 2] {
+    [
+        vk::Offset3D::default(),
+        vk::Offset3D {
+            x: image.width() as i32,
+            y: image.height() as i32,
+            z: image.depth() as i32,
+        },
+    ]
+}
+
+impl<'cb, D: TransferSupport + ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D, U, A> {
+    /// Create a pass that copies `src` to `dst`. Both resources can be images or buffers: buffer to buffer copies copy
+    /// the entire buffer view, all other copies copy the base mip level of the image view.
+    pub fn copy(name: impl Into<String>, src: &VirtualResource, dst: &VirtualResource) -> Pass<'cb, D, U, A> {
+        let (src, dst) = (src.clone(), dst.clone());
+        PassBuilder::new(name)
+            .transfer_read(&src)
+            .transfer_write(&dst)
+            .parallel_execute_fn(move |cmd, _, bindings, _| {
+                match (resolve(&src, bindings)?, resolve(&dst, bindings)?) {
+                    (PhysicalResource::Buffer(src), PhysicalResource::Buffer(dst)) => {
+                        cmd.copy_buffer(&src, &dst)
+                    }
+                    (PhysicalResource::Buffer(src), PhysicalResource::Image(dst)) => {
+                        cmd.copy_buffer_to_image(&src, &dst)
+                    }
+                    (PhysicalResource::Image(src), PhysicalResource::Buffer(dst)) => {
+                        cmd.copy_image_to_buffer(&src, &dst)
+                    }
+                    (PhysicalResource::Image(src), PhysicalResource::Image(dst)) => {
+                        cmd.copy_image(&src, &dst)
+                    }
+                }
+            })
+            .build()
+    }
+
+    /// Create a pass that fills a buffer with a repeated 32-bit value.
+    /// # Errors
+    /// * Fails if `buffer` is not a buffer resource.
+    pub fn fill(name: impl Into<String>, buffer: &VirtualResource, value: u32) -> Result<Pass<'cb, D, U, A>> {
+        if buffer.resource_type() != ResourceType::Buffer {
+            return Err(Error::Uncategorized("Only buffers can be filled").into());
+        }
+        let buffer = buffer.clone();
+        Ok(PassBuilder::new(name)
+            .transfer_write(&buffer)
+            .parallel_execute_fn(move |cmd, _, bindings, _| {
+                cmd.fill_buffer(&resolve_buffer(&buffer, bindings)?, value)
+            })
+            .build())
+    }
+}
+
+impl<'cb, D: GfxSupport + ExecutionDomain, U, A: Allocator> PassBuilder<'cb, D, U, A> {
+    /// Create a pass that blits the entire `src` image to the entire `dst` image, scaling it with `filter`.
+    /// # Errors
+    /// * Fails if `src` or `dst` is not an image resource.
+    pub fn blit(
+        name: impl Into<String>,
+        src: &VirtualResource,
+        dst: &VirtualResource,
+        filter: vk::Filter,
+    ) -> Result<Pass<'cb, D, U, A>> {
+        if src.resource_type() != ResourceType::Image || dst.resource_type() != ResourceType::Image {
+            return Err(Error::Uncategorized("Only images can be blitted").into());
+        }
+        let (src, dst) = (src.clone(), dst.clone());
+        Ok(PassBuilder::new(name)
+            .transfer_read(&src)
+            .transfer_write(&dst)
+            .parallel_execute_fn(move |cmd, _, bindings, _| {
+                let src = resolve_image(&src, bindings)?;
+                let dst = resolve_image(&dst, bindings)?;
+                Ok(cmd.blit_image(&src, &dst, &full_extent(&src), &full_extent(&dst), filter))
+            })
+            .build())
+    }
+
+    /// Create a pass that clears a color image outside of a renderpass.
+    /// # Errors
+    /// * Fails if `image` is not an image resource.
+    pub fn clear(
+        name: impl Into<String>,
+        image: &VirtualResource,
+        color: ClearColor,
+    ) -> Result<Pass<'cb, D, U, A>> {
+        if image.resource_type() != ResourceType::Image {
+            return Err(Error::Uncategorized("Only images can be cleared").into());
+        }
+        let image = image.clone();
+        Ok(PassBuilder::new(name)
+            .transfer_write(&image)
+            .parallel_execute_fn(move |cmd, _, bindings, _| {
+                Ok(cmd.clear_color_image(&resolve_image(&image, bindings)?, color.into_vulkan()))
+            })
+            .build())
+    }
+}
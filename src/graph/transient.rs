@@ -0,0 +1,689 @@
+//! Transient resources are images and buffers that are owned by a pass graph instead of by the application.
+//!
+//! A transient resource is declared on the graph with a [`TransientImageInfo`] or [`TransientBufferInfo`], using
+//! [`PassGraph::add_transient_image()`](crate::PassGraph::add_transient_image) or [`PassGraph::add_transient_buffer()`](crate::PassGraph::add_transient_buffer).
+//! When the graph is built, the lifetime of each transient resource is computed from the passes that use it. Resources whose lifetimes
+//! do not overlap are placed in the same alias group, and share the same memory once allocated. The first barrier on each resource
+//! in a group waits for the passes using the resource that had the memory before it, and transitions it from an undefined layout.
+//!
+//! Physical resources are created by calling [`BuiltPassGraph::update_transients()`](crate::graph::pass_graph::BuiltPassGraph::update_transients).
+//! After this, they are automatically bound when recording the graph, so they do not need to be added to the
+//! [`PhysicalResourceBindings`].
+//!
+//! Image sizes can be relative to the swapchain, or to the render resolution the scene is rendered at before it is upscaled,
+//! for example by FSR2. Both are given as [`ReferenceExtents`], which [`FrameManager::reference_extents()`](crate::FrameManager::reference_extents)
+//! provides for the current frame. Calling [`BuiltPassGraph::update_transients()`](crate::graph::pass_graph::BuiltPassGraph::update_transients)
+//! every frame recreates the resources whenever their size changes, after a swapchain resize or a change of the render scale.
+//!
+//! # Example
+//! ```
+//! use phobos::prelude::*;
+//! use phobos::graph::transient::{TransientImageInfo, TransientSize};
+//!
+//! let gbuffer = image!("gbuffer_albedo");
+//! let graph = PassGraph::<domain::Graphics>::new()
+//!     .add_transient_image(&gbuffer, TransientImageInfo {
+//!         size: TransientSize::SwapchainRelative { width: 1.0, height: 1.0 },
+//!         format: vk::Format::R8G8B8A8_UNORM,
+//!         ..Default::default()
+//!     })?;
+//! // Add passes using `gbuffer`, then build the graph.
+//! let mut graph = graph.build()?;
+//! // Once per frame, before recording.
+//! graph.update_transients(device.clone(), &mut allocator, ifc.extents)?;
+//! ```
+
+use std::hash::{Hash, Hasher};
+
+use anyhow::Result;
+use ash::vk;
+use petgraph::algo::has_path_connecting;
+use petgraph::Direction::Outgoing;
+use petgraph::graph::NodeIndex;
+
+use crate::{
+    Allocation, Allocator, BufferView, DeletionQueue, Device, Error, Image, MemoryType,
+    PhysicalResourceBindings, PipelineStage, VirtualResource,
+};
+use crate::graph::pass_graph::{PassGraphInner, PassNode, PassResource};
+use crate::graph::record::WRITE_ACCESS;
+use crate::graph::resource::{AttachmentType, ResourceType, ResourceUsage};
+use crate::graph::task_graph::Node;
+use crate::resource::buffer::create_buffer_handle;
+use crate::resource::image::{create_image_handle, ImageCreateInfo};
+use crate::sync::domain::ExecutionDomain;
+use crate::wsi::frame::FRAMES_IN_FLIGHT;
+
+/// Extents that relative sizes of graph resources are resolved against.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ReferenceExtents {
+    /// Size of the swapchain.
+    pub swapchain: vk::Extent2D,
+    /// Resolution the scene is rendered at before it is upscaled to the swapchain size.
+    pub render: vk::Extent2D,
+}
+
+impl ReferenceExtents {
+    /// Reference extents for rendering at `render_scale` times the swapchain size.
+    pub fn scaled(swapchain: vk::Extent2D, render_scale: f32) -> Self {
+        Self {
+            swapchain,
+            render: scale_extent(swapchain, render_scale, render_scale),
+        }
+    }
+}
+
+impl From<vk::Extent2D> for ReferenceExtents {
+    /// Reference extents for rendering at the swapchain size.
+    fn from(swapchain: vk::Extent2D) -> Self {
+        Self {
+            swapchain,
+            render: swapchain,
+        }
+    }
+}
+
+// Relative sizes are rounded down, but never become smaller than one pixel.
+fn scale_extent(extent: vk::Extent2D, width: f32, height: f32) -> vk::Extent2D {
+    vk::Extent2D {
+        width: ((extent.width as f32 * width) as u32).max(1),
+        height: ((extent.height as f32 * height) as u32).max(1),
+    }
+}
+
+/// Size of a transient image.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TransientSize {
+    /// Absolute size in pixels.
+    Absolute(vk::Extent2D),
+    /// Size relative to the swapchain size.
+    SwapchainRelative {
+        /// Scale factor applied to the swapchain width.
+        width: f32,
+        /// Scale factor applied to the swapchain height.
+        height: f32,
+    },
+    /// Size relative to the render resolution, which changes with dynamic resolution scaling.
+    RenderRelative {
+        /// Scale factor applied to the render width.
+        width: f32,
+        /// Scale factor applied to the render height.
+        height: f32,
+    },
+}
+
+impl Default for TransientSize {
+    fn default() -> Self {
+        TransientSize::SwapchainRelative {
+            width: 1.0,
+            height: 1.0,
+        }
+    }
+}
+
+impl TransientSize {
+    /// Resolve this size to an actual extent, given the reference extents. A single extent is used
+    /// as both the swapchain size and the render resolution.
+    /// Relative sizes are rounded down, but never become smaller than one pixel.
+    pub fn resolve(&self, reference: impl Into<ReferenceExtents>) -> vk::Extent2D {
+        let reference = reference.into();
+        match *self {
+            TransientSize::Absolute(extent) => extent,
+            TransientSize::SwapchainRelative {
+                width,
+                height,
+            } => scale_extent(reference.swapchain, width, height),
+            TransientSize::RenderRelative {
+                width,
+                height,
+            } => scale_extent(reference.render, width, height),
+        }
+    }
+}
+
+/// Describes a transient image owned by the pass graph.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TransientImageInfo {
+    /// Size of the image
+    pub size: TransientSize,
+    /// Pixel format of the image
+    pub format: vk::Format,
+    /// Additional usage flags. Usage flags required by the passes in the graph are always added to this.
+    pub usage: vk::ImageUsageFlags,
+    /// Aspect of the image view that is bound to the virtual resource.
+    pub aspect: vk::ImageAspectFlags,
+    /// MSAA samples
+    pub samples: vk::SampleCountFlags,
+    /// Number of mip levels
+    pub mip_levels: u32,
+    /// Number of array layers
+    pub layers: u32,
+}
+
+impl Default for TransientImageInfo {
+    fn default() -> Self {
+        Self {
+            size: TransientSize::default(),
+            format: vk::Format::R8G8B8A8_UNORM,
+            usage: vk::ImageUsageFlags::empty(),
+            aspect: vk::ImageAspectFlags::COLOR,
+            samples: vk::SampleCountFlags::TYPE_1,
+            mip_levels: 1,
+            layers: 1,
+        }
+    }
+}
+
+/// Describes a transient buffer owned by the pass graph.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct TransientBufferInfo {
+    /// Size of the buffer in bytes
+    pub size: vk::DeviceSize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum TransientInfo {
+    Image(TransientImageInfo),
+    Buffer(TransientBufferInfo),
+}
+
+/// The lifetime of a transient resource, as an inclusive range of indices into the order in which passes are executed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TransientLifetime {
+    /// Index of the first pass using the resource
+    pub first_pass: usize,
+    /// Index of the last pass using the resource
+    pub last_pass: usize,
+}
+
+#[derive(Debug)]
+pub(crate) struct TransientResource {
+    pub(crate) name: String,
+    pub(crate) info: TransientInfo,
+    usage: vk::ImageUsageFlags,
+    lifetime: Option<TransientLifetime>,
+    users: Vec<NodeIndex>,
+}
+
+/// Memory and handles of all allocated transient resources. The resources are destroyed when this is dropped,
+/// so it must be kept alive until the GPU has finished executing every command buffer it was recorded into.
+/// One way to do this is by moving it into a [`DeletionQueue`](crate::DeletionQueue).
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct TransientAllocation<A: Allocator> {
+    #[derivative(Debug = "ignore")]
+    device: Device,
+    #[derivative(Debug = "ignore")]
+    memory: Vec<A::Allocation>,
+    images: Vec<Image<A>>,
+    // Raw handles of all created images and buffers, including ones that were not bound to memory yet.
+    image_handles: Vec<vk::Image>,
+    buffers: Vec<vk::Buffer>,
+    bindings: PhysicalResourceBindings,
+    size: vk::DeviceSize,
+    extents: ReferenceExtents,
+}
+
+/// Stores all transient resources declared on a pass graph, together with their lifetimes and alias groups.
+#[derive(Derivative)]
+#[derivative(Debug, Default(bound = ""))]
+pub struct TransientResources<A: Allocator> {
+    resources: Vec<TransientResource>,
+    alias_groups: Vec<Vec<usize>>,
+    allocation: Option<TransientAllocation<A>>,
+    // Allocations replaced after a resize may still be in use by frames in flight.
+    #[derivative(Default(value = "DeletionQueue::new(FRAMES_IN_FLIGHT as u32 + 1)"))]
+    retired: DeletionQueue<TransientAllocation<A>>,
+}
+
+pub(crate) fn image_usage_from_layout(layout: vk::ImageLayout) -> vk::ImageUsageFlags {
+    match layout {
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => vk::ImageUsageFlags::COLOR_ATTACHMENT,
+        vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL
+        | vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL
+        | vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL
+        | vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL => vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => vk::ImageUsageFlags::SAMPLED,
+        vk::ImageLayout::GENERAL => vk::ImageUsageFlags::STORAGE,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL => vk::ImageUsageFlags::TRANSFER_SRC,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL => vk::ImageUsageFlags::TRANSFER_DST,
+        _ => vk::ImageUsageFlags::empty(),
+    }
+}
+
+/// Image usage flags required by a pass resource.
+pub(crate) fn image_usage(resource: &PassResource) -> vk::ImageUsageFlags {
+    match resource.usage {
+        // Read-only depth attachments can be sampled in the same layout.
+        ResourceUsage::Attachment(AttachmentType::DepthReadOnly {
+            sampled: true,
+        }) => image_usage_from_layout(resource.layout) | vk::ImageUsageFlags::SAMPLED,
+        _ => image_usage_from_layout(resource.layout),
+    }
+}
+
+impl<A: Allocator> TransientResources<A> {
+    pub(crate) fn add(&mut self, resource: &VirtualResource, info: TransientInfo) -> Result<()> {
+        let expected_type = match info {
+            TransientInfo::Image(_) => ResourceType::Image,
+            TransientInfo::Buffer(_) => ResourceType::Buffer,
+        };
+        if resource.resource_type() != expected_type {
+            return Err(Error::Uncategorized("Transient resource type does not match virtual resource type").into());
+        }
+        if self.get(resource.name()).is_some() {
+            return Err(Error::Uncategorized("Transient resource was declared twice").into());
+        }
+        self.resources.push(TransientResource {
+            name: resource.name().to_owned(),
+            info,
+            usage: vk::ImageUsageFlags::empty(),
+            lifetime: None,
+            users: vec![],
+        });
+        Ok(())
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&TransientResource> {
+        self.resources.iter().find(|resource| resource.name == name)
+    }
+
+    /// Hash the declarations of all transient resources.
+    pub(crate) fn hash_structure<H: Hasher>(&self, state: &mut H) {
+        self.resources.len().hash(state);
+        for resource in &self.resources {
+            resource.name.hash(state);
+            match resource.info {
+                TransientInfo::Image(info) => {
+                    match info.size {
+                        TransientSize::Absolute(extent) => (0u8, extent.width, extent.height).hash(state),
+                        TransientSize::SwapchainRelative {
+                            width,
+                            height,
+                        } => (1u8, width.to_bits(), height.to_bits()).hash(state),
+                        TransientSize::RenderRelative {
+                            width,
+                            height,
+                        } => (2u8, width.to_bits(), height.to_bits()).hash(state),
+                    }
+                    info.format.hash(state);
+                    info.usage.hash(state);
+                    info.aspect.hash(state);
+                    info.samples.hash(state);
+                    info.mip_levels.hash(state);
+                    info.layers.hash(state);
+                }
+                TransientInfo::Buffer(info) => info.size.hash(state),
+            }
+        }
+    }
+
+    /// Returns true if no transient resources were declared.
+    pub fn is_empty(&self) -> bool {
+        self.resources.is_empty()
+    }
+
+    /// Get the lifetime of a transient resource. Returns `None` if the resource does not exist, or if it is not used
+    /// by any pass.
+    pub fn lifetime(&self, name: &str) -> Option<TransientLifetime> {
+        self.get(name).and_then(|resource| resource.lifetime)
+    }
+
+    /// Get the names of the resources in each alias group. All resources in the same group share the same memory.
+    pub fn alias_groups(&self) -> Vec<Vec<&str>> {
+        self.alias_groups
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .map(|&index| self.resources[index].name.as_str())
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Get the physical allocation, if [`BuiltPassGraph::update_transients()`](crate::graph::pass_graph::BuiltPassGraph::update_transients) was called.
+    pub fn allocation(&self) -> Option<&TransientAllocation<A>> {
+        self.allocation.as_ref()
+    }
+
+    pub(crate) fn take_allocation(&mut self) -> Option<TransientAllocation<A>> {
+        self.allocation.take()
+    }
+
+    /// Compute the lifetime and required usage flags of each transient resource, and assign
+    /// every resource to an alias group.
+    pub(crate) fn compute_lifetimes<D: ExecutionDomain, U>(
+        &mut self,
+        graph: &PassGraphInner<'_, D, U, A>,
+        source: NodeIndex,
+    ) -> Result<()> {
+        let order = petgraph::algo::toposort(graph, None).map_err(|_| Error::GraphHasCycle)?;
+        let pass_order = order
+            .into_iter()
+            .filter(|&node| node != source && matches!(graph.node_weight(node), Some(Node::Task(_))))
+            .collect::<Vec<_>>();
+
+        for resource in &mut self.resources {
+            resource.users.clear();
+            resource.usage = vk::ImageUsageFlags::empty();
+            let mut lifetime: Option<TransientLifetime> = None;
+            for (index, &node) in pass_order.iter().enumerate() {
+                let Some(Node::Task(pass)) = graph.node_weight(node) else { continue; };
+                let mut used = false;
+                for r in Self::pass_resources(pass).filter(|r| r.resource.name() == resource.name) {
+                    resource.usage |= image_usage(r);
+                    used = true;
+                }
+                if !used {
+                    continue;
+                }
+                resource.users.push(node);
+                lifetime = Some(match lifetime {
+                    None => TransientLifetime {
+                        first_pass: index,
+                        last_pass: index,
+                    },
+                    Some(lifetime) => TransientLifetime {
+                        first_pass: lifetime.first_pass,
+                        last_pass: index,
+                    },
+                });
+            }
+            resource.lifetime = lifetime;
+        }
+
+        self.assign_alias_groups(graph);
+        Ok(())
+    }
+
+    /// Make the first barrier of every resource in an alias group wait for the previous resource using the same memory.
+    /// Different resources are not synchronized with each other otherwise. The first resource of a group follows the last one,
+    /// which used the memory in the previous frame.
+    ///
+    /// Barriers after the source node are normally recorded before any pass, so these barriers are moved in `steps` to right
+    /// before their first consumer. All users of the previous resource are ancestors of that consumer, so they are recorded before it.
+    pub(crate) fn add_aliasing_barriers<D: ExecutionDomain, U>(
+        &self,
+        graph: &mut PassGraphInner<'_, D, U, A>,
+        source: NodeIndex,
+        steps: &mut Vec<Vec<NodeIndex>>,
+    ) {
+        for group in self.alias_groups.iter().filter(|group| group.len() > 1) {
+            for (position, &index) in group.iter().enumerate() {
+                let previous = &self.resources[group[(position + group.len() - 1) % group.len()]];
+                // A pipeline barrier waits for all earlier work in its source stages, so the stages of every use are included.
+                let (stage, access) = previous
+                    .users
+                    .iter()
+                    .filter_map(|&node| match graph.node_weight(node) {
+                        Some(Node::Task(pass)) => Some(pass),
+                        _ => None,
+                    })
+                    .flat_map(|pass| Self::pass_resources(pass))
+                    .filter(|resource| resource.resource.name() == previous.name)
+                    .fold((PipelineStage::NONE, vk::AccessFlags2::NONE), |(stage, access), resource| {
+                        (stage | resource.stage, access | (resource.usage.access() & WRITE_ACCESS))
+                    });
+                let name = &self.resources[index].name;
+                let barriers = graph
+                    .neighbors_directed(source, Outgoing)
+                    .filter(|&node| {
+                        matches!(graph.node_weight(node), Some(Node::Barrier(barrier)) if barrier.resource.resource.name() == name)
+                    })
+                    .collect::<Vec<_>>();
+                for node in barriers {
+                    let Some(Node::Barrier(barrier)) = graph.node_weight_mut(node) else { continue };
+                    barrier.src_stage |= stage;
+                    barrier.src_access |= access;
+                    Self::record_before_consumer(graph, node, steps);
+                }
+            }
+        }
+        steps.retain(|step| !step.is_empty());
+    }
+
+    /// Move a barrier node in the recording steps to right before the first of its consumers.
+    fn record_before_consumer<D: ExecutionDomain, U>(
+        graph: &PassGraphInner<'_, D, U, A>,
+        barrier: NodeIndex,
+        steps: &mut [Vec<NodeIndex>],
+    ) {
+        for step in steps.iter_mut() {
+            step.retain(|&node| node != barrier);
+        }
+        let consumers = graph.neighbors_directed(barrier, Outgoing).collect::<Vec<_>>();
+        let position = steps.iter().enumerate().find_map(|(index, step)| {
+            step.iter()
+                .position(|node| consumers.contains(node))
+                .map(|position| (index, position))
+        });
+        if let Some((index, position)) = position {
+            steps[index].insert(position, barrier);
+        }
+    }
+
+    fn pass_resources<'a, D: ExecutionDomain, U>(
+        pass: &'a PassNode<'_, PassResource, D, U, A>,
+    ) -> impl Iterator<Item = &'a PassResource> {
+        pass.inputs.iter().chain(pass.outputs.iter())
+    }
+
+    /// Returns true if every pass using `lhs` is guaranteed to execute before every pass using `rhs`.
+    fn strictly_before<D: ExecutionDomain, U>(
+        graph: &PassGraphInner<'_, D, U, A>,
+        lhs: &TransientResource,
+        rhs: &TransientResource,
+    ) -> bool {
+        lhs.users.iter().all(|&src| {
+            rhs.users
+                .iter()
+                .all(|&dst| src != dst && has_path_connecting(graph, src, dst, None))
+        })
+    }
+
+    fn assign_alias_groups<D: ExecutionDomain, U>(&mut self, graph: &PassGraphInner<'_, D, U, A>) {
+        // Greedy interval assignment: visit resources in order of first use, and place each one in the first group
+        // whose most recent member is fully done before this resource is first used. Only resources ordered by a dependency
+        // chain in the graph may alias, so the aliasing barrier added by `add_aliasing_barriers` is recorded after the previous
+        // resource was last used.
+        let mut order = (0..self.resources.len())
+            .filter(|&index| self.resources[index].lifetime.is_some())
+            .collect::<Vec<_>>();
+        order.sort_by_key(|&index| self.resources[index].lifetime.unwrap().first_pass);
+
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        for index in order {
+            let resource = &self.resources[index];
+            let group = groups.iter_mut().find(|group| {
+                let last = &self.resources[*group.last().unwrap()];
+                std::mem::discriminant(&last.info) == std::mem::discriminant(&resource.info)
+                    && Self::strictly_before(graph, last, resource)
+            });
+            match group {
+                Some(group) => group.push(index),
+                None => groups.push(vec![index]),
+            }
+        }
+        self.alias_groups = groups;
+    }
+
+    /// Returns true if the size of any image changes when resolving it against `extents` instead of the extents it was allocated with.
+    fn needs_reallocation(&self, extents: ReferenceExtents) -> bool {
+        let Some(allocation) = &self.allocation else { return true };
+        self.resources.iter().any(|resource| match resource.info {
+            TransientInfo::Image(info) => info.size.resolve(allocation.extents) != info.size.resolve(extents),
+            TransientInfo::Buffer(_) => false,
+        })
+    }
+
+    /// Allocate the physical resources if they do not exist yet or their size changed. Replaced resources are kept alive
+    /// until the frames in flight using them are done. Returns true if the resources were (re)created.
+    pub(crate) fn update(&mut self, device: Device, allocator: &mut A, extents: ReferenceExtents) -> Result<bool> {
+        self.retired.next_frame();
+        if !self.needs_reallocation(extents) {
+            return Ok(false);
+        }
+        self.allocate(device, allocator, extents)?;
+        Ok(true)
+    }
+
+    /// Allocate memory for every alias group and create the physical resources. The previous allocation may still be in use
+    /// by frames in flight, so it is retired instead of destroyed.
+    pub(crate) fn allocate(
+        &mut self,
+        device: Device,
+        allocator: &mut A,
+        reference_extent: ReferenceExtents,
+    ) -> Result<()> {
+        if let Some(old) = self.allocation.take() {
+            self.retired.push(old);
+        }
+
+        enum Handle {
+            Image(vk::Image, ImageCreateInfo, vk::ImageAspectFlags, vk::SharingMode),
+            Buffer(vk::Buffer, vk::DeviceSize),
+        }
+
+        let mut allocation = TransientAllocation {
+            device: device.clone(),
+            memory: vec![],
+            images: vec![],
+            image_handles: vec![],
+            buffers: vec![],
+            bindings: PhysicalResourceBindings::new(),
+            size: 0,
+            extents: reference_extent,
+        };
+
+        for group in &self.alias_groups {
+            // Create all handles in this group first so we know their memory requirements.
+            let mut handles = Vec::with_capacity(group.len());
+            for &index in group {
+                let resource = &self.resources[index];
+                let handle = match resource.info {
+                    TransientInfo::Image(info) => {
+                        let extent = info.size.resolve(reference_extent);
+                        let create_info = ImageCreateInfo {
+                            width: extent.width,
+                            height: extent.height,
+                            depth: 1,
+                            usage: info.usage | resource.usage,
+                            format: info.format,
+                            samples: info.samples,
+                            mip_levels: info.mip_levels,
+                            layers: info.layers,
+                            memory_type: MemoryType::GpuOnly,
+                        };
+                        // If anything below fails, dropping the partial allocation destroys the handles created so far.
+                        let (handle, _, sharing_mode) = create_image_handle(&device, &create_info)?;
+                        allocation.image_handles.push(handle);
+                        let requirements = unsafe { device.get_image_memory_requirements(handle) };
+                        (
+                            Handle::Image(handle, create_info, info.aspect, sharing_mode),
+                            requirements,
+                        )
+                    }
+                    TransientInfo::Buffer(info) => {
+                        let handle = create_buffer_handle(&device, info.size)?;
+                        allocation.buffers.push(handle);
+                        let requirements = unsafe { device.get_buffer_memory_requirements(handle) };
+                        (Handle::Buffer(handle, info.size), requirements)
+                    }
+                };
+                handles.push((index, handle));
+            }
+
+            // Split the group into memory blocks with compatible memory types, and allocate one block for each.
+            let mut blocks: Vec<(vk::MemoryRequirements, Vec<usize>)> = Vec::new();
+            for (i, (_, (_, requirements))) in handles.iter().enumerate() {
+                let block = blocks.iter_mut().find(|(block, _)| {
+                    block.memory_type_bits & requirements.memory_type_bits != 0
+                });
+                match block {
+                    Some((block, members)) => {
+                        block.size = block.size.max(requirements.size);
+                        block.alignment = block.alignment.max(requirements.alignment);
+                        block.memory_type_bits &= requirements.memory_type_bits;
+                        members.push(i);
+                    }
+                    None => blocks.push((*requirements, vec![i])),
+                }
+            }
+
+            for (requirements, members) in blocks {
+                let memory = allocator.allocate("transient_", &requirements, MemoryType::GpuOnly)?;
+                for i in members {
+                    let (index, (handle, _)) = &handles[i];
+                    let name = &self.resources[*index].name;
+                    match handle {
+                        Handle::Image(handle, info, aspect, sharing_mode) => unsafe {
+                            device.bind_image_memory(*handle, memory.memory(), memory.offset())?;
+                            let image = Image::new_managed(
+                                device.clone(),
+                                *handle,
+                                info.format,
+                                vk::Extent3D {
+                                    width: info.width,
+                                    height: info.height,
+                                    depth: info.depth,
+                                },
+                                info.layers,
+                                info.mip_levels,
+                                info.samples,
+                                *sharing_mode,
+                                info.usage,
+                            );
+                            allocation
+                                .bindings
+                                .bind_image(name.clone(), &image.whole_view(*aspect)?);
+                            allocation.images.push(image);
+                        },
+                        Handle::Buffer(handle, size) => unsafe {
+                            device.bind_buffer_memory(*handle, memory.memory(), memory.offset())?;
+                            allocation
+                                .bindings
+                                .bind_buffer(name.clone(), &BufferView::from_raw(&device, *handle, *size));
+                        },
+                    }
+                }
+                allocation.size += requirements.size;
+                allocation.memory.push(memory);
+            }
+        }
+
+        self.allocation = Some(allocation);
+        Ok(())
+    }
+}
+
+impl<A: Allocator> TransientAllocation<A> {
+    /// Get the bindings from each transient virtual resource to its physical resource.
+    pub fn bindings(&self) -> &PhysicalResourceBindings {
+        &self.bindings
+    }
+
+    /// Get the total amount of memory allocated for transient resources, in bytes.
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+}
+
+impl<A: Allocator> Drop for TransientAllocation<A> {
+    fn drop(&mut self) {
+        // Views must be destroyed before their images.
+        self.bindings = PhysicalResourceBindings::new();
+        self.images.clear();
+        for image in self.image_handles.drain(..) {
+            // SAFETY: Transient images are not owned by their `Image` wrapper, so we destroy them here.
+            unsafe {
+                self.device.destroy_image(image, None);
+            }
+        }
+        for buffer in self.buffers.drain(..) {
+            unsafe {
+                self.device.destroy_buffer(buffer, None);
+            }
+        }
+    }
+}
@@ -0,0 +1,314 @@
+//! Static validation of built pass graphs against a set of physical resource bindings.
+//!
+//! Most mistakes in a pass graph only show up as validation layer errors or corrupted images once the graph is recorded
+//! and submitted. [`BuiltPassGraph::validate()`] checks for the most common ones before anything is recorded:
+//! * Resources that are read before any pass writes them, and that are neither [imported](crate::PassGraph::import_resource)
+//!   nor a [history resource](crate::VirtualResource::history).
+//! * Resources that are written twice without being read in between, so the first write is useless.
+//! * Images that are used as an attachment, sampled image or storage image without the matching usage flags or format.
+//! * Resources that have no physical resource bound to them.
+//! * Attachments that are read after the pass writing them discarded their contents with its store op.
+//!
+//! # Example
+//! ```
+//! use phobos::prelude::*;
+//!
+//! let graph = graph.build()?;
+//! if let Err(report) = graph.validate(&bindings) {
+//!     for error in report.errors() {
+//!         eprintln!("{error}");
+//!     }
+//! }
+//! ```
+
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::{Allocator, PhysicalResourceBindings};
+use crate::graph::pass_graph::{BuiltPassGraph, PassNode, PassResource};
+use crate::graph::physical_resource::PhysicalResource;
+use crate::graph::resource::{AttachmentType, ResourceType, ResourceUsage};
+use crate::graph::task_graph::Node;
+use crate::graph::transient::{image_usage, TransientInfo};
+use crate::sync::domain::ExecutionDomain;
+
+/// A single problem found by [`BuiltPassGraph::validate()`]. Every error names the pass and the resource it was found on.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// A pass reads the initial contents of a resource that is not written by an earlier pass, not imported with
+    /// [`PassGraph::import_resource()`](crate::PassGraph::import_resource), and not a history resource.
+    #[error("Pass `{pass}` reads `{resource}`, but it is never written or imported.")]
+    ReadBeforeWrite {
+        /// Name of the reading pass.
+        pass: String,
+        /// Virtual resource that is read.
+        resource: String,
+    },
+    /// A pass overwrites a resource that was written by an earlier pass, and no pass read it in between.
+    #[error("Pass `{overwritten_by}` overwrites `{resource}` written by pass `{pass}` before anything reads it.")]
+    WriteAfterWrite {
+        /// Name of the pass whose write is lost.
+        pass: String,
+        /// Virtual resource that is written by `pass`.
+        resource: String,
+        /// Name of the pass that overwrites the resource.
+        overwritten_by: String,
+    },
+    /// A pass reads the contents of an attachment that the pass writing it discards with its store op.
+    #[error("Pass `{read_by}` reads `{resource}`, but pass `{pass}` discards its contents.")]
+    ReadAfterDiscard {
+        /// Name of the pass discarding the attachment.
+        pass: String,
+        /// Virtual resource that is discarded.
+        resource: String,
+        /// Name of the pass reading the discarded contents.
+        read_by: String,
+    },
+    /// The image bound to a resource was not created with the usage flags required by a pass.
+    #[error("Image bound to `{resource}` is missing usage flags {required:?} required by pass `{pass}`.")]
+    MissingUsage {
+        /// Name of the pass using the image.
+        pass: String,
+        /// Virtual resource the image is bound to.
+        resource: String,
+        /// Usage flags that are required but missing.
+        required: vk::ImageUsageFlags,
+    },
+    /// The format of the image bound to a resource cannot be used as the attachment type declared by a pass.
+    #[error("Image bound to `{resource}` has format {format:?}, which does not match its attachment type in pass `{pass}`.")]
+    FormatMismatch {
+        /// Name of the pass using the image.
+        pass: String,
+        /// Virtual resource the image is bound to.
+        resource: String,
+        /// Format of the bound image.
+        format: vk::Format,
+    },
+    /// A buffer is bound to an image resource, or the other way around.
+    #[error("Pass `{pass}` uses `{resource}` as a {expected:?}, but a different type of resource is bound to it.")]
+    TypeMismatch {
+        /// Name of the pass using the resource.
+        pass: String,
+        /// Virtual resource with the wrong binding.
+        resource: String,
+        /// Resource type the pass expects.
+        expected: ResourceType,
+    },
+    /// No physical resource is bound to a resource used by a pass, and it is not a transient resource.
+    #[error("Pass `{pass}` uses `{resource}`, but no physical resource is bound to it.")]
+    MissingBinding {
+        /// Name of the first pass using the resource.
+        pass: String,
+        /// Virtual resource without binding.
+        resource: String,
+    },
+}
+
+/// All errors found by [`BuiltPassGraph::validate()`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ValidationReport {
+    errors: Vec<ValidationError>,
+}
+
+impl ValidationReport {
+    /// Get all errors that were found.
+    pub fn errors(&self) -> &[ValidationError] {
+        &self.errors
+    }
+
+    /// Returns true if no errors were found.
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl Display for ValidationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Pass graph validation failed with {} error(s):", self.errors.len())?;
+        for error in &self.errors {
+            write!(f, "\n - {error}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationReport {}
+
+fn is_depth_stencil_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::D16_UNORM
+            | vk::Format::X8_D24_UNORM_PACK32
+            | vk::Format::D32_SFLOAT
+            | vk::Format::S8_UINT
+            | vk::Format::D16_UNORM_S8_UINT
+            | vk::Format::D24_UNORM_S8_UINT
+            | vk::Format::D32_SFLOAT_S8_UINT
+    )
+}
+
+/// Whether a pass depends on the previous contents of this input. Only attachments that are not loaded and
+/// write-only usages discard the previous contents.
+fn reads_previous_contents<D: ExecutionDomain, U, A: Allocator>(
+    pass: &PassNode<PassResource, D, U, A>,
+    input: &PassResource,
+) -> bool {
+    match &input.usage {
+        ResourceUsage::Attachment(AttachmentType::Resolve(_)) => false,
+        ResourceUsage::Attachment(AttachmentType::DepthReadOnly { .. }) => true,
+        ResourceUsage::Attachment(_) => pass.outputs.iter().any(|output| {
+            output.resource.is_associated_with(&input.resource) && output.load_op == Some(vk::AttachmentLoadOp::LOAD)
+        }),
+        ResourceUsage::ShaderWrite | ResourceUsage::BufferWrite(_) | ResourceUsage::TransferWrite => false,
+        _ => true,
+    }
+}
+
+impl<'cb, D: ExecutionDomain, U, A: Allocator> BuiltPassGraph<'cb, D, U, A> {
+    /// Check this graph for common mistakes before recording it, using the bindings it will be recorded with.
+    /// Transient resources are always considered bound, so this can be called before
+    /// [`BuiltPassGraph::update_transients()`].
+    /// # Errors
+    /// * Fails with a [`ValidationReport`] listing every problem found. See [`ValidationError`] for all checks.
+    pub fn validate(&self, bindings: &PhysicalResourceBindings) -> std::result::Result<(), ValidationReport> {
+        let graph = &self.task_graph().graph;
+        let passes = self
+            .traversal_order()
+            .into_iter()
+            .filter(|node| *node != self.source())
+            .filter_map(|node| match graph.node_weight(node) {
+                Some(Node::Task(pass)) => Some(pass),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let mut errors = Vec::new();
+        let mut uninitialized = HashSet::new();
+        for pass in &passes {
+            for input in &pass.inputs {
+                let resource = &input.resource;
+                if !resource.is_source() || !reads_previous_contents(pass, input) {
+                    continue;
+                }
+                // Binding a resource does not initialize it, its contents are only known if it is imported or kept from the previous frame.
+                let initialized = self.import_of(resource).is_some() || resource.is_history();
+                if !initialized && uninitialized.insert(resource.name()) {
+                    errors.push(ValidationError::ReadBeforeWrite {
+                        pass: pass.identifier.clone(),
+                        resource: resource.uid(),
+                    });
+                }
+            }
+        }
+
+        for writer in &passes {
+            for output in &writer.outputs {
+                let consumers = passes
+                    .iter()
+                    .flat_map(|pass| pass.inputs.iter().map(move |input| (pass, input)))
+                    .filter(|(_, input)| input.resource == output.resource)
+                    .collect::<Vec<_>>();
+                // A store op of NONE skips the store, but keeps the contents defined.
+                let discarded = output.store_op == Some(vk::AttachmentStoreOp::DONT_CARE);
+                if let Some((pass, _)) = consumers.iter().find(|(pass, input)| reads_previous_contents(pass, input)) {
+                    if discarded {
+                        errors.push(ValidationError::ReadAfterDiscard {
+                            pass: writer.identifier.clone(),
+                            resource: output.resource.uid(),
+                            read_by: pass.identifier.clone(),
+                        });
+                    }
+                    continue;
+                }
+                if let Some((pass, _)) = consumers.first() {
+                    errors.push(ValidationError::WriteAfterWrite {
+                        pass: writer.identifier.clone(),
+                        resource: output.resource.uid(),
+                        overwritten_by: pass.identifier.clone(),
+                    });
+                }
+            }
+        }
+
+        let mut reported = uninitialized;
+        for pass in &passes {
+            for input in &pass.inputs {
+                let resource = &input.resource;
+                let expected = resource.resource_type();
+                let format = match self.transients().get(resource.name()) {
+                    Some(transient) => match transient.info {
+                        // Usage flags of transient images are derived from the graph, so only the format can be wrong.
+                        TransientInfo::Image(info) if expected == ResourceType::Image => Some(info.format),
+                        TransientInfo::Buffer(_) if expected == ResourceType::Buffer => None,
+                        _ => {
+                            errors.push(ValidationError::TypeMismatch {
+                                pass: pass.identifier.clone(),
+                                resource: resource.uid(),
+                                expected,
+                            });
+                            continue;
+                        }
+                    },
+                    None => match bindings.resolve(resource) {
+                        Some(PhysicalResource::Image(image)) if expected == ResourceType::Image => {
+                            let required = image_usage(input);
+                            if !image.usage().contains(required) {
+                                errors.push(ValidationError::MissingUsage {
+                                    pass: pass.identifier.clone(),
+                                    resource: resource.uid(),
+                                    required: required & !image.usage(),
+                                });
+                            }
+                            Some(image.format())
+                        }
+                        Some(PhysicalResource::Buffer(_)) if expected == ResourceType::Buffer => None,
+                        Some(_) => {
+                            errors.push(ValidationError::TypeMismatch {
+                                pass: pass.identifier.clone(),
+                                resource: resource.uid(),
+                                expected,
+                            });
+                            continue;
+                        }
+                        None => {
+                            if reported.insert(resource.name()) {
+                                errors.push(ValidationError::MissingBinding {
+                                    pass: pass.identifier.clone(),
+                                    resource: resource.uid(),
+                                });
+                            }
+                            continue;
+                        }
+                    },
+                };
+
+                let Some(format) = format else { continue };
+                let mismatch = match input.layout {
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => is_depth_stencil_format(format),
+                    vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL
+                    | vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL
+                    | vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL
+                    | vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL => !is_depth_stencil_format(format),
+                    _ => false,
+                };
+                if mismatch {
+                    errors.push(ValidationError::FormatMismatch {
+                        pass: pass.identifier.clone(),
+                        resource: resource.uid(),
+                        format,
+                    });
+                }
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(ValidationReport {
+                errors,
+            }),
+        }
+    }
+}
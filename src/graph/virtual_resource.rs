@@ -4,14 +4,80 @@ use std::fmt::{Display, Formatter};
 
 use crate::graph::resource::ResourceType;
 
+const HISTORY_SUFFIX: &str = "@history";
+
 /// Represents a virtual resource in the system, uniquely identified by a string.
 ///
 /// Note that the resource named `swapchain` is assumed to always be the swapchain resource for presenting.
+/// Names ending in `@history` are reserved for [history resources](VirtualResource::history).
 #[derive(Debug, Default, Clone, Hash, Eq, PartialEq)]
 pub struct VirtualResource {
     pub(crate) name: String,
     pub(crate) version: usize,
     ty: ResourceType,
+    range: Option<SubresourceRange>,
+}
+
+/// A range of mip levels and array layers of an image. The default range covers the whole image.
+#[derive(Debug, Default, Copy, Clone, Hash, Eq, PartialEq)]
+pub struct SubresourceRange {
+    /// First mip level in the range.
+    pub base_level: u32,
+    /// Number of mip levels in the range. Set to `None` to use all remaining mip levels.
+    pub level_count: Option<u32>,
+    /// First array layer in the range.
+    pub base_layer: u32,
+    /// Number of array layers in the range. Set to `None` to use all remaining array layers.
+    pub layer_count: Option<u32>,
+}
+
+impl SubresourceRange {
+    /// Range covering a single mip level of all array layers.
+    pub fn mip(level: u32) -> Self {
+        Self {
+            base_level: level,
+            level_count: Some(1),
+            ..Default::default()
+        }
+    }
+
+    /// Range covering a single array layer of all mip levels.
+    pub fn layer(layer: u32) -> Self {
+        Self {
+            base_layer: layer,
+            layer_count: Some(1),
+            ..Default::default()
+        }
+    }
+
+    /// Returns true if both ranges contain a mip level of the same array layer.
+    pub fn overlaps(&self, other: &SubresourceRange) -> bool {
+        let overlap = |base: u32, count: Option<u32>, other_base: u32, other_count: Option<u32>| {
+            // A range ending past the last mip level or array layer extends to the end.
+            let before_end = |index: u32, base: u32, count: Option<u32>| {
+                count.is_none_or(|count| base.checked_add(count).is_none_or(|end| index < end))
+            };
+            before_end(other_base, base, count) && before_end(base, other_base, other_count)
+        };
+        overlap(self.base_level, self.level_count, other.base_level, other.level_count)
+            && overlap(self.base_layer, self.layer_count, other.base_layer, other.layer_count)
+    }
+}
+
+impl Display for SubresourceRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let range = |f: &mut Formatter<'_>, what: &str, base: u32, count: Option<u32>| match count {
+            Some(1) => write!(f, "{what} {base}"),
+            Some(count) => match base.checked_add(count) {
+                Some(end) => write!(f, "{what}s {base}..{end}"),
+                None => write!(f, "{what}s {base}.."),
+            },
+            None => write!(f, "{what}s {base}.."),
+        };
+        range(f, "mip", self.base_level, self.level_count)?;
+        write!(f, ", ")?;
+        range(f, "layer", self.base_layer, self.layer_count)
+    }
 }
 
 /// Holds a hashed resource in the pass graph implementation
@@ -33,6 +99,7 @@ impl VirtualResource {
             name: name.into(),
             version: usize::MAX,
             ty: ResourceType::Image,
+            range: None,
         }
     }
 
@@ -42,6 +109,7 @@ impl VirtualResource {
             name: name.into(),
             version: 0,
             ty: ResourceType::Image,
+            range: None,
         }
     }
 
@@ -51,6 +119,7 @@ impl VirtualResource {
             name: name.into(),
             version: 0,
             ty: ResourceType::Buffer,
+            range: None,
         }
     }
 
@@ -61,9 +130,104 @@ impl VirtualResource {
             name: self.name.clone(),
             version: self.version + 1,
             ty: self.ty,
+            range: self.range,
+        }
+    }
+
+    /// Get a virtual resource that refers to a range of the mip levels and array layers of this image. Each range is tracked as
+    /// a separate resource with its own versions and barriers, so passes can for example read one mip level while writing the next one.
+    /// The range is relative to the image view bound to this resource, and is resolved to a view of only this range
+    /// by [`PhysicalResourceBindings::resolve_subresource()`](crate::PhysicalResourceBindings::resolve_subresource).
+    ///
+    /// Ranges of the same image that are used in one graph must not overlap, and must not be used together with the whole image,
+    /// except to read the whole image after its ranges were used. [`PassGraph::build()`](crate::PassGraph::build) fails otherwise.
+    /// A pass reading the whole image depends on the latest version of every range used before it, so these ranges should cover the
+    /// entire image.
+    /// # Example
+    /// ```
+    /// # use anyhow::Result;
+    /// use phobos::prelude::*;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let bloom = VirtualResource::image("bloom");
+    /// let clear = ClearColor::Float([0.0, 0.0, 0.0, 0.0]);
+    /// let first = PassBuilder::<domain::All>::render("downsample_0")
+    ///     .clear_color_attachment(&bloom.mip(0), clear)?
+    ///     .build();
+    /// // Sample the previous mip level and render into the next one.
+    /// let second = PassBuilder::<domain::All>::render("downsample_1")
+    ///     .sample_image(first.output(&bloom.mip(0)).unwrap(), PipelineStage::FRAGMENT_SHADER)
+    ///     .clear_color_attachment(&bloom.mip(1), clear)?
+    ///     .build();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn subresource(&self, range: SubresourceRange) -> Self {
+        VirtualResource {
+            name: self.name.clone(),
+            version: self.version,
+            ty: self.ty,
+            range: Some(range),
         }
     }
 
+    /// Get a virtual resource that refers to a single mip level of this image. See [`VirtualResource::subresource()`].
+    pub fn mip(&self, level: u32) -> Self {
+        self.subresource(SubresourceRange::mip(level))
+    }
+
+    /// Get a virtual resource that refers to a single array layer of this image. See [`VirtualResource::subresource()`].
+    pub fn layer(&self, layer: u32) -> Self {
+        self.subresource(SubresourceRange::layer(layer))
+    }
+
+    /// Get this resource under another name and version, keeping its type and range.
+    pub(crate) fn renamed(&self, name: impl Into<String>, version: usize) -> Self {
+        VirtualResource {
+            name: name.into(),
+            version,
+            ty: self.ty,
+            range: self.range,
+        }
+    }
+
+    /// Get the virtual resource holding the contents this resource had at the end of the previous frame.
+    /// History resources are read with [`PassBuilder::read_history()`](crate::PassBuilder::read_history), and
+    /// their images are managed by [`HistoryResources`](crate::graph::history::HistoryResources).
+    pub fn history(&self) -> Self {
+        VirtualResource {
+            name: format!("{}{HISTORY_SUFFIX}", self.history_of().unwrap_or(&self.name)),
+            version: 0,
+            ty: self.ty,
+            range: self.range,
+        }
+    }
+
+    /// Returns true if this is the history of another resource, see [`VirtualResource::history()`].
+    pub fn is_history(&self) -> bool {
+        self.history_of().is_some()
+    }
+
+    /// Get the name of the resource this is the history of.
+    pub(crate) fn history_of(&self) -> Option<&str> {
+        self.name.strip_suffix(HISTORY_SUFFIX)
+    }
+
+    /// Get the initial version of the resource this is the history of.
+    pub(crate) fn history_source(&self) -> Option<VirtualResource> {
+        Some(VirtualResource {
+            name: self.history_of()?.to_owned(),
+            version: 0,
+            ty: self.ty,
+            range: self.range,
+        })
+    }
+
+    /// Get the range of the image this resource refers to, or `None` if it refers to the whole image.
+    pub fn subresource_range(&self) -> Option<SubresourceRange> {
+        self.range
+    }
+
     /// Returns the full, original name of the resource
     pub fn name(&self) -> &str {
         &self.name
@@ -79,9 +243,9 @@ impl VirtualResource {
         self.version() == 0
     }
 
-    /// Check if these virtual resources refer to the same physical resource
+    /// Check if these virtual resources refer to the same physical resource, and the same range of it.
     pub fn is_associated_with(&self, rhs: &VirtualResource) -> bool {
-        self.name() == rhs.name()
+        self.name() == rhs.name() && self.range == rhs.range
     }
 
     /// One virtual resource is older than another if it has less '+' symbols.
@@ -115,10 +279,13 @@ impl VirtualResource {
 impl Display for VirtualResource {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         if self.version == usize::MAX {
-            write!(f, "{}_final", self.name())
-        } else {
-            write!(f, "{}{}", self.name(), String::from_utf8(vec![b'+'; self.version]).unwrap())
+            return write!(f, "{}_final", self.name());
+        }
+        write!(f, "{}", self.name())?;
+        if let Some(range) = &self.range {
+            write!(f, "[{range}]")?;
         }
+        write!(f, "{}", String::from_utf8(vec![b'+'; self.version]).unwrap())
     }
 }
 
@@ -19,10 +19,11 @@ pub use crate::core::physical_device::*;
 pub use crate::core::queue::QueueType;
 pub use crate::descriptor::cache::DescriptorCache;
 pub use crate::descriptor::descriptor_set::DescriptorSet;
-pub use crate::graph::pass::{ClearColor, ClearDepthStencil, Pass, PassBuilder};
+pub use crate::graph::pass::{AttachmentOps, ClearColor, ClearDepthStencil, Pass, PassBuilder};
 pub use crate::graph::pass_graph::PassGraph;
 pub use crate::graph::physical_resource::PhysicalResourceBindings;
-pub use crate::graph::virtual_resource::VirtualResource;
+pub use crate::graph::resource::{BufferUsage, ResourceState};
+pub use crate::graph::virtual_resource::{SubresourceRange, VirtualResource};
 pub use crate::pipeline::{PipelineStage, PipelineType};
 pub use crate::pipeline::builder::PipelineBuilder;
 pub use crate::pipeline::cache::PipelineCache;
@@ -38,6 +39,7 @@ pub use crate::resource::query_pool::*;
 pub use crate::resource::raytracing::*;
 pub use crate::sampler::Sampler;
 pub use crate::sync::domain;
+pub use crate::sync::event::*;
 pub use crate::sync::execution_manager::ExecutionManager;
 pub use crate::sync::fence::*;
 pub use crate::sync::semaphore::*;
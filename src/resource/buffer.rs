@@ -35,6 +35,7 @@ use anyhow::Result;
 use ash::vk;
 use ash::vk::Handle;
 
+use crate::command_buffer::command_log::detached_handle;
 use crate::core::device::ExtensionID;
 use crate::core::traits::{AsRaw, Nameable};
 use crate::util::align::align;
@@ -75,6 +76,9 @@ pub struct BufferView {
 // so its value is not dropped when sending this to a different thread.
 unsafe impl Send for BufferView {}
 
+// SAFETY: BufferView is Copy, so sharing a reference is no different from sending a copy to another thread.
+unsafe impl Sync for BufferView {}
+
 fn get_buffer_usage_flags(device: &Device) -> vk::BufferUsageFlags {
     let mut usage = vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
         | vk::BufferUsageFlags::INDEX_BUFFER
@@ -97,6 +101,42 @@ fn get_buffer_usage_flags(device: &Device) -> vk::BufferUsageFlags {
     usage
 }
 
+/// Create a raw [`VkBuffer`](vk::Buffer) handle with all supported usage flags and no memory bound to it.
+pub(crate) fn create_buffer_handle(device: &Device, size: vk::DeviceSize) -> Result<vk::Buffer> {
+    let sharing_mode = if device.is_single_queue() {
+        vk::SharingMode::EXCLUSIVE
+    } else {
+        vk::SharingMode::CONCURRENT
+    };
+
+    let usage = get_buffer_usage_flags(device);
+
+    let handle = unsafe {
+        device.create_buffer(
+            &vk::BufferCreateInfo {
+                s_type: vk::StructureType::BUFFER_CREATE_INFO,
+                p_next: std::ptr::null(),
+                flags: vk::BufferCreateFlags::empty(),
+                size,
+                usage,
+                sharing_mode,
+                queue_family_index_count: if sharing_mode == vk::SharingMode::CONCURRENT {
+                    device.queue_families().len() as u32
+                } else {
+                    0
+                },
+                p_queue_family_indices: if sharing_mode == vk::SharingMode::CONCURRENT {
+                    device.queue_families().as_ptr()
+                } else {
+                    std::ptr::null()
+                },
+            },
+            None,
+        )?
+    };
+    Ok(handle)
+}
+
 impl<A: Allocator> Buffer<A> {
     /// Allocate a new buffer with a specific size, at a specific memory location.
     /// Buffers are created with all possible usage flags, excecpt for sparse memory flags.
@@ -107,37 +147,7 @@ impl<A: Allocator> Buffer<A> {
         location: MemoryType,
     ) -> Result<Self> {
         let size = size.into();
-        let sharing_mode = if device.is_single_queue() {
-            vk::SharingMode::EXCLUSIVE
-        } else {
-            vk::SharingMode::CONCURRENT
-        };
-
-        let usage = get_buffer_usage_flags(&device);
-
-        let handle = unsafe {
-            device.create_buffer(
-                &vk::BufferCreateInfo {
-                    s_type: vk::StructureType::BUFFER_CREATE_INFO,
-                    p_next: std::ptr::null(),
-                    flags: vk::BufferCreateFlags::empty(),
-                    size,
-                    usage,
-                    sharing_mode,
-                    queue_family_index_count: if sharing_mode == vk::SharingMode::CONCURRENT {
-                        device.queue_families().len() as u32
-                    } else {
-                        0
-                    },
-                    p_queue_family_indices: if sharing_mode == vk::SharingMode::CONCURRENT {
-                        device.queue_families().as_ptr()
-                    } else {
-                        std::ptr::null()
-                    },
-                },
-                None,
-            )?
-        };
+        let handle = create_buffer_handle(&device, size)?;
         #[cfg(feature = "log-objects")]
         trace!("Created new VkBuffer {handle:p} (size = {size} bytes)");
 
@@ -174,35 +184,7 @@ impl<A: Allocator> Buffer<A> {
     ) -> Result<Self> {
         let alignment = alignment.into();
         let size = align(size.into(), alignment);
-        let sharing_mode = if device.is_single_queue() {
-            vk::SharingMode::EXCLUSIVE
-        } else {
-            vk::SharingMode::CONCURRENT
-        };
-        let usage = get_buffer_usage_flags(&device);
-        let handle = unsafe {
-            device.create_buffer(
-                &vk::BufferCreateInfo {
-                    s_type: vk::StructureType::BUFFER_CREATE_INFO,
-                    p_next: std::ptr::null(),
-                    flags: vk::BufferCreateFlags::empty(),
-                    size,
-                    usage,
-                    sharing_mode,
-                    queue_family_index_count: if sharing_mode == vk::SharingMode::CONCURRENT {
-                        device.queue_families().len() as u32
-                    } else {
-                        0
-                    },
-                    p_queue_family_indices: if sharing_mode == vk::SharingMode::CONCURRENT {
-                        device.queue_families().as_ptr()
-                    } else {
-                        std::ptr::null()
-                    },
-                },
-                None,
-            )?
-        };
+        let handle = create_buffer_handle(&device, size)?;
         #[cfg(feature = "log-objects")]
         trace!("Created new VkBuffer {handle:p} (size = {size} bytes)");
 
@@ -325,6 +307,40 @@ impl<A: Allocator> Drop for Buffer<A> {
 }
 
 impl BufferView {
+    /// Create a view of an entire unmapped buffer that is not owned by a [`Buffer`].
+    /// # Safety
+    /// * `handle` must be a valid buffer with memory bound to it and at least `size` bytes large.
+    pub(crate) unsafe fn from_raw(device: &Device, handle: vk::Buffer, size: vk::DeviceSize) -> Self {
+        let address = device.get_buffer_device_address(&vk::BufferDeviceAddressInfo {
+            s_type: vk::StructureType::BUFFER_DEVICE_ADDRESS_INFO,
+            p_next: std::ptr::null(),
+            buffer: handle,
+        });
+        BufferView {
+            handle,
+            pointer: None,
+            address,
+            offset: 0,
+            size,
+        }
+    }
+
+    /// Create a view of `size` bytes at `offset` into a buffer that is not backed by a Vulkan object, for recording to a
+    /// [`CommandLog`](crate::command_buffer::command_log::CommandLog). The buffer gets a unique handle, so it can be identified in the log.
+    /// Detached views are not mapped.
+    /// # Safety
+    /// The view must only be used with command buffers created with [`IncompleteCommandBuffer::new_logged()`](crate::IncompleteCommandBuffer::new_logged).
+    /// Its handle is not a valid Vulkan object, so using it with a Vulkan command buffer is undefined behaviour.
+    pub unsafe fn detached(offset: vk::DeviceSize, size: vk::DeviceSize) -> Self {
+        BufferView {
+            handle: detached_handle(),
+            pointer: None,
+            address: 0,
+            offset,
+            size,
+        }
+    }
+
     /// Obtain a slice to the mapped memory of this buffer.
     /// # Errors
     /// Fails if this buffer is not mappable (not `HOST_VISIBLE`).
@@ -12,15 +12,17 @@
 //! [`ImgView`] also owns a full Vulkan resource. For this reason, we wrap it in a reference-counted `Arc` so we can safely treat it as if it were
 //! a `str` to a `String`. Most API functions will ask for an [`ImageView`].
 
+use std::collections::HashMap;
 use std::ops::Deref;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicU64, Ordering};
 
 use anyhow::Result;
 use ash::vk;
 use ash::vk::Handle;
 
-use crate::{Allocation, Allocator, DefaultAllocator, Device, MemoryType};
+use crate::{Allocation, Allocator, DefaultAllocator, Device, Error, MemoryType};
+use crate::command_buffer::command_log::detached_handle;
 use crate::core::traits::{AsRaw, Nameable};
 
 /// Abstraction over a [`VkImage`](vk::Image). Stores information about size, format, etc. Additionally couples the image data together
@@ -48,6 +50,10 @@ pub struct Image<A: Allocator = DefaultAllocator> {
     mip_levels: u32,
     /// Number of samples. Useful for multisampled attachments
     samples: vk::SampleCountFlags,
+    /// Sharing mode of the image. Exclusive images need queue family ownership transfers when used on multiple queues.
+    sharing_mode: vk::SharingMode,
+    /// Usage flags the image was created with.
+    usage: vk::ImageUsageFlags,
 }
 
 unsafe impl<A: Allocator> Send for Image<A> {}
@@ -60,11 +66,11 @@ unsafe impl<A: Allocator> Sync for Image<A> {}
 #[derive(Derivative)]
 #[derivative(Debug, Hash, PartialEq, Eq)]
 pub struct ImgView {
-    /// Reference to the [`VkDevice`](vk::Device)
+    /// Reference to the [`VkDevice`](vk::Device), or `None` for detached views.
     #[derivative(Debug = "ignore")]
     #[derivative(Hash = "ignore")]
     #[derivative(PartialEq = "ignore")]
-    device: Device,
+    device: Option<Device>,
     /// [`VkImageView`](vk::ImageView) handle
     handle: vk::ImageView,
     /// Reference to the [`VkImage`](vk::Image).
@@ -73,6 +79,10 @@ pub struct ImgView {
     format: vk::Format,
     /// Number of samples.
     samples: vk::SampleCountFlags,
+    /// Sharing mode of the owning image.
+    sharing_mode: vk::SharingMode,
+    /// Usage flags of the owning image.
+    usage: vk::ImageUsageFlags,
     /// Image aspect.
     aspect: vk::ImageAspectFlags,
     /// Size of the corresponding image region.
@@ -87,6 +97,11 @@ pub struct ImgView {
     layer_count: u32,
     /// Unique ID for this image view, because vk handles may be reused.
     id: u64,
+    /// Views of a range of this view, created through [`ImgView::view()`]. These are kept alive as long as this view.
+    #[derivative(Debug = "ignore")]
+    #[derivative(Hash = "ignore")]
+    #[derivative(PartialEq = "ignore")]
+    sub_views: Mutex<HashMap<ImageViewCreateInfo, ImageView>>,
 }
 
 /// Settings that describe how an image view should be created from its Image
@@ -119,6 +134,38 @@ impl Deref for ImageView {
     }
 }
 
+impl ImageView {
+    /// Create a view of all mip levels and array layers of an image that is not backed by a Vulkan object, for recording to a
+    /// [`CommandLog`](crate::command_buffer::command_log::CommandLog). The view and its image get unique handles, so they can be
+    /// identified in the log.
+    /// # Safety
+    /// The view must only be used with command buffers created with [`IncompleteCommandBuffer::new_logged()`](crate::IncompleteCommandBuffer::new_logged).
+    /// Its handles are not valid Vulkan objects, so using it with a Vulkan command buffer or creating Vulkan objects from it is undefined behaviour.
+    pub unsafe fn detached(info: &ImageCreateInfo, aspect: vk::ImageAspectFlags) -> ImageView {
+        ImageView(Arc::new(ImgView {
+            device: None,
+            handle: detached_handle(),
+            image: detached_handle(),
+            format: info.format,
+            samples: info.samples,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            usage: info.usage,
+            aspect,
+            size: vk::Extent3D {
+                width: info.width,
+                height: info.height,
+                depth: info.depth,
+            },
+            base_level: 0,
+            level_count: info.mip_levels,
+            base_layer: 0,
+            layer_count: info.layers,
+            id: ImgView::get_new_id(),
+            sub_views: Mutex::default(),
+        }))
+    }
+}
+
 unsafe impl Send for ImageView {}
 
 unsafe impl Sync for ImageView {}
@@ -146,6 +193,72 @@ pub struct ImageCreateInfo {
     pub memory_type: MemoryType,
 }
 
+/// Create a raw [`VkImage`](vk::Image) handle with no memory bound to it.
+pub(crate) fn create_image_handle(
+    device: &Device,
+    info: &ImageCreateInfo,
+) -> Result<(vk::Image, vk::Extent3D, vk::SharingMode)> {
+    let sharing_mode = if device.is_single_queue()
+        || info.usage.intersects(
+            vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        ) {
+        vk::SharingMode::EXCLUSIVE
+    } else {
+        vk::SharingMode::CONCURRENT
+    };
+
+    let image_type = if info.height == 1 && info.depth == 1 {
+        vk::ImageType::TYPE_1D
+    } else if info.depth > 1 {
+        vk::ImageType::TYPE_3D
+    } else if info.height > 1 {
+        vk::ImageType::TYPE_2D
+    } else {
+        return Err(anyhow::anyhow!("Image extents invalid"));
+    };
+
+    let extent = vk::Extent3D {
+        width: info.width,
+        height: info.height,
+        depth: info.depth
+    };
+    let handle = unsafe {
+        device.create_image(
+            &vk::ImageCreateInfo {
+                s_type: vk::StructureType::IMAGE_CREATE_INFO,
+                p_next: std::ptr::null(),
+                flags: Default::default(),
+                image_type,
+                format: info.format,
+                extent,
+                mip_levels: info.mip_levels,
+                array_layers: info.layers,
+                samples: info.samples,
+                tiling: vk::ImageTiling::OPTIMAL,
+                usage: info.usage,
+                sharing_mode,
+                queue_family_index_count: if sharing_mode == vk::SharingMode::CONCURRENT {
+                    device.queue_families().len() as u32
+                } else {
+                    0
+                },
+                p_queue_family_indices: if sharing_mode == vk::SharingMode::CONCURRENT {
+                    device.queue_families().as_ptr()
+                } else {
+                    std::ptr::null()
+                },
+                initial_layout: vk::ImageLayout::UNDEFINED,
+            },
+            None,
+        )?
+    };
+    #[cfg(feature = "log-objects")]
+    trace!("Created new VkImage {handle:p}");
+
+    Ok((handle, extent, sharing_mode))
+}
+
 impl<A: Allocator> Image<A> {
     /// Create a new simple [`VkImage`] and allocate some memory to it.
     pub fn new(
@@ -153,63 +266,7 @@ impl<A: Allocator> Image<A> {
         alloc: &mut A,
         info: ImageCreateInfo,
     ) -> Result<Self> {
-        let sharing_mode = if device.is_single_queue()
-            || info.usage.intersects(
-                vk::ImageUsageFlags::COLOR_ATTACHMENT
-                    | vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
-            ) {
-            vk::SharingMode::EXCLUSIVE
-        } else {
-            vk::SharingMode::CONCURRENT
-        };
-
-        let image_type = if info.height == 1 && info.depth == 1 {
-            vk::ImageType::TYPE_1D
-        } else if info.depth > 1 {
-            vk::ImageType::TYPE_3D
-        } else if info.height > 1 {
-            vk::ImageType::TYPE_2D
-        } else {
-            return Err(anyhow::anyhow!("Image extents invalid"));
-        };
-
-        let extent = vk::Extent3D {
-            width: info.width,
-            height: info.height,
-            depth: info.depth
-        };
-        let handle = unsafe {
-            device.create_image(
-                &vk::ImageCreateInfo {
-                    s_type: vk::StructureType::IMAGE_CREATE_INFO,
-                    p_next: std::ptr::null(),
-                    flags: Default::default(),
-                    image_type,
-                    format: info.format,
-                    extent,
-                    mip_levels: info.mip_levels,
-                    array_layers: info.layers,
-                    samples: info.samples,
-                    tiling: vk::ImageTiling::OPTIMAL,
-                    usage: info.usage,
-                    sharing_mode,
-                    queue_family_index_count: if sharing_mode == vk::SharingMode::CONCURRENT {
-                        device.queue_families().len() as u32
-                    } else {
-                        0
-                    },
-                    p_queue_family_indices: if sharing_mode == vk::SharingMode::CONCURRENT {
-                        device.queue_families().as_ptr()
-                    } else {
-                        std::ptr::null()
-                    },
-                    initial_layout: vk::ImageLayout::UNDEFINED,
-                },
-                None,
-            )?
-        };
-        #[cfg(feature = "log-objects")]
-        trace!("Created new VkImage {handle:p}");
+        let (handle, extent, sharing_mode) = create_image_handle(&device, &info)?;
 
         let requirements = unsafe { device.get_image_memory_requirements(handle) };
 
@@ -226,10 +283,13 @@ impl<A: Allocator> Image<A> {
             layers: info.layers,
             mip_levels: info.mip_levels,
             samples: info.samples,
+            sharing_mode,
+            usage: info.usage,
             memory: Some(memory),
         })
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new_managed(
         device: Device,
         handle: vk::Image,
@@ -238,6 +298,8 @@ impl<A: Allocator> Image<A> {
         layers: u32,
         mip_levels: u32,
         samples: vk::SampleCountFlags,
+        sharing_mode: vk::SharingMode,
+        usage: vk::ImageUsageFlags,
     ) -> Self {
         Self {
             device,
@@ -248,6 +310,8 @@ impl<A: Allocator> Image<A> {
             layers,
             mip_levels,
             samples,
+            sharing_mode,
+            usage,
         }
     }
 
@@ -263,6 +327,8 @@ impl<A: Allocator> Image<A> {
             vk::ImageViewType::TYPE_1D
         } else if self.size.depth > 1 {
             vk::ImageViewType::TYPE_3D
+        } else if self.size.height > 1 && self.layers > 1 {
+            vk::ImageViewType::TYPE_2D_ARRAY
         } else if self.size.height > 1 {
             vk::ImageViewType::TYPE_2D
         } else {
@@ -334,11 +400,13 @@ impl<A: Allocator> Image<A> {
         #[cfg(feature = "log-objects")]
         trace!("Created new VkImageView {view_handle:p}");
         Ok(ImageView(Arc::new(ImgView {
-            device: self.device.clone(),
+            device: Some(self.device.clone()),
             handle: view_handle,
             image: self.handle,
             format: self.format,
             samples: self.samples,
+            sharing_mode: self.sharing_mode,
+            usage: self.usage,
             aspect,
             size: self.size,
             base_level: base_mip_level,
@@ -346,6 +414,7 @@ impl<A: Allocator> Image<A> {
             base_layer,
             layer_count: info.subresource_range.layer_count,
             id: ImgView::get_new_id(),
+            sub_views: Mutex::default(),
         })))
     }
 
@@ -400,6 +469,16 @@ impl<A: Allocator> Image<A> {
     pub fn samples(&self) -> vk::SampleCountFlags {
         self.samples
     }
+
+    /// Get the sharing mode of this image.
+    pub fn sharing_mode(&self) -> vk::SharingMode {
+        self.sharing_mode
+    }
+
+    /// Get the usage flags this image was created with.
+    pub fn usage(&self) -> vk::ImageUsageFlags {
+        self.usage
+    }
 }
 
 unsafe impl AsRaw for Image {
@@ -430,6 +509,82 @@ impl ImgView {
         COUNTER.fetch_add(1, Ordering::Relaxed)
     }
 
+    /// Get a view of a range of the mip levels and array layers covered by this view. The range in `create_info` is relative to
+    /// this view, so mip level 0 is the first mip level of this view. Views are created on first use and kept alive together with this view,
+    /// so this is cheap to call every frame.
+    /// The size of the returned view is the size of its first mip level.
+    /// # Errors
+    /// * Fails if the requested range is not inside the range of this view.
+    /// * Fails if creating the image view fails.
+    pub fn view(&self, create_info: ImageViewCreateInfo) -> Result<ImageView> {
+        let mut sub_views = self.sub_views.lock().map_err(|_| Error::PoisonError)?;
+        if let Some(view) = sub_views.get(&create_info) {
+            return Ok(view.clone());
+        }
+
+        let level_count = create_info
+            .level_count
+            .unwrap_or(self.level_count.saturating_sub(create_info.base_mip_level));
+        let layer_count = create_info
+            .layers
+            .unwrap_or(self.layer_count.saturating_sub(create_info.base_layer));
+        if level_count == 0
+            || layer_count == 0
+            || create_info.base_mip_level + level_count > self.level_count
+            || create_info.base_layer + layer_count > self.layer_count
+        {
+            return Err(Error::Uncategorized("Image view range is not inside the range of the parent view").into());
+        }
+
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: create_info.aspect,
+            base_mip_level: self.base_level + create_info.base_mip_level,
+            level_count,
+            base_array_layer: self.base_layer + create_info.base_layer,
+            layer_count,
+        };
+        let info = vk::ImageViewCreateInfo {
+            s_type: vk::StructureType::IMAGE_VIEW_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: Default::default(),
+            image: self.image,
+            view_type: create_info.view_type,
+            format: self.format,
+            components: vk::ComponentMapping::default(),
+            subresource_range,
+        };
+        let handle = match &self.device {
+            Some(device) => unsafe { device.create_image_view(&info, None)? },
+            None => detached_handle(),
+        };
+        #[cfg(feature = "log-objects")]
+        trace!("Created new VkImageView {handle:p}");
+        let level = create_info.base_mip_level;
+        let view = ImageView(Arc::new(ImgView {
+            device: self.device.clone(),
+            handle,
+            image: self.image,
+            format: self.format,
+            samples: self.samples,
+            sharing_mode: self.sharing_mode,
+            usage: self.usage,
+            aspect: create_info.aspect,
+            size: vk::Extent3D {
+                width: (self.size.width >> level).max(1),
+                height: (self.size.height >> level).max(1),
+                depth: (self.size.depth >> level).max(1),
+            },
+            base_level: subresource_range.base_mip_level,
+            level_count,
+            base_layer: subresource_range.base_array_layer,
+            layer_count,
+            id: ImgView::get_new_id(),
+            sub_views: Mutex::default(),
+        }));
+        sub_views.insert(create_info, view.clone());
+        Ok(view)
+    }
+
     /// Returns the subresource range of the original image that this image view covers.
     pub fn subresource_range(&self) -> vk::ImageSubresourceRange {
         vk::ImageSubresourceRange {
@@ -465,6 +620,16 @@ impl ImgView {
         self.samples
     }
 
+    /// Get the sharing mode of the image this view was built from.
+    pub fn sharing_mode(&self) -> vk::SharingMode {
+        self.sharing_mode
+    }
+
+    /// Get the usage flags of the image this view was built from.
+    pub fn usage(&self) -> vk::ImageUsageFlags {
+        self.usage
+    }
+
     /// Get the image aspect that this view was built from
     pub fn aspect(&self) -> vk::ImageAspectFlags {
         self.aspect
@@ -519,10 +684,11 @@ impl ImgView {
 
 impl Drop for ImgView {
     fn drop(&mut self) {
+        let Some(device) = &self.device else { return };
         #[cfg(feature = "log-objects")]
         trace!("Destroying VkImageView {:p}", self.handle);
         unsafe {
-            self.device.destroy_image_view(self.handle, None);
+            device.destroy_image_view(self.handle, None);
         }
     }
 }
@@ -12,7 +12,7 @@ use futures::Future;
 use multimap::{Entry, MultiMap};
 
 use crate::{
-    Allocator, BufferView, DefaultAllocator, DescriptorCache, Device, Fence, PipelineCache,
+    Allocator, BufferView, DefaultAllocator, DescriptorCache, Device, Event, Fence, PipelineCache,
     ScratchAllocator,
 };
 
@@ -48,7 +48,22 @@ pub struct Pooled<P: Poolable> {
     key: Option<P::Key>,
 }
 
-type BoxedCreateFunc<P> = Box<dyn FnMut(&<P as Poolable>::Key) -> Result<P>>;
+/// Callback used by a [`Pool`] to create new objects. With the `rayon` feature, pools are used from worker threads
+/// when recording in parallel, so the callback must also be [`Send`].
+#[cfg(feature = "rayon")]
+pub trait CreateFn<P: Poolable>: FnMut(&P::Key) -> Result<P> + Send {}
+
+#[cfg(feature = "rayon")]
+impl<P: Poolable, F: FnMut(&P::Key) -> Result<P> + Send> CreateFn<P> for F {}
+
+/// Callback used by a [`Pool`] to create new objects.
+#[cfg(not(feature = "rayon"))]
+pub trait CreateFn<P: Poolable>: FnMut(&P::Key) -> Result<P> {}
+
+#[cfg(not(feature = "rayon"))]
+impl<P: Poolable, F: FnMut(&P::Key) -> Result<P>> CreateFn<P> for F {}
+
+type BoxedCreateFunc<P> = Box<dyn CreateFn<P>>;
 
 struct PoolInner<P: Poolable> {
     items: MultiMap<P::Key, P>,
@@ -74,6 +89,9 @@ pub struct ResourcePool<A: Allocator = DefaultAllocator> {
     /// Fence pool to reuse fences where possible
     #[derivative(Debug = "ignore")]
     pub fences: Pool<Fence<()>>,
+    /// Event pool to reuse events where possible
+    #[derivative(Debug = "ignore")]
+    pub events: Pool<Event>,
 }
 
 /// Information needed to create a resource pool
@@ -89,9 +107,10 @@ pub struct ResourcePoolCreateInfo<A: Allocator = DefaultAllocator> {
 /// A local pool that will release its resources back to the main resource pool when it goes out of scope.
 /// Such a scope could be a frame context, or a task spawned on a background thread.
 pub struct LocalPool<A: Allocator = DefaultAllocator> {
-    #[allow(dead_code)]
-    pool: ResourcePool<A>,
-    scratch_allocator: Pooled<ScratchAllocator<A>>,
+    /// The resource pool, or `None` for detached pools.
+    pool: Option<ResourcePool<A>>,
+    scratch_allocator: Option<Pooled<ScratchAllocator<A>>>,
+    events: Vec<Pooled<Event>>,
 }
 
 impl<P: Poolable> Clone for Pool<P> {
@@ -185,7 +204,7 @@ impl<P: Poolable> Pool<P> {
     /// Create a new pool. This must be supplied with a callback to be called
     /// when the pool needs to allocate a new object.
     /// Optionally also takes in a count of objects to preallocate using this callback.
-    pub fn new(create_fn: impl FnMut(&P::Key) -> Result<P> + 'static) -> Result<Self> {
+    pub fn new(create_fn: impl CreateFn<P> + 'static) -> Result<Self> {
         let inner = PoolInner {
             items: MultiMap::new(),
             create_fn: Box::new(create_fn),
@@ -209,12 +228,15 @@ impl<A: Allocator + 'static> ResourcePool<A> {
         })?;
         let device = info.device.clone();
         let fences = Pool::new(move |_| Ok(Fence::new(device.clone(), false)?))?;
+        let device = info.device.clone();
+        let events = Pool::new(move |_| Ok(Event::new(device.clone())?))?;
 
         Ok(Self {
             pipelines,
             descriptors,
             allocators,
             fences,
+            events,
         })
     }
 }
@@ -243,14 +265,72 @@ impl<A: Allocator> LocalPool<A> {
         let alloc = pool.get_scratch_allocator()?;
 
         Ok(Self {
-            pool,
-            scratch_allocator: alloc,
+            pool: Some(pool),
+            scratch_allocator: Some(alloc),
+            events: vec![],
         })
     }
 
+    /// Create a local pool that is not backed by a resource pool, for recording to a
+    /// [`CommandLog`](crate::command_buffer::command_log::CommandLog). Scratch buffers and events allocated from it
+    /// are detached, see [`BufferView::detached()`] and [`Event::detached()`].
+    /// # Safety
+    /// The pool must only be used with command buffers created with [`IncompleteCommandBuffer::new_logged()`](crate::IncompleteCommandBuffer::new_logged),
+    /// since the buffers and events allocated from it are not valid Vulkan objects.
+    pub unsafe fn detached() -> Self {
+        Self {
+            pool: None,
+            scratch_allocator: None,
+            events: vec![],
+        }
+    }
+
+    /// Get the global resource pool this local pool was created from. This panics if the pool is [detached](LocalPool::detached),
+    /// use [`LocalPool::try_resource_pool()`] if that is possible.
+    pub fn resource_pool(&self) -> &ResourcePool<A> {
+        self.pool
+            .as_ref()
+            .expect("Detached local pools are not created from a resource pool")
+    }
+
+    /// Get the global resource pool this local pool was created from, or `None` if this pool is [detached](LocalPool::detached).
+    pub fn try_resource_pool(&self) -> Option<&ResourcePool<A>> {
+        self.pool.as_ref()
+    }
+
     /// Allocate a scratch buffer, which is only valid for the scope of this local pool.
     /// See also: [`ScratchAllocator`](crate::ScratchAllocator)
     pub fn allocate_scratch_buffer(&mut self, size: vk::DeviceSize) -> Result<BufferView> {
-        self.scratch_allocator.allocate(size)
+        match &mut self.scratch_allocator {
+            Some(allocator) => allocator.allocate(size),
+            // Only detached pools have no scratch allocator, and these are only used with logged command buffers.
+            None => Ok(unsafe { BufferView::detached(0, size) }),
+        }
+    }
+
+    /// Reset the scratch allocator and release all events back to the resource pool, so this pool can be reused for a new frame.
+    /// # Safety
+    /// The GPU must have finished using all scratch buffers and events allocated from this pool.
+    /// # Errors
+    /// * Fails if the scratch allocator fails to reset.
+    #[cfg(feature = "rayon")]
+    pub(crate) unsafe fn reset(&mut self) -> Result<()> {
+        if let Some(allocator) = &mut self.scratch_allocator {
+            allocator.reset(None)?;
+        }
+        self.events.clear();
+        Ok(())
+    }
+
+    /// Allocate an unsignaled event, which is only valid for the scope of this local pool. The event is reset and
+    /// released back to the resource pool when this local pool is dropped.
+    /// # Errors
+    /// * Fails if creating a new event fails.
+    pub fn allocate_event(&mut self) -> Result<Event> {
+        let Some(pool) = &self.pool else { return Ok(unsafe { Event::detached() }) };
+        let event = Event::new_in_pool(&pool.events, &())?;
+        let handle = (*event).clone();
+        self.events.push(event);
+        Ok(handle)
     }
 }
@@ -6,7 +6,8 @@ use std::time::Duration;
 use anyhow::{ensure, Result};
 use ash::vk;
 
-use crate::{Device, PipelineStage};
+use crate::{Device, Error, PipelineStage};
+use crate::command_buffer::command_log::detached_handle;
 
 /// Trait that must be implemented for each Vulkan query
 pub trait Query: Clone + Sized {
@@ -278,7 +279,8 @@ pub struct QueryPoolCreateInfo {
 /// This trait provides information needed to parse the results of the Vulkan query.
 pub struct QueryPool<Q: Query> {
     handle: vk::QueryPool,
-    device: Device,
+    /// `None` for detached query pools.
+    device: Option<Device>,
     current: u32,
     count: u32,
     queries: Vec<Q>,
@@ -305,13 +307,35 @@ impl<Q: Query> QueryPool<Q> {
 
         Ok(Self {
             handle,
-            device,
+            device: Some(device),
             current: 0,
             count: info.count,
             queries: vec![Q::new(&info); info.count as usize],
         })
     }
 
+    /// Create a query pool with at most `count` entries that is not backed by a Vulkan object, for recording to a
+    /// [`CommandLog`](crate::command_buffer::command_log::CommandLog). The pool gets a unique handle, so it can be identified in the log.
+    /// Results of a detached query pool can not be read.
+    /// # Safety
+    /// The pool must only be used with command buffers created with [`IncompleteCommandBuffer::new_logged()`](crate::IncompleteCommandBuffer::new_logged).
+    /// Its handle is not a valid Vulkan object, so using it with a Vulkan command buffer is undefined behaviour.
+    pub unsafe fn detached(info: QueryPoolCreateInfo) -> Self {
+        Self {
+            handle: detached_handle(),
+            device: None,
+            current: 0,
+            count: info.count,
+            queries: vec![Q::new(&info); info.count as usize],
+        }
+    }
+
+    fn device(&self) -> Result<&Device> {
+        self.device
+            .as_ref()
+            .ok_or_else(|| Error::Uncategorized("Cannot read results of a detached query pool").into())
+    }
+
     /// Advance the query pool to the next query, and return the previous index.
     /// Returns None if the query pool was out of entries.
     pub fn next(&mut self) -> Option<u32> {
@@ -337,6 +361,7 @@ impl<Q: Query> QueryPool<Q> {
     pub fn wait_for_results(&mut self, first: u32, count: u32) -> Result<Vec<Q::Output>> {
         ensure!(first < self.count, "Query range out of range of query pool");
         ensure!(first + count <= self.count, "Query range out of range of query pool");
+        let device = self.device()?;
 
         let flags = vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT;
         // Assumption: Every query in the pool has the same number of items, this should always be the case.
@@ -347,7 +372,7 @@ impl<Q: Query> QueryPool<Q> {
             .unwrap_or_default();
         let mut buffer = vec![u64::default(); count as usize * items_per_query];
         unsafe {
-            self.device.get_query_pool_results(
+            device.get_query_pool_results(
                 self.handle,
                 first,
                 count,
@@ -359,7 +384,7 @@ impl<Q: Query> QueryPool<Q> {
             .chunks_exact(items_per_query)
             .into_iter()
             .zip(self.queries.iter())
-            .map(|(data, query)| query.parse_query(&self.device, data))
+            .map(|(data, query)| query.parse_query(device, data))
             .collect::<Vec<_>>();
 
         Ok(data)
@@ -373,12 +398,13 @@ impl<Q: Query> QueryPool<Q> {
     /// Wait for the result of a single query in the pool
     pub fn wait_for_single_result(&mut self, index: u32) -> Result<Q::Output> {
         ensure!(index < self.count, "Query range out of range of query pool");
+        let device = self.device()?;
         let flags = vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT;
         let query = self.queries.get(index as usize).unwrap();
         let num_items = query.size();
         let mut buffer = vec![u64::default(); num_items];
         unsafe {
-            self.device.get_query_pool_results(
+            device.get_query_pool_results(
                 self.handle,
                 index,
                 1,
@@ -386,13 +412,15 @@ impl<Q: Query> QueryPool<Q> {
                 flags,
             )?;
         }
-        let data = query.parse_query(&self.device, buffer.as_slice());
+        let data = query.parse_query(device, buffer.as_slice());
         Ok(data)
     }
 
     /// Reset the query pool
     pub fn reset(&mut self) {
-        unsafe { self.device.reset_query_pool(self.handle, 0, self.count) };
+        if let Some(device) = &self.device {
+            unsafe { device.reset_query_pool(self.handle, 0, self.count) };
+        }
         self.current = 0;
     }
 
@@ -413,8 +441,9 @@ impl QueryPool<TimestampQuery> {
         query: u32,
     ) {
         self.queries.get_mut(query as usize).unwrap().valid_bits = bits;
+        let Some(device) = &self.device else { return };
         unsafe {
-            self.device
+            device
                 .cmd_write_timestamp2(cmd, stage, self.handle, query);
         }
     }
@@ -422,11 +451,12 @@ impl QueryPool<TimestampQuery> {
 
 impl<Q: Query> Drop for QueryPool<Q> {
     fn drop(&mut self) {
+        let Some(device) = &self.device else { return };
         #[cfg(feature = "log-objects")]
         trace!("Destroying VkQueryPool {:p}", self.handle);
 
         unsafe {
-            self.device.destroy_query_pool(self.handle, None);
+            device.destroy_query_pool(self.handle, None);
         }
     }
 }
@@ -24,6 +24,9 @@ pub trait ExecutionDomain {
     /// Returns true if the selected queue can be used to submit commands from this entire domain
     /// to.
     fn queue_is_compatible(queue: &Queue) -> bool;
+    /// The type of queue passes over this domain are submitted to in a multi-queue pass graph.
+    /// See [`PassBuilder::on_domain()`](crate::PassBuilder::on_domain).
+    const QUEUE_TYPE: QueueType = QueueType::Graphics;
     /// Type of the command buffer that will be submitted to this domain.
     /// This type must implement the [`IncompleteCmdBuffer`] trait.
     type CmdBuf<'q, A: Allocator>: IncompleteCmdBuffer<'q, A>;
@@ -62,6 +65,8 @@ impl ExecutionDomain for Transfer {
         queue.info().queue_type == QueueType::Transfer
     }
 
+    const QUEUE_TYPE: QueueType = QueueType::Transfer;
+
     /// Type of the command buffer that will be submitted to this domain.
     type CmdBuf<'q, A: Allocator> = IncompleteCommandBuffer<'q, Transfer, A>;
 }
@@ -73,6 +78,8 @@ impl ExecutionDomain for Compute {
         queue.info().queue_type == QueueType::Compute
     }
 
+    const QUEUE_TYPE: QueueType = QueueType::Compute;
+
     /// Type of the command buffer that will be submitted to this domain.
     type CmdBuf<'q, A: Allocator> = IncompleteCommandBuffer<'q, Compute, A>;
 }
@@ -0,0 +1,105 @@
+//! Abstraction for `VkEvent` objects.
+
+use std::sync::Arc;
+
+use ash::vk;
+
+use crate::command_buffer::command_log::detached_handle;
+use crate::Device;
+use crate::pool::Poolable;
+
+#[derive(Debug)]
+struct EventInner {
+    /// `None` for detached events.
+    device: Option<Device>,
+    handle: vk::Event,
+}
+
+/// Wrapper around a [`VkEvent`](vk::Event) object. Events are used for fine-grained GPU sync within a single queue,
+/// by splitting a barrier into a signal with [`IncompleteCommandBuffer::set_event()`](crate::IncompleteCommandBuffer::set_event)
+/// and a wait with [`IncompleteCommandBuffer::wait_events()`](crate::IncompleteCommandBuffer::wait_events). Work recorded
+/// between the two does not have to wait for the barrier.
+///
+/// This is a cheap handle to the event, cloning it does not create a new event. The event is destroyed when the last clone is dropped.
+#[derive(Debug, Clone)]
+pub struct Event {
+    inner: Arc<EventInner>,
+}
+
+impl Event {
+    /// Create a new `VkEvent` object in the unsignaled state.
+    pub fn new(device: Device) -> Result<Self, vk::Result> {
+        let info = vk::EventCreateInfo {
+            s_type: vk::StructureType::EVENT_CREATE_INFO,
+            p_next: std::ptr::null(),
+            // Not device-only, so the event can be reset from the host when it is released to a pool.
+            flags: vk::EventCreateFlags::empty(),
+        };
+
+        let handle = unsafe { device.create_event(&info, None)? };
+        #[cfg(feature = "log-objects")]
+        trace!("Created new VkEvent {handle:p}");
+
+        Ok(Event {
+            inner: Arc::new(EventInner {
+                device: Some(device),
+                handle,
+            }),
+        })
+    }
+
+    /// Create an event that is not backed by a Vulkan object, for recording to a
+    /// [`CommandLog`](crate::command_buffer::command_log::CommandLog). The event gets a unique handle, so it can be identified in the log.
+    /// # Safety
+    /// The event must only be used with command buffers created with [`IncompleteCommandBuffer::new_logged()`](crate::IncompleteCommandBuffer::new_logged).
+    /// Its handle is not a valid Vulkan object, so using it with a Vulkan command buffer is undefined behaviour.
+    pub unsafe fn detached() -> Self {
+        Event {
+            inner: Arc::new(EventInner {
+                device: None,
+                handle: detached_handle(),
+            }),
+        }
+    }
+
+    /// Reset the event to the unsignaled state from the host.
+    /// # Errors
+    /// * Fails if resetting the event fails.
+    /// # Safety
+    /// No command buffer that sets or waits on this event may still be executing.
+    pub unsafe fn reset(&self) -> Result<(), vk::Result> {
+        match &self.inner.device {
+            Some(device) => device.reset_event(self.inner.handle),
+            None => Ok(()),
+        }
+    }
+
+    /// Get unsafe access to the underlying `VkEvent` object.
+    /// # Safety
+    /// Any vulkan calls that mutate the event may put the system in an undefined state.
+    pub unsafe fn handle(&self) -> vk::Event {
+        self.inner.handle
+    }
+}
+
+impl Drop for EventInner {
+    fn drop(&mut self) {
+        let Some(device) = &self.device else { return };
+        #[cfg(feature = "log-objects")]
+        trace!("Destroying VkEvent {:p}", self.handle);
+        unsafe {
+            device.destroy_event(self.handle, None);
+        }
+    }
+}
+
+impl Poolable for Event {
+    /// All events are created equal
+    type Key = ();
+
+    fn on_release(&mut self) {
+        // SAFETY: Events are only released to the pool once the local pool they were allocated from is dropped,
+        // after the GPU has finished executing the commands using them.
+        unsafe { self.reset().unwrap() };
+    }
+}
@@ -6,7 +6,9 @@ use std::sync::{Arc, Mutex, MutexGuard, TryLockError, TryLockResult};
 use anyhow::Result;
 use ash::vk;
 
-use crate::{Allocator, CmdBuffer, DefaultAllocator, Device, Error, Fence, PhysicalDevice};
+use crate::{
+    Allocator, CmdBuffer, DefaultAllocator, Device, Error, Fence, PhysicalDevice, QueueType,
+};
 use crate::command_buffer::*;
 use crate::core::queue::{DeviceQueue, Queue};
 use crate::pool::{Poolable, Pooled, ResourcePool};
@@ -158,14 +160,33 @@ impl<A: Allocator> ExecutionManager<A> {
         SubmitBatch::new(self.device.clone(), self.clone(), &self.pool)
     }
 
-    /// Submit multiple SubmitInfo2 structures.
+    /// Obtain a command buffer over the domain `D`, allocated from the queue returned by [`ExecutionManager::get_queue_for()`].
+    pub(crate) fn on_queue<'q, D: ExecutionDomain>(
+        &'q self,
+        queue: Option<QueueType>,
+    ) -> Result<IncompleteCommandBuffer<'q, D, A>> {
+        let queue = self
+            .get_queue_for::<D>(queue)
+            .ok_or(Error::NoCapableQueue)?;
+        Queue::allocate_command_buffer::<'q, A, IncompleteCommandBuffer<'q, D, A>>(
+            self.device.clone(),
+            queue,
+            self.pool.pipelines.clone(),
+            self.pool.descriptors.clone(),
+        )
+    }
+
+    /// Submit multiple SubmitInfo2 structures to the queue returned by [`ExecutionManager::get_queue_for()`].
     pub(crate) fn submit_batch<D: ExecutionDomain>(
         &self,
+        queue: Option<QueueType>,
         submits: &[vk::SubmitInfo2],
-        fence: &Fence,
+        fence: Option<&Fence>,
     ) -> Result<()> {
-        let queue = self.get_queue::<D>().ok_or_else(|| Error::NoCapableQueue)?;
-        queue.submit2(submits, Some(fence))?;
+        let queue = self
+            .get_queue_for::<D>(queue)
+            .ok_or(Error::NoCapableQueue)?;
+        queue.submit2(submits, fence)?;
         Ok(())
     }
 
@@ -203,6 +224,23 @@ impl<A: Allocator> ExecutionManager<A> {
             })
             .map(|q| q.lock().unwrap())
     }
+
+    /// Obtain a reference to a queue of the given type. If `queue` is `None`, or there is no queue of this type,
+    /// this returns a queue matching the domain instead. Blocks if this queue is currently locked.
+    pub(crate) fn get_queue_for<D: ExecutionDomain>(
+        &self,
+        queue: Option<QueueType>,
+    ) -> Option<MutexGuard<'_, Queue>> {
+        let Some(queue_type) = queue else { return self.get_queue::<D>() };
+        // Keep the guard of the matching queue, so no other thread can take it before it is returned.
+        self.queues
+            .iter()
+            .find_map(|q| {
+                let guard = q.lock().unwrap();
+                (guard.info().queue_type == queue_type).then_some(guard)
+            })
+            .or_else(|| self.get_queue::<D>())
+    }
 }
 
 impl<A: Allocator + 'static> ExecutionManager<A> {
@@ -3,6 +3,7 @@
 //!
 //! - The [`fence`] module provides a wrapper around `VkFence` objects, used for CPU-GPU sync,
 //! as well as an implementation for [`Future`](std::future::Future) for them.
+//! - The [`event`] module provides a wrapper around `VkEvent` objects, used for split barriers within a single queue.
 //! - The [`semaphore`] module provides a simple wrapper around `VkSemaphore` objects, used for GPU-GPU sync.
 //! - The [`execution_manager`] module abstracts away vulkan queues and synchronizes access to them by using the
 //! [`domain`](crate::domain) system. Most of the time, submissions should go through here.
@@ -10,6 +11,7 @@
 //! as one batch.
 
 pub mod domain;
+pub mod event;
 pub mod execution_manager;
 pub mod fence;
 pub mod semaphore;
@@ -14,9 +14,24 @@ pub struct Semaphore {
 impl Semaphore {
     /// Create a new `VkSemaphore` object.
     pub fn new(device: Device) -> Result<Self, vk::Result> {
+        Self::create(device, std::ptr::null())
+    }
+
+    /// Create a new timeline `VkSemaphore` object, with a counter starting at `initial_value`.
+    pub fn new_timeline(device: Device, initial_value: u64) -> Result<Self, vk::Result> {
+        let type_info = vk::SemaphoreTypeCreateInfo {
+            s_type: vk::StructureType::SEMAPHORE_TYPE_CREATE_INFO,
+            p_next: std::ptr::null(),
+            semaphore_type: vk::SemaphoreType::TIMELINE,
+            initial_value,
+        };
+        Self::create(device, &type_info as *const _ as *const std::ffi::c_void)
+    }
+
+    fn create(device: Device, p_next: *const std::ffi::c_void) -> Result<Self, vk::Result> {
         let info = vk::SemaphoreCreateInfo {
             s_type: vk::StructureType::SEMAPHORE_CREATE_INFO,
-            p_next: std::ptr::null(),
+            p_next,
             flags: Default::default(),
         };
 
@@ -9,16 +9,23 @@ use crate::command_buffer::CommandBuffer;
 use crate::pool::{LocalPool, Poolable, Pooled, ResourcePool};
 use crate::sync::domain::ExecutionDomain;
 use crate::{
-    Allocator, CmdBuffer, DefaultAllocator, Device, ExecutionManager, Fence, InFlightContext,
-    PipelineStage, Semaphore,
+    Allocator, CmdBuffer, DefaultAllocator, Device, Error, ExecutionManager, Fence, InFlightContext,
+    PipelineStage, QueueType, Semaphore,
 };
 
 #[derive(Debug)]
 struct SubmitInfo<D: ExecutionDomain> {
     cmd: CommandBuffer<D>,
-    signal_semaphore: Option<Arc<Semaphore>>,
+    // Queue to submit to, or None to use the queue of the domain.
+    queue: Option<QueueType>,
+    // Every submit waiting on this one gets its own semaphore, since a binary semaphore can only be waited on once.
+    signal_semaphores: Vec<Arc<Semaphore>>,
     wait_semaphores: Vec<Arc<Semaphore>>,
     wait_stages: Vec<PipelineStage>,
+    // Timeline semaphores with the value to wait for or signal.
+    timeline_waits: Vec<(Arc<Semaphore>, u64, PipelineStage)>,
+    timeline_signals: Vec<(Arc<Semaphore>, u64)>,
+    waited_on: bool,
 }
 
 /// A handle to a submit inside a batch.
@@ -59,10 +66,12 @@ impl<D: ExecutionDomain + 'static, A: Allocator> SubmitBatch<D, A> {
         })
     }
 
-    fn get_submit_semaphore(&self, submit: SubmitHandle) -> Option<Arc<Semaphore>> {
-        self.submits
-            .get(submit.index)
-            .and_then(|submit| submit.signal_semaphore.clone())
+    fn get_submit_semaphore(&mut self, submit: SubmitHandle) -> Result<Arc<Semaphore>> {
+        let semaphore = Arc::new(Semaphore::new(self.device.clone())?);
+        let submit = self.submit_info(submit)?;
+        submit.signal_semaphores.push(semaphore.clone());
+        submit.waited_on = true;
+        Ok(semaphore)
     }
 
     fn submit_after(
@@ -71,16 +80,37 @@ impl<D: ExecutionDomain + 'static, A: Allocator> SubmitBatch<D, A> {
         cmd: CommandBuffer<D>,
         wait_stages: &[PipelineStage],
     ) -> Result<SubmitHandle> {
+        self.submit_on(None, cmd, handles, wait_stages)
+    }
+
+    /// Submit a command buffer to a queue of the given type, waiting on the given previous submits.
+    /// If `queue` is `None`, the queue of the domain is used.
+    pub(crate) fn submit_on(
+        &mut self,
+        queue: Option<QueueType>,
+        cmd: CommandBuffer<D>,
+        handles: &[SubmitHandle],
+        wait_stages: &[PipelineStage],
+    ) -> Result<SubmitHandle> {
+        ensure!(
+            handles.len() == wait_stages.len(),
+            "Number of wait stages must match number of submits"
+        );
+
         let wait_semaphores = handles
             .iter()
-            .map(|handle| self.get_submit_semaphore(*handle).unwrap())
-            .collect::<Vec<_>>();
+            .map(|handle| self.get_submit_semaphore(*handle))
+            .collect::<Result<Vec<_>>>()?;
 
         self.submits.push(SubmitInfo {
             cmd,
-            signal_semaphore: Some(Arc::new(Semaphore::new(self.device.clone())?)),
+            queue,
+            signal_semaphores: vec![],
             wait_semaphores,
             wait_stages: wait_stages.to_vec(),
+            timeline_waits: vec![],
+            timeline_signals: vec![],
+            waited_on: false,
         });
 
         Ok(SubmitHandle {
@@ -88,6 +118,40 @@ impl<D: ExecutionDomain + 'static, A: Allocator> SubmitBatch<D, A> {
         })
     }
 
+    /// Make a submit wait until `semaphore` reaches `value`, at the given stage.
+    pub(crate) fn wait_timeline(
+        &mut self,
+        submit: SubmitHandle,
+        semaphore: Arc<Semaphore>,
+        value: u64,
+        stage: PipelineStage,
+    ) -> Result<()> {
+        self.submit_info(submit)?
+            .timeline_waits
+            .push((semaphore, value, stage));
+        Ok(())
+    }
+
+    /// Make a submit set `semaphore` to `value` when it completes.
+    pub(crate) fn signal_timeline(&mut self, submit: SubmitHandle, semaphore: Arc<Semaphore>, value: u64) -> Result<()> {
+        self.submit_info(submit)?
+            .timeline_signals
+            .push((semaphore, value));
+        Ok(())
+    }
+
+    /// Get the device this batch submits to.
+    pub(crate) fn device(&self) -> &Device {
+        &self.device
+    }
+
+    fn submit_info(&mut self, submit: SubmitHandle) -> Result<&mut SubmitInfo<D>> {
+        Ok(self
+            .submits
+            .get_mut(submit.index)
+            .ok_or(Error::Uncategorized("Invalid submit handle"))?)
+    }
+
     /// Must be used to submit the final command buffer in the frame.
     /// This takes ownership of the frame-local memory pool so it is only released back to the
     /// global pool once the frame is completed.
@@ -120,8 +184,8 @@ impl<D: ExecutionDomain + 'static, A: Allocator> SubmitBatch<D, A> {
 
         let mut wait_semaphores = submits
             .iter()
-            .map(|handle| self.get_submit_semaphore(*handle).unwrap())
-            .collect::<Vec<_>>();
+            .map(|handle| self.get_submit_semaphore(*handle))
+            .collect::<Result<Vec<_>>>()?;
         let mut wait_stages = wait_stages.to_vec();
         let frame_wait_semaphore = ifc.wait_semaphore;
         // Add this semaphore as a wait semaphore for the first submit, or to the frame commands if there is no other submit
@@ -138,9 +202,13 @@ impl<D: ExecutionDomain + 'static, A: Allocator> SubmitBatch<D, A> {
 
         self.submits.push(SubmitInfo {
             cmd,
-            signal_semaphore: Some(ifc.signal_semaphore),
+            queue: None,
+            signal_semaphores: vec![ifc.signal_semaphore],
             wait_semaphores,
             wait_stages,
+            timeline_waits: vec![],
+            timeline_signals: vec![],
+            waited_on: false,
         });
 
         Ok(SubmitHandle {
@@ -167,22 +235,14 @@ impl<D: ExecutionDomain + 'static, A: Allocator> SubmitBatch<D, A> {
 
     /// Submit a new command buffer in this batch with no dependencies.
     pub fn submit(&mut self, cmd: CommandBuffer<D>) -> Result<SubmitHandle> {
-        self.submits.push(SubmitInfo {
-            cmd,
-            signal_semaphore: Some(Arc::new(Semaphore::new(self.device.clone())?)),
-            wait_semaphores: vec![],
-            wait_stages: vec![],
-        });
-
-        Ok(SubmitHandle {
-            index: self.submits.len() - 1,
-        })
+        self.submit_on(None, cmd, &[], &[])
     }
 }
 
 impl<D: ExecutionDomain + 'static, A: Allocator + 'static> SubmitBatch<D, A> {
     /// Finish this batch by submitting it to the execution manager.
     /// This returns a [`Fence`] that can be awaited to wait for completion.
+    /// If the batch contains submits on multiple queues, the fence also waits for all of those to complete.
     pub fn finish(mut self) -> Result<Pooled<Fence>> {
         struct PerSubmit {
             wait_semaphores: Vec<vk::SemaphoreSubmitInfo>,
@@ -190,6 +250,30 @@ impl<D: ExecutionDomain + 'static, A: Allocator + 'static> SubmitBatch<D, A> {
             signal_semaphores: Vec<vk::SemaphoreSubmitInfo>,
         }
 
+        // The value is ignored for binary semaphores.
+        fn semaphore_info(semaphore: &Semaphore, value: u64, stage: PipelineStage) -> vk::SemaphoreSubmitInfo {
+            vk::SemaphoreSubmitInfo {
+                s_type: vk::StructureType::SEMAPHORE_SUBMIT_INFO,
+                p_next: std::ptr::null(),
+                semaphore: unsafe { semaphore.handle() },
+                value,
+                stage_mask: stage,
+                device_index: 0,
+            }
+        }
+
+        // The fence is only signaled by the last queue we submit to. Submits on other queues that nothing waits on
+        // must be joined into the final queue submission so the fence also covers them.
+        let final_queue = self.submits.last().map(|submit| submit.queue);
+        let mut join_semaphores = Vec::new();
+        for submit in &mut self.submits {
+            if !submit.waited_on && Some(submit.queue) != final_queue {
+                let semaphore = Arc::new(Semaphore::new(self.device.clone())?);
+                submit.signal_semaphores.push(semaphore.clone());
+                join_semaphores.push(semaphore);
+            }
+        }
+
         let mut per_submit_info = Vec::new();
         for submit in &self.submits {
             let info = PerSubmit {
@@ -197,14 +281,13 @@ impl<D: ExecutionDomain + 'static, A: Allocator + 'static> SubmitBatch<D, A> {
                     .wait_semaphores
                     .iter()
                     .zip(&submit.wait_stages)
-                    .map(|(semaphore, stage)| vk::SemaphoreSubmitInfo {
-                        s_type: vk::StructureType::SEMAPHORE_SUBMIT_INFO,
-                        p_next: std::ptr::null(),
-                        semaphore: unsafe { semaphore.handle() },
-                        value: 0,
-                        stage_mask: *stage,
-                        device_index: 0,
-                    })
+                    .map(|(semaphore, stage)| semaphore_info(semaphore, 0, *stage))
+                    .chain(
+                        submit
+                            .timeline_waits
+                            .iter()
+                            .map(|(semaphore, value, stage)| semaphore_info(semaphore, *value, *stage)),
+                    )
                     .collect(),
                 cmd_buffer: vec![vk::CommandBufferSubmitInfo {
                     s_type: vk::StructureType::COMMAND_BUFFER_SUBMIT_INFO,
@@ -212,48 +295,82 @@ impl<D: ExecutionDomain + 'static, A: Allocator + 'static> SubmitBatch<D, A> {
                     command_buffer: unsafe { submit.cmd.handle() },
                     device_mask: 0,
                 }],
-                signal_semaphores: match &submit.signal_semaphore {
-                    None => {
-                        vec![]
-                    }
-                    Some(semaphore) => {
-                        vec![vk::SemaphoreSubmitInfo {
-                            s_type: vk::StructureType::SEMAPHORE_SUBMIT_INFO,
-                            p_next: std::ptr::null(),
-                            semaphore: unsafe { semaphore.handle() },
-                            value: 0,
-                            stage_mask: PipelineStage::BOTTOM_OF_PIPE,
-                            device_index: 0,
-                        }]
-                    }
-                },
+                signal_semaphores: submit
+                    .signal_semaphores
+                    .iter()
+                    .map(|semaphore| semaphore_info(semaphore, 0, PipelineStage::BOTTOM_OF_PIPE))
+                    .chain(
+                        submit
+                            .timeline_signals
+                            .iter()
+                            .map(|(semaphore, value)| semaphore_info(semaphore, *value, PipelineStage::ALL_COMMANDS)),
+                    )
+                    .collect(),
             };
             per_submit_info.push(info);
         }
-        let submits = per_submit_info
-            .iter()
-            .map(|submit| vk::SubmitInfo2 {
-                s_type: vk::StructureType::SUBMIT_INFO_2,
-                p_next: std::ptr::null(),
-                flags: Default::default(),
-                wait_semaphore_info_count: submit.wait_semaphores.len() as u32,
-                p_wait_semaphore_infos: submit.wait_semaphores.as_ptr(),
-                command_buffer_info_count: submit.cmd_buffer.len() as u32,
-                p_command_buffer_infos: submit.cmd_buffer.as_ptr(),
-                signal_semaphore_info_count: submit.signal_semaphores.len() as u32,
-                p_signal_semaphore_infos: submit.signal_semaphores.as_ptr(),
-            })
-            .collect::<Vec<_>>();
+        let join = PerSubmit {
+            wait_semaphores: join_semaphores
+                .iter()
+                .map(|semaphore| semaphore_info(semaphore, 0, PipelineStage::ALL_COMMANDS))
+                .collect(),
+            cmd_buffer: vec![],
+            signal_semaphores: vec![],
+        };
+        let to_vk = |submit: &PerSubmit| vk::SubmitInfo2 {
+            s_type: vk::StructureType::SUBMIT_INFO_2,
+            p_next: std::ptr::null(),
+            flags: Default::default(),
+            wait_semaphore_info_count: submit.wait_semaphores.len() as u32,
+            p_wait_semaphore_infos: submit.wait_semaphores.as_ptr(),
+            command_buffer_info_count: submit.cmd_buffer.len() as u32,
+            p_command_buffer_infos: submit.cmd_buffer.as_ptr(),
+            signal_semaphore_info_count: submit.signal_semaphores.len() as u32,
+            p_signal_semaphore_infos: submit.signal_semaphores.as_ptr(),
+        };
+
+        // Submit consecutive submits on the same queue together, in order. This guarantees every semaphore
+        // is signaled by an earlier queue submission before it is waited on.
+        let mut start = 0;
+        while start < self.submits.len() {
+            let queue = self.submits[start].queue;
+            let end = self.submits[start..]
+                .iter()
+                .position(|submit| submit.queue != queue)
+                .map_or(self.submits.len(), |count| start + count);
+            let mut submits = per_submit_info[start..end]
+                .iter()
+                .map(to_vk)
+                .collect::<Vec<_>>();
+            let fence = if end == self.submits.len() {
+                if !join_semaphores.is_empty() {
+                    submits.push(to_vk(&join));
+                }
+                Some(&*self.signal_fence)
+            } else {
+                None
+            };
+            self.exec
+                .submit_batch::<D>(queue, submits.as_slice(), fence)?;
+            start = end;
+        }
 
-        self.exec
-            .submit_batch::<D>(submits.as_slice(), &self.signal_fence)?;
         self.signal_fence.replace(move |fence| {
             fence.with_cleanup(move || {
                 // Take ownership of every resource inside the submit batch, to delete it afterwards
                 let _pool = self.local_pool;
+                let _join_semaphores = join_semaphores;
                 for mut submit in self.submits {
                     unsafe {
-                        submit.cmd.delete(self.exec.clone()).unwrap();
+                        match submit.queue {
+                            None => submit.cmd.delete(self.exec.clone()).unwrap(),
+                            Some(queue) => {
+                                let queue = self.exec.get_queue_for::<D>(Some(queue)).unwrap();
+                                queue
+                                    .free_command_buffer::<CommandBuffer<D>, A>(submit.cmd.handle())
+                                    .unwrap();
+                            }
+                        }
                     }
                 }
             })
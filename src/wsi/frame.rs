@@ -66,13 +66,14 @@
 
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{ensure, Result};
 use ash::vk;
 
 use crate::{
     Allocator, CmdBuffer, DefaultAllocator, Device, Error, ExecutionManager, Fence,
     Image, ImageView, Instance, Semaphore, Surface, Swapchain, SurfaceSettings,
 };
+use crate::graph::transient::ReferenceExtents;
 use crate::pool::{Poolable, Pooled, ResourcePool};
 use crate::sync::domain::ExecutionDomain;
 use crate::sync::submit_batch::SubmitBatch;
@@ -115,6 +116,8 @@ struct PerFrame<A> {
 pub struct InFlightContext {
     /// The current frame's swapchain image
     pub swapchain_image: ImageView,
+    /// The swapchain size and render resolution of this frame. See [`FrameManager::reference_extents()`].
+    pub extents: ReferenceExtents,
     pub(crate) wait_semaphore: Arc<Semaphore>,
     pub(crate) signal_semaphore: Arc<Semaphore>,
 }
@@ -135,6 +138,7 @@ pub struct FrameManager<A: Allocator = DefaultAllocator> {
     swapchain: Swapchain,
     swapchain_delete: DeletionQueue<Swapchain>,
     pool: ResourcePool<A>,
+    render_scale: f32,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -230,6 +234,8 @@ impl<A: Allocator> FrameManager<A> {
                         1,
                         1,
                         vk::SampleCountFlags::TYPE_1,
+                        vk::SharingMode::EXCLUSIVE,
+                        vk::ImageUsageFlags::COLOR_ATTACHMENT,
                     );
                     // Create a trivial ImageView.
                     let view = image.whole_view(vk::ImageAspectFlags::COLOR)?;
@@ -316,6 +322,7 @@ impl<A: Allocator> FrameManager<A> {
             swapchain,
             swapchain_delete: DeletionQueue::<Swapchain>::new((FRAMES_IN_FLIGHT + 2) as u32),
             pool,
+            render_scale: 1.0,
         })
     }
 
@@ -369,6 +376,7 @@ impl<A: Allocator> FrameManager<A> {
             self.current_image = index;
         }
 
+        let extents = self.reference_extents();
         let submission = {
             let per_frame = &mut self.per_frame[self.current_frame as usize];
             // Delete the command buffer used the previous time this frame was allocated.
@@ -382,6 +390,7 @@ impl<A: Allocator> FrameManager<A> {
 
             let ifc = InFlightContext {
                 swapchain_image: image,
+                extents,
                 wait_semaphore: per_frame.image_ready.clone(),
                 signal_semaphore: per_frame.gpu_finished.clone(),
             };
@@ -397,4 +406,25 @@ impl<A: Allocator> FrameManager<A> {
     pub unsafe fn get_swapchain(&self) -> &Swapchain {
         &self.swapchain
     }
+
+    /// Set the scale of the render resolution relative to the swapchain size, for dynamic resolution scaling.
+    /// Graph resources sized relative to the render resolution are recreated on the next frame.
+    /// # Errors
+    /// * Fails if `scale` is not a positive number.
+    pub fn set_render_scale(&mut self, scale: f32) -> Result<()> {
+        ensure!(scale > 0.0, "Render scale must be positive, got {scale}");
+        self.render_scale = scale;
+        Ok(())
+    }
+
+    /// Get the scale of the render resolution relative to the swapchain size.
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    /// Get the swapchain size and the render resolution that relative sizes of graph resources are resolved against.
+    /// These change when the swapchain is resized, or when the render scale changes.
+    pub fn reference_extents(&self) -> ReferenceExtents {
+        ReferenceExtents::scaled(*self.swapchain.extent(), self.render_scale)
+    }
 }
@@ -92,6 +92,8 @@ impl Swapchain {
                     1,
                     1,
                     vk::SampleCountFlags::TYPE_1,
+                    vk::SharingMode::EXCLUSIVE,
+                    vk::ImageUsageFlags::COLOR_ATTACHMENT,
                 );
                 // Create a trivial ImgView.
                 let view = image.whole_view(vk::ImageAspectFlags::COLOR)?;
@@ -0,0 +1,215 @@
+//! Graph fixtures shared by the pass graph tests. None of these need a GPU.
+
+use anyhow::Result;
+
+use phobos::{
+    domain, vk, BufferUsage, ClearColor, ClearDepthStencil, ImageView, MemoryType, PassBuilder, PassGraph,
+    PipelineStage, VirtualResource,
+};
+use phobos::graph::declarative::{GraphDeclaration, PassDeclaration, PassResourceDeclaration};
+use phobos::graph::ordering::PassOrdering;
+use phobos::graph::pass_graph::BuiltPassGraph;
+use phobos::graph::subgraph::Subgraph;
+use phobos::graph::transient::TransientImageInfo;
+use phobos::image::ImageCreateInfo;
+
+/// Creates a graph that clears the swapchain to `clear` and presents it.
+pub fn present_graph<'cb>(clear: [f32; 4]) -> Result<PassGraph<'cb, domain::All>> {
+    let swapchain = VirtualResource::image("swapchain");
+    let render = PassBuilder::render("render")
+        .clear_color_attachment(&swapchain, ClearColor::Float(clear))?
+        .build();
+    let present = PassBuilder::present("present", render.output(&swapchain).unwrap());
+    PassGraph::new().add_pass(render)?.add_pass(present)
+}
+
+/// Creates a graph that clears a color and a depth attachment, and only presents the color attachment.
+pub fn depth_prepass_graph(infer: bool) -> Result<BuiltPassGraph<'static, domain::All>> {
+    let swapchain = VirtualResource::image("swapchain");
+    let depth = VirtualResource::image("depth");
+    let render = PassBuilder::render("render")
+        .clear_color_attachment(&swapchain, ClearColor::Float([0.0, 0.0, 0.0, 1.0]))?
+        .clear_depth_attachment(&depth, ClearDepthStencil::default())?
+        .build();
+    let present = PassBuilder::present("present", render.output(&swapchain).unwrap());
+    // Store ops are only inferred when enabled.
+    let graph = match infer {
+        true => PassGraph::new().infer_store_ops(true),
+        false => PassGraph::new(),
+    };
+    graph
+        .add_pass(render)?
+        .add_pass(present)?
+        .build()
+}
+
+/// Creates a deferred renderer where the transient `gbuffer` and `post` images can share memory.
+pub fn deferred_graph() -> Result<BuiltPassGraph<'static, domain::Graphics>> {
+    let gbuffer = VirtualResource::image("gbuffer");
+    let lit = VirtualResource::image("lit");
+    let post = VirtualResource::image("post");
+    let swapchain = VirtualResource::image("swapchain");
+    let clear = ClearColor::Float([0.0, 0.0, 0.0, 0.0]);
+
+    let gbuffer_pass = PassBuilder::<domain::Graphics>::render("gbuffer")
+        .clear_color_attachment(&gbuffer, clear)?
+        .build();
+    let lighting_pass = PassBuilder::render("lighting")
+        .clear_color_attachment(&lit, clear)?
+        .sample_image(gbuffer_pass.output(&gbuffer).unwrap(), PipelineStage::FRAGMENT_SHADER)
+        .build();
+    let post_pass = PassBuilder::render("post")
+        .clear_color_attachment(&post, clear)?
+        .sample_image(lighting_pass.output(&lit).unwrap(), PipelineStage::FRAGMENT_SHADER)
+        .build();
+    let final_pass = PassBuilder::render("final")
+        .clear_color_attachment(&swapchain, clear)?
+        .sample_image(post_pass.output(&post).unwrap(), PipelineStage::FRAGMENT_SHADER)
+        .build();
+    let present = PassBuilder::present("present", final_pass.output(&swapchain).unwrap());
+
+    PassGraph::new()
+        .add_transient_image(&gbuffer, TransientImageInfo::default())?
+        .add_transient_image(&lit, TransientImageInfo::default())?
+        .add_transient_image(&post, TransientImageInfo::default())?
+        .add_pass(gbuffer_pass)?
+        .add_pass(lighting_pass)?
+        .add_pass(post_pass)?
+        .add_pass(final_pass)?
+        .add_pass(present)?
+        .build()
+}
+
+/// Creates a subgraph that samples `input` and renders to `blurred`.
+pub fn blur_subgraph<'cb>() -> Result<Subgraph<'cb, domain::All>> {
+    let input = VirtualResource::image("input");
+    let blurred = VirtualResource::image("blurred");
+    let pass = PassBuilder::render("blur")
+        .sample_image(&input, PipelineStage::FRAGMENT_SHADER)
+        .clear_color_attachment(&blurred, ClearColor::Float([0.0; 4]))?
+        .build();
+    Subgraph::new().input(&input).output(&blurred).add_pass(pass)
+}
+
+/// Creates a graph of compute passes with an independent `upload` pass, built with `ordering`.
+pub fn ordering_graph(
+    ordering: PassOrdering,
+) -> Result<BuiltPassGraph<'static, domain::All>> {
+    let lut = VirtualResource::image("lut");
+    let scene = VirtualResource::image("scene");
+    let generate = PassBuilder::new("generate_lut")
+        .write_storage_image(&lut, PipelineStage::COMPUTE_SHADER)
+        .build();
+    let shade = PassBuilder::new("shade")
+        .sample_image(generate.output(&lut).unwrap(), PipelineStage::COMPUTE_SHADER)
+        .write_storage_image(&scene, PipelineStage::COMPUTE_SHADER)
+        .build();
+    let composite = PassBuilder::new("composite")
+        .sample_image(generate.output(&lut).unwrap(), PipelineStage::COMPUTE_SHADER)
+        .sample_image(shade.output(&scene).unwrap(), PipelineStage::COMPUTE_SHADER)
+        .build();
+    let upload = PassBuilder::new("upload").build();
+    PassGraph::new()
+        .pass_ordering(ordering)
+        .add_pass(upload)?
+        .add_pass(generate)?
+        .add_pass(shade)?
+        .add_pass(composite)?
+        .build()
+}
+
+/// Creates a graph where two independent passes are recorded between writing and sampling `volume`.
+pub fn split_barrier_graph<'cb>() -> Result<PassGraph<'cb, domain::All>> {
+    let volume = VirtualResource::image("volume");
+    let inject = PassBuilder::new("inject")
+        .write_storage_image(&volume, PipelineStage::COMPUTE_SHADER)
+        .build();
+    let skin = PassBuilder::new("skin")
+        .write_buffer(&VirtualResource::buffer("vertices"), BufferUsage::Storage, PipelineStage::COMPUTE_SHADER)?
+        .build();
+    let cull = PassBuilder::new("cull")
+        .write_buffer(&VirtualResource::buffer("draws"), BufferUsage::Storage, PipelineStage::COMPUTE_SHADER)?
+        .build();
+    let integrate = PassBuilder::new("integrate")
+        .sample_image(inject.output(&volume).unwrap(), PipelineStage::COMPUTE_SHADER)
+        .build();
+    PassGraph::new()
+        .add_pass(inject)?
+        .add_pass(skin)?
+        .add_pass(cull)?
+        .add_pass(integrate)
+}
+
+/// Creates a declaration of a compute pass shading `scene`, and a render pass tonemapping it to the swapchain.
+pub fn declared_frame() -> GraphDeclaration {
+    GraphDeclaration {
+        passes: vec![
+            PassDeclaration {
+                name: "shade".to_owned(),
+                pipeline: Some("shade".to_owned()),
+                resources: vec![
+                    PassResourceDeclaration::ReadBuffer {
+                        resource: "lights".to_owned(),
+                        buffer_usage: BufferUsage::Uniform,
+                        stage: "COMPUTE_SHADER".to_owned(),
+                    },
+                    PassResourceDeclaration::WriteStorageImage {
+                        resource: "scene".to_owned(),
+                        stage: "COMPUTE_SHADER".to_owned(),
+                    },
+                ],
+                ..Default::default()
+            },
+            PassDeclaration {
+                name: "tonemap".to_owned(),
+                render: true,
+                pipeline: Some("tonemap".to_owned()),
+                executor: Some("fullscreen".to_owned()),
+                resources: vec![
+                    PassResourceDeclaration::SampleImage {
+                        resource: "scene".to_owned(),
+                        stage: "FRAGMENT_SHADER".to_owned(),
+                    },
+                    PassResourceDeclaration::ColorAttachment {
+                        resource: "swapchain".to_owned(),
+                        clear: Some(ClearColor::Float([0.0, 0.0, 0.0, 1.0])),
+                    },
+                ],
+                ..Default::default()
+            },
+        ],
+        present: Some("swapchain".to_owned()),
+        ..Default::default()
+    }
+}
+
+/// Creates a graph rendering `layers` shadow cascades, which are sampled by a lighting pass.
+pub fn shadow_cascades(layers: u32) -> Result<PassGraph<'static, domain::All>> {
+    let shadows = VirtualResource::image("shadow_cascades");
+    let render = PassBuilder::<domain::All>::render("cascades")
+        .layers(layers)?
+        .depth_attachment(&shadows, vk::AttachmentLoadOp::CLEAR, Some(vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 }))?
+        .build();
+    let sample = PassBuilder::<domain::All>::render("lighting")
+        .sample_image(render.output(&shadows).unwrap(), PipelineStage::FRAGMENT_SHADER)
+        .color_attachment(&VirtualResource::image("swapchain"), vk::AttachmentLoadOp::CLEAR, Some(vk::ClearColorValue::default()))?
+        .build();
+    PassGraph::<domain::All>::new().add_pass(render)?.add_pass(sample)
+}
+
+/// Creates a detached 1920x1080 image view.
+/// Images created with this helper must only be used with logged command buffers.
+pub fn detached_image(format: vk::Format, usage: vk::ImageUsageFlags, aspect: vk::ImageAspectFlags) -> ImageView {
+    let info = ImageCreateInfo {
+        width: 1920,
+        height: 1080,
+        depth: 1,
+        usage,
+        format,
+        samples: vk::SampleCountFlags::TYPE_1,
+        mip_levels: 1,
+        layers: 1,
+        memory_type: MemoryType::GpuOnly,
+    };
+    unsafe { ImageView::detached(&info, aspect) }
+}
@@ -10,6 +10,8 @@ use phobos::{
 };
 use phobos::pool::ResourcePool;
 
+pub mod graph;
+
 #[derive(Clone, Debug)]
 pub struct Context<A: Allocator> {
     pub exec: ExecutionManager<A>,
@@ -0,0 +1,1819 @@
+use anyhow::Result;
+
+use phobos::{
+    domain, vk, AttachmentOps, BufferUsage, BufferView, ClearColor, ClearDepthStencil, ComputeCmdBuffer,
+    GraphicsCmdBuffer, IncompleteCommandBuffer, PassBuilder, PassGraph,
+    PhysicalResourceBindings, PipelineStage, QueueType, RecordGraphToCommandBuffer, ResourceState,
+    SubresourceRange, VirtualResource,
+};
+use phobos::command_buffer::command_log::{Command, CommandLog};
+use phobos::graph::cache::PassGraphCache;
+use phobos::graph::declarative::{ExecutorRegistry, PassResourceDeclaration};
+use phobos::graph::ordering::{OrderCost, PassOrdering};
+use phobos::graph::physical_resource::PhysicalResource;
+use phobos::graph::profiler::{PassProfiler, PassProfilerCreateInfo, PassTiming};
+#[cfg(feature = "rayon")]
+use phobos::graph::record::ParallelRecording;
+use phobos::graph::subgraph::Subgraph;
+use phobos::graph::transient::{ReferenceExtents, TransientImageInfo, TransientLifetime, TransientSize};
+use phobos::graph::validate::ValidationError;
+use phobos::pool::LocalPool;
+
+use framework::graph::{
+    blur_subgraph, declared_frame, deferred_graph, depth_prepass_graph, detached_image, ordering_graph, present_graph,
+    shadow_cascades, split_barrier_graph,
+};
+
+mod framework;
+
+#[test]
+pub fn buffer_write_then_indirect_read() -> Result<()> {
+    let buffer = VirtualResource::buffer("draw_commands");
+    let cull = PassBuilder::<domain::All>::new("cull")
+        .write_buffer(&buffer, BufferUsage::Storage, PipelineStage::COMPUTE_SHADER)?
+        .build();
+    let draw = PassBuilder::<domain::All>::render("draw")
+        .read_buffer(cull.output(&buffer).unwrap(), BufferUsage::Indirect, PipelineStage::NONE)?
+        .build();
+    let graph = PassGraph::<domain::All>::new()
+        .add_pass(cull)?
+        .add_pass(draw)?
+        .build()?;
+    // Source, cull and draw tasks, plus one barrier before each pass.
+    assert_eq!(graph.num_nodes(), 5);
+    Ok(())
+}
+
+#[test]
+pub fn buffer_write_requires_writable_usage() {
+    let buffer = VirtualResource::buffer("vertices");
+    let result = PassBuilder::<domain::All>::new("generate").write_buffer(
+        &buffer,
+        BufferUsage::Vertex,
+        PipelineStage::COMPUTE_SHADER,
+    );
+    assert!(result.is_err(), "Vertex buffer usage should not allow writes.");
+}
+
+#[test]
+pub fn transient_images_alias_when_lifetimes_are_disjoint() -> Result<()> {
+    let graph = deferred_graph()?;
+    let transients = graph.transients();
+    assert_eq!(
+        transients.lifetime("gbuffer"),
+        Some(TransientLifetime {
+            first_pass: 0,
+            last_pass: 1,
+        })
+    );
+    assert_eq!(
+        transients.lifetime("post"),
+        Some(TransientLifetime {
+            first_pass: 2,
+            last_pass: 3,
+        })
+    );
+    assert_eq!(transients.alias_groups(), vec![vec!["gbuffer", "post"], vec!["lit"]]);
+    Ok(())
+}
+
+#[test]
+pub fn aliased_transients_wait_for_previous_tenant() -> Result<()> {
+    let description = deferred_graph()?.describe()?;
+    let pass_order = |name: &str| {
+        description
+            .passes
+            .iter()
+            .find(|pass| pass.name == name)
+            .unwrap()
+            .order
+    };
+    // `post` reuses the memory of `gbuffer`, which is last sampled by the lighting pass.
+    let barrier = description
+        .barriers
+        .iter()
+        .find(|barrier| barrier.resource == "post")
+        .unwrap();
+    assert_eq!(barrier.src_pass, None);
+    assert_eq!(barrier.kind, "image");
+    assert_eq!(barrier.old_layout, "UNDEFINED");
+    assert_eq!(barrier.new_layout, "COLOR_ATTACHMENT_OPTIMAL");
+    assert!(barrier.src_stage.contains("FRAGMENT_SHADER"));
+    assert!(barrier.src_stage.contains("COLOR_ATTACHMENT_OUTPUT"));
+    assert!(barrier.src_access.contains("COLOR_ATTACHMENT_WRITE"));
+    assert!(barrier.order > pass_order("lighting"));
+    assert!(barrier.order < pass_order("post"));
+    Ok(())
+}
+
+#[test]
+pub fn relative_sizes_follow_swapchain_and_render_resolution() -> Result<()> {
+    let swapchain = vk::Extent2D {
+        width: 1920,
+        height: 1080,
+    };
+    let extents = ReferenceExtents::scaled(swapchain, 0.5);
+    assert_eq!(extents.swapchain, swapchain);
+    assert_eq!(
+        extents.render,
+        vk::Extent2D {
+            width: 960,
+            height: 540,
+        }
+    );
+
+    let half = TransientSize::SwapchainRelative {
+        width: 0.5,
+        height: 0.5,
+    };
+    let quarter_render = TransientSize::RenderRelative {
+        width: 0.25,
+        height: 0.25,
+    };
+    assert_eq!(half.resolve(extents), extents.render);
+    assert_eq!(
+        quarter_render.resolve(extents),
+        vk::Extent2D {
+            width: 240,
+            height: 135,
+        }
+    );
+    // A single extent is both the swapchain size and the render resolution.
+    let quarter = TransientSize::SwapchainRelative {
+        width: 0.25,
+        height: 0.25,
+    };
+    assert_eq!(quarter_render.resolve(swapchain), quarter.resolve(swapchain));
+
+    let graph = |size| -> Result<u64> {
+        let image = VirtualResource::image("bloom");
+        let pass = PassBuilder::<domain::All>::render("bloom")
+            .clear_color_attachment(&image, ClearColor::Float([0.0, 0.0, 0.0, 0.0]))?
+            .build();
+        Ok(PassGraph::<domain::All>::new()
+            .add_transient_image(&image, TransientImageInfo {
+                size,
+                ..Default::default()
+            })?
+            .mark_external(pass.output(&image).unwrap())
+            .add_pass(pass)?
+            .structure_hash())
+    };
+    assert_ne!(graph(half)?, graph(TransientSize::RenderRelative {
+        width: 0.5,
+        height: 0.5,
+    })?);
+    Ok(())
+}
+
+#[test]
+pub fn transient_resource_type_must_match() {
+    let buffer = VirtualResource::buffer("buffer");
+    let result = PassGraph::<domain::Graphics>::new()
+        .add_transient_image(&buffer, TransientImageInfo::default());
+    assert!(result.is_err(), "Buffer resource should not be accepted as transient image.");
+}
+
+#[test]
+pub fn async_compute_pass_gets_own_queue_batch() -> Result<()> {
+    let particles = VirtualResource::buffer("particles");
+    let swapchain = VirtualResource::image("swapchain");
+    let simulate = PassBuilder::<domain::All>::new("simulate")
+        .on_domain::<domain::Compute>()
+        .write_buffer(&particles, BufferUsage::Storage, PipelineStage::COMPUTE_SHADER)?
+        .build();
+    let render = PassBuilder::render("render")
+        .clear_color_attachment(&swapchain, ClearColor::Float([0.0, 0.0, 0.0, 1.0]))?
+        .read_buffer(
+            simulate.output(&particles).unwrap(),
+            BufferUsage::Vertex,
+            PipelineStage::VERTEX_SHADER,
+        )?
+        .build();
+    let present = PassBuilder::present("present", render.output(&swapchain).unwrap());
+
+    let graph = PassGraph::<domain::All>::new()
+        .add_pass(simulate)?
+        .add_pass(render)?
+        .add_pass(present)?
+        .build()?;
+
+    assert!(graph.is_multi_queue());
+    let batches = graph.queue_batches();
+    assert_eq!(batches.len(), 2);
+    assert_eq!(batches[0].queue(), QueueType::Compute);
+    assert_eq!(batches[0].passes(), ["simulate"]);
+    assert_eq!(batches[1].queue(), QueueType::Graphics);
+    assert_eq!(batches[1].passes(), ["render", "present"]);
+    assert_eq!(
+        batches[1].waits(),
+        [(0, PipelineStage::VERTEX_ATTRIBUTE_INPUT | PipelineStage::VERTEX_SHADER)]
+    );
+    Ok(())
+}
+
+#[test]
+pub fn queue_round_trip_splits_batches() -> Result<()> {
+    let depth = VirtualResource::image("depth");
+    let occlusion = VirtualResource::image("occlusion");
+    let swapchain = VirtualResource::image("swapchain");
+    let prepass = PassBuilder::<domain::All>::render("prepass")
+        .clear_depth_attachment(&depth, ClearDepthStencil::default())?
+        .build();
+    let ssao = PassBuilder::new("ssao")
+        .on_domain::<domain::Compute>()
+        .sample_image(prepass.output(&depth).unwrap(), PipelineStage::COMPUTE_SHADER)
+        .write_storage_image(&occlusion, PipelineStage::COMPUTE_SHADER)
+        .build();
+    let lighting = PassBuilder::render("lighting")
+        .clear_color_attachment(&swapchain, ClearColor::Float([0.0, 0.0, 0.0, 1.0]))?
+        .sample_image(ssao.output(&occlusion).unwrap(), PipelineStage::FRAGMENT_SHADER)
+        .build();
+
+    let graph = PassGraph::<domain::All>::new()
+        .add_pass(prepass)?
+        .add_pass(ssao)?
+        .add_pass(lighting)?
+        .build()?;
+
+    let batches = graph.queue_batches();
+    let queues = batches.iter().map(|batch| batch.queue()).collect::<Vec<_>>();
+    assert_eq!(queues, [QueueType::Graphics, QueueType::Compute, QueueType::Graphics]);
+    assert_eq!(batches[1].waits(), [(0, PipelineStage::COMPUTE_SHADER)]);
+    assert_eq!(batches[2].waits(), [(1, PipelineStage::FRAGMENT_SHADER)]);
+    Ok(())
+}
+
+#[test]
+pub fn secondary_queues_wait_on_previous_frame() -> Result<()> {
+    let accum = VirtualResource::image("accum");
+    let swapchain = VirtualResource::image("swapchain");
+    // Accumulate on the async compute queue, and sample the result on the graphics queue.
+    let accumulate = PassBuilder::<domain::All>::new("accumulate")
+        .on_domain::<domain::Compute>()
+        .read_history(&accum, PipelineStage::COMPUTE_SHADER)
+        .write_storage_image(&accum, PipelineStage::COMPUTE_SHADER)
+        .build();
+    let render = PassBuilder::render("render")
+        .clear_color_attachment(&swapchain, ClearColor::Float([0.0, 0.0, 0.0, 1.0]))?
+        .sample_image(accumulate.output(&accum).unwrap(), PipelineStage::FRAGMENT_SHADER)
+        .build();
+    let present = PassBuilder::present("present", render.output(&swapchain).unwrap());
+    let mut graph = PassGraph::<domain::All>::new()
+        .add_pass(accumulate)?
+        .add_pass(render)?
+        .add_pass(present)?
+        .build()?;
+    let batches = graph.queue_batches();
+    assert_eq!(batches[0].queue(), QueueType::Compute);
+    assert_eq!(batches[0].previous_frame_wait(), Some(PipelineStage::ALL_COMMANDS));
+    assert_eq!(batches[1].previous_frame_wait(), None);
+
+    let mut bindings = PhysicalResourceBindings::new();
+    let storage = vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED;
+    let current = detached_image(vk::Format::R16G16B16A16_SFLOAT, storage, vk::ImageAspectFlags::COLOR);
+    let history = detached_image(vk::Format::R16G16B16A16_SFLOAT, storage, vk::ImageAspectFlags::COLOR);
+    bindings.bind_image("accum", &current);
+    bindings.bind_image("accum@history", &history);
+    bindings.bind_image("swapchain", &detached_image(vk::Format::B8G8R8A8_SRGB, vk::ImageUsageFlags::COLOR_ATTACHMENT, vk::ImageAspectFlags::COLOR));
+    let mut local_pool = unsafe { LocalPool::detached() };
+    // Queue families are numbered in order of first use, so compute is 0 and graphics is 1.
+    let transfers = |log: &CommandLog, src: u32, dst: u32| {
+        log.commands()
+            .iter()
+            .filter_map(|command| match command {
+                Command::PipelineBarrier(dependency) => Some(&dependency.image_barriers),
+                _ => None,
+            })
+            .flatten()
+            .filter(|barrier| barrier.src_queue_family_index == src && barrier.dst_queue_family_index == dst)
+            .map(|barrier| barrier.image)
+            .collect::<Vec<_>>()
+    };
+
+    let first = graph.record_to_logs(&bindings, &mut local_pool, &mut ())?;
+    assert_eq!(first[0].previous_frame_wait, None);
+    assert_eq!(first[0].frame_signal, None);
+    assert_eq!(first[1].frame_signal, Some(1));
+    // Nothing was released to the compute queue before the first frame.
+    assert!(transfers(&first[0].log, 1, 0).is_empty());
+    // The graphics queue releases the image it sampled, which the next frame reads as history. The image accumulated into next
+    // is the current history, which was last used on the compute queue.
+    assert_eq!(transfers(&first[1].log, 1, 0), [unsafe { current.image() }]);
+
+    let second = graph.record_to_logs(&bindings, &mut local_pool, &mut ())?;
+    assert_eq!(second[0].previous_frame_wait, Some((1, PipelineStage::ALL_COMMANDS)));
+    assert_eq!(second[1].frame_signal, Some(2));
+    // Bindings are not swapped here, so the history image is acquired.
+    assert_eq!(transfers(&second[0].log, 1, 0), [unsafe { history.image() }]);
+    Ok(())
+}
+
+#[test]
+pub fn single_queue_graph_has_one_batch() -> Result<()> {
+    let swapchain = VirtualResource::image("swapchain");
+    let render = PassBuilder::<domain::All>::render("render")
+        .clear_color_attachment(&swapchain, ClearColor::Float([0.0, 0.0, 0.0, 1.0]))?
+        .build();
+    let present = PassBuilder::present("present", render.output(&swapchain).unwrap());
+    let graph = PassGraph::<domain::All>::new()
+        .add_pass(render)?
+        .add_pass(present)?
+        .build()?;
+    assert!(!graph.is_multi_queue());
+    assert_eq!(graph.queue_batches().len(), 1);
+    Ok(())
+}
+
+#[test]
+pub fn renderpass_requires_graphics_queue() -> Result<()> {
+    let target = VirtualResource::image("target");
+    let render = PassBuilder::<domain::All>::render("render")
+        .on_domain::<domain::Compute>()
+        .clear_color_attachment(&target, ClearColor::Float([0.0, 0.0, 0.0, 1.0]))?
+        .build();
+    let result = PassGraph::<domain::All>::new().add_pass(render);
+    assert!(result.is_err(), "Renderpass on a compute queue should be rejected.");
+    Ok(())
+}
+
+#[test]
+pub fn unused_passes_are_culled() -> Result<()> {
+    let swapchain = VirtualResource::image("swapchain");
+    let debug_view = VirtualResource::image("debug_view");
+    let readback = VirtualResource::buffer("readback");
+    let clear = ClearColor::Float([0.0, 0.0, 0.0, 1.0]);
+
+    let render = PassBuilder::<domain::All>::render("render")
+        .clear_color_attachment(&swapchain, clear)?
+        .build();
+    let debug = PassBuilder::render("debug")
+        .clear_color_attachment(&debug_view, clear)?
+        .build();
+    let stats = PassBuilder::new("stats")
+        .write_buffer(&readback, BufferUsage::Storage, PipelineStage::COMPUTE_SHADER)?
+        .build();
+    let stats_output = stats.output(&readback).unwrap().clone();
+    let present = PassBuilder::present("present", render.output(&swapchain).unwrap());
+
+    let graph = PassGraph::<domain::All>::new()
+        .add_pass(render)?
+        .add_pass(debug)?
+        .add_pass(stats)?
+        .add_pass(present)?
+        .mark_external(&stats_output)
+        .build()?;
+
+    assert_eq!(graph.cull_report().culled(), ["debug"]);
+    // source, render, stats, present and three barriers
+    assert_eq!(graph.num_nodes(), 7);
+    Ok(())
+}
+
+#[test]
+pub fn graph_without_outputs_is_not_culled() -> Result<()> {
+    let buffer = VirtualResource::buffer("buffer");
+    let compute = PassBuilder::<domain::All>::new("compute")
+        .write_buffer(&buffer, BufferUsage::Storage, PipelineStage::COMPUTE_SHADER)?
+        .build();
+    let graph = PassGraph::<domain::All>::new()
+        .add_pass(compute)?
+        .build()?;
+    assert!(graph.cull_report().is_empty());
+    assert_eq!(graph.num_nodes(), 3);
+    Ok(())
+}
+
+#[test]
+pub fn barriers_in_same_step_are_batched() -> Result<()> {
+    let particles = VirtualResource::buffer("particles");
+    let field = VirtualResource::image("field");
+    let swapchain = VirtualResource::image("swapchain");
+
+    let simulate = PassBuilder::<domain::All>::new("simulate")
+        .write_buffer(&particles, BufferUsage::Storage, PipelineStage::COMPUTE_SHADER)?
+        .write_storage_image(&field, PipelineStage::COMPUTE_SHADER)
+        .build();
+    let render = PassBuilder::render("render")
+        .clear_color_attachment(&swapchain, ClearColor::Float([0.0, 0.0, 0.0, 1.0]))?
+        .read_buffer(simulate.output(&particles).unwrap(), BufferUsage::Vertex, PipelineStage::VERTEX_SHADER)?
+        .sample_image(simulate.output(&field).unwrap(), PipelineStage::FRAGMENT_SHADER)
+        .build();
+    let present = PassBuilder::present("present", render.output(&swapchain).unwrap());
+
+    let graph = PassGraph::<domain::All>::new()
+        .add_pass(simulate)?
+        .add_pass(render)?
+        .add_pass(present)?
+        .build()?;
+
+    // One pipeline barrier before each pass, even though the graph has six barrier nodes.
+    let stats = graph.barrier_stats();
+    assert_eq!(stats.pipeline_barriers, 3);
+    assert_eq!(stats.image_barriers, 4);
+    assert_eq!(stats.memory_barriers, 2);
+    assert_eq!(stats.skipped, 0);
+    Ok(())
+}
+
+#[test]
+pub fn redundant_barriers_are_minimized() -> Result<()> {
+    let mesh = VirtualResource::buffer("mesh");
+    let image = VirtualResource::image("image");
+
+    let write = PassBuilder::<domain::All>::new("write")
+        .read_buffer(&mesh, BufferUsage::Storage, PipelineStage::COMPUTE_SHADER)?
+        .write_storage_image(&image, PipelineStage::COMPUTE_SHADER)
+        .build();
+    let read = PassBuilder::new("read")
+        .read_storage_image(write.output(&image).unwrap(), PipelineStage::COMPUTE_SHADER)
+        .build();
+
+    let graph = PassGraph::<domain::All>::new()
+        .add_pass(write)?
+        .add_pass(read)?
+        .build()?;
+
+    // The mesh is never written so its barrier is dropped. The storage image stays in the general layout, so
+    // it only needs a memory barrier.
+    let stats = graph.barrier_stats();
+    assert_eq!(stats.pipeline_barriers, 2);
+    assert_eq!(stats.image_barriers, 1);
+    assert_eq!(stats.memory_barriers, 1);
+    assert_eq!(stats.skipped, 1);
+    Ok(())
+}
+
+#[test]
+pub fn structure_hash_depends_on_structure() -> Result<()> {
+    let black = [0.0, 0.0, 0.0, 1.0];
+    let a = present_graph(black)?;
+    let b = present_graph(black)?;
+    let c = present_graph([1.0, 0.0, 0.0, 1.0])?;
+    assert_eq!(a.structure_hash(), b.structure_hash());
+    assert_ne!(a.structure_hash(), c.structure_hash());
+    Ok(())
+}
+
+#[test]
+pub fn identical_graphs_are_built_once() -> Result<()> {
+    let black = [0.0, 0.0, 0.0, 1.0];
+    let mut cache = PassGraphCache::<domain::All>::new();
+    for _ in 0..3 {
+        let graph = cache.get_or_build(present_graph(black)?)?;
+        assert_eq!(graph.num_nodes(), 5);
+    }
+    cache.get_or_build(present_graph([1.0, 1.0, 1.0, 1.0])?)?;
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.hits(), 2);
+    assert_eq!(cache.misses(), 2);
+    Ok(())
+}
+
+#[test]
+pub fn least_recently_used_graph_is_evicted() -> Result<()> {
+    let colors = [[0.0, 0.0, 0.0, 1.0], [1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 1.0]];
+    let hashes = colors
+        .iter()
+        .map(|&clear| Ok(present_graph(clear)?.structure_hash()))
+        .collect::<Result<Vec<_>>>()?;
+    let mut cache = PassGraphCache::<domain::All>::with_capacity(2);
+    cache.get_or_build(present_graph(colors[0])?)?;
+    cache.get_or_build(present_graph(colors[1])?)?;
+    // Use the first graph again, so the second one is the least recently used.
+    cache.get_or_build(present_graph(colors[0])?)?;
+    cache.get_or_build(present_graph(colors[2])?)?;
+    assert_eq!(cache.len(), 2);
+    assert!(cache.contains(hashes[0]));
+    assert!(!cache.contains(hashes[1]));
+    assert!(cache.contains(hashes[2]));
+    Ok(())
+}
+
+#[test]
+pub fn parallel_executors_do_not_change_structure() -> Result<()> {
+    let build = |parallel: bool| -> Result<PassGraph<domain::All>> {
+        let swapchain = VirtualResource::image("swapchain");
+        let render = PassBuilder::render("render")
+            .clear_color_attachment(&swapchain, ClearColor::Float([0.0, 0.0, 0.0, 1.0]))?;
+        let render = match parallel {
+            true => render.parallel_execute_fn(|cmd, _, _, _| Ok(cmd)),
+            false => render.execute_fn(|cmd, _, _, _| Ok(cmd)),
+        }
+        .build();
+        let present = PassBuilder::present("present", render.output(&swapchain).unwrap());
+        PassGraph::new().add_pass(render)?.add_pass(present)
+    };
+    assert_eq!(build(true)?.structure_hash(), build(false)?.structure_hash());
+    Ok(())
+}
+
+#[test]
+pub fn conditional_pass_keeps_barriers() -> Result<()> {
+    let build = |conditional: bool| -> Result<PassGraph<domain::All, bool>> {
+        let swapchain = VirtualResource::image("swapchain");
+        let render = PassBuilder::render("render")
+            .clear_color_attachment(&swapchain, ClearColor::Float([0.0, 0.0, 0.0, 1.0]))?
+            .build();
+        let overlay = PassBuilder::render("overlay")
+            .load_color_attachment(render.output(&swapchain).unwrap())?
+            .execute_fn(|cmd, _, _, _| cmd.draw(3, 1, 0, 0));
+        let overlay = match conditional {
+            true => overlay.enabled_if(|show_overlay: &bool| *show_overlay),
+            false => overlay,
+        }
+        .build();
+        let present = PassBuilder::present("present", overlay.output(&swapchain).unwrap());
+        PassGraph::new()
+            .add_pass(render)?
+            .add_pass(overlay)?
+            .add_pass(present)
+    };
+    let conditional = build(true)?;
+    assert_eq!(conditional.structure_hash(), build(false)?.structure_hash());
+    // The overlay is not culled, and its barriers are kept so the graph is valid whether it is recorded or not.
+    let conditional = conditional.build()?;
+    let unconditional = build(false)?.build()?;
+    assert_eq!(conditional.num_nodes(), unconditional.num_nodes());
+    assert_eq!(conditional.barrier_stats(), unconditional.barrier_stats());
+
+    // The overlay is only recorded if its predicate holds, but the barriers around it are recorded either way.
+    let mut conditional = conditional;
+    let mut bindings = PhysicalResourceBindings::new();
+    bindings.bind_image("swapchain", &detached_image(vk::Format::B8G8R8A8_SRGB, vk::ImageUsageFlags::COLOR_ATTACHMENT, vk::ImageAspectFlags::COLOR));
+    let mut record = |mut show_overlay: bool| -> Result<Vec<&'static str>> {
+        let mut local_pool = unsafe { LocalPool::detached() };
+        let cmd = conditional.record(IncompleteCommandBuffer::new_logged(), &bindings, &mut local_pool, None, &mut show_overlay)?;
+        Ok(cmd.into_command_log()?.names())
+    };
+    let count = |names: &[&str], name: &str| names.iter().filter(|&&other| other == name).count();
+    let hidden = record(false)?;
+    let shown = record(true)?;
+    assert_eq!(count(&hidden, "vkCmdDraw"), 0);
+    assert_eq!(count(&shown, "vkCmdDraw"), 1);
+    assert_eq!(count(&hidden, "vkCmdBeginRendering"), 1);
+    assert_eq!(count(&shown, "vkCmdBeginRendering"), 2);
+    assert_eq!(count(&hidden, "vkCmdPipelineBarrier2"), count(&shown, "vkCmdPipelineBarrier2"));
+    assert_eq!(count(&shown, "vkCmdPipelineBarrier2"), unconditional.barrier_stats().pipeline_barriers);
+    Ok(())
+}
+
+#[test]
+pub fn validate_reports_unbound_and_uninitialized_resources() -> Result<()> {
+    let input = VirtualResource::image("input");
+    let swapchain = VirtualResource::image("swapchain");
+    let render = PassBuilder::render("render")
+        .sample_image(&input, PipelineStage::FRAGMENT_SHADER)
+        .clear_color_attachment(&swapchain, ClearColor::Float([0.0, 0.0, 0.0, 1.0]))?
+        .build();
+    let present = PassBuilder::present("present", render.output(&swapchain).unwrap());
+    let graph = PassGraph::<domain::All>::new()
+        .add_pass(render)?
+        .add_pass(present)?
+        .build()?;
+
+    let report = graph.validate(&PhysicalResourceBindings::new()).unwrap_err();
+    assert_eq!(
+        report.errors(),
+        &[
+            ValidationError::ReadBeforeWrite {
+                pass: "render".to_owned(),
+                resource: "input".to_owned(),
+            },
+            ValidationError::MissingBinding {
+                pass: "render".to_owned(),
+                resource: "swapchain".to_owned(),
+            },
+        ]
+    );
+
+    // Binding the image does not initialize it, but importing it does.
+    let image = detached_image(vk::Format::R8G8B8A8_UNORM, vk::ImageUsageFlags::SAMPLED, vk::ImageAspectFlags::COLOR);
+    let mut bindings = PhysicalResourceBindings::new();
+    bindings.bind_image("input", &image);
+    let uninitialized = |graph: &phobos::graph::pass_graph::BuiltPassGraph<domain::All>| {
+        graph
+            .validate(&bindings)
+            .unwrap_err()
+            .errors()
+            .iter()
+            .any(|error| matches!(error, ValidationError::ReadBeforeWrite { .. }))
+    };
+    assert!(uninitialized(&graph));
+    let render = PassBuilder::render("render")
+        .sample_image(&input, PipelineStage::FRAGMENT_SHADER)
+        .clear_color_attachment(&swapchain, ClearColor::Float([0.0, 0.0, 0.0, 1.0]))?
+        .build();
+    let present = PassBuilder::present("present", render.output(&swapchain).unwrap());
+    let sampled = ResourceState {
+        layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        access: vk::AccessFlags2::SHADER_READ,
+        stage: PipelineStage::FRAGMENT_SHADER,
+    };
+    let imported = PassGraph::<domain::All>::new()
+        .import_resource(&input, sampled, None)?
+        .add_pass(render)?
+        .add_pass(present)?
+        .build()?;
+    assert!(!uninitialized(&imported));
+    Ok(())
+}
+
+#[test]
+pub fn validate_reports_lost_writes_and_wrong_formats() -> Result<()> {
+    let swapchain = VirtualResource::image("swapchain");
+    let depth = VirtualResource::image("depth");
+    let render = PassBuilder::render("render")
+        .clear_color_attachment(&swapchain, ClearColor::Float([0.0, 0.0, 0.0, 1.0]))?
+        .clear_depth_attachment(&depth, ClearDepthStencil::default())?
+        .build();
+    let overlay = PassBuilder::render("overlay")
+        .clear_color_attachment(
+            render.output(&swapchain).unwrap(),
+            ClearColor::Float([0.0, 0.0, 0.0, 1.0]),
+        )?
+        .build();
+    let present = PassBuilder::present("present", overlay.output(&swapchain).unwrap());
+    let graph = PassGraph::<domain::All>::new()
+        .add_transient_image(&depth, TransientImageInfo::default())?
+        .add_pass(render)?
+        .add_pass(overlay)?
+        .add_pass(present)?
+        .build()?;
+
+    let report = graph.validate(&PhysicalResourceBindings::new()).unwrap_err();
+    assert!(report.errors().contains(&ValidationError::WriteAfterWrite {
+        pass: "render".to_owned(),
+        resource: "swapchain+".to_owned(),
+        overwritten_by: "overlay".to_owned(),
+    }));
+    assert!(report.errors().contains(&ValidationError::FormatMismatch {
+        pass: "render".to_owned(),
+        resource: "depth".to_owned(),
+        format: phobos::vk::Format::R8G8B8A8_UNORM,
+    }));
+    // Transient resources never need a binding.
+    assert!(!report.errors().iter().any(|error| matches!(
+        error,
+        ValidationError::MissingBinding { resource, .. } if resource == "depth"
+    )));
+    Ok(())
+}
+
+#[test]
+pub fn read_only_depth_is_sampled_while_depth_testing() -> Result<()> {
+    let depth = VirtualResource::image("depth");
+    let gbuffer = VirtualResource::image("gbuffer");
+    let clear = ClearColor::Float([0.0, 0.0, 0.0, 0.0]);
+    let prepass = PassBuilder::<domain::All>::render("prepass")
+        .clear_depth_attachment(&depth, ClearDepthStencil::default())?
+        .build();
+    let depth = prepass.output(&depth).unwrap().clone();
+    let decals = PassBuilder::render("decals")
+        .clear_color_attachment(&gbuffer, clear)?
+        .read_only_depth_attachment(&depth, Some(PipelineStage::FRAGMENT_SHADER))?
+        .build();
+    let lighting = PassBuilder::render("lighting")
+        .load_color_attachment(decals.output(&gbuffer).unwrap())?
+        .read_only_depth_attachment(&depth, None)?
+        .build();
+    let graph = PassGraph::<domain::All>::new()
+        .mark_external(lighting.output(&gbuffer).unwrap())
+        .add_pass(prepass)?
+        .add_pass(decals)?
+        .add_pass(lighting)?
+        .build()?;
+    assert!(graph.cull_report().is_empty());
+
+    let description = graph.describe()?;
+    // Both passes only read the depth buffer, so a single transition is enough.
+    let [barrier] = description
+        .barriers
+        .iter()
+        .filter(|barrier| barrier.resource == "depth+")
+        .collect::<Vec<_>>()[..]
+    else {
+        panic!("Expected one barrier for the depth buffer")
+    };
+    assert_eq!(barrier.old_layout, "DEPTH_ATTACHMENT_OPTIMAL");
+    assert_eq!(barrier.new_layout, "DEPTH_READ_ONLY_OPTIMAL");
+    assert_eq!(barrier.dst_passes, ["decals", "lighting"]);
+    assert!(barrier.dst_access.contains("DEPTH_STENCIL_ATTACHMENT_READ"));
+    assert!(barrier.dst_access.contains("SHADER_READ"));
+    assert!(!barrier.dst_access.contains("WRITE"));
+    Ok(())
+}
+
+#[test]
+pub fn stencil_attachments_use_their_own_layouts() -> Result<()> {
+    let stencil = VirtualResource::image("stencil");
+    let depth_stencil = VirtualResource::image("depth_stencil");
+    let clear = vk::ClearDepthStencilValue {
+        depth: 1.0,
+        stencil: 0,
+    };
+    let depth_ops = AttachmentOps::new(vk::AttachmentLoadOp::LOAD, vk::AttachmentStoreOp::DONT_CARE);
+    assert!(PassBuilder::<domain::All>::render("mask")
+        .depth_stencil_attachment(&depth_stencil, depth_ops, AttachmentOps::CLEAR, None)
+        .is_err());
+    assert!(PassBuilder::<domain::All>::new("mask")
+        .stencil_attachment(&stencil, AttachmentOps::LOAD, None)
+        .is_err());
+
+    let mask = PassBuilder::<domain::All>::render("mask")
+        .stencil_attachment(&stencil, AttachmentOps::CLEAR, Some(0))?
+        .depth_stencil_attachment(&depth_stencil, depth_ops, AttachmentOps::CLEAR, Some(clear))?
+        .build();
+    let graph = PassGraph::<domain::All>::new()
+        .mark_external(mask.output(&stencil).unwrap())
+        .mark_external(mask.output(&depth_stencil).unwrap())
+        .add_pass(mask)?
+        .build()?;
+    let description = graph.describe()?;
+    let layout = |resource: &str| {
+        description
+            .barriers
+            .iter()
+            .find(|barrier| barrier.resource == resource)
+            .map(|barrier| barrier.new_layout.clone())
+    };
+    assert_eq!(layout("stencil").as_deref(), Some("STENCIL_ATTACHMENT_OPTIMAL"));
+    assert_eq!(layout("depth_stencil").as_deref(), Some("DEPTH_STENCIL_ATTACHMENT_OPTIMAL"));
+    Ok(())
+}
+
+#[test]
+pub fn unread_attachments_are_discarded() -> Result<()> {
+    let store_ops = |infer| -> Result<Vec<(String, Option<String>)>> {
+        let description = depth_prepass_graph(infer)?.describe()?;
+        Ok(description.passes[0]
+            .outputs
+            .iter()
+            .map(|output| (output.uid.clone(), output.store_op.clone()))
+            .collect())
+    };
+    // The swapchain is presented, but nothing reads the depth buffer.
+    assert_eq!(
+        store_ops(true)?,
+        [("swapchain+".to_owned(), None), ("depth+".to_owned(), Some("DONT_CARE".to_owned()))]
+    );
+    assert_eq!(store_ops(false)?, [("swapchain+".to_owned(), None), ("depth+".to_owned(), None)]);
+    Ok(())
+}
+
+#[test]
+pub fn discarded_attachments_must_not_be_read() -> Result<()> {
+    let gbuffer = VirtualResource::image("gbuffer");
+    let swapchain = VirtualResource::image("swapchain");
+    let clear = ClearColor::Float([0.0, 0.0, 0.0, 0.0]);
+    assert!(PassBuilder::<domain::All>::render("gbuffer")
+        .sample_image(&gbuffer, PipelineStage::FRAGMENT_SHADER)
+        .discard_attachment(&gbuffer)
+        .is_err());
+
+    let build = |store_op: vk::AttachmentStoreOp| -> Result<_> {
+        let gbuffer_pass = PassBuilder::<domain::All>::render("gbuffer")
+            .clear_color_attachment(&gbuffer, clear)?
+            .store_op(&gbuffer, store_op)?
+            .build();
+        let lighting = PassBuilder::render("lighting")
+            .clear_color_attachment(&swapchain, clear)?
+            .sample_image(gbuffer_pass.output(&gbuffer).unwrap(), PipelineStage::FRAGMENT_SHADER)
+            .build();
+        let present = PassBuilder::present("present", lighting.output(&swapchain).unwrap());
+        PassGraph::<domain::All>::new()
+            .add_pass(gbuffer_pass)?
+            .add_pass(lighting)?
+            .add_pass(present)?
+            .build()
+    };
+    let report = build(vk::AttachmentStoreOp::DONT_CARE)?
+        .validate(&PhysicalResourceBindings::new())
+        .unwrap_err();
+    assert!(report.errors().contains(&ValidationError::ReadAfterDiscard {
+        pass: "gbuffer".to_owned(),
+        resource: "gbuffer+".to_owned(),
+        read_by: "lighting".to_owned(),
+    }));
+    // Skipping the store keeps the contents, so they may be read.
+    let errors = build(vk::AttachmentStoreOp::NONE)?
+        .validate(&PhysicalResourceBindings::new())
+        .err()
+        .map(|report| report.errors().to_vec())
+        .unwrap_or_default();
+    assert!(!errors
+        .iter()
+        .any(|error| matches!(error, ValidationError::ReadAfterDiscard { .. })));
+    Ok(())
+}
+
+#[test]
+pub fn mip_levels_are_tracked_separately() -> Result<()> {
+    let bloom = VirtualResource::image("bloom");
+    let clear = ClearColor::Float([0.0, 0.0, 0.0, 0.0]);
+    let mut passes = vec![PassBuilder::<domain::All>::render("bloom_0")
+        .clear_color_attachment(&bloom.mip(0), clear)?
+        .build()];
+    for level in 1..3 {
+        let previous = passes.last().unwrap().output(&bloom.mip(level - 1)).unwrap().clone();
+        let pass = PassBuilder::render(format!("bloom_{level}"))
+            .sample_image(&previous, PipelineStage::FRAGMENT_SHADER)
+            .clear_color_attachment(&bloom.mip(level), clear)?
+            .build();
+        passes.push(pass);
+    }
+    let mut graph = PassGraph::<domain::All>::new().mark_external(&bloom.mip(2));
+    for pass in passes {
+        graph = graph.add_pass(pass)?;
+    }
+    let graph = graph.build()?;
+
+    assert!(graph.cull_report().is_empty());
+    // One layout transition from the initial layout per mip level, and one to sample each of the first two levels.
+    assert_eq!(graph.barrier_stats().image_barriers, 5);
+    assert_eq!(bloom.mip(1).upgrade().uid(), "bloom[mip 1, layers 0..]+");
+    assert!(!bloom.mip(0).is_associated_with(&bloom.mip(1)));
+    Ok(())
+}
+
+#[test]
+pub fn whole_image_reads_depend_on_every_range() -> Result<()> {
+    let bloom = VirtualResource::image("bloom");
+    let swapchain = VirtualResource::image("swapchain");
+    let clear = ClearColor::Float([0.0; 4]);
+    let first = PassBuilder::<domain::All>::render("bloom_0")
+        .clear_color_attachment(&bloom.mip(0), clear)?
+        .build();
+    let second = PassBuilder::render("bloom_1")
+        .sample_image(first.output(&bloom.mip(0)).unwrap(), PipelineStage::FRAGMENT_SHADER)
+        .clear_color_attachment(&bloom.mip(1), clear)?
+        .build();
+    let composite = PassBuilder::render("composite")
+        .sample_image(&bloom, PipelineStage::FRAGMENT_SHADER)
+        .clear_color_attachment(&swapchain, clear)?
+        .build();
+    let present = PassBuilder::present("present", composite.output(&swapchain).unwrap());
+    let graph = PassGraph::<domain::All>::new()
+        .add_pass(first)?
+        .add_pass(second)?
+        .add_pass(composite)?
+        .add_pass(present)?
+        .build()?;
+    assert!(graph.cull_report().is_empty());
+
+    let description = graph.describe()?;
+    let mut sources = description
+        .barriers
+        .iter()
+        .filter(|barrier| barrier.dst_passes.iter().any(|pass| pass == "composite"))
+        .filter(|barrier| barrier.resource.starts_with("bloom"))
+        .map(|barrier| (barrier.src_pass.as_deref(), barrier.old_layout.as_str()))
+        .collect::<Vec<_>>();
+    sources.sort();
+    // Each mip level is transitioned from the layout its producer left it in.
+    assert_eq!(sources, [(Some("bloom_0"), "COLOR_ATTACHMENT_OPTIMAL"), (Some("bloom_1"), "COLOR_ATTACHMENT_OPTIMAL")]);
+    Ok(())
+}
+
+#[test]
+pub fn subresource_ranges_at_the_end_do_not_overflow() {
+    let last = SubresourceRange {
+        base_level: u32::MAX - 1,
+        level_count: Some(4),
+        ..Default::default()
+    };
+    assert!(last.overlaps(&SubresourceRange::mip(u32::MAX)));
+    assert!(!last.overlaps(&SubresourceRange::mip(0)));
+    assert_eq!(last.to_string(), format!("mips {}.., layers 0..", u32::MAX - 1));
+}
+
+#[test]
+pub fn overlapping_subresource_ranges_fail_to_build() -> Result<()> {
+    let shadows = VirtualResource::image("shadows");
+    let build = |first: &VirtualResource, second: &VirtualResource| -> Result<_> {
+        let clear = ClearColor::Float([0.0; 4]);
+        let first = PassBuilder::<domain::All>::render("first")
+            .clear_color_attachment(first, clear)?
+            .build();
+        let second = PassBuilder::render("second")
+            .clear_color_attachment(second, clear)?
+            .build();
+        PassGraph::new().add_pass(first)?.add_pass(second)?.build()
+    };
+    assert!(build(&shadows.layer(0), &shadows.layer(1)).is_ok());
+    assert!(build(&shadows.mip(0), &shadows).is_err());
+    assert!(build(&shadows, &shadows.layer(1)).is_err());
+    // Mip level 0 of layer 1 is in both ranges.
+    assert!(build(&shadows.mip(0), &shadows.layer(1)).is_err());
+    let layers = SubresourceRange {
+        layer_count: Some(2),
+        ..Default::default()
+    };
+    assert!(build(&shadows.subresource(layers), &shadows.layer(1)).is_err());
+    assert!(build(&shadows.subresource(layers), &shadows.layer(2)).is_ok());
+    Ok(())
+}
+
+#[test]
+pub fn pass_timings_can_be_found_by_identifier() {
+    let timing = |identifier: &str, millis: u64, children: Vec<PassTiming>| PassTiming {
+        identifier: identifier.to_owned(),
+        duration: std::time::Duration::from_millis(millis),
+        statistics: None,
+        children,
+    };
+    let frame = timing(
+        "frame",
+        10,
+        vec![timing("shadows", 3, vec![]), timing("lighting", 6, vec![timing("sky", 1, vec![])])],
+    );
+    assert_eq!(frame.find("frame").unwrap().duration.as_millis(), 10);
+    assert_eq!(frame.find("sky").unwrap().duration.as_millis(), 1);
+    assert!(frame.find("bloom").is_none());
+}
+
+#[test]
+pub fn profiled_passes_are_wrapped_in_timestamps() -> Result<()> {
+    let target = VirtualResource::image("albedo");
+    let gbuffer = PassBuilder::<domain::All>::render("gbuffer")
+        .color_attachment(&target, vk::AttachmentLoadOp::CLEAR, Some(vk::ClearColorValue::default()))?
+        .execute_fn(|cmd, _, _, _| cmd.draw(3, 1, 0, 0))
+        .build();
+    let overlay = PassBuilder::<domain::All>::render("overlay")
+        .color_attachment(&gbuffer.output(&target).unwrap(), vk::AttachmentLoadOp::LOAD, None)?
+        .execute_fn(|cmd, _, _, _| cmd.draw(3, 1, 0, 0))
+        .build();
+    let mut graph = PassGraph::<domain::All>::new()
+        .add_pass(gbuffer)?
+        .add_pass(overlay)?
+        .build()?;
+
+    let mut bindings = PhysicalResourceBindings::new();
+    bindings.bind_image("albedo", &detached_image(vk::Format::R8G8B8A8_UNORM, vk::ImageUsageFlags::COLOR_ATTACHMENT, vk::ImageAspectFlags::COLOR));
+    let mut local_pool = unsafe { LocalPool::detached() };
+    let mut profiler = unsafe { PassProfiler::detached(PassProfilerCreateInfo::default())? };
+    let cmd = graph.record_profiled(IncompleteCommandBuffer::new_logged(), &bindings, &mut local_pool, None, &mut (), &mut profiler)?;
+    let log = cmd.into_command_log()?;
+
+    // Every pass is enclosed by a timestamp at the top and at the bottom of the pipe.
+    let names = log
+        .names()
+        .into_iter()
+        .filter(|&name| name == "vkCmdWriteTimestamp2" || name == "vkCmdDraw")
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        ["vkCmdWriteTimestamp2", "vkCmdDraw", "vkCmdWriteTimestamp2", "vkCmdWriteTimestamp2", "vkCmdDraw", "vkCmdWriteTimestamp2"]
+    );
+    let timestamps = log
+        .commands()
+        .iter()
+        .filter_map(|command| match command {
+            Command::WriteTimestamp { stage, index, .. } => Some((*stage, *index)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        timestamps,
+        [
+            (PipelineStage::TOP_OF_PIPE, 0),
+            (PipelineStage::BOTTOM_OF_PIPE, 1),
+            (PipelineStage::TOP_OF_PIPE, 2),
+            (PipelineStage::BOTTOM_OF_PIPE, 3),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+pub fn describe_lists_barriers_in_recording_order() -> Result<()> {
+    let description = present_graph([0.0, 0.0, 0.0, 1.0])?.build()?.describe()?;
+    let passes = description
+        .passes
+        .iter()
+        .map(|pass| pass.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(passes, ["render", "present"]);
+
+    let [first, second] = description.barriers.as_slice() else { panic!("Expected two barriers") };
+    assert_eq!(first.resource, "swapchain");
+    assert_eq!(first.src_pass, None);
+    assert_eq!(first.dst_passes, ["render"]);
+    assert_eq!(first.old_layout, "UNDEFINED");
+    assert_eq!(first.new_layout, "COLOR_ATTACHMENT_OPTIMAL");
+    assert_eq!(second.resource, "swapchain+");
+    assert_eq!(second.src_pass.as_deref(), Some("render"));
+    assert_eq!(second.new_layout, "PRESENT_SRC_KHR");
+    assert_eq!(second.kind, "image");
+    assert!(first.order < description.passes[0].order);
+    assert!(second.order > description.passes[0].order);
+    assert!(second.batch > first.batch);
+
+    let mermaid = description.to_mermaid();
+    assert!(mermaid.starts_with("flowchart TD\n"));
+    assert!(mermaid.contains("    p0[\"render\"]\n"));
+    assert!(mermaid.contains("    b0 --> p0\n"));
+    assert!(mermaid.contains("    p0 --> b1\n"));
+    assert!(mermaid.contains("    b1 --> p1\n"));
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+pub fn description_json_round_trip() -> Result<()> {
+    use phobos::graph::export::GraphDescription;
+
+    let description = present_graph([0.0, 0.0, 0.0, 1.0])?.build()?.describe()?;
+    let json = description.to_json()?;
+    assert_eq!(GraphDescription::from_json(&json)?, description);
+    Ok(())
+}
+
+#[test]
+pub fn history_resources_carry_state_across_frames() -> Result<()> {
+    let taa = VirtualResource::image("taa");
+    let swapchain = VirtualResource::image("swapchain");
+    assert!(taa.history().is_history());
+    assert_eq!(taa.history().history().name(), "taa@history");
+
+    // Nothing reads the output of the TAA pass in this frame, but the next frame does.
+    let resolve = PassBuilder::render("taa")
+        .clear_color_attachment(&taa, ClearColor::Float([0.0, 0.0, 0.0, 0.0]))?
+        .read_history(&taa, PipelineStage::FRAGMENT_SHADER)
+        .build();
+    let render = PassBuilder::render("render")
+        .clear_color_attachment(&swapchain, ClearColor::Float([0.0, 0.0, 0.0, 1.0]))?
+        .build();
+    let present = PassBuilder::present("present", render.output(&swapchain).unwrap());
+    let graph = PassGraph::<domain::All>::new()
+        .add_pass(resolve)?
+        .add_pass(render)?
+        .add_pass(present)?
+        .build()?;
+    assert!(graph.cull_report().is_empty());
+
+    let description = graph.describe()?;
+    let history = description
+        .barriers
+        .iter()
+        .find(|barrier| barrier.resource == "taa@history")
+        .unwrap();
+    assert_eq!(history.kind, "image");
+    assert_eq!(history.old_layout, "COLOR_ATTACHMENT_OPTIMAL");
+    assert_eq!(history.new_layout, "SHADER_READ_ONLY_OPTIMAL");
+    assert!(history.src_access.contains("COLOR_ATTACHMENT_WRITE"));
+    // Writing the image must wait until the previous frame finished reading it as history.
+    let current = description
+        .barriers
+        .iter()
+        .find(|barrier| barrier.resource == "taa")
+        .unwrap();
+    assert!(current.src_stage.contains("FRAGMENT_SHADER"));
+
+    let mut bindings = PhysicalResourceBindings::new();
+    bindings.invalidate("taa@history");
+    assert!(bindings.is_invalidated("taa@history"));
+    assert!(!bindings.is_invalidated("taa"));
+    Ok(())
+}
+
+#[test]
+pub fn history_waits_on_every_reader_of_the_last_version() -> Result<()> {
+    let lighting = VirtualResource::image("lighting");
+    let bloom = VirtualResource::image("bloom");
+    let swapchain = VirtualResource::image("swapchain");
+    let light = PassBuilder::render("light")
+        .clear_color_attachment(&lighting, ClearColor::Float([0.0, 0.0, 0.0, 0.0]))?
+        .read_history(&lighting, PipelineStage::FRAGMENT_SHADER)
+        .build();
+    let blur = PassBuilder::new("bloom")
+        .sample_image(light.output(&lighting).unwrap(), PipelineStage::COMPUTE_SHADER)
+        .write_storage_image(&bloom, PipelineStage::COMPUTE_SHADER)
+        .build();
+    let composite = PassBuilder::render("composite")
+        .sample_image(light.output(&lighting).unwrap(), PipelineStage::FRAGMENT_SHADER)
+        .sample_image(blur.output(&bloom).unwrap(), PipelineStage::FRAGMENT_SHADER)
+        .clear_color_attachment(&swapchain, ClearColor::Float([0.0, 0.0, 0.0, 1.0]))?
+        .build();
+    let present = PassBuilder::present("present", composite.output(&swapchain).unwrap());
+    let graph = PassGraph::<domain::All>::new()
+        .add_pass(light)?
+        .add_pass(blur)?
+        .add_pass(composite)?
+        .add_pass(present)?
+        .build()?;
+
+    // The previous frame sampled the image in both the compute and the fragment shader.
+    let description = graph.describe()?;
+    let history = description
+        .barriers
+        .iter()
+        .find(|barrier| barrier.resource == "lighting@history")
+        .unwrap();
+    assert!(history.src_stage.contains("FRAGMENT_SHADER"));
+    assert!(history.src_stage.contains("COMPUTE_SHADER"));
+    assert!(history.src_access.contains("SHADER_READ"));
+    Ok(())
+}
+
+#[test]
+pub fn history_requires_resource_in_graph() -> Result<()> {
+    let pass = PassBuilder::<domain::All>::new("reproject")
+        .read_history(&VirtualResource::image("velocity"), PipelineStage::COMPUTE_SHADER)
+        .build();
+    assert!(PassGraph::new().add_pass(pass)?.build().is_err());
+    Ok(())
+}
+
+#[test]
+pub fn imported_resources_keep_their_state() -> Result<()> {
+    let texture = VirtualResource::image("streamed_texture");
+    let result = VirtualResource::image("compute_result");
+    let swapchain = VirtualResource::image("swapchain");
+    let sampled = ResourceState {
+        layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        access: vk::AccessFlags2::SHADER_READ,
+        stage: PipelineStage::FRAGMENT_SHADER,
+    };
+
+    // The result of this pass is only used after the graph, so it would be culled if it was not imported.
+    let compute = PassBuilder::new("compute")
+        .write_storage_image(&result, PipelineStage::COMPUTE_SHADER)
+        .build();
+    let render = PassBuilder::render("render")
+        .clear_color_attachment(&swapchain, ClearColor::Float([0.0, 0.0, 0.0, 1.0]))?
+        .sample_image(&texture, PipelineStage::FRAGMENT_SHADER)
+        .build();
+    let present = PassBuilder::present("present", render.output(&swapchain).unwrap());
+    let graph = PassGraph::<domain::All>::new()
+        .import_resource(&texture, sampled, None)?
+        .import_resource(
+            &result,
+            ResourceState {
+                layout: vk::ImageLayout::GENERAL,
+                access: vk::AccessFlags2::SHADER_READ,
+                stage: PipelineStage::COMPUTE_SHADER,
+            },
+            Some(sampled),
+        )?
+        .add_pass(compute)?
+        .add_pass(render)?
+        .add_pass(present)?;
+    assert!(PassGraph::<domain::All>::new()
+        .import_resource(&texture, sampled, None)?
+        .import_resource(&texture, sampled, None)
+        .is_err());
+    let graph = graph.build()?;
+    assert!(graph.cull_report().is_empty());
+
+    let description = graph.describe()?;
+    let find = |resource: &str| {
+        description
+            .barriers
+            .iter()
+            .find(|barrier| barrier.resource == resource)
+            .unwrap()
+    };
+    // The texture is already in the right layout, so its contents are kept without a barrier.
+    assert_eq!(find("streamed_texture").old_layout, "SHADER_READ_ONLY_OPTIMAL");
+    assert_eq!(find("streamed_texture").kind, "skipped");
+    assert_eq!(find("compute_result").old_layout, "GENERAL");
+    assert!(find("compute_result").src_stage.contains("COMPUTE_SHADER"));
+
+    let last = description.barriers.last().unwrap();
+    assert_eq!(last.resource, "compute_result+");
+    assert_eq!(last.src_pass.as_deref(), Some("compute"));
+    assert!(last.dst_passes.is_empty());
+    assert_eq!(last.kind, "image");
+    assert_eq!(last.old_layout, "GENERAL");
+    assert_eq!(last.new_layout, "SHADER_READ_ONLY_OPTIMAL");
+    assert!(last.src_access.contains("SHADER_WRITE"));
+    assert!(last.dst_stage.contains("FRAGMENT_SHADER"));
+    Ok(())
+}
+
+#[test]
+pub fn imported_resources_wait_on_every_last_reader() -> Result<()> {
+    let texture = VirtualResource::image("streamed_texture");
+    let swapchain = VirtualResource::image("swapchain");
+    let sampled = ResourceState {
+        layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        access: vk::AccessFlags2::SHADER_READ,
+        stage: PipelineStage::FRAGMENT_SHADER,
+    };
+    let histogram = VirtualResource::image("histogram");
+    let analyze = PassBuilder::new("analyze")
+        .sample_image(&texture, PipelineStage::COMPUTE_SHADER)
+        .write_storage_image(&histogram, PipelineStage::COMPUTE_SHADER)
+        .build();
+    let render = PassBuilder::render("render")
+        .clear_color_attachment(&swapchain, ClearColor::Float([0.0, 0.0, 0.0, 1.0]))?
+        .sample_image(&texture, PipelineStage::FRAGMENT_SHADER)
+        .sample_image(analyze.output(&histogram).unwrap(), PipelineStage::FRAGMENT_SHADER)
+        .build();
+    let present = PassBuilder::present("present", render.output(&swapchain).unwrap());
+    let transfer = ResourceState {
+        layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        access: vk::AccessFlags2::TRANSFER_WRITE,
+        stage: PipelineStage::TRANSFER,
+    };
+    let graph = PassGraph::<domain::All>::new()
+        .import_resource(&texture, sampled, Some(transfer))?
+        .add_pass(analyze)?
+        .add_pass(render)?
+        .add_pass(present)?
+        .build()?;
+
+    // Streaming into the texture after the graph must wait on both passes sampling it.
+    let description = graph.describe()?;
+    let last = description.barriers.last().unwrap();
+    assert_eq!(last.resource, "streamed_texture");
+    assert_eq!(last.new_layout, "TRANSFER_DST_OPTIMAL");
+    assert!(last.src_stage.contains("COMPUTE_SHADER"));
+    assert!(last.src_stage.contains("FRAGMENT_SHADER"));
+    Ok(())
+}
+
+#[test]
+pub fn subgraph_instances_are_namespaced() -> Result<()> {
+    let input = VirtualResource::image("input");
+    let blurred = VirtualResource::image("blurred");
+    let scene = VirtualResource::image("scene");
+
+    let first = blur_subgraph()?.instantiate("blur_0", &[(&input, &scene)])?;
+    let second = blur_subgraph()?.instantiate("blur_1", &[(&input, first.output(&blurred).unwrap())])?;
+    assert_eq!(first.output(&blurred).unwrap().uid(), "blur_0/blurred+");
+    assert_eq!(second.output(&blurred).unwrap().uid(), "blur_1/blurred+");
+    assert!(first.output(&scene).is_none());
+
+    let graph = PassGraph::<domain::All>::new()
+        .add_subgraph(first)?
+        .add_subgraph(second)?
+        .build()?;
+    let description = graph.describe()?;
+    let passes = description
+        .passes
+        .iter()
+        .map(|pass| pass.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(passes, ["blur_0/blur", "blur_1/blur"]);
+    assert_eq!(description.passes[1].inputs[0].uid, "blur_0/blurred+");
+    Ok(())
+}
+
+#[test]
+pub fn subgraph_versions_carry_over_bindings() -> Result<()> {
+    let input = VirtualResource::image("input");
+    let hdr = VirtualResource::image("hdr").upgrade();
+    let pass = PassBuilder::<domain::All>::new("tonemap")
+        .write_storage_image(&input, PipelineStage::COMPUTE_SHADER)
+        .build();
+    let subgraph = Subgraph::new().input(&input).output(&input).add_pass(pass)?;
+    let instance = subgraph.instantiate("post", &[(&input, &hdr)])?;
+    assert_eq!(instance.output(&input).unwrap().uid(), "hdr++");
+
+    assert!(blur_subgraph()?.instantiate("blur", &[]).is_err());
+    assert!(blur_subgraph()?
+        .instantiate("blur", &[(&input, &hdr), (&hdr, &input)])
+        .is_err());
+    Ok(())
+}
+
+#[test]
+pub fn subgraph_bindings_keep_subresource_ranges() -> Result<()> {
+    let input = VirtualResource::image("input");
+    let scene = VirtualResource::image("scene");
+    let subgraph = |used: &VirtualResource| -> Result<Subgraph<domain::All>> {
+        let pass = PassBuilder::new("filter")
+            .write_storage_image(used, PipelineStage::COMPUTE_SHADER)
+            .build();
+        Subgraph::new().input(&input.mip(0)).output(used).add_pass(pass)
+    };
+    // The declared range is mapped to the range of the bound resource.
+    let instance = subgraph(&input.mip(0))?.instantiate("filter", &[(&input.mip(0), &scene.mip(2))])?;
+    assert_eq!(instance.output(&input.mip(0)).unwrap().uid(), "scene[mip 2, layers 0..]+");
+    let instance = subgraph(&input.mip(0))?.instantiate("filter", &[(&input.mip(0), &scene)])?;
+    assert_eq!(instance.output(&input.mip(0)).unwrap().uid(), "scene[mip 0, layers 0..]+");
+    // Another range of the input can not be mapped into the bound range.
+    assert!(subgraph(&input.mip(1))?
+        .instantiate("filter", &[(&input.mip(0), &scene.mip(2))])
+        .is_err());
+    Ok(())
+}
+
+#[test]
+pub fn subgraph_aliases_are_part_of_the_structure_hash() -> Result<()> {
+    let scene = VirtualResource::image("scene");
+    let build = |name: &str| -> Result<PassGraph<domain::All>> {
+        let input = VirtualResource::image(name);
+        let blurred = VirtualResource::image("blurred");
+        let pass = PassBuilder::render("blur")
+            .sample_image(&input, PipelineStage::FRAGMENT_SHADER)
+            .clear_color_attachment(&blurred, ClearColor::Float([0.0; 4]))?
+            .build();
+        let subgraph = Subgraph::new().input(&input).output(&blurred).add_pass(pass)?;
+        PassGraph::new().add_subgraph(subgraph.instantiate("blur", &[(&input, &scene)])?)
+    };
+    // Both instances use the same resources, but their executors resolve them by different names.
+    assert_eq!(build("input")?.structure_hash(), build("input")?.structure_hash());
+    assert_ne!(build("input")?.structure_hash(), build("source")?.structure_hash());
+    Ok(())
+}
+
+#[test]
+pub fn transfer_passes_transition_to_transfer_layouts() -> Result<()> {
+    let scene = VirtualResource::image("scene");
+    let small = VirtualResource::image("small");
+    let readback = VirtualResource::buffer("readback");
+    let render = PassBuilder::render("render")
+        .clear_color_attachment(&scene, ClearColor::Float([0.0; 4]))?
+        .build();
+    let clear = PassBuilder::clear("clear", &small, ClearColor::Float([0.0; 4]))?;
+    let blit = PassBuilder::blit(
+        "downsample",
+        render.output(&scene).unwrap(),
+        clear.output(&small).unwrap(),
+        vk::Filter::LINEAR,
+    )?;
+    let copy = PassBuilder::copy("readback", blit.output(&small).unwrap(), &readback);
+    let graph = PassGraph::<domain::All>::new()
+        .add_pass(render)?
+        .add_pass(clear)?
+        .add_pass(blit)?
+        .add_pass(copy)?
+        .build()?;
+    let description = graph.describe()?;
+    let barrier = |resource: &str| {
+        description
+            .barriers
+            .iter()
+            .find(|barrier| barrier.resource == resource)
+            .unwrap()
+    };
+    assert_eq!(barrier("small").new_layout, "TRANSFER_DST_OPTIMAL");
+    assert_eq!(barrier("scene+").new_layout, "TRANSFER_SRC_OPTIMAL");
+    assert_eq!(barrier("scene+").dst_access, "TRANSFER_READ");
+    assert_eq!(barrier("small+").old_layout, "TRANSFER_DST_OPTIMAL");
+    assert_eq!(barrier("small+").dst_stage, "ALL_TRANSFER");
+    assert_eq!(barrier("small++").new_layout, "TRANSFER_SRC_OPTIMAL");
+    assert_eq!(barrier("readback").dst_access, "TRANSFER_WRITE");
+    Ok(())
+}
+
+#[test]
+pub fn transfer_passes_check_resource_types() {
+    let image = VirtualResource::image("image");
+    let buffer = VirtualResource::buffer("buffer");
+    assert!(PassBuilder::<domain::All>::fill("fill", &image, 0).is_err());
+    assert!(PassBuilder::<domain::All>::fill("fill", &buffer, 0).is_ok());
+    assert!(PassBuilder::<domain::All>::blit("blit", &buffer, &image, vk::Filter::LINEAR).is_err());
+    assert!(PassBuilder::<domain::All>::clear("clear", &buffer, ClearColor::Float([0.0; 4])).is_err());
+}
+
+#[test]
+pub fn buffer_usages_reject_images() {
+    let image = VirtualResource::image("image");
+    let buffer = VirtualResource::buffer("buffer");
+    let stage = PipelineStage::COMPUTE_SHADER;
+    assert!(PassBuilder::<domain::All>::new("read").read_buffer(&image, BufferUsage::Storage, stage).is_err());
+    assert!(PassBuilder::<domain::All>::new("write").write_buffer(&image, BufferUsage::Storage, stage).is_err());
+    assert!(PassBuilder::<domain::All>::new("read_write").read_write_buffer(&image, BufferUsage::Storage, stage).is_err());
+    assert!(PassBuilder::<domain::All>::new("read").read_buffer(&buffer, BufferUsage::Storage, stage).is_ok());
+}
+
+#[test]
+pub fn minimize_barriers_fills_gaps_between_producers_and_consumers() -> Result<()> {
+    let traversal = ordering_graph(PassOrdering::Traversal)?;
+    assert_eq!(traversal.pass_order(), ["upload", "generate_lut", "shade", "composite"]);
+    assert_eq!(
+        traversal.order_cost(),
+        OrderCost {
+            pipeline_barriers: 3,
+            stalls: 2,
+        }
+    );
+
+    let minimized = ordering_graph(PassOrdering::MinimizeBarriers)?;
+    assert_eq!(minimized.pass_order(), ["generate_lut", "upload", "shade", "composite"]);
+    assert!(minimized.order_cost().total() < traversal.order_cost().total());
+    assert_eq!(minimized.barrier_stats().image_barriers, traversal.barrier_stats().image_barriers);
+
+    // Barriers in the description follow the chosen order.
+    let description = minimized.describe()?;
+    let names = description
+        .passes
+        .iter()
+        .map(|pass| pass.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, minimized.pass_order());
+    Ok(())
+}
+
+#[test]
+pub fn held_back_barriers_are_recorded_before_their_consumer() -> Result<()> {
+    let lut = VirtualResource::image("lut");
+    let mesh = VirtualResource::buffer("mesh");
+    let generate = PassBuilder::<domain::All>::new("generate_lut")
+        .write_storage_image(&lut, PipelineStage::COMPUTE_SHADER)
+        .build();
+    let read = PassBuilder::new("read_mesh")
+        .read_buffer(&mesh, BufferUsage::Storage, PipelineStage::COMPUTE_SHADER)?
+        .build();
+    let shade = PassBuilder::new("shade")
+        .sample_image(generate.output(&lut).unwrap(), PipelineStage::COMPUTE_SHADER)
+        .build();
+    let graph = PassGraph::new()
+        .pass_ordering(PassOrdering::MinimizeBarriers)
+        .add_pass(generate)?
+        .add_pass(read)?
+        .add_pass(shade)?
+        .build()?;
+    // The mesh is only read, so its barrier is held back until the pass reading it fills the gap before `shade`.
+    assert_eq!(graph.pass_order(), ["generate_lut", "read_mesh", "shade"]);
+    let description = graph.describe()?;
+    let order = |name: &str| description.passes.iter().find(|pass| pass.name == name).unwrap().order;
+    let barrier = description
+        .barriers
+        .iter()
+        .find(|barrier| barrier.resource == "mesh")
+        .unwrap();
+    assert!(order("generate_lut") < barrier.order);
+    assert!(barrier.order < order("read_mesh"));
+    Ok(())
+}
+
+#[test]
+pub fn ordering_is_part_of_the_structure_hash() {
+    let graph = PassGraph::<domain::All>::new();
+    let hash = graph.structure_hash();
+    assert_ne!(graph.pass_ordering(PassOrdering::MinimizeBarriers).structure_hash(), hash);
+}
+
+#[test]
+pub fn distant_barriers_are_split() -> Result<()> {
+    let graph = split_barrier_graph()?.build()?;
+    assert_eq!(graph.pass_order(), ["inject", "skin", "cull", "integrate"]);
+    // The barriers before the first passes are batched into one pipeline barrier. The barrier between inject and
+    // integrate is split around the two independent passes.
+    let stats = graph.barrier_stats();
+    assert_eq!(stats.pipeline_barriers, 1);
+    assert_eq!(stats.image_barriers, 2);
+    assert_eq!(stats.memory_barriers, 2);
+    assert_eq!(stats.split_barriers, 1);
+    assert_eq!(stats.event_waits, 1);
+
+    let description = graph.describe()?;
+    let split = description
+        .barriers
+        .iter()
+        .filter(|barrier| barrier.split)
+        .map(|barrier| barrier.resource.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(split, ["volume+"]);
+    Ok(())
+}
+
+#[test]
+pub fn split_barriers_can_be_disabled() -> Result<()> {
+    let forced = split_barrier_graph()?.force_pipeline_barriers(true).build()?;
+    let stats = forced.barrier_stats();
+    assert_eq!(stats.pipeline_barriers, 2);
+    assert_eq!(stats.split_barriers, 0);
+    assert_eq!(stats.event_waits, 0);
+
+    let distant = split_barrier_graph()?.split_barrier_distance(3).build()?;
+    assert_eq!(distant.barrier_stats(), stats);
+
+    let graph = split_barrier_graph()?;
+    let hash = graph.structure_hash();
+    assert_ne!(graph.force_pipeline_barriers(true).structure_hash(), hash);
+    Ok(())
+}
+
+#[test]
+pub fn graphs_load_from_declarations() -> Result<()> {
+    let used = std::cell::RefCell::new(Vec::new());
+    let registry = ExecutorRegistry::<domain::All>::new().register("fullscreen", |pass, declaration| {
+        used.borrow_mut().push(declaration.pipeline.clone().unwrap());
+        Ok(pass.execute_fn(|cmd, _, _, _| Ok(cmd)))
+    });
+    let graph = PassGraph::from_declaration(&declared_frame(), &registry)?.build()?;
+    assert_eq!(graph.pass_order(), ["shade", "tonemap", "present"]);
+    assert_eq!(*used.borrow(), ["tonemap"]);
+
+    // Later passes use the newest version of each resource.
+    let description = graph.describe()?;
+    let tonemap = description.passes.iter().find(|pass| pass.name == "tonemap").unwrap();
+    assert!(tonemap.renderpass);
+    let inputs = tonemap.inputs.iter().map(|input| input.uid.as_str()).collect::<Vec<_>>();
+    assert_eq!(inputs, ["scene+", "swapchain"]);
+    assert_eq!(tonemap.inputs[0].stage, "FRAGMENT_SHADER");
+    Ok(())
+}
+
+#[test]
+pub fn invalid_declarations_are_rejected() {
+    let registry = ExecutorRegistry::<domain::All>::new();
+    // The executor was not registered.
+    assert!(PassGraph::from_declaration(&declared_frame(), &registry).is_err());
+
+    let mut declaration = declared_frame();
+    declaration.passes[1].executor = None;
+    assert!(PassGraph::from_declaration(&declaration, &registry).is_ok());
+
+    let mut unknown_stage = declaration.clone();
+    unknown_stage.passes[0].resources[1] = PassResourceDeclaration::WriteStorageImage {
+        resource: "scene".to_owned(),
+        stage: "COMPUTE_SHADER | WARP_SHADER".to_owned(),
+    };
+    assert!(PassGraph::from_declaration(&unknown_stage, &registry).is_err());
+
+    let mut wrong_type = declaration.clone();
+    wrong_type.passes[1].resources[0] = PassResourceDeclaration::ReadBuffer {
+        resource: "scene".to_owned(),
+        buffer_usage: BufferUsage::Storage,
+        stage: "FRAGMENT_SHADER".to_owned(),
+    };
+    assert!(PassGraph::from_declaration(&wrong_type, &registry).is_err());
+
+    let mut not_render = declaration;
+    not_render.passes[1].render = false;
+    assert!(PassGraph::from_declaration(&not_render, &registry).is_err());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+pub fn declarations_load_from_json() -> Result<()> {
+    use phobos::graph::declarative::GraphDeclaration;
+    let json = r#"{
+        "passes": [
+            {
+                "name": "shade",
+                "pipeline": "shade",
+                "resources": [
+                    { "usage": "read_buffer", "resource": "lights", "buffer_usage": "uniform", "stage": "COMPUTE_SHADER" },
+                    { "usage": "write_storage_image", "resource": "scene", "stage": "COMPUTE_SHADER" }
+                ]
+            },
+            {
+                "name": "tonemap",
+                "render": true,
+                "pipeline": "tonemap",
+                "executor": "fullscreen",
+                "resources": [
+                    { "usage": "sample_image", "resource": "scene", "stage": "FRAGMENT_SHADER" },
+                    { "usage": "color_attachment", "resource": "swapchain", "clear": { "float": [0.0, 0.0, 0.0, 1.0] } }
+                ]
+            }
+        ],
+        "present": "swapchain"
+    }"#;
+    let declaration = GraphDeclaration::from_json(json)?;
+    assert_eq!(declaration, declared_frame());
+    assert_eq!(GraphDeclaration::from_json(&declaration.to_json()?)?, declaration);
+    Ok(())
+}
+
+#[test]
+pub fn layered_and_multiview_passes() -> Result<()> {
+    let target = VirtualResource::image("eyes");
+    let stereo = PassBuilder::<domain::All>::render("stereo").view_mask(0b11)?;
+    assert!(stereo.layers(2).is_err());
+    assert!(PassBuilder::<domain::All>::render("cascades").layers(4)?.view_mask(0b1).is_err());
+    assert!(PassBuilder::<domain::All>::render("empty").view_mask(0).is_err());
+    assert!(PassBuilder::<domain::All>::render("empty").layers(0).is_err());
+    assert!(PassBuilder::<domain::All>::new("compute").layers(4).is_err());
+    assert!(PassBuilder::<domain::All>::new("compute").view_mask(0b11).is_err());
+
+    let stereo = PassBuilder::<domain::All>::render("stereo")
+        .view_mask(0b11)?
+        .color_attachment(&target, vk::AttachmentLoadOp::CLEAR, Some(vk::ClearColorValue::default()))?
+        .build();
+    let graph = PassGraph::<domain::All>::new().add_pass(stereo)?.build()?;
+    let description = graph.describe()?;
+    assert_eq!(description.passes[0].view_mask, 0b11);
+    assert_eq!(description.passes[0].layers, 1);
+
+    let description = shadow_cascades(4)?.build()?.describe()?;
+    let cascades = description.passes.iter().find(|pass| pass.name == "cascades").unwrap();
+    assert_eq!((cascades.view_mask, cascades.layers), (0, 4));
+
+    assert_ne!(shadow_cascades(4)?.structure_hash(), shadow_cascades(2)?.structure_hash());
+    Ok(())
+}
+
+#[test]
+pub fn render_passes_record_to_a_command_log() -> Result<()> {
+    let target = VirtualResource::image("albedo");
+    let gbuffer = PassBuilder::<domain::All>::render("gbuffer")
+        .color_attachment(&target, vk::AttachmentLoadOp::CLEAR, Some(vk::ClearColorValue::default()))?
+        .execute_fn(|cmd, _, _, _| cmd.bind_graphics_pipeline("gbuffer")?.full_viewport_scissor().draw(3, 1, 0, 0))
+        .build();
+    let mut graph = PassGraph::<domain::All>::new().add_pass(gbuffer)?.build()?;
+
+    let albedo = detached_image(vk::Format::R8G8B8A8_UNORM, vk::ImageUsageFlags::COLOR_ATTACHMENT, vk::ImageAspectFlags::COLOR);
+    let mut bindings = PhysicalResourceBindings::new();
+    bindings.bind_image("albedo", &albedo);
+    let mut local_pool = unsafe { LocalPool::detached() };
+    let cmd = graph.record(IncompleteCommandBuffer::new_logged(), &bindings, &mut local_pool, None, &mut ())?;
+    let log = cmd.into_command_log()?;
+    assert_eq!(
+        log.names(),
+        [
+            "vkCmdPipelineBarrier2",
+            "vkCmdBeginRendering",
+            "vkCmdBindPipeline",
+            "vkCmdSetViewport",
+            "vkCmdSetScissor",
+            "vkCmdDraw",
+            "vkCmdEndRendering",
+        ]
+    );
+
+    let Command::PipelineBarrier(dependency) = &log.commands()[0] else { unreachable!() };
+    let barrier = &dependency.image_barriers[0];
+    assert_eq!(barrier.image, unsafe { albedo.image() });
+    assert_eq!(barrier.old_layout, vk::ImageLayout::UNDEFINED);
+    assert_eq!(barrier.new_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+    let Command::BeginRendering(rendering) = &log.commands()[1] else { unreachable!() };
+    assert_eq!(rendering.render_area.extent, vk::Extent2D { width: 1920, height: 1080 });
+    assert_eq!(rendering.color_attachments[0].image_view, unsafe { albedo.handle() });
+    assert_eq!(rendering.color_attachments[0].load_op, vk::AttachmentLoadOp::CLEAR);
+    assert!(matches!(&log.commands()[2], Command::BindPipeline { name, .. } if name == "gbuffer"));
+    Ok(())
+}
+
+#[test]
+pub fn split_barriers_record_events_to_a_command_log() -> Result<()> {
+    let mut graph = split_barrier_graph()?.build()?;
+    let volume = detached_image(vk::Format::R16G16B16A16_SFLOAT, vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED, vk::ImageAspectFlags::COLOR);
+    let mut bindings = PhysicalResourceBindings::new();
+    bindings.bind_image("volume", &volume);
+    bindings.bind_buffer("vertices", &unsafe { BufferView::detached(0, 1024) });
+    bindings.bind_buffer("draws", &unsafe { BufferView::detached(0, 1024) });
+    let mut local_pool = unsafe { LocalPool::detached() };
+    let cmd = graph.record(IncompleteCommandBuffer::new_logged(), &bindings, &mut local_pool, None, &mut ())?;
+    let log = cmd.into_command_log()?;
+    assert_eq!(log.names(), ["vkCmdPipelineBarrier2", "vkCmdSetEvent2", "vkCmdWaitEvents2"]);
+
+    let (Command::SetEvent { event: set, dependency }, Command::WaitEvents { events, dependencies }) = (&log.commands()[1], &log.commands()[2]) else {
+        unreachable!()
+    };
+    assert_eq!(events, &[*set]);
+    let barrier = &dependency.image_barriers[0];
+    assert_eq!(barrier.image, unsafe { volume.image() });
+    assert_eq!(barrier.old_layout, vk::ImageLayout::GENERAL);
+    assert_eq!(barrier.new_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+    assert_eq!(dependencies[0].image_barriers[0].new_layout, barrier.new_layout);
+    Ok(())
+}
+
+#[test]
+pub fn subgraph_executors_resolve_names_of_the_subgraph() -> Result<()> {
+    let scene = detached_image(vk::Format::R8G8B8A8_UNORM, vk::ImageUsageFlags::SAMPLED, vk::ImageAspectFlags::COLOR);
+    let blurred = detached_image(vk::Format::R8G8B8A8_UNORM, vk::ImageUsageFlags::COLOR_ATTACHMENT, vk::ImageAspectFlags::COLOR);
+    let scene_view = unsafe { scene.handle() };
+    let input = VirtualResource::image("input");
+    let pass = PassBuilder::render("blur")
+        .sample_image(&input, PipelineStage::FRAGMENT_SHADER)
+        .clear_color_attachment(&VirtualResource::image("blurred"), ClearColor::Float([0.0; 4]))?
+        .execute_fn(move |cmd, _, bindings, _| {
+            let Some(PhysicalResource::Image(image)) = bindings.resolve(&VirtualResource::image("input")) else { panic!("input is not bound") };
+            assert_eq!(unsafe { image.handle() }, scene_view);
+            Ok(cmd)
+        })
+        .build();
+    let subgraph = Subgraph::new().input(&input).add_pass(pass)?;
+    let instance = subgraph.instantiate("blur", &[(&input, &VirtualResource::image("scene"))])?;
+    let mut graph = PassGraph::<domain::All>::new().add_subgraph(instance)?.build()?;
+
+    let mut bindings = PhysicalResourceBindings::new();
+    bindings.bind_image("scene", &scene);
+    bindings.bind_image("blur/blurred", &blurred);
+    let mut local_pool = unsafe { LocalPool::detached() };
+    graph.record(IncompleteCommandBuffer::new_logged(), &bindings, &mut local_pool, None, &mut ())?;
+    Ok(())
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+pub fn parallel_passes_record_to_secondary_command_logs() -> Result<()> {
+    let shadow = |name: &str| -> Result<phobos::Pass<'static, domain::All>> {
+        let pipeline = format!("{name}_pipeline");
+        Ok(PassBuilder::render(name)
+            .clear_color_attachment(&VirtualResource::image(name), ClearColor::Float([0.0; 4]))?
+            .parallel_execute_fn(move |cmd, _, _, _| cmd.bind_graphics_pipeline(&pipeline)?.full_viewport_scissor().draw(3, 1, 0, 0))
+            .build())
+    };
+    let mut graph = PassGraph::<domain::All>::new()
+        .add_pass(shadow("near")?)?
+        .add_pass(shadow("far")?)?
+        .build()?;
+
+    let mut bindings = PhysicalResourceBindings::new();
+    for name in ["near", "far"] {
+        bindings.bind_image(name, &detached_image(vk::Format::R8G8B8A8_UNORM, vk::ImageUsageFlags::COLOR_ATTACHMENT, vk::ImageAspectFlags::COLOR));
+    }
+    let mut local_pool = unsafe { LocalPool::detached() };
+    let mut recording = ParallelRecording::new();
+    let mut record = |recording: &mut ParallelRecording| {
+        graph.record_parallel(IncompleteCommandBuffer::new_logged(), &bindings, &mut local_pool, None, &mut (), recording)
+    };
+    let cmd = record(&mut recording)?;
+    assert_eq!(recording.secondary_count(), 2);
+    // Every frame in flight creates its pools once, and later frames reuse them.
+    record(&mut recording)?;
+    let pools = recording.pool_count();
+    assert!(pools > 0);
+    for _ in 0..4 {
+        record(&mut recording)?;
+        assert_eq!(recording.secondary_count(), 2);
+    }
+    assert_eq!(recording.pool_count(), pools);
+    let log = cmd.into_command_log()?;
+    let secondaries = log
+        .commands()
+        .iter()
+        .filter_map(|command| match command {
+            Command::ExecuteCommands { logs, .. } => Some(logs),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(secondaries.len(), 2);
+    for logs in secondaries {
+        assert_eq!(logs[0].names(), ["vkCmdBindPipeline", "vkCmdSetViewport", "vkCmdSetScissor", "vkCmdDraw"]);
+        let Command::SetViewport(viewport) = &logs[0].commands()[1] else { unreachable!() };
+        assert_eq!(viewport.width, 1920.0);
+    }
+    for (index, command) in log.commands().iter().enumerate() {
+        let Command::BeginRendering(rendering) = command else { continue };
+        assert!(rendering.flags.contains(vk::RenderingFlags::CONTENTS_SECONDARY_COMMAND_BUFFERS));
+        assert_eq!(log.names()[index + 1..index + 3], ["vkCmdExecuteCommands", "vkCmdEndRendering"]);
+    }
+    Ok(())
+}
+
+#[test]
+pub fn descriptor_sets_are_logged_when_flushed() -> Result<()> {
+    let buffer = unsafe { BufferView::detached(256, 512) };
+    let image = detached_image(vk::Format::R8G8B8A8_UNORM, vk::ImageUsageFlags::STORAGE, vk::ImageAspectFlags::COLOR);
+    let cmd = IncompleteCommandBuffer::<domain::Compute>::new_logged()
+        .bind_storage_buffer(0, 1, &buffer)?
+        .bind_storage_image(0, 0, &image)?;
+    assert!(cmd.dispatch(1, 1, 1).is_err(), "Descriptor sets cannot be bound without a pipeline.");
+
+    let log = IncompleteCommandBuffer::<domain::Compute>::new_logged()
+        .bind_compute_pipeline("cull")?
+        .bind_storage_buffer(0, 1, &buffer)?
+        .bind_storage_image(0, 0, &image)?
+        .dispatch(64, 1, 1)?
+        .dispatch(64, 1, 1)?
+        .into_command_log()?;
+    assert_eq!(log.names(), ["vkCmdBindPipeline", "vkCmdBindDescriptorSets", "vkCmdDispatch", "vkCmdDispatch"]);
+    let Command::BindDescriptorSet { set, descriptors, .. } = &log.commands()[1] else { unreachable!() };
+    assert_eq!(*set, 0);
+    let descriptors = descriptors.iter().map(|write| (write.binding, write.ty)).collect::<Vec<_>>();
+    assert_eq!(
+        descriptors,
+        [(0, vk::DescriptorType::STORAGE_IMAGE), (1, vk::DescriptorType::STORAGE_BUFFER)]
+    );
+    Ok(())
+}
+
+#[test]
+pub fn indirect_draws_check_alignment_and_stride() -> Result<()> {
+    let draws = unsafe { BufferView::detached(64, 20 * 16) };
+    let count = unsafe { BufferView::detached(0, 4) };
+    let cmd = || IncompleteCommandBuffer::<domain::Graphics>::new_logged();
+    assert!(cmd().draw_indirect(&unsafe { BufferView::detached(2, 64) }, 1, 16).is_err(), "Offset must be a multiple of 4.");
+    assert!(cmd().draw_indirect(&draws, 2, 12).is_err(), "Stride must hold a draw command.");
+    assert!(cmd().draw_indirect(&draws, 2, 18).is_err(), "Stride must be a multiple of 4.");
+    assert!(cmd().draw_indexed_indirect(&draws, 2, 16).is_err(), "Indexed draw commands are 20 bytes.");
+    assert!(cmd().draw_indirect(&draws, 21, 16).is_err(), "Buffer must hold every draw.");
+    assert!(cmd().draw_indirect_count(&draws, &count, 1, 0).is_err(), "Count draws always check the stride.");
+    assert!(cmd().draw_indirect_count(&draws, &unsafe { BufferView::detached(6, 4) }, 1, 16).is_err(), "Count offset must be a multiple of 4.");
+
+    let log = cmd()
+        .draw_indirect(&draws, 1, 0)?
+        .draw_indirect(&draws, 20, 16)?
+        .draw_indexed_indirect(&draws, 16, 20)?
+        .draw_indirect_count(&draws, &count, 20, 16)?
+        .draw_indexed_indirect_count(&draws, &count, 8, 40)?
+        .into_command_log()?;
+    assert_eq!(
+        log.names(),
+        [
+            "vkCmdDrawIndirect",
+            "vkCmdDrawIndirect",
+            "vkCmdDrawIndexedIndirect",
+            "vkCmdDrawIndirectCount",
+            "vkCmdDrawIndexedIndirectCount",
+        ]
+    );
+    let Command::DrawIndexedIndirectCount { offset, count_buffer, max_draw_count, stride, .. } = &log.commands()[4] else {
+        unreachable!()
+    };
+    assert_eq!((*offset, *max_draw_count, *stride), (64, 8, 40));
+    assert_eq!(*count_buffer, unsafe { count.handle() });
+    Ok(())
+}